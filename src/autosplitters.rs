@@ -1,8 +1,15 @@
+pub mod asl;
 pub mod json;
+pub mod registry;
 pub mod supermetroid;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 use anyhow::Result;
 use livesplit_core::TimeSpan;
+use std::collections::BTreeMap;
+
+use crate::memory_source::MemorySource;
 
 #[derive(Debug, Copy, Clone)]
 pub struct SNESSummary {
@@ -11,8 +18,42 @@ pub struct SNESSummary {
     pub split: bool,
 }
 
+/// Memory that only ever moves forward during a run (items, upgrades,
+/// bosses beaten), by watcher name. Kept across reconnects so splits
+/// missed while disconnected can be found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress(pub BTreeMap<&'static str, u32>);
+
+/// A split the autosplitter would have made while it couldn't see the game.
+#[derive(Clone, Debug)]
+pub struct MissedSplit {
+    /// What changed, for the log and the control panel.
+    pub reason: String,
+    /// Game time when the split was noticed; the real moment is unknown.
+    pub game_time: Option<TimeSpan>,
+}
+
 pub trait AutoSplitter: Send {
-    fn update(&mut self, client: &mut crate::usb2snes::SyncClient) -> Result<SNESSummary>;
+    fn update(&mut self, client: &mut dyn MemorySource) -> Result<SNESSummary>;
     fn gametime_to_seconds(&self) -> Option<TimeSpan>;
     fn reset_game_tracking(&mut self);
+
+    /// Tells the autosplitter which segment the timer is on, so splits
+    /// made, skipped or undone by hand are followed. `None` when the timer
+    /// isn't running.
+    fn set_current_split(&mut self, _index: Option<usize>) {}
+
+    /// The progress read so far, or `None` if this autosplitter can't
+    /// catch up after a reconnect.
+    fn progress(&self) -> Option<Progress> {
+        None
+    }
+
+    /// Compares `before`, the progress from the previous connection, with
+    /// what has been read since reconnecting, and returns the splits that
+    /// would have happened in between. Nothing is returned when progress
+    /// went backwards, since that means a different save or a reset.
+    fn missed_splits(&self, _before: &Progress) -> Vec<MissedSplit> {
+        vec![]
+    }
 }
@@ -0,0 +1,1326 @@
+//! Runs LiveSplit ASL scripts written for SNES games against the memory
+//! read over usb2snes, so community autosplitters work unmodified.
+//!
+//! Only the subset SNES scripts use is supported: `settings.Add` and
+//! `SetToolTip`, `state` blocks and `MemoryWatcher`s for memory, and the
+//! actions as C#-like statements over numbers, strings, dictionaries and
+//! lambdas.
+//!
+//! Memory addresses are WRAM offsets. Scripts written for emulators look
+//! for WRAM in the emulator's process with `memory`, `modules` and `game`;
+//! statements that use them are skipped, which leaves offsets such as
+//! `memoryOffset` at zero. In a `state` block the last offset of each path
+//! is the WRAM offset, and a block for the `"usb2snes"` process is
+//! preferred when there are several.
+
+pub mod parser;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use livesplit_core::TimeSpan;
+use parking_lot::RwLock;
+
+use self::parser::{BinOp, Expr, Initializer, Lambda, LambdaBody, Script, Stmt, TypeName, UnOp};
+use super::supermetroid::Settings;
+use super::{AutoSplitter, SNESSummary};
+use crate::memory_source::{MemoryDomain, MemorySource};
+
+/// Names that belong to the emulator process, which usb2snes doesn't have.
+const PROCESS_NAMES: [&str; 3] = ["memory", "modules", "game"];
+
+/// How deeply lambdas may call each other before giving up.
+const MAX_CALL_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatcherType {
+    Bool,
+    Byte,
+    SByte,
+    UShort,
+    Short,
+    UInt,
+    Int,
+    ULong,
+    Long,
+}
+
+impl WatcherType {
+    pub fn parse(name: &str) -> Result<WatcherType> {
+        Ok(match name {
+            "bool" | "Boolean" => WatcherType::Bool,
+            "byte" | "Byte" => WatcherType::Byte,
+            "sbyte" | "SByte" => WatcherType::SByte,
+            "ushort" | "UInt16" => WatcherType::UShort,
+            "short" | "Int16" => WatcherType::Short,
+            "uint" | "UInt32" => WatcherType::UInt,
+            "int" | "Int32" => WatcherType::Int,
+            "ulong" | "UInt64" => WatcherType::ULong,
+            "long" | "Int64" => WatcherType::Long,
+            other => Err(anyhow!("{other} memory is not supported"))?,
+        })
+    }
+
+    pub fn width(self) -> usize {
+        match self {
+            WatcherType::Bool | WatcherType::Byte | WatcherType::SByte => 1,
+            WatcherType::UShort | WatcherType::Short => 2,
+            WatcherType::UInt | WatcherType::Int => 4,
+            WatcherType::ULong | WatcherType::Long => 8,
+        }
+    }
+
+    /// The little-endian value at the start of `bytes`.
+    fn decode(self, bytes: &[u8]) -> Value {
+        let raw = bytes
+            .iter()
+            .take(self.width())
+            .rev()
+            .fold(0u64, |value, &byte| value << 8 | byte as u64);
+        Value::Int(match self {
+            WatcherType::Bool => return Value::Bool(raw != 0),
+            WatcherType::SByte => raw as i8 as i64,
+            WatcherType::Short => raw as i16 as i64,
+            WatcherType::Int => raw as i32 as i64,
+            _ => raw as i64,
+        })
+    }
+}
+
+/// Memory read each poll, from a `state` block or a `MemoryWatcher`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watcher {
+    pub name: String,
+    /// Offset into WRAM.
+    pub offset: u32,
+    pub typ: WatcherType,
+}
+
+/// The WRAM offset of `address`, which may also be given in bank `$7E`
+/// or `$7F`.
+fn wram_offset(address: i64, width: usize) -> Result<u32> {
+    let offset = match address {
+        0x7E0000..=0x7FFFFF => address - 0x7E0000,
+        address => address,
+    };
+    let offset =
+        u32::try_from(offset).map_err(|_| anyhow!("{address:#x} is not a WRAM address"))?;
+    MemoryDomain::Wram.address(offset, width)?;
+    Ok(offset)
+}
+
+/// A key of a `Dictionary`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+}
+
+/// Names the script uses without defining them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Global {
+    Vars,
+    Settings,
+    Current,
+    Old,
+    Timer,
+    TimeSpan,
+    IntPtr,
+    Math,
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Dict(Arc<BTreeMap<Key, Value>>),
+    List(Arc<Vec<Value>>),
+    Watcher(Arc<Watcher>),
+    /// A `MemoryWatcherList`.
+    Watchers(Arc<Vec<Arc<Watcher>>>),
+    /// A `TimeSpan`, in seconds.
+    TimeSpan(f64),
+    Function(Arc<Lambda>),
+    Global(Global),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "double",
+            Value::Str(_) => "string",
+            Value::Dict(_) => "Dictionary",
+            Value::List(_) => "List",
+            Value::Watcher(_) => "MemoryWatcher",
+            Value::Watchers(_) => "MemoryWatcherList",
+            Value::TimeSpan(_) => "TimeSpan",
+            Value::Function(_) => "lambda",
+            Value::Global(_) => "object",
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Float(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn to_bool(&self) -> Result<bool> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            other => Err(anyhow!("expected a bool, found {}", other.type_name())),
+        }
+    }
+
+    fn to_int(&self) -> Result<i64> {
+        match self {
+            Value::Int(n) => Ok(*n),
+            other => Err(anyhow!("expected an integer, found {}", other.type_name())),
+        }
+    }
+
+    fn to_str(&self) -> Result<&str> {
+        match self {
+            Value::Str(s) => Ok(s),
+            other => Err(anyhow!("expected a string, found {}", other.type_name())),
+        }
+    }
+
+    fn to_key(&self) -> Result<Key> {
+        Ok(match self {
+            Value::Bool(b) => Key::Bool(*b),
+            Value::Int(n) => Key::Int(*n),
+            Value::Str(s) => Key::Str(s.clone()),
+            other => Err(anyhow!("{} can't be a dictionary key", other.type_name()))?,
+        })
+    }
+
+    fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::TimeSpan(a), Value::TimeSpan(b)) => a == b,
+            (Value::Watcher(a), Value::Watcher(b)) => a == b,
+            (a, b) => match (a.number(), b.number()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+}
+
+impl From<&Key> for Value {
+    fn from(key: &Key) -> Value {
+        match key {
+            Key::Bool(b) => Value::Bool(*b),
+            Key::Int(n) => Value::Int(*n),
+            Key::Str(s) => Value::Str(s.clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(true) => write!(f, "True"),
+            Value::Bool(false) => write!(f, "False"),
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Float(n) => write!(f, "{n}"),
+            Value::Str(s) => write!(f, "{s}"),
+            Value::TimeSpan(seconds) => {
+                let whole = *seconds as i64;
+                write!(
+                    f,
+                    "{:02}:{:02}:{:02}",
+                    whole / 3600,
+                    whole / 60 % 60,
+                    whole % 60
+                )?;
+                let fraction = seconds.fract();
+                if fraction != 0.0 {
+                    write!(f, ".{:07}", (fraction * 1e7).round() as i64)?;
+                }
+                Ok(())
+            }
+            Value::Watcher(w) => write!(f, "{}", w.name),
+            other => write!(f, "{}", other.type_name()),
+        }
+    }
+}
+
+/// A setting added with `settings.Add`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AslSetting {
+    pub key: String,
+    pub default: bool,
+    pub label: String,
+    pub tooltip: Option<String>,
+    pub parent: Option<String>,
+}
+
+enum Flow {
+    Next,
+    Return(Value),
+}
+
+/// The script's variables and the memory it sees, and the code that runs
+/// the statements of an action against them.
+#[derive(Default)]
+struct Interpreter {
+    settings: Arc<RwLock<Settings>>,
+    script_settings: Vec<AslSetting>,
+    /// `settings.CurrentDefaultParent`
+    default_parent: Option<String>,
+    in_startup: bool,
+    vars: BTreeMap<String, Value>,
+    current: BTreeMap<String, Value>,
+    old: BTreeMap<String, Value>,
+    /// Bytes read this poll and the one before, by WRAM offset.
+    memory: BTreeMap<u32, Vec<u8>>,
+    old_memory: BTreeMap<u32, Vec<u8>>,
+    current_split: Option<usize>,
+    /// Local variables, innermost scope last.
+    locals: Vec<BTreeMap<String, Value>>,
+    depth: usize,
+}
+
+impl Interpreter {
+    /// Runs `stmts` in a new scope, returning the value of a `return`.
+    fn run(&mut self, stmts: &[Stmt]) -> Result<Option<Value>> {
+        self.locals.push(BTreeMap::new());
+        let mut result = Ok(None);
+        for stmt in stmts {
+            match self.exec(stmt) {
+                Ok(Flow::Next) => {}
+                Ok(Flow::Return(value)) => {
+                    result = Ok(Some(value));
+                    break;
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.locals.pop();
+        result
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> Result<Flow> {
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            Stmt::Var { typ, name, init } => {
+                let value = match init {
+                    Some(init) => self.eval(init)?,
+                    None => Value::Null,
+                };
+                let value = match (typ.name.as_str(), value) {
+                    ("float" | "double", Value::Int(n)) => Value::Float(n as f64),
+                    (_, value) => value,
+                };
+                self.locals
+                    .last_mut()
+                    .expect("a scope")
+                    .insert(name.clone(), value);
+            }
+            Stmt::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                if self.eval(cond)?.to_bool()? {
+                    return self.exec(then);
+                } else if let Some(otherwise) = otherwise {
+                    return self.exec(otherwise);
+                }
+            }
+            Stmt::Foreach { name, items, body } => {
+                let items: Vec<Value> = match self.eval(items)? {
+                    Value::List(items) => items.to_vec(),
+                    Value::Watchers(watchers) => {
+                        watchers.iter().cloned().map(Value::Watcher).collect()
+                    }
+                    Value::Dict(dict) => dict.keys().map(Value::from).collect(),
+                    other => Err(anyhow!("can't loop over {}", other.type_name()))?,
+                };
+                for item in items {
+                    self.locals.push(BTreeMap::from([(name.clone(), item)]));
+                    let flow = self.exec(body);
+                    self.locals.pop();
+                    if let Flow::Return(value) = flow? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Stmt::Block(stmts) => {
+                if let Some(value) = self.run(stmts)? {
+                    return Ok(Flow::Return(value));
+                }
+            }
+            Stmt::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Null,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Stmt::Throw(value) => {
+                let value = self.eval(value)?;
+                Err(anyhow!("the script threw {value}"))?;
+            }
+            Stmt::Empty => {}
+        }
+        Ok(Flow::Next)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        Ok(match expr {
+            Expr::Null => Value::Null,
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Int(n) => Value::Int(*n),
+            Expr::Float(n) => Value::Float(*n),
+            Expr::Str(s) => Value::Str(s.clone()),
+            Expr::Ident(name) => self.variable(name)?,
+            Expr::Member(value, name) => {
+                let value = self.eval(value)?;
+                self.member(value, name)?
+            }
+            Expr::Index(value, index) => {
+                let value = self.eval(value)?;
+                let index = self.eval(index)?;
+                self.index(value, index)?
+            }
+            Expr::Call(callee, args) => self.call(callee, args)?,
+            Expr::Out(_) => Err(anyhow!("`out` only works with TryGetValue"))?,
+            Expr::Unary(op, value) => {
+                let value = self.eval(value)?;
+                match (op, value) {
+                    (UnOp::Neg, Value::Int(n)) => Value::Int(n.wrapping_neg()),
+                    (UnOp::Neg, Value::Float(n)) => Value::Float(-n),
+                    (UnOp::Not, Value::Bool(b)) => Value::Bool(!b),
+                    (UnOp::BitNot, Value::Int(n)) => Value::Int(!n),
+                    (op, value) => Err(anyhow!("can't apply {op:?} to {}", value.type_name()))?,
+                }
+            }
+            Expr::Binary(BinOp::And, a, b) => {
+                Value::Bool(self.eval(a)?.to_bool()? && self.eval(b)?.to_bool()?)
+            }
+            Expr::Binary(BinOp::Or, a, b) => {
+                Value::Bool(self.eval(a)?.to_bool()? || self.eval(b)?.to_bool()?)
+            }
+            Expr::Binary(BinOp::Coalesce, a, b) => match self.eval(a)? {
+                Value::Null => self.eval(b)?,
+                value => value,
+            },
+            Expr::Binary(op, a, b) => {
+                let a = self.eval(a)?;
+                let b = self.eval(b)?;
+                binary(*op, a, b)?
+            }
+            Expr::Assign(op, target, value) => {
+                let value = self.eval(value)?;
+                let value = match op {
+                    Some(op) => binary(*op, self.eval(target)?, value)?,
+                    None => value,
+                };
+                self.assign(target, value.clone())?;
+                value
+            }
+            Expr::Conditional(cond, then, otherwise) => {
+                if self.eval(cond)?.to_bool()? {
+                    self.eval(then)?
+                } else {
+                    self.eval(otherwise)?
+                }
+            }
+            Expr::Cast(typ, value) => cast(typ, self.eval(value)?)?,
+            Expr::New { typ, args, init } => self.construct(typ, args, init)?,
+            Expr::Lambda(lambda) => Value::Function(lambda.clone()),
+        })
+    }
+
+    fn variable(&self, name: &str) -> Result<Value> {
+        if let Some(value) = self.locals.iter().rev().find_map(|scope| scope.get(name)) {
+            return Ok(value.clone());
+        }
+        Ok(Value::Global(match name {
+            "vars" => Global::Vars,
+            "settings" => Global::Settings,
+            "current" => Global::Current,
+            "old" => Global::Old,
+            "timer" => Global::Timer,
+            "TimeSpan" => Global::TimeSpan,
+            "IntPtr" => Global::IntPtr,
+            "Math" => Global::Math,
+            _ => Err(anyhow!("`{name}` is not defined"))?,
+        }))
+    }
+
+    fn member(&self, value: Value, name: &str) -> Result<Value> {
+        let missing = |value: &Value| anyhow!("{} has no {name}", value.type_name());
+        Ok(match (&value, name) {
+            (Value::Global(Global::Vars), _) => self
+                .vars
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("vars.{name} is not set"))?,
+            (Value::Global(Global::Current), _) => self
+                .current
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("current.{name} is not set"))?,
+            (Value::Global(Global::Old), _) => self
+                .old
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("old.{name} is not set"))?,
+            (Value::Global(Global::Settings), "CurrentDefaultParent") => {
+                self.default_parent.clone().map_or(Value::Null, Value::Str)
+            }
+            (Value::Global(Global::Timer), "CurrentSplitIndex") => {
+                Value::Int(self.current_split.map_or(-1, |i| i as i64))
+            }
+            (Value::Global(Global::TimeSpan), "Zero") => Value::TimeSpan(0.0),
+            (Value::Global(Global::IntPtr), "Zero") => Value::Int(0),
+            (Value::Watcher(watcher), "Current") => self.read(watcher, &self.memory)?,
+            (Value::Watcher(watcher), "Old") => self.read(watcher, &self.old_memory)?,
+            (Value::Watcher(watcher), "Changed") => Value::Bool(
+                !self
+                    .read(watcher, &self.memory)?
+                    .equals(&self.read(watcher, &self.old_memory)?),
+            ),
+            (Value::Watcher(watcher), "Name") => Value::Str(watcher.name.clone()),
+            (Value::Str(s), "Length") => Value::Int(s.chars().count() as i64),
+            (Value::Dict(dict), "Count") => Value::Int(dict.len() as i64),
+            (Value::Dict(dict), "Keys") => {
+                Value::List(Arc::new(dict.keys().map(Value::from).collect()))
+            }
+            (Value::Dict(dict), "Values") => {
+                Value::List(Arc::new(dict.values().cloned().collect()))
+            }
+            (Value::List(list), "Count") => Value::Int(list.len() as i64),
+            (Value::Watchers(list), "Count") => Value::Int(list.len() as i64),
+            (Value::TimeSpan(seconds), "TotalSeconds") => Value::Float(*seconds),
+            (Value::TimeSpan(seconds), "TotalMilliseconds") => Value::Float(seconds * 1000.0),
+            (Value::TimeSpan(seconds), "TotalMinutes") => Value::Float(seconds / 60.0),
+            (Value::TimeSpan(seconds), "TotalHours") => Value::Float(seconds / 3600.0),
+            _ => Err(missing(&value))?,
+        })
+    }
+
+    /// What `watcher` read from `memory`.
+    fn read(&self, watcher: &Watcher, memory: &BTreeMap<u32, Vec<u8>>) -> Result<Value> {
+        let bytes = memory.get(&watcher.offset).ok_or_else(|| {
+            anyhow!(
+                "{} isn't read; watchers must be created in init",
+                watcher.name
+            )
+        })?;
+        Ok(watcher.typ.decode(bytes))
+    }
+
+    fn index(&self, value: Value, index: Value) -> Result<Value> {
+        Ok(match (&value, &index) {
+            (Value::Global(Global::Settings), Value::Str(key)) => {
+                let settings = self.settings.read();
+                if !settings.contains(key) {
+                    Err(anyhow!("there is no setting {key:?}"))?;
+                }
+                Value::Bool(settings.get(key))
+            }
+            (Value::Dict(dict), _) => dict
+                .get(&index.to_key()?)
+                .cloned()
+                .ok_or_else(|| anyhow!("the dictionary has no key {index}"))?,
+            (Value::List(list), Value::Int(i)) => usize::try_from(*i)
+                .ok()
+                .and_then(|i| list.get(i))
+                .cloned()
+                .ok_or_else(|| anyhow!("index {i} is out of range"))?,
+            (Value::Watchers(list), Value::Str(name)) => Value::Watcher(
+                list.iter()
+                    .find(|w| &w.name == name)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("there is no watcher named {name:?}"))?,
+            ),
+            (Value::Watchers(list), Value::Int(i)) => Value::Watcher(
+                usize::try_from(*i)
+                    .ok()
+                    .and_then(|i| list.get(i))
+                    .cloned()
+                    .ok_or_else(|| anyhow!("index {i} is out of range"))?,
+            ),
+            _ => Err(anyhow!(
+                "can't index {} with {}",
+                value.type_name(),
+                index.type_name()
+            ))?,
+        })
+    }
+
+    fn assign(&mut self, place: &Expr, value: Value) -> Result<()> {
+        match place {
+            Expr::Ident(name) => {
+                let slot = self
+                    .locals
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(name))
+                    .ok_or_else(|| anyhow!("`{name}` is not declared"))?;
+                *slot = value;
+            }
+            Expr::Member(object, name) => match self.eval(object)? {
+                Value::Global(Global::Vars) => {
+                    self.vars.insert(name.clone(), value);
+                }
+                Value::Global(Global::Current) => {
+                    self.current.insert(name.clone(), value);
+                }
+                Value::Global(Global::Old) => {
+                    self.old.insert(name.clone(), value);
+                }
+                Value::Global(Global::Settings) if name == "CurrentDefaultParent" => {
+                    self.default_parent = match value {
+                        Value::Null => None,
+                        value => Some(value.to_str()?.to_owned()),
+                    };
+                }
+                other => Err(anyhow!("can't set {name} on {}", other.type_name()))?,
+            },
+            Expr::Index(object, index) => {
+                let mut container = self.eval(object)?;
+                let index = self.eval(index)?;
+                match &mut container {
+                    Value::Dict(dict) => {
+                        Arc::make_mut(dict).insert(index.to_key()?, value);
+                    }
+                    Value::List(list) => {
+                        let i = index.to_int()?;
+                        let slot = usize::try_from(i)
+                            .ok()
+                            .and_then(|i| Arc::make_mut(list).get_mut(i))
+                            .ok_or_else(|| anyhow!("index {i} is out of range"))?;
+                        *slot = value;
+                    }
+                    other => Err(anyhow!("can't set an element of {}", other.type_name()))?,
+                }
+                self.assign(object, container)?;
+            }
+            _ => Err(anyhow!("can't assign to that"))?,
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr]) -> Result<Value> {
+        let (receiver_expr, name) = match callee {
+            Expr::Member(receiver, name) => (&**receiver, name.as_str()),
+            Expr::Ident(name) if name == "print" => {
+                let text = match args {
+                    [text] => self.eval(text)?,
+                    _ => Err(anyhow!("print takes one argument"))?,
+                };
+                tracing::debug!("ASL: {text}");
+                return Ok(Value::Null);
+            }
+            callee => {
+                let function = self.eval(callee)?;
+                let args = self.arguments(args)?;
+                return self.call_function(&function, args);
+            }
+        };
+        let receiver = self.eval(receiver_expr)?;
+        if name == "TryGetValue" {
+            let (Value::Dict(dict), [key, Expr::Out(out)]) = (&receiver, args) else {
+                Err(anyhow!("TryGetValue needs a dictionary, a key and `out`"))?
+            };
+            let key = self.eval(key)?.to_key()?;
+            let found = dict.get(&key).cloned();
+            let success = found.is_some();
+            if let Some(value) = found {
+                self.assign(out, value)?;
+            }
+            return Ok(Value::Bool(success));
+        }
+        let args = self.arguments(args)?;
+        match (&receiver, name) {
+            (Value::Global(Global::Vars), _) => {
+                let function = self.member(receiver, name)?;
+                self.call_function(&function, args)
+            }
+            (Value::Function(_), "Invoke") => self.call_function(&receiver, args),
+            _ => {
+                let (value, updated) = self.method(receiver, name, args)?;
+                if let Some(updated) = updated {
+                    self.assign(receiver_expr, updated)?;
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    fn arguments(&mut self, args: &[Expr]) -> Result<Vec<Value>> {
+        args.iter().map(|arg| self.eval(arg)).collect()
+    }
+
+    fn call_function(&mut self, function: &Value, args: Vec<Value>) -> Result<Value> {
+        let Value::Function(lambda) = function else {
+            Err(anyhow!("{} is not a function", function.type_name()))?
+        };
+        if self.depth == MAX_CALL_DEPTH {
+            Err(anyhow!("lambdas call each other too deeply"))?;
+        }
+        // Lambdas see their parameters and the globals, not the locals
+        // around them.
+        let mut args = args.into_iter();
+        let scope = lambda
+            .params
+            .iter()
+            .map(|param| (param.clone(), args.next().unwrap_or_default()))
+            .collect();
+        let outer = std::mem::replace(&mut self.locals, vec![scope]);
+        self.depth += 1;
+        let result = match &lambda.body {
+            LambdaBody::Expr(expr) => self.eval(expr),
+            LambdaBody::Block(stmts) => self.run(stmts).map(Option::unwrap_or_default),
+        };
+        self.depth -= 1;
+        self.locals = outer;
+        result
+    }
+
+    /// Calls a built-in method. Methods that change their receiver, such
+    /// as `List.Add`, return its new value too so it can be stored back.
+    fn method(
+        &mut self,
+        receiver: Value,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<(Value, Option<Value>)> {
+        let arg = |i: usize| {
+            args.get(i)
+                .ok_or_else(|| anyhow!("{name} needs at least {} arguments", i + 1))
+        };
+        let value = match (&receiver, name) {
+            (Value::Global(Global::Settings), "Add") => {
+                self.add_setting(&args)?;
+                Value::Null
+            }
+            (Value::Global(Global::Settings), "SetToolTip") => {
+                let key = arg(0)?.to_str()?;
+                let setting = self
+                    .script_settings
+                    .iter_mut()
+                    .find(|s| s.key == key)
+                    .ok_or_else(|| anyhow!("there is no setting {key:?}"))?;
+                let tooltip = arg(1)?.to_string();
+                self.settings.write().set_tooltip(key, &tooltip);
+                setting.tooltip = Some(tooltip);
+                Value::Null
+            }
+            (Value::Global(Global::TimeSpan), _) => {
+                let n = arg(0)?
+                    .number()
+                    .ok_or_else(|| anyhow!("TimeSpan.{name} needs a number"))?;
+                Value::TimeSpan(match name {
+                    "FromMilliseconds" => n / 1000.0,
+                    "FromSeconds" => n,
+                    "FromMinutes" => n * 60.0,
+                    "FromHours" => n * 3600.0,
+                    _ => Err(anyhow!("TimeSpan has no {name}"))?,
+                })
+            }
+            (Value::Global(Global::Math), _) => math(name, &args)?,
+            (Value::Watchers(_), "UpdateAll" | "ResetAll")
+            | (Value::Watcher(_), "Update" | "Reset") => {
+                // Memory is read before each poll's actions run.
+                Value::Null
+            }
+            (_, "ToString") => match (&receiver, args.first()) {
+                (Value::Int(n), Some(Value::Str(format))) => Value::Str(format_int(*n, format)?),
+                _ => Value::Str(receiver.to_string()),
+            },
+            (_, "Equals") => Value::Bool(receiver.equals(arg(0)?)),
+            (Value::Str(s), _) => match name {
+                "ToLower" | "ToLowerInvariant" => Value::Str(s.to_lowercase()),
+                "ToUpper" | "ToUpperInvariant" => Value::Str(s.to_uppercase()),
+                "Trim" => Value::Str(s.trim().to_owned()),
+                "Contains" => Value::Bool(s.contains(arg(0)?.to_str()?)),
+                "StartsWith" => Value::Bool(s.starts_with(arg(0)?.to_str()?)),
+                "EndsWith" => Value::Bool(s.ends_with(arg(0)?.to_str()?)),
+                _ => Err(anyhow!("string has no {name}"))?,
+            },
+            (Value::Dict(dict), _) => {
+                let mut dict = dict.clone();
+                let value = match name {
+                    "ContainsKey" => Value::Bool(dict.contains_key(&arg(0)?.to_key()?)),
+                    "Add" => {
+                        let key = arg(0)?.to_key()?;
+                        if dict.contains_key(&key) {
+                            Err(anyhow!("the dictionary already has {}", arg(0)?))?;
+                        }
+                        Arc::make_mut(&mut dict).insert(key, arg(1)?.clone());
+                        Value::Null
+                    }
+                    "Remove" => Value::Bool(
+                        Arc::make_mut(&mut dict)
+                            .remove(&arg(0)?.to_key()?)
+                            .is_some(),
+                    ),
+                    "Clear" => {
+                        Arc::make_mut(&mut dict).clear();
+                        Value::Null
+                    }
+                    _ => Err(anyhow!("Dictionary has no {name}"))?,
+                };
+                let changed = matches!(name, "Add" | "Remove" | "Clear");
+                return Ok((value, changed.then_some(Value::Dict(dict))));
+            }
+            (Value::List(list), _) => {
+                let mut list = list.clone();
+                let value = match name {
+                    "Contains" => {
+                        let item = arg(0)?;
+                        Value::Bool(list.iter().any(|v| v.equals(item)))
+                    }
+                    "IndexOf" => {
+                        let item = arg(0)?;
+                        let position = list.iter().position(|v| v.equals(item));
+                        Value::Int(position.map_or(-1, |i| i as i64))
+                    }
+                    "Add" => {
+                        Arc::make_mut(&mut list).push(arg(0)?.clone());
+                        Value::Null
+                    }
+                    "Remove" => {
+                        let item = arg(0)?;
+                        let position = list.iter().position(|v| v.equals(item));
+                        if let Some(i) = position {
+                            Arc::make_mut(&mut list).remove(i);
+                        }
+                        Value::Bool(position.is_some())
+                    }
+                    "Clear" => {
+                        Arc::make_mut(&mut list).clear();
+                        Value::Null
+                    }
+                    _ => Err(anyhow!("List has no {name}"))?,
+                };
+                let changed = matches!(name, "Add" | "Remove" | "Clear");
+                return Ok((value, changed.then_some(Value::List(list))));
+            }
+            _ => Err(anyhow!("{} has no {name}", receiver.type_name()))?,
+        };
+        Ok((value, None))
+    }
+
+    /// `settings.Add(key, default = true, label = key, parent =
+    /// CurrentDefaultParent)`. The setting is added to Annelid's settings
+    /// too, unless it's already there, so it can be changed and saved.
+    fn add_setting(&mut self, args: &[Value]) -> Result<()> {
+        if !self.in_startup {
+            Err(anyhow!("settings can only be added in startup"))?;
+        }
+        let key = args
+            .first()
+            .ok_or_else(|| anyhow!("settings.Add needs a key"))?
+            .to_str()?
+            .to_owned();
+        let default = args.get(1).map_or(Ok(true), Value::to_bool)?;
+        let label = match args.get(2) {
+            Some(Value::Null) | None => key.clone(),
+            Some(label) => label.to_str()?.to_owned(),
+        };
+        let parent = match args.get(3) {
+            Some(Value::Null) => None,
+            Some(parent) => Some(parent.to_str()?.to_owned()),
+            None => self.default_parent.clone(),
+        };
+        if self.script_settings.iter().any(|s| s.key == key) {
+            Err(anyhow!("setting {key:?} is added twice"))?;
+        }
+        if let Some(parent) = &parent {
+            if !self.script_settings.iter().any(|s| &s.key == parent) {
+                Err(anyhow!(
+                    "setting {key:?} has parent {parent:?}, which hasn't been added"
+                ))?;
+            }
+        }
+        self.settings
+            .write()
+            .declare(&key, default, parent.as_deref(), &label);
+        self.script_settings.push(AslSetting {
+            key,
+            default,
+            label,
+            tooltip: None,
+            parent,
+        });
+        Ok(())
+    }
+
+    fn construct(&mut self, typ: &TypeName, args: &[Expr], init: &[Initializer]) -> Result<Value> {
+        let args = self.arguments(args)?;
+        let mut elements = vec![];
+        let mut fields = BTreeMap::new();
+        for entry in init {
+            match entry {
+                Initializer::Field(name, value) => {
+                    fields.insert(name.as_str(), self.eval(value)?);
+                }
+                Initializer::Element(values) => elements.push(self.arguments(values)?),
+            }
+        }
+        let single = |elements: Vec<Vec<Value>>| -> Result<Vec<Value>> {
+            elements
+                .into_iter()
+                .map(|mut values| match values.len() {
+                    1 => Ok(values.remove(0)),
+                    n => Err(anyhow!("expected one value, found {n}")),
+                })
+                .collect()
+        };
+        Ok(match typ.name.as_str() {
+            "Dictionary" => {
+                let mut dict = BTreeMap::new();
+                for values in elements {
+                    let [key, value] = <[Value; 2]>::try_from(values)
+                        .map_err(|_| anyhow!("dictionary entries need a key and a value"))?;
+                    dict.insert(key.to_key()?, value);
+                }
+                Value::Dict(Arc::new(dict))
+            }
+            "List" | "HashSet" => Value::List(Arc::new(single(elements)?)),
+            "MemoryWatcherList" => {
+                let watchers = single(elements)?
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::Watcher(watcher) => Ok(watcher),
+                        other => Err(anyhow!(
+                            "a MemoryWatcherList can't hold {}",
+                            other.type_name()
+                        )),
+                    })
+                    .collect::<Result<_>>()?;
+                Value::Watchers(Arc::new(watchers))
+            }
+            "MemoryWatcher" => {
+                let [arg] = typ.args.as_slice() else {
+                    Err(anyhow!("MemoryWatcher needs a type"))?
+                };
+                let typ = WatcherType::parse(&arg.name)?;
+                let address = match args.as_slice() {
+                    [Value::Int(address)] => *address,
+                    _ => Err(anyhow!("MemoryWatcher needs a WRAM address"))?,
+                };
+                let name = match fields.get("Name") {
+                    Some(name) => name.to_str()?.to_owned(),
+                    None => format!("{address:#x}"),
+                };
+                Value::Watcher(Arc::new(Watcher {
+                    name,
+                    offset: wram_offset(address, typ.width())?,
+                    typ,
+                }))
+            }
+            "Exception" | "InvalidOperationException" | "ArgumentException" => {
+                args.into_iter().next().unwrap_or_default()
+            }
+            other => Err(anyhow!("can't create a {other}"))?,
+        })
+    }
+
+    /// Every watcher reachable from `vars`.
+    fn watchers(&self) -> Vec<Arc<Watcher>> {
+        fn collect(value: &Value, out: &mut Vec<Arc<Watcher>>) {
+            match value {
+                Value::Watcher(watcher) => out.push(watcher.clone()),
+                Value::Watchers(watchers) => out.extend(watchers.iter().cloned()),
+                Value::List(values) => values.iter().for_each(|v| collect(v, out)),
+                Value::Dict(values) => values.values().for_each(|v| collect(v, out)),
+                _ => {}
+            }
+        }
+        let mut out = vec![];
+        self.vars.values().for_each(|v| collect(v, &mut out));
+        out
+    }
+}
+
+fn binary(op: BinOp, a: Value, b: Value) -> Result<Value> {
+    use Value::{Bool, Float, Int, Str};
+    let mismatch = |a: &Value, b: &Value| {
+        anyhow!(
+            "can't apply {op:?} to {} and {}",
+            a.type_name(),
+            b.type_name()
+        )
+    };
+    Ok(match (op, &a, &b) {
+        (BinOp::Eq, _, _) => Bool(a.equals(&b)),
+        (BinOp::Ne, _, _) => Bool(!a.equals(&b)),
+        (BinOp::Add, Str(_), _) | (BinOp::Add, _, Str(_)) => Str(format!("{a}{b}")),
+        (BinOp::Add, Value::TimeSpan(x), Value::TimeSpan(y)) => Value::TimeSpan(x + y),
+        (BinOp::Sub, Value::TimeSpan(x), Value::TimeSpan(y)) => Value::TimeSpan(x - y),
+        (BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge, Value::TimeSpan(x), Value::TimeSpan(y)) => {
+            Bool(compare(op, *x, *y))
+        }
+        (BinOp::BitAnd, Bool(x), Bool(y)) => Bool(x & y),
+        (BinOp::BitOr, Bool(x), Bool(y)) => Bool(x | y),
+        (BinOp::BitXor, Bool(x), Bool(y)) => Bool(x ^ y),
+        (_, Int(x), Int(y)) => {
+            let (x, y) = (*x, *y);
+            match op {
+                BinOp::Mul => Int(x.wrapping_mul(y)),
+                BinOp::Div | BinOp::Rem if y == 0 => Err(anyhow!("division by zero"))?,
+                BinOp::Div => Int(x.wrapping_div(y)),
+                BinOp::Rem => Int(x.wrapping_rem(y)),
+                BinOp::Add => Int(x.wrapping_add(y)),
+                BinOp::Sub => Int(x.wrapping_sub(y)),
+                BinOp::Shl => Int(x.wrapping_shl(y as u32)),
+                BinOp::Shr => Int(x.wrapping_shr(y as u32)),
+                BinOp::BitAnd => Int(x & y),
+                BinOp::BitOr => Int(x | y),
+                BinOp::BitXor => Int(x ^ y),
+                _ => Bool(compare(op, x as f64, y as f64)),
+            }
+        }
+        _ => {
+            let (Some(x), Some(y)) = (a.number(), b.number()) else {
+                Err(mismatch(&a, &b))?
+            };
+            match op {
+                BinOp::Mul => Float(x * y),
+                BinOp::Div => Float(x / y),
+                BinOp::Rem => Float(x % y),
+                BinOp::Add => Float(x + y),
+                BinOp::Sub => Float(x - y),
+                BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => Bool(compare(op, x, y)),
+                _ => Err(mismatch(&a, &b))?,
+            }
+        }
+    })
+}
+
+fn compare(op: BinOp, x: f64, y: f64) -> bool {
+    match op {
+        BinOp::Lt => x < y,
+        BinOp::Gt => x > y,
+        BinOp::Le => x <= y,
+        _ => x >= y,
+    }
+}
+
+fn cast(typ: &TypeName, value: Value) -> Result<Value> {
+    let n = match &value {
+        Value::Int(n) => *n,
+        Value::Float(n) => *n as i64,
+        _ => match typ.name.as_str() {
+            "bool" => return Ok(Value::Bool(value.to_bool()?)),
+            "string" => return Ok(Value::Str(value.to_str()?.to_owned())),
+            _ => Err(anyhow!("can't cast {} to {}", value.type_name(), typ.name))?,
+        },
+    };
+    Ok(match typ.name.as_str() {
+        "float" | "double" => Value::Float(value.number().expect("a number")),
+        "byte" => Value::Int(n as u8 as i64),
+        "sbyte" => Value::Int(n as i8 as i64),
+        "ushort" => Value::Int(n as u16 as i64),
+        "short" => Value::Int(n as i16 as i64),
+        "uint" => Value::Int(n as u32 as i64),
+        "int" => Value::Int(n as i32 as i64),
+        "long" | "ulong" | "IntPtr" | "UIntPtr" => Value::Int(n),
+        other => Err(anyhow!("can't cast {} to {other}", value.type_name()))?,
+    })
+}
+
+fn math(name: &str, args: &[Value]) -> Result<Value> {
+    let all_ints = args.iter().all(|a| matches!(a, Value::Int(_)));
+    let numbers = args
+        .iter()
+        .map(|a| {
+            a.number()
+                .ok_or_else(|| anyhow!("Math.{name} needs numbers"))
+        })
+        .collect::<Result<Vec<f64>>>()?;
+    let result = match (name, numbers.as_slice()) {
+        ("Abs", [x]) => x.abs(),
+        ("Min", [x, y]) => x.min(*y),
+        ("Max", [x, y]) => x.max(*y),
+        ("Floor", [x]) => x.floor(),
+        ("Ceiling", [x]) => x.ceil(),
+        ("Round", [x]) => x.round_ties_even(),
+        _ => Err(anyhow!("Math has no {name} for {} arguments", args.len()))?,
+    };
+    Ok(if all_ints {
+        Value::Int(result as i64)
+    } else {
+        Value::Float(result)
+    })
+}
+
+/// `n.ToString(format)` for the `X`, `x` and `D` formats.
+fn format_int(n: i64, format: &str) -> Result<String> {
+    let mut chars = format.chars();
+    let kind = chars.next().unwrap_or('D');
+    let width: usize = match chars.as_str() {
+        "" => 0,
+        digits => digits
+            .parse()
+            .map_err(|_| anyhow!("unsupported format {format:?}"))?,
+    };
+    Ok(match kind {
+        'X' => format!("{n:0width$X}"),
+        'x' => format!("{n:0width$x}"),
+        'D' | 'd' => format!("{n:0width$}"),
+        _ => Err(anyhow!("unsupported format {format:?}"))?,
+    })
+}
+
+/// An ASL script, parsed and ready to run.
+#[derive(Clone, Debug, Default)]
+pub struct AslScript {
+    script: Script,
+    /// Memory declared by the `state` block that was picked.
+    state: Vec<Arc<Watcher>>,
+}
+
+impl AslScript {
+    pub fn parse(source: &str) -> Result<AslScript> {
+        let mut script = parser::parse(source)?;
+        let block = script
+            .states
+            .iter()
+            .find(|s| s.process.eq_ignore_ascii_case("usb2snes"))
+            .or_else(|| script.states.iter().find(|s| !s.vars.is_empty()));
+        let mut state = vec![];
+        for var in block.map_or(&[][..], |b| &b.vars) {
+            let typ = WatcherType::parse(&var.typ)
+                .with_context(|| format!("state variable {}", var.name))?;
+            let address = *var.offsets.last().expect("at least one offset");
+            state.push(Arc::new(Watcher {
+                name: var.name.clone(),
+                offset: wram_offset(address, typ.width())
+                    .with_context(|| format!("state variable {}", var.name))?,
+                typ,
+            }));
+        }
+        for (action, stmts) in script.actions.iter_mut() {
+            stmts.retain(|stmt| {
+                let uses_process = PROCESS_NAMES.iter().any(|name| stmt.mentions(name));
+                if uses_process {
+                    tracing::debug!("Skipping a statement in {action} that uses the emulator");
+                }
+                !uses_process
+            });
+        }
+        Ok(AslScript { script, state })
+    }
+
+    pub fn from_file<P>(path: P) -> Result<AslScript>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        AslScript::parse(&source).with_context(|| format!("failed to load {}", path.display()))
+    }
+}
+
+/// Runs an [`AslScript`] the way LiveSplit does: each poll runs `update`,
+/// then `start` while the timer is stopped, or `gameTime`, `reset` and
+/// `split` while it runs. `startup` and `init` run once, when the
+/// autosplitter is created.
+///
+/// Errors in an action are logged and count as `false`, so a bug in a
+/// script doesn't drop the connection. On the first poll, `old` is the same
+/// as `current`, so nothing happens just because Annelid connected.
+#[derive(Default)]
+pub struct AslAutoSplitter {
+    script: AslScript,
+    interpreter: Interpreter,
+    /// The regions read each poll, one per address used.
+    reads: Vec<(u32, usize)>,
+    running: bool,
+    first_poll: bool,
+    game_time: Option<TimeSpan>,
+    /// The last error from each action, so each is only logged once.
+    errors: BTreeMap<&'static str, String>,
+}
+
+impl AslAutoSplitter {
+    /// Runs `startup` and `init`, adding the script's settings to
+    /// `settings`, and works out what to read each poll.
+    pub fn new(script: AslScript, settings: Arc<RwLock<Settings>>) -> Result<Self> {
+        let mut autosplitter = AslAutoSplitter {
+            script,
+            interpreter: Interpreter {
+                settings,
+                ..Interpreter::default()
+            },
+            first_poll: true,
+            ..AslAutoSplitter::default()
+        };
+        let actions = &autosplitter.script.script.actions;
+        let interpreter = &mut autosplitter.interpreter;
+        if let Some(startup) = actions.get("startup") {
+            interpreter.in_startup = true;
+            let result = interpreter.run(startup);
+            interpreter.in_startup = false;
+            result.context("startup failed")?;
+        }
+        if let Some(init) = actions.get("init") {
+            interpreter.run(init).context("init failed")?;
+        }
+
+        let mut widths = BTreeMap::new();
+        for watcher in autosplitter
+            .script
+            .state
+            .iter()
+            .chain(autosplitter.interpreter.watchers().iter())
+        {
+            let width = widths.entry(watcher.offset).or_insert(0);
+            *width = watcher.typ.width().max(*width);
+        }
+        autosplitter.reads = widths
+            .into_iter()
+            .map(|(offset, width)| (MemoryDomain::Wram.base() + offset, width))
+            .collect();
+        Ok(autosplitter)
+    }
+
+    /// Loads a script from a file and prepares it to run.
+    pub fn from_file<P>(path: P, settings: Arc<RwLock<Settings>>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        AslAutoSplitter::new(AslScript::from_file(path)?, settings)
+    }
+
+    /// The settings the script added, in the order it added them.
+    pub fn settings(&self) -> &[AslSetting] {
+        &self.interpreter.script_settings
+    }
+
+    /// A variable the script set on `vars`.
+    pub fn var(&self, name: &str) -> Option<&Value> {
+        self.interpreter.vars.get(name)
+    }
+
+    /// Runs `action`, returning what it returned. Errors are logged the
+    /// first time they happen.
+    fn action(&mut self, name: &'static str) -> Option<Value> {
+        let stmts = self.script.script.actions.get(name)?;
+        match self.interpreter.run(stmts) {
+            Ok(value) => {
+                self.errors.remove(name);
+                value
+            }
+            Err(e) => {
+                let message = format!("{e:#}");
+                if self.errors.get(name) != Some(&message) {
+                    tracing::warn!("ASL {name} failed: {message}");
+                    self.errors.insert(name, message);
+                }
+                None
+            }
+        }
+    }
+
+    fn action_passes(&mut self, name: &'static str) -> bool {
+        matches!(self.action(name), Some(Value::Bool(true)))
+    }
+}
+
+impl AutoSplitter for AslAutoSplitter {
+    fn update(&mut self, client: &mut dyn MemorySource) -> Result<SNESSummary> {
+        let data = client.get_addresses(&self.reads)?;
+        let memory: BTreeMap<u32, Vec<u8>> = self
+            .reads
+            .iter()
+            .zip(data)
+            .map(|(&(address, _), bytes)| (address - MemoryDomain::Wram.base(), bytes))
+            .collect();
+        let interpreter = &mut self.interpreter;
+        interpreter.old_memory = std::mem::replace(&mut interpreter.memory, memory);
+        interpreter.old = interpreter.current.clone();
+        for watcher in self.script.state.iter() {
+            let value = interpreter.read(watcher, &interpreter.memory)?;
+            interpreter.current.insert(watcher.name.clone(), value);
+        }
+        if self.first_poll {
+            interpreter.old_memory = interpreter.memory.clone();
+            interpreter.old = interpreter.current.clone();
+            self.first_poll = false;
+        }
+
+        let mut summary = SNESSummary {
+            start: false,
+            reset: false,
+            split: false,
+        };
+        if let Some(Value::Bool(false)) = self.action("update") {
+            return Ok(summary);
+        }
+        if self.running {
+            if let Some(Value::TimeSpan(seconds)) = self.action("gameTime") {
+                self.game_time = Some(TimeSpan::from_seconds(seconds));
+            }
+            summary.reset = self.action_passes("reset");
+            if summary.reset {
+                self.running = false;
+                self.interpreter.current_split = None;
+                self.action("onReset");
+            } else {
+                summary.split = self.action_passes("split");
+                if summary.split {
+                    self.interpreter.current_split = self.interpreter.current_split.map(|i| i + 1);
+                    self.action("onSplit");
+                }
+            }
+        } else {
+            summary.start = self.action_passes("start");
+            if summary.start {
+                self.running = true;
+                self.interpreter.current_split = Some(0);
+                self.action("onStart");
+            }
+        }
+        Ok(summary)
+    }
+
+    fn gametime_to_seconds(&self) -> Option<TimeSpan> {
+        self.game_time
+    }
+
+    fn reset_game_tracking(&mut self) {
+        if self.running {
+            self.running = false;
+            self.action("onReset");
+        }
+        self.interpreter.current_split = None;
+        self.game_time = None;
+    }
+
+    fn set_current_split(&mut self, index: Option<usize>) {
+        self.running = index.is_some();
+        self.interpreter.current_split = index;
+    }
+}
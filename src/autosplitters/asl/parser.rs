@@ -0,0 +1,972 @@
+//! Parses the C#-like subset of ASL that SNES autosplitters use: `state`
+//! blocks declaring memory, and action blocks (`startup`, `init`, `split`,
+//! ...) of statements.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+/// The actions an ASL script may define.
+pub const ACTIONS: &[&str] = &[
+    "startup",
+    "shutdown",
+    "init",
+    "exit",
+    "update",
+    "start",
+    "split",
+    "reset",
+    "isLoading",
+    "gameTime",
+    "onStart",
+    "onSplit",
+    "onReset",
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct Script {
+    pub states: Vec<StateBlock>,
+    /// Action name to its statements.
+    pub actions: BTreeMap<String, Vec<Stmt>>,
+}
+
+/// `state("process", "version") { ... }`
+#[derive(Clone, Debug)]
+pub struct StateBlock {
+    pub process: String,
+    pub version: Option<String>,
+    pub vars: Vec<StateVar>,
+}
+
+/// `ushort roomID : "module", 0x1234, 0x079B;`
+#[derive(Clone, Debug)]
+pub struct StateVar {
+    pub typ: String,
+    pub name: String,
+    pub module: Option<String>,
+    pub offsets: Vec<i64>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Stmt {
+    Expr(Expr),
+    /// `var x = ...;` or `int x;`. The type is only kept for casting.
+    Var {
+        typ: TypeName,
+        name: String,
+        init: Option<Expr>,
+    },
+    If {
+        cond: Expr,
+        then: Box<Stmt>,
+        otherwise: Option<Box<Stmt>>,
+    },
+    Foreach {
+        name: String,
+        items: Expr,
+        body: Box<Stmt>,
+    },
+    Block(Vec<Stmt>),
+    Return(Option<Expr>),
+    Throw(Expr),
+    Empty,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeName {
+    pub name: String,
+    pub args: Vec<TypeName>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+    Coalesce,
+}
+
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Ident(String),
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    /// `out x` as a call argument.
+    Out(Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    /// `=` when the operator is `None`, otherwise `+=` and friends, which
+    /// `++` and `--` are parsed as too.
+    Assign(Option<BinOp>, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Cast(TypeName, Box<Expr>),
+    New {
+        typ: TypeName,
+        args: Vec<Expr>,
+        init: Vec<Initializer>,
+    },
+    Lambda(Arc<Lambda>),
+}
+
+/// An entry between the braces after `new T(...)`.
+#[derive(Clone, Debug)]
+pub enum Initializer {
+    /// `Name = "roomID"`
+    Field(String, Expr),
+    /// `{ "key", 1 }`, or a single value in a collection.
+    Element(Vec<Expr>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Lambda {
+    pub params: Vec<String>,
+    pub body: LambdaBody,
+}
+
+#[derive(Clone, Debug)]
+pub enum LambdaBody {
+    Expr(Expr),
+    Block(Vec<Stmt>),
+}
+
+impl Stmt {
+    /// Whether the statement mentions `name` as a variable anywhere.
+    pub fn mentions(&self, name: &str) -> bool {
+        match self {
+            Stmt::Expr(e) | Stmt::Throw(e) | Stmt::Return(Some(e)) => e.mentions(name),
+            Stmt::Var { init, .. } => init.as_ref().is_some_and(|e| e.mentions(name)),
+            Stmt::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                cond.mentions(name)
+                    || then.mentions(name)
+                    || otherwise.as_ref().is_some_and(|s| s.mentions(name))
+            }
+            Stmt::Foreach { items, body, .. } => items.mentions(name) || body.mentions(name),
+            Stmt::Block(stmts) => stmts.iter().any(|s| s.mentions(name)),
+            Stmt::Return(None) | Stmt::Empty => false,
+        }
+    }
+}
+
+impl Expr {
+    /// Whether the expression mentions `name` as a variable anywhere.
+    pub fn mentions(&self, name: &str) -> bool {
+        match self {
+            Expr::Ident(n) => n == name,
+            Expr::Null | Expr::Bool(_) | Expr::Int(_) | Expr::Float(_) | Expr::Str(_) => false,
+            Expr::Member(e, _) | Expr::Out(e) | Expr::Unary(_, e) | Expr::Cast(_, e) => {
+                e.mentions(name)
+            }
+            Expr::Index(a, b) | Expr::Binary(_, a, b) | Expr::Assign(_, a, b) => {
+                a.mentions(name) || b.mentions(name)
+            }
+            Expr::Call(f, args) => f.mentions(name) || args.iter().any(|a| a.mentions(name)),
+            Expr::Conditional(a, b, c) => a.mentions(name) || b.mentions(name) || c.mentions(name),
+            Expr::New { args, init, .. } => {
+                args.iter().any(|a| a.mentions(name))
+                    || init.iter().any(|i| match i {
+                        Initializer::Field(_, e) => e.mentions(name),
+                        Initializer::Element(es) => es.iter().any(|e| e.mentions(name)),
+                    })
+            }
+            Expr::Lambda(lambda) => match &lambda.body {
+                LambdaBody::Expr(e) => e.mentions(name),
+                LambdaBody::Block(stmts) => stmts.iter().any(|s| s.mentions(name)),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Punct(&'static str),
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    line: usize,
+}
+
+const PUNCTUATION: &[&str] = &[
+    "<<=", ">>=", "=>", "==", "!=", "<=", ">=", "&&", "||", "++", "--", "+=", "-=", "*=", "/=",
+    "%=", "&=", "|=", "^=", "<<", ">>", "??", "+", "-", "*", "/", "%", "&", "|", "^", "!", "~",
+    "<", ">", "=", "?", ":", ";", ",", ".", "(", ")", "[", "]", "{", "}",
+];
+
+fn lex(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let at = |text: &str| {
+            text.chars()
+                .enumerate()
+                .all(|(k, t)| chars.get(i + k) == Some(&t))
+        };
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if at("//") {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if at("/*") {
+            i += 2;
+            while i < chars.len() && !chars[i..].starts_with(&['*', '/']) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(Token {
+                tok: Tok::Ident(word),
+                line,
+            });
+        } else if c.is_ascii_digit() {
+            let start = i;
+            let tok = if at("0x") || at("0X") {
+                i += 2;
+                let digits = i;
+                while i < chars.len() && chars[i].is_ascii_hexdigit() {
+                    i += 1;
+                }
+                let hex: String = chars[digits..i].iter().collect();
+                Tok::Int(
+                    u64::from_str_radix(&hex, 16)
+                        .map_err(|e| anyhow!("line {line}: bad number 0x{hex}: {e}"))?
+                        as i64,
+                )
+            } else {
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let mut float = false;
+                if i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit() {
+                    float = true;
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                if float || (i < chars.len() && "fFdDmM".contains(chars[i])) {
+                    Tok::Float(text.parse()?)
+                } else {
+                    Tok::Int(
+                        text.parse()
+                            .map_err(|e| anyhow!("line {line}: bad number {text}: {e}"))?,
+                    )
+                }
+            };
+            // Type suffixes: 5u, 5L, 5UL, 1.5f, ...
+            while i < chars.len() && "uUlLfFdDmM".contains(chars[i]) {
+                i += 1;
+            }
+            tokens.push(Token { tok, line });
+        } else if c == '"' || (c == '@' && chars.get(i + 1) == Some(&'"')) {
+            let verbatim = c == '@';
+            i += if verbatim { 2 } else { 1 };
+            let mut text = String::new();
+            loop {
+                let Some(&c) = chars.get(i) else {
+                    Err(anyhow!("line {line}: unterminated string"))?
+                };
+                i += 1;
+                match c {
+                    '"' if verbatim && chars.get(i) == Some(&'"') => {
+                        text.push('"');
+                        i += 1;
+                    }
+                    '"' => break,
+                    '\\' if !verbatim => {
+                        let escaped = chars.get(i).copied().unwrap_or('\\');
+                        i += 1;
+                        text.push(match escaped {
+                            'n' => '\n',
+                            't' => '\t',
+                            'r' => '\r',
+                            '0' => '\0',
+                            other => other,
+                        });
+                    }
+                    '\n' => {
+                        line += 1;
+                        text.push(c);
+                    }
+                    c => text.push(c),
+                }
+            }
+            tokens.push(Token {
+                tok: Tok::Str(text),
+                line,
+            });
+        } else if let Some(p) = PUNCTUATION.iter().find(|p| at(p)) {
+            i += p.len();
+            tokens.push(Token {
+                tok: Tok::Punct(p),
+                line,
+            });
+        } else {
+            Err(anyhow!("line {line}: unexpected character {c:?}"))?;
+        }
+    }
+    tokens.push(Token {
+        tok: Tok::Eof,
+        line,
+    });
+    Ok(tokens)
+}
+
+/// Types that are always a cast when written `(type)` before a value.
+const CAST_TYPES: &[&str] = &[
+    "bool", "byte", "sbyte", "short", "ushort", "int", "uint", "long", "ulong", "float", "double",
+    "string", "IntPtr", "UIntPtr",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+pub fn parse(source: &str) -> Result<Script> {
+    let mut parser = Parser {
+        tokens: lex(source)?,
+        pos: 0,
+    };
+    let mut script = Script::default();
+    while parser.peek() != &Tok::Eof {
+        let line = parser.line();
+        let name = parser.ident()?;
+        if name == "state" {
+            script.states.push(parser.state_block()?);
+        } else if ACTIONS.contains(&name.as_str()) {
+            let body = parser.block()?;
+            if script.actions.insert(name.clone(), body).is_some() {
+                Err(anyhow!("line {line}: {name} is defined twice"))?;
+            }
+        } else {
+            Err(anyhow!("line {line}: unknown action {name}"))?;
+        }
+    }
+    Ok(script)
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].tok
+    }
+
+    fn peek_at(&self, n: usize) -> &Tok {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.pos + n).min(last)].tok
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos].line
+    }
+
+    fn next(&mut self) -> Tok {
+        let tok = self.peek().clone();
+        if tok != Tok::Eof {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn is(&self, punct: &str) -> bool {
+        matches!(self.peek(), Tok::Punct(p) if *p == punct)
+    }
+
+    fn is_keyword(&self, word: &str) -> bool {
+        matches!(self.peek(), Tok::Ident(w) if w == word)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.is(punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T> {
+        let found = match self.peek() {
+            Tok::Ident(w) => w.clone(),
+            Tok::Int(n) => n.to_string(),
+            Tok::Float(n) => n.to_string(),
+            Tok::Str(s) => format!("{s:?}"),
+            Tok::Punct(p) => p.to_string(),
+            Tok::Eof => "the end of the script".to_owned(),
+        };
+        Err(anyhow!(
+            "line {}: expected {expected}, found {found}",
+            self.line()
+        ))
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if !self.eat(punct) {
+            self.error(&format!("`{punct}`"))?;
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.peek().clone() {
+            Tok::Ident(w) => {
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => self.error("a name"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.peek().clone() {
+            Tok::Str(s) => {
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => self.error("a string"),
+        }
+    }
+
+    fn state_block(&mut self) -> Result<StateBlock> {
+        self.expect("(")?;
+        let process = self.string()?;
+        let version = if self.eat(",") {
+            Some(self.string()?)
+        } else {
+            None
+        };
+        self.expect(")")?;
+        self.expect("{")?;
+        let mut vars = vec![];
+        while !self.eat("}") {
+            let mut typ = self.ident()?;
+            // string32, byte16 and friends are written like this in older
+            // scripts; `byte[16]` in newer ones.
+            if self.eat("[") {
+                match self.next() {
+                    Tok::Int(n) => typ = format!("{typ}{n}"),
+                    _ => self.error("an array length")?,
+                }
+                self.expect("]")?;
+            }
+            let name = self.ident()?;
+            self.expect(":")?;
+            let module = match self.peek().clone() {
+                Tok::Str(s) => {
+                    self.pos += 1;
+                    self.expect(",")?;
+                    Some(s)
+                }
+                _ => None,
+            };
+            let mut offsets = vec![];
+            loop {
+                let negative = self.eat("-");
+                match self.next() {
+                    Tok::Int(n) => offsets.push(if negative { -n } else { n }),
+                    _ => {
+                        self.pos -= 1;
+                        self.error("an offset")?
+                    }
+                }
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            self.expect(";")?;
+            vars.push(StateVar {
+                typ,
+                name,
+                module,
+                offsets,
+            });
+        }
+        Ok(StateBlock {
+            process,
+            version,
+            vars,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        self.expect("{")?;
+        let mut stmts = vec![];
+        while !self.eat("}") {
+            if self.peek() == &Tok::Eof {
+                self.error("`}`")?;
+            }
+            stmts.push(self.statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        if self.is("{") {
+            return Ok(Stmt::Block(self.block()?));
+        }
+        if self.eat(";") {
+            return Ok(Stmt::Empty);
+        }
+        if self.is_keyword("if") {
+            self.pos += 1;
+            self.expect("(")?;
+            let cond = self.expr()?;
+            self.expect(")")?;
+            let then = Box::new(self.statement()?);
+            let otherwise = if self.is_keyword("else") {
+                self.pos += 1;
+                Some(Box::new(self.statement()?))
+            } else {
+                None
+            };
+            return Ok(Stmt::If {
+                cond,
+                then,
+                otherwise,
+            });
+        }
+        if self.is_keyword("foreach") {
+            self.pos += 1;
+            self.expect("(")?;
+            if !matches!(self.peek_at(1), Tok::Ident(w) if w == "in") {
+                self.type_name()?;
+            }
+            let name = self.ident()?;
+            if !self.is_keyword("in") {
+                self.error("`in`")?;
+            }
+            self.pos += 1;
+            let items = self.expr()?;
+            self.expect(")")?;
+            let body = Box::new(self.statement()?);
+            return Ok(Stmt::Foreach { name, items, body });
+        }
+        if self.is_keyword("return") {
+            self.pos += 1;
+            if self.eat(";") {
+                return Ok(Stmt::Return(None));
+            }
+            let value = self.expr()?;
+            self.expect(";")?;
+            return Ok(Stmt::Return(Some(value)));
+        }
+        if self.is_keyword("throw") {
+            self.pos += 1;
+            let value = self.expr()?;
+            self.expect(";")?;
+            return Ok(Stmt::Throw(value));
+        }
+        for unsupported in ["for", "while", "do", "switch", "break", "continue", "goto"] {
+            if self.is_keyword(unsupported) {
+                Err(anyhow!(
+                    "line {}: `{unsupported}` is not supported",
+                    self.line()
+                ))?;
+            }
+        }
+        if let Some(stmt) = self.declaration()? {
+            return Ok(stmt);
+        }
+        let expr = self.expr()?;
+        self.expect(";")?;
+        Ok(Stmt::Expr(expr))
+    }
+
+    /// `Type name = value;` or `Type name;`, or `None` (having consumed
+    /// nothing) if the statement is something else.
+    fn declaration(&mut self) -> Result<Option<Stmt>> {
+        let start = self.pos;
+        let Ok(typ) = self.type_name() else {
+            self.pos = start;
+            return Ok(None);
+        };
+        let Tok::Ident(name) = self.peek().clone() else {
+            self.pos = start;
+            return Ok(None);
+        };
+        self.pos += 1;
+        let init = if self.eat("=") {
+            Some(self.expr()?)
+        } else if self.is(";") {
+            None
+        } else {
+            self.pos = start;
+            return Ok(None);
+        };
+        self.expect(";")?;
+        Ok(Some(Stmt::Var { typ, name, init }))
+    }
+
+    fn type_name(&mut self) -> Result<TypeName> {
+        let mut name = self.ident()?;
+        while self.is(".") && matches!(self.peek_at(1), Tok::Ident(_)) {
+            self.pos += 1;
+            name = format!("{name}.{}", self.ident()?);
+        }
+        let mut args = vec![];
+        if self.eat("<") {
+            loop {
+                args.push(self.type_name()?);
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            // `List<List<int>>` lexes the closing brackets as a shift.
+            if self.is(">>") {
+                self.tokens[self.pos].tok = Tok::Punct(">");
+                let token = self.tokens[self.pos].clone();
+                self.tokens.insert(self.pos, token);
+            }
+            self.expect(">")?;
+        }
+        while self.is("[") && self.peek_at(1) == &Tok::Punct("]") {
+            self.pos += 2;
+            name.push_str("[]");
+        }
+        self.eat("?");
+        Ok(TypeName { name, args })
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let target = self.conditional()?;
+        const ASSIGNMENTS: &[(&str, Option<BinOp>)] = &[
+            ("=", None),
+            ("+=", Some(BinOp::Add)),
+            ("-=", Some(BinOp::Sub)),
+            ("*=", Some(BinOp::Mul)),
+            ("/=", Some(BinOp::Div)),
+            ("%=", Some(BinOp::Rem)),
+            ("&=", Some(BinOp::BitAnd)),
+            ("|=", Some(BinOp::BitOr)),
+            ("^=", Some(BinOp::BitXor)),
+            ("<<=", Some(BinOp::Shl)),
+            (">>=", Some(BinOp::Shr)),
+        ];
+        for (punct, op) in ASSIGNMENTS {
+            if self.eat(punct) {
+                let value = self.expr()?;
+                return Ok(Expr::Assign(*op, Box::new(target), Box::new(value)));
+            }
+        }
+        Ok(target)
+    }
+
+    fn conditional(&mut self) -> Result<Expr> {
+        let cond = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(cond);
+        }
+        let then = self.expr()?;
+        self.expect(":")?;
+        let otherwise = self.expr()?;
+        Ok(Expr::Conditional(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    /// Binary operators by precedence, loosest first.
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: &[&[(&str, BinOp)]] = &[
+            &[("??", BinOp::Coalesce)],
+            &[("||", BinOp::Or)],
+            &[("&&", BinOp::And)],
+            &[("|", BinOp::BitOr)],
+            &[("^", BinOp::BitXor)],
+            &[("&", BinOp::BitAnd)],
+            &[("==", BinOp::Eq), ("!=", BinOp::Ne)],
+            &[
+                ("<", BinOp::Lt),
+                (">", BinOp::Gt),
+                ("<=", BinOp::Le),
+                (">=", BinOp::Ge),
+            ],
+            &[("<<", BinOp::Shl), (">>", BinOp::Shr)],
+            &[("+", BinOp::Add), ("-", BinOp::Sub)],
+            &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'operators: loop {
+            for (punct, op) in LEVELS[level] {
+                if self.eat(punct) {
+                    // `??` groups to the right, the rest to the left.
+                    let rhs = if *op == BinOp::Coalesce {
+                        self.binary(level)?
+                    } else {
+                        self.binary(level + 1)?
+                    };
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        for (punct, op) in [("-", UnOp::Neg), ("!", UnOp::Not), ("~", UnOp::BitNot)] {
+            if self.eat(punct) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        for (punct, op) in [("++", BinOp::Add), ("--", BinOp::Sub)] {
+            if self.eat(punct) {
+                let target = self.unary()?;
+                return Ok(Expr::Assign(
+                    Some(op),
+                    Box::new(target),
+                    Box::new(Expr::Int(1)),
+                ));
+            }
+        }
+        if let Some(typ) = self.cast()? {
+            return Ok(Expr::Cast(typ, Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    /// `(type)` before a value, for the built-in types.
+    fn cast(&mut self) -> Result<Option<TypeName>> {
+        if !self.is("(") {
+            return Ok(None);
+        }
+        let is_cast = matches!(self.peek_at(1), Tok::Ident(w) if CAST_TYPES.contains(&w.as_str()))
+            && self.peek_at(2) == &Tok::Punct(")")
+            && match self.peek_at(3) {
+                Tok::Punct(p) => ["(", "!", "~", "-"].contains(p),
+                Tok::Eof => false,
+                _ => true,
+            };
+        if !is_cast {
+            return Ok(None);
+        }
+        self.pos += 1;
+        let typ = self.type_name()?;
+        self.expect(")")?;
+        Ok(Some(typ))
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat(".") {
+                let name = self.ident()?;
+                expr = Expr::Member(Box::new(expr), name);
+            } else if self.eat("[") {
+                let index = self.expr()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.eat("(") {
+                let args = self.arguments()?;
+                expr = Expr::Call(Box::new(expr), args);
+            } else if self.is("++") || self.is("--") {
+                let op = if self.is("++") {
+                    BinOp::Add
+                } else {
+                    BinOp::Sub
+                };
+                self.pos += 1;
+                expr = Expr::Assign(Some(op), Box::new(expr), Box::new(Expr::Int(1)));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Call arguments after the opening parenthesis.
+    fn arguments(&mut self) -> Result<Vec<Expr>> {
+        let mut args = vec![];
+        if self.eat(")") {
+            return Ok(args);
+        }
+        loop {
+            if self.is_keyword("out") || self.is_keyword("ref") {
+                self.pos += 1;
+                args.push(Expr::Out(Box::new(self.postfix()?)));
+            } else {
+                args.push(self.expr()?);
+            }
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(")")?;
+        Ok(args)
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        if let Some(lambda) = self.lambda()? {
+            return Ok(lambda);
+        }
+        match self.next() {
+            Tok::Int(n) => Ok(Expr::Int(n)),
+            Tok::Float(n) => Ok(Expr::Float(n)),
+            Tok::Str(s) => Ok(Expr::Str(s)),
+            Tok::Punct("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Tok::Ident(w) => match w.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                "null" => Ok(Expr::Null),
+                "new" => self.new_expr(),
+                _ => Ok(Expr::Ident(w)),
+            },
+            _ => {
+                self.pos -= 1;
+                self.error("a value")
+            }
+        }
+    }
+
+    fn new_expr(&mut self) -> Result<Expr> {
+        let typ = self.type_name()?;
+        let args = if self.eat("(") {
+            self.arguments()?
+        } else {
+            vec![]
+        };
+        let mut init = vec![];
+        if self.eat("{") {
+            while !self.eat("}") {
+                if self.eat("{") {
+                    let mut values = vec![];
+                    while !self.eat("}") {
+                        values.push(self.expr()?);
+                        if !self.eat(",") {
+                            self.expect("}")?;
+                            break;
+                        }
+                    }
+                    init.push(Initializer::Element(values));
+                } else if matches!(self.peek(), Tok::Ident(_))
+                    && self.peek_at(1) == &Tok::Punct("=")
+                {
+                    let field = self.ident()?;
+                    self.pos += 1;
+                    init.push(Initializer::Field(field, self.expr()?));
+                } else {
+                    init.push(Initializer::Element(vec![self.expr()?]));
+                }
+                if !self.eat(",") {
+                    self.expect("}")?;
+                    break;
+                }
+            }
+        }
+        Ok(Expr::New { typ, args, init })
+    }
+
+    /// `x => ...`, `(x, y) => ...` or `(string x) => ...`.
+    fn lambda(&mut self) -> Result<Option<Expr>> {
+        let params = match self.peek().clone() {
+            Tok::Ident(name) if self.peek_at(1) == &Tok::Punct("=>") => {
+                self.pos += 2;
+                vec![name]
+            }
+            Tok::Punct("(") => {
+                let mut depth = 0;
+                let mut end = self.pos;
+                loop {
+                    match &self.tokens[end].tok {
+                        Tok::Punct("(") => depth += 1,
+                        Tok::Punct(")") => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        Tok::Eof => return Ok(None),
+                        _ => {}
+                    }
+                    end += 1;
+                }
+                if self.tokens[end + 1].tok != Tok::Punct("=>") {
+                    return Ok(None);
+                }
+                // Each parameter's name is the last word before its comma.
+                let mut params = vec![];
+                let mut last = None;
+                for token in &self.tokens[self.pos + 1..end] {
+                    match &token.tok {
+                        Tok::Ident(w) => last = Some(w.clone()),
+                        Tok::Punct(",") => params.extend(last.take()),
+                        _ => {}
+                    }
+                }
+                params.extend(last);
+                self.pos = end + 2;
+                params
+            }
+            _ => return Ok(None),
+        };
+        let body = if self.is("{") {
+            LambdaBody::Block(self.block()?)
+        } else {
+            LambdaBody::Expr(self.expr()?)
+        };
+        Ok(Some(Expr::Lambda(Arc::new(Lambda { params, body }))))
+    }
+}
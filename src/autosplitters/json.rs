@@ -1,8 +1,18 @@
+//! QUsb2snes-style JSON split definitions, and an autosplitter that runs
+//! them. Addresses are offsets into WRAM, as in the LiveSplit usb2snes
+//! splitter; `$7E`/`$7F` bank addresses are accepted too.
+
+use std::collections::BTreeMap;
 use std::path::Path;
 
+use anyhow::{anyhow, Result};
+use livesplit_core::TimeSpan;
 use serde::de::{self, Deserializer};
 use serde::{Deserialize, Serialize};
 
+use super::{AutoSplitter, SNESSummary};
+use crate::memory_source::{MemoryDomain, MemorySource};
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Check {
     pub name: String,
@@ -31,6 +41,21 @@ pub enum CheckType {
     Wlte,
 }
 
+impl CheckType {
+    /// Whether the check compares a little-endian word rather than a byte.
+    pub fn is_word(&self) -> bool {
+        matches!(
+            self,
+            CheckType::Wbit
+                | CheckType::Weq
+                | CheckType::Wgt
+                | CheckType::Wlt
+                | CheckType::Wgte
+                | CheckType::Wlte
+        )
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CheckDetails {
     pub note: Option<String>,
@@ -42,7 +67,53 @@ pub struct CheckDetails {
     pub typ: CheckType,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl CheckDetails {
+    /// How many bytes the check reads.
+    pub fn width(&self) -> usize {
+        if self.typ.is_word() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The WRAM offset of `address`, which may also be given in bank
+    /// `$7E` or `$7F`.
+    pub fn offset(&self) -> u32 {
+        match self.address {
+            0x7E0000..=0x7FFFFF => self.address - 0x7E0000,
+            address => address,
+        }
+    }
+
+    /// Compares `current`, the byte or word at the address, with the value.
+    pub fn test(&self, current: u32) -> bool {
+        match self.typ {
+            CheckType::Bit | CheckType::Wbit => current & self.value != 0,
+            CheckType::Eq | CheckType::Weq => current == self.value,
+            CheckType::Gt | CheckType::Wgt => current > self.value,
+            CheckType::Lt | CheckType::Wlt => current < self.value,
+            CheckType::Gte | CheckType::Wgte => current >= self.value,
+            CheckType::Lte | CheckType::Wlte => current <= self.value,
+        }
+    }
+}
+
+impl Check {
+    /// The checks that must pass one after another, a poll at a time, for
+    /// this split: the check itself along with `more`, then each of `next`.
+    pub fn steps(&self) -> usize {
+        1 + self.next.as_ref().map_or(0, |next| next.len())
+    }
+
+    fn details(&self) -> impl Iterator<Item = &CheckDetails> {
+        std::iter::once(&self.check)
+            .chain(self.more.iter().flatten())
+            .chain(self.next.iter().flatten())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Splits {
     #[serde(alias = "name", alias = "game")]
     pub game: String,
@@ -62,6 +133,181 @@ impl Splits {
         let s = std::fs::read_to_string(p)?;
         Self::parse(&s)
     }
+
+    /// Whether the definitions start the timer themselves.
+    pub fn has_autostart(&self) -> bool {
+        matches!(self.autostart, Some(Autostart::Active { .. }))
+    }
+
+    fn details(&self) -> impl Iterator<Item = &CheckDetails> {
+        let autostart = match &self.autostart {
+            Some(Autostart::Active { check }) => Some(check),
+            _ => None,
+        };
+        autostart
+            .into_iter()
+            .chain(self.definitions.iter().flat_map(Check::details))
+    }
+}
+
+/// Runs [`Splits`] definitions in order: only the next definition is
+/// checked, and once all its steps have passed it splits and the one after
+/// it is checked instead.
+///
+/// With an active autostart, definitions are only checked after it has
+/// fired, so progress in a save file doesn't run through them before the
+/// run begins. The autostart fires when its check starts to pass, not
+/// while it keeps passing.
+#[derive(Default)]
+pub struct JsonAutoSplitter {
+    splits: Splits,
+    /// The regions read each poll, one per address used.
+    reads: Vec<(u32, usize)>,
+    /// The last values read, by WRAM offset.
+    memory: BTreeMap<u32, u32>,
+    /// The definition being waited on.
+    current: usize,
+    /// How far through that definition's steps we are.
+    step: usize,
+    autostart_passed: Option<bool>,
+    started: bool,
+}
+
+impl JsonAutoSplitter {
+    /// Works out what to read for `splits`, rejecting addresses outside
+    /// WRAM.
+    pub fn new(splits: Splits) -> Result<Self> {
+        let mut widths = BTreeMap::new();
+        for details in splits.details() {
+            let offset = details.offset();
+            MemoryDomain::Wram.address(offset, details.width())?;
+            let width = widths.entry(offset).or_insert(0);
+            *width = details.width().max(*width);
+        }
+        let reads = widths
+            .into_iter()
+            .map(|(offset, width)| (MemoryDomain::Wram.base() + offset, width))
+            .collect();
+        let started = !splits.has_autostart();
+        Ok(JsonAutoSplitter {
+            splits,
+            reads,
+            started,
+            ..JsonAutoSplitter::default()
+        })
+    }
+
+    /// Loads definitions from a file and prepares them to run.
+    pub fn from_file<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let splits = Splits::from_file(path)
+            .map_err(|e| anyhow!("failed to load {}: {e}", path.display()))?;
+        JsonAutoSplitter::new(splits)
+    }
+
+    pub fn splits(&self) -> &Splits {
+        &self.splits
+    }
+
+    /// The definition that will split next, if any are left.
+    pub fn next_split(&self) -> Option<&Check> {
+        self.splits.definitions.get(self.current)
+    }
+
+    fn passes(&self, details: &CheckDetails) -> bool {
+        let value = self.memory.get(&details.offset()).copied().unwrap_or(0);
+        let value = if details.typ.is_word() {
+            value
+        } else {
+            value & 0xFF
+        };
+        details.test(value)
+    }
+
+    /// Checks the current step of the next definition, moving on a step if
+    /// it passes. Returns true when the last step passes.
+    fn advance(&mut self) -> bool {
+        let Some(check) = self.splits.definitions.get(self.current) else {
+            return false;
+        };
+        let passed = match self.step {
+            0 => {
+                self.passes(&check.check)
+                    && check.more.iter().flatten().all(|more| self.passes(more))
+            }
+            step => self.passes(&check.next.as_ref().expect("steps past the first")[step - 1]),
+        };
+        if !passed {
+            return false;
+        }
+        self.step += 1;
+        if self.step < check.steps() {
+            return false;
+        }
+        tracing::debug!("JSON split {} passed", check.name);
+        self.current += 1;
+        self.step = 0;
+        true
+    }
+}
+
+impl AutoSplitter for JsonAutoSplitter {
+    fn update(&mut self, client: &mut dyn MemorySource) -> Result<SNESSummary> {
+        let data = client.get_addresses(&self.reads)?;
+        for (&(address, _), bytes) in self.reads.iter().zip(data.iter()) {
+            let value = bytes
+                .iter()
+                .rev()
+                .fold(0, |value, &byte| value << 8 | byte as u32);
+            self.memory
+                .insert(address - MemoryDomain::Wram.base(), value);
+        }
+
+        let mut start = false;
+        if let Some(Autostart::Active { check }) = &self.splits.autostart {
+            let passed = self.passes(check);
+            start = passed && self.autostart_passed == Some(false);
+            self.autostart_passed = Some(passed);
+        }
+        if start {
+            self.current = 0;
+            self.step = 0;
+            self.started = true;
+        }
+        let split = self.started && !start && self.advance();
+        Ok(SNESSummary {
+            start,
+            reset: false,
+            split,
+        })
+    }
+
+    fn gametime_to_seconds(&self) -> Option<TimeSpan> {
+        None
+    }
+
+    fn reset_game_tracking(&mut self) {
+        self.current = 0;
+        self.step = 0;
+        self.started = !self.splits.has_autostart();
+    }
+
+    /// Called before every poll, so only a different index means the timer
+    /// moved on its own, e.g. a manual split, skip or undo. The steps of
+    /// the definition we were on no longer count. A running timer means
+    /// the run has started, however it was started.
+    fn set_current_split(&mut self, index: Option<usize>) {
+        if index.is_some() {
+            self.started = true;
+        }
+        if let Some(index) = index.filter(|&index| index != self.current) {
+            self.current = index;
+            self.step = 0;
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -0,0 +1,232 @@
+//! Working out which game is running and which autosplitter handles it.
+
+use parking_lot::RwLock;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::asl::AslAutoSplitter;
+use super::json::JsonAutoSplitter;
+use super::supermetroid::{Settings, SuperMetroidAutoSplitter};
+#[cfg(feature = "wasm")]
+use super::wasm::WasmAutoSplitter;
+use super::AutoSplitter;
+use crate::usb2snes::{device_matches, Capability, Infos};
+
+/// Where the internal header may live in the usb2snes `SNES` space, which
+/// maps ROM file offsets directly: LoROM, then HiROM.
+pub const HEADER_ADDRESSES: [u32; 2] = [0x7FC0, 0xFFC0];
+/// Title through checksum.
+pub const HEADER_SIZE: usize = 0x20;
+
+/// The parts of a cartridge's internal header we use to identify it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomHeader {
+    pub title: String,
+    pub checksum: u16,
+}
+
+impl RomHeader {
+    /// Parses a header read from one of `HEADER_ADDRESSES`. Returns `None`
+    /// unless the checksum and its complement agree, which is how we tell
+    /// a real header from whatever else sits at that address.
+    pub fn parse(bytes: &[u8]) -> Option<RomHeader> {
+        let bytes = bytes.get(..HEADER_SIZE)?;
+        let complement = u16::from_le_bytes([bytes[0x1C], bytes[0x1D]]);
+        let checksum = u16::from_le_bytes([bytes[0x1E], bytes[0x1F]]);
+        if checksum ^ complement != 0xFFFF {
+            return None;
+        }
+        let title = bytes[..0x15]
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { ' ' })
+            .collect::<String>()
+            .trim()
+            .to_owned();
+        Some(RomHeader { title, checksum })
+    }
+
+    /// Picks the first valid header out of the `HEADER_ADDRESSES` reads.
+    pub fn find(candidates: &[Vec<u8>]) -> Option<RomHeader> {
+        candidates.iter().find_map(|bytes| RomHeader::parse(bytes))
+    }
+}
+
+/// What we know about the game the device is running.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameInfo {
+    /// The ROM path reported by `Info`. Emulators often leave this empty.
+    pub rom_name: String,
+    /// `None` when the device can't read ROM or the header was not valid.
+    pub header: Option<RomHeader>,
+}
+
+impl GameInfo {
+    /// A short name to show to the user.
+    pub fn display_name(&self) -> &str {
+        match &self.header {
+            Some(header) if !header.title.is_empty() => &header.title,
+            _ => {
+                let name = self.rom_name.rsplit(['/', '\\']).next().unwrap_or_default();
+                if name.is_empty() {
+                    "unknown game"
+                } else {
+                    name
+                }
+            }
+        }
+    }
+}
+
+pub struct AutoSplitterEntry {
+    pub name: &'static str,
+    /// Header checksums of the ROMs this autosplitter is known to handle.
+    pub checksums: &'static [u16],
+    /// Header titles, compared ignoring case. ROM hacks usually keep the
+    /// original title but change the checksum.
+    pub titles: &'static [&'static str],
+    /// Patterns for the ROM file name, as accepted by `device_matches`.
+    pub rom_names: &'static [&'static str],
+    /// What the device must be able to do beyond reading WRAM.
+    pub requires: &'static [Capability],
+    pub create: fn(Arc<RwLock<Settings>>) -> Box<dyn AutoSplitter>,
+}
+
+pub static AUTOSPLITTERS: &[AutoSplitterEntry] = &[AutoSplitterEntry {
+    name: "Super Metroid",
+    // NTSC; other releases fall through to the title.
+    checksums: &[0xF8DF],
+    titles: &["Super Metroid"],
+    rom_names: &["*metroid*", "sm.sfc", "sm.smc", "sm_*"],
+    requires: &[],
+    create: |settings| Box::new(SuperMetroidAutoSplitter::new(settings)),
+}];
+
+/// Stands in for a registry entry when JSON split definitions are loaded.
+/// They are used for whatever game is running, so nothing here is matched;
+/// `create` makes an autosplitter with no definitions.
+pub static JSON_AUTOSPLITTER: AutoSplitterEntry = AutoSplitterEntry {
+    name: "JSON definitions",
+    checksums: &[],
+    titles: &[],
+    rom_names: &[],
+    requires: &[],
+    create: |_| Box::new(JsonAutoSplitter::default()),
+};
+
+/// Stands in for a registry entry when an ASL script is loaded, like
+/// [`JSON_AUTOSPLITTER`]; `create` makes an autosplitter with no script.
+pub static ASL_AUTOSPLITTER: AutoSplitterEntry = AutoSplitterEntry {
+    name: "ASL script",
+    checksums: &[],
+    titles: &[],
+    rom_names: &[],
+    requires: &[],
+    create: |_| Box::new(AslAutoSplitter::default()),
+};
+
+/// Stands in for a registry entry when a WebAssembly autosplitter is
+/// loaded, like [`JSON_AUTOSPLITTER`]; `create` makes one with no module.
+#[cfg(feature = "wasm")]
+pub static WASM_AUTOSPLITTER: AutoSplitterEntry = AutoSplitterEntry {
+    name: "WASM module",
+    checksums: &[],
+    titles: &[],
+    rom_names: &[],
+    requires: &[],
+    create: |_| Box::new(WasmAutoSplitter::default()),
+};
+
+/// Loads the JSON split definitions, ASL script or WebAssembly module the
+/// user picked, if any, to use for whatever game is running. The first of
+/// those given wins. ASL and WASM settings are added to `settings`.
+pub fn load_script(
+    json_splits: Option<&Path>,
+    asl_script: Option<&Path>,
+    wasm_module: Option<&Path>,
+    settings: &Arc<RwLock<Settings>>,
+) -> anyhow::Result<Option<(&'static AutoSplitterEntry, Box<dyn AutoSplitter>)>> {
+    if let Some(path) = json_splits {
+        let autosplitter = JsonAutoSplitter::from_file(path)?;
+        tracing::info!("Loaded JSON definitions for {}", autosplitter.splits().game);
+        return Ok(Some((&JSON_AUTOSPLITTER, Box::new(autosplitter))));
+    }
+    if let Some(path) = asl_script {
+        let autosplitter = AslAutoSplitter::from_file(path, settings.clone())?;
+        tracing::info!("Loaded the ASL script {}", path.display());
+        return Ok(Some((&ASL_AUTOSPLITTER, Box::new(autosplitter))));
+    }
+    if let Some(path) = wasm_module {
+        #[cfg(feature = "wasm")]
+        {
+            let autosplitter = WasmAutoSplitter::from_file(path, settings.clone())?;
+            tracing::info!("Loaded the WASM module {}", path.display());
+            return Ok(Some((&WASM_AUTOSPLITTER, Box::new(autosplitter))));
+        }
+        #[cfg(not(feature = "wasm"))]
+        Err(anyhow::anyhow!(
+            "can't load {}: Annelid was built without the wasm feature",
+            path.display()
+        ))?;
+    }
+    Ok(None)
+}
+
+/// Finds the autosplitter for `game`. A checksum match beats a title
+/// match, which beats a match on the ROM file name.
+pub fn find_autosplitter(game: &GameInfo) -> Option<&'static AutoSplitterEntry> {
+    let file_name = game.rom_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let by_checksum = |e: &&AutoSplitterEntry| {
+        game.header
+            .as_ref()
+            .is_some_and(|h| e.checksums.contains(&h.checksum))
+    };
+    let by_title = |e: &&AutoSplitterEntry| {
+        game.header
+            .as_ref()
+            .is_some_and(|h| e.titles.iter().any(|t| t.eq_ignore_ascii_case(&h.title)))
+    };
+    let by_rom_name = |e: &&AutoSplitterEntry| {
+        !file_name.is_empty() && e.rom_names.iter().any(|p| device_matches(p, file_name))
+    };
+    AUTOSPLITTERS
+        .iter()
+        .find(by_checksum)
+        .or_else(|| AUTOSPLITTERS.iter().find(by_title))
+        .or_else(|| AUTOSPLITTERS.iter().find(by_rom_name))
+}
+
+/// Explains each thing the device can't do that `entry` needs, or that
+/// resetting the game on timer reset needs, so the user knows why part of
+/// the autosplitter won't work.
+pub fn capability_warnings(
+    entry: &AutoSplitterEntry,
+    infos: &Infos,
+    reset_game_on_timer_reset: bool,
+) -> Vec<String> {
+    let mut warnings: Vec<String> = infos
+        .missing(entry.requires)
+        .into_iter()
+        .map(|c| {
+            format!(
+                "{} doesn't support {c}, which the {} autosplitter needs",
+                infos.dev_type, entry.name
+            )
+        })
+        .collect();
+    if reset_game_on_timer_reset && !infos.supports(Capability::ControlCommands) {
+        warnings.push(format!(
+            "{} doesn't support {}, so \"Reset game on timer reset\" does nothing",
+            infos.dev_type,
+            Capability::ControlCommands
+        ));
+    }
+    warnings
+}
+
+/// The result of the last detection, for the UI.
+#[derive(Clone, Debug)]
+pub struct DetectedGame {
+    pub game: GameInfo,
+    /// `None` when no registered autosplitter handles the game.
+    pub autosplitter: Option<&'static str>,
+}
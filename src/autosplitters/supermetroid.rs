@@ -2,14 +2,18 @@
 
 use anyhow::Result;
 use livesplit_core::TimeSpan;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ops::Index;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use time::Duration;
 
-use super::{AutoSplitter, SNESSummary};
+use super::{AutoSplitter, MissedSplit, Progress, SNESSummary};
+use crate::memory_source::{read_domains, MemoryDomain, MemorySource};
+use crate::usb2snes::WRAM_SIZE;
 
 lazy_static! {
     static ref roomIDEnum: HashMap<&'static str, u32> = {
@@ -255,403 +259,449 @@ lazy_static! {
     };
 }
 
+/// Whether any enabled condition can end any segment, or each segment
+/// only ends on its own conditions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplitOrder {
+    #[default]
+    AnyOrder,
+    InOrder,
+}
+
+/// The settings keys that end one segment of the run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedSegment {
+    /// The segment name when the plan was made, to line it up with the
+    /// splits again if they change.
+    pub name: String,
+    /// A condition that has children also allows whichever of them are
+    /// enabled.
+    pub conditions: Vec<String>,
+}
+
+/// Which conditions end each segment, by segment index.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitPlan {
+    pub order: SplitOrder,
+    pub segments: Vec<PlannedSegment>,
+}
+
+impl SplitPlan {
+    /// The conditions for the segment at `index`; none past the end.
+    pub fn conditions(&self, index: usize) -> &[String] {
+        self.segments
+            .get(index)
+            .map_or(&[], |segment| &segment.conditions)
+    }
+
+    /// Makes one planned segment per name, taking the conditions of the
+    /// segment with the same name, or failing that the same position.
+    pub fn align(&mut self, names: &[String]) {
+        let old = std::mem::take(&mut self.segments);
+        self.segments = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let conditions = old
+                    .iter()
+                    .find(|segment| &segment.name == name)
+                    .or_else(|| old.get(i).filter(|segment| !names.contains(&segment.name)))
+                    .map(|segment| segment.conditions.clone())
+                    .unwrap_or_default();
+                PlannedSegment {
+                    name: name.clone(),
+                    conditions,
+                }
+            })
+            .collect();
+    }
+}
+
+/// How a setting is shown in the settings tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SettingSchema {
+    pub label: String,
+    pub tooltip: Option<String>,
+}
+
+/// Labels and tooltips for settings, and the order they were declared in,
+/// which is the order siblings are shown in.
+#[derive(Clone, Debug, Default)]
+pub struct Schema {
+    entries: HashMap<Arc<str>, (usize, SettingSchema)>,
+}
+
+impl Schema {
+    /// The built-in Super Metroid settings.
+    pub fn super_metroid() -> Self {
+        let mut schema = Schema::default();
+        for &(key, _, _, label, tooltip) in SUPER_METROID_SETTINGS {
+            schema.declare(key, label, Some(tooltip));
+        }
+        schema
+    }
+
+    /// Adds `key` after everything declared so far. Declaring it again
+    /// keeps its place and replaces the label, and the tooltip if one is
+    /// given.
+    pub fn declare(&mut self, key: &str, label: &str, tooltip: Option<&str>) {
+        let order = self.entries.len();
+        let (_, entry) = self.entries.entry(key.into()).or_insert_with(|| {
+            (
+                order,
+                SettingSchema {
+                    label: String::new(),
+                    tooltip: None,
+                },
+            )
+        });
+        entry.label = label.to_owned();
+        if let Some(tooltip) = tooltip {
+            entry.tooltip = Some(tooltip.to_owned());
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&SettingSchema> {
+        self.entries.get(key).map(|(_, entry)| entry)
+    }
+
+    /// Every declared key, in declaration order.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.entries.keys().map(|k| k.to_string()).collect();
+        self.sort(&mut keys);
+        keys
+    }
+
+    /// Sorts `keys` by when they were declared. Keys that never were, such
+    /// as ones from a settings file saved with a script loaded, go last in
+    /// alphabetical order.
+    pub fn sort(&self, keys: &mut [String]) {
+        keys.sort_by_cached_key(|key| {
+            let order = self.entries.get(key.as_str()).map(|&(order, _)| order);
+            (order.unwrap_or(usize::MAX), key.clone())
+        });
+    }
+}
+
+/// The Super Metroid settings in the order they're shown, as `(key,
+/// default, parent, label, tooltip)`. Labels follow SuperMetroid.asl.
+#[rustfmt::skip]
+const SUPER_METROID_SETTINGS: &[(&str, bool, Option<&str>, &str, &str)] = &[
+    ("ammoPickups", true, None, "Ammo Pickups", "Split on Missiles, Super Missiles, and Power Bombs"),
+    ("firstMissile", false, Some("ammoPickups"), "First Missiles", "Split on the first Missile pickup"),
+    ("allMissiles", false, Some("ammoPickups"), "All Missiles", "Split on each Missile upgrade"),
+    ("specificMissiles", false, Some("ammoPickups"), "Specific Missile Packs", "Split on specific Missile Pack locations"),
+    ("crateriaMissiles", false, Some("specificMissiles"), "Crateria Missile Packs", "Split on Crateria Missile Pack locations"),
+    ("oceanBottomMissiles", false, Some("crateriaMissiles"), "Ocean Bottom Missile Pack", "Split on picking up the Missile Pack located at the bottom left of the West Ocean"),
+    ("oceanTopMissiles", false, Some("crateriaMissiles"), "Ocean Top Missile Pack", "Split on picking up the Missile Pack located in the ceiling tile in West Ocean"),
+    ("oceanMiddleMissiles", false, Some("crateriaMissiles"), "Ocean Middle Missile Pack", "Split on picking up the Missile Pack located in the Morphball maze section of West Ocean"),
+    ("moatMissiles", false, Some("crateriaMissiles"), "Moat Missile Pack", "Split on picking up the Missile Pack in The Moat, also known as The Lake"),
+    ("oldTourianMissiles", false, Some("crateriaMissiles"), "Old Tourian Missile Pack", "Split on picking up the Missile Pack in the Pit Room"),
+    ("gauntletRightMissiles", false, Some("crateriaMissiles"), "Gauntlet Right Missile Pack", "Split on picking up the right side Missile Pack at the end of Gauntlet(Green Pirates Shaft)"),
+    ("gauntletLeftMissiles", false, Some("crateriaMissiles"), "Gauntlet Left Missile Pack", "Split on picking up the left side Missile Pack at the end of Gauntlet(Green Pirates Shaft)"),
+    ("dentalPlan", false, Some("crateriaMissiles"), "Dental Plan Missile Pack", "Split on picking up the Missile Pack located in The Final Missile"),
+    ("brinstarMissiles", false, Some("specificMissiles"), "Brinstar Missile Packs", "Split on Brinstar Missile Pack locations"),
+    ("earlySuperBridgeMissiles", false, Some("brinstarMissiles"), "Early Supers Under Bridge Missile Pack", "Split on picking up the Missile Pack located below the crumble bridge in the Early Supers Room"),
+    ("greenBrinstarReserveMissiles", false, Some("brinstarMissiles"), "Brinstar Reserve Missile Pack", "Split on picking up the first Missile Pack behind the Brinstar Reserve Tank"),
+    ("greenBrinstarExtraReserveMissiles", false, Some("brinstarMissiles"), "Brinstar Reserve Missile Pack 2", "Split on picking up the second Missile Pack behind the Brinstar Reserve Tank Room"),
+    ("bigPinkTopMissiles", false, Some("brinstarMissiles"), "Big Pink Top Missile Pack", "Split on picking up the Missile Pack located left of center in Big Pink"),
+    ("chargeMissiles", false, Some("brinstarMissiles"), "Charge Missile Pack", "Split on picking up the Missile Pack located at the bottom left of Big Pink"),
+    ("greenHillsMissiles", false, Some("brinstarMissiles"), "Green Hills Missile Pack", "Split on picking up the Missile Pack in Green Hill Zone"),
+    ("blueBrinstarETankMissiles", false, Some("brinstarMissiles"), "Classic Brinstar East Missile Pack", "Split on picking up the Missile Pack in the Blue Brinstar Energy Tank Room"),
+    ("alphaMissiles", false, Some("brinstarMissiles"), "Alpha Missile Pack", "Split on picking up the first Missile Pack of the game(First Missile Room)"),
+    ("billyMaysMissiles", false, Some("brinstarMissiles"), "Billy Mays Missile Pack", "Split on picking up the Missile Pack located on the pedestal in Billy Mays' Room"),
+    ("butWaitTheresMoreMissiles", false, Some("brinstarMissiles"), "But Wait... There's MORE! Missile Pack", "Split on picking up the Missile Pack located in the floor of Billy Mays' Room"),
+    ("redBrinstarMissiles", false, Some("brinstarMissiles"), "Red Brinstar Missile Pack", "Split on picking up the Missile Pack in the Alpha Power Bombs Room"),
+    ("warehouseMissiles", false, Some("brinstarMissiles"), "Warehouse Missile Pack", "Split on picking up the Missile Pack in the Warehouse Kihunter Room"),
+    ("norfairMissiles", false, Some("specificMissiles"), "Norfair Missile Packs", "Split on Norfair Missile Pack locations"),
+    ("cathedralMissiles", false, Some("norfairMissiles"), "Cathedral Missile Pack", "Split on picking up the Missile Pack in Cathedral"),
+    ("crumbleShaftMissiles", false, Some("norfairMissiles"), "Crumble Shaft Missile Pack", "Split on picking up the Missile Pack in Crumble Shaft"),
+    ("crocomireEscapeMissiles", false, Some("norfairMissiles"), "Crocomire Escape Missile Pack", "Split on picking up the Missile Pack in Crocomire Escape"),
+    ("hiJumpMissiles", false, Some("norfairMissiles"), "Hi Jump Missile Pack", "Split on picking up the Missile Pack in the Hi Jump Energy Tank Room"),
+    ("postCrocomireMissiles", false, Some("norfairMissiles"), "Post Crocomire Missile Pack", "Split on picking up the Missile Pack in the Post Crocomire Missile Room, also known as Cosine Room"),
+    ("grappleMissiles", false, Some("norfairMissiles"), "Grapple Missile Pack", "Split on picking up the Missile Pack in the Post Crocomire Jump Room"),
+    ("norfairReserveMissiles", false, Some("norfairMissiles"), "Norfair Reserve Missile Pack", "Split on picking up the Missile Pack in the Norfair Reserve Tank Room"),
+    ("greenBubblesMissiles", false, Some("norfairMissiles"), "Green Bubbles Missile Pack", "Split on picking up the Missile Pack in the Green Bubbles Missile Room"),
+    ("bubbleMountainMissiles", false, Some("norfairMissiles"), "Bubble Mountain Missile Pack", "Split on picking up the Missile Pack in Bubble Mountain"),
+    ("speedBoostMissiles", false, Some("norfairMissiles"), "Speed Booster Missile Pack", "Split on picking up the Missile Pack in Speed Booster Hall"),
+    ("waveMissiles", false, Some("norfairMissiles"), "Wave Beam Missile Pack", "Split on picking up the Wave Missile Pack in Double Chamber"),
+    ("goldTorizoMissiles", false, Some("norfairMissiles"), "Golden Torizo Missile Pack", "Split on picking up the Missile Pack in the Golden Torizo's Room"),
+    ("mickeyMouseMissiles", false, Some("norfairMissiles"), "Mickey Mouse Missile Pack", "Split on picking up the Missile Pack in the Mickey Mouse Room"),
+    ("lowerNorfairSpringMazeMissiles", false, Some("norfairMissiles"), "Lower Norfair Springball Maze Missile Pack", "Split on picking up the Missile Pack in the Lower Norfair Springball Maze Room"),
+    ("threeMusketeersMissiles", false, Some("norfairMissiles"), "Three Musketeers Missile Pack", "Split on picking up the Missile Pack in the The Musketeers' Room"),
+    ("wreckedShipMissiles", false, Some("specificMissiles"), "Wrecked Ship Missile Packs", "Split on Wrecked Ship Missile Pack locations"),
+    ("wreckedShipMainShaftMissiles", false, Some("wreckedShipMissiles"), "Wrecked Ship Main Shaft Missile Pack", "Split on picking up the Missile Pack in Wrecked Ship Main Shaft"),
+    ("bowlingMissiles", false, Some("wreckedShipMissiles"), "Bowling Alley Missile Pack", "Split on picking up the Missile Pack in Bowling Alley"),
+    ("atticMissiles", false, Some("wreckedShipMissiles"), "Attic Missile Pack", "Split on picking up the Missile Pack in the Wrecked Ship East Missile Room"),
+    ("maridiaMissiles", false, Some("specificMissiles"), "Maridia Missile Packs", "Split on Maridia Missile Pack locations"),
+    ("mainStreetMissiles", false, Some("maridiaMissiles"), "Main Street Missile Pack", "Split on picking up the Missile Pack in Main Street"),
+    ("mamaTurtleMissiles", false, Some("maridiaMissiles"), "Mama Turtle Missile Pack", "Split on picking up the Missile Pack in the Mama Turtle Room"),
+    ("wateringHoleMissiles", false, Some("maridiaMissiles"), "Watering Hole Missile Pack", "Split on picking up the Missile Pack in Watering Hole"),
+    ("beachMissiles", false, Some("maridiaMissiles"), "Beach Missile Pack", "Split on picking up the Missile Pack in the Pseudo Plasma Spark Room"),
+    ("leftSandPitMissiles", false, Some("maridiaMissiles"), "Left Sand Pit Missile Pack", "Split on picking up the Missile Pack in West Sand Hole"),
+    ("rightSandPitMissiles", false, Some("maridiaMissiles"), "Right Sand Pit Missile Pack", "Split on picking up the Missile Pack in East Sand Hole"),
+    ("aqueductMissiles", false, Some("maridiaMissiles"), "Aqueduct Missile Pack", "Split on picking up the Missile Pack in Aqueduct"),
+    ("preDraygonMissiles", false, Some("maridiaMissiles"), "Pre Draygon Missile Pack", "Split on picking up the Missile Pack in The Precious Room"),
+    ("firstSuper", false, Some("ammoPickups"), "First Supers", "Split on the first Super Missile pickup"),
+    ("allSupers", false, Some("ammoPickups"), "All Super Missiles", "Split on each Super Missile upgrade"),
+    ("specificSupers", false, Some("ammoPickups"), "Specific Super Missile Packs", "Split on specific Super Missile Pack locations"),
+    ("climbSupers", false, Some("specificSupers"), "Crateria Super Missile Pack", "Split on picking up the Super Missile Pack in the Crateria Super Room"),
+    ("sporeSpawnSupers", false, Some("specificSupers"), "Spore Spawn Super Missile Pack", "Split on picking up the Super Missile Pack in the Spore Spawn Super Room (NOTE: SSTRA splits when the dialogue box disappears, not on touch. Use Spore Spawn RTA Finish for SSTRA runs.)"),
+    ("earlySupers", false, Some("specificSupers"), "Early Super Missile Pack", "Split on picking up the Super Missile Pack in the Early Supers Room"),
+    ("etecoonSupers", false, Some("specificSupers"), "Etecoon Super Missile Pack", "Split on picking up the Super Missile Pack in the Etecoon Super Room"),
+    ("goldTorizoSupers", false, Some("specificSupers"), "Golden Torizo Super Missile Pack", "Split on picking up the Super Missile Pack in the Golden Torizo's Room"),
+    ("wreckedShipLeftSupers", false, Some("specificSupers"), "Wrecked Ship Left Super Missile Pack", "Split on picking up the Super Missile Pack in the Wrecked Ship West Super Room"),
+    ("wreckedShipRightSupers", false, Some("specificSupers"), "Wrecked Ship Right Super Missile Pack", "Split on picking up the Super Missile Pack in the Wrecked Ship East Super Room"),
+    ("crabSupers", false, Some("specificSupers"), "Crab Super Missile Pack", "Split on picking up the Super Missile Pack in Main Street"),
+    ("wateringHoleSupers", false, Some("specificSupers"), "Watering Hole Super Missile Pack", "Split on picking up the Super Missile Pack in Watering Hole"),
+    ("aqueductSupers", false, Some("specificSupers"), "Aqueduct Super Missile Pack", "Split on picking up the Super Missile Pack in Aqueduct"),
+    ("firstPowerBomb", true, Some("ammoPickups"), "First Power Bomb", "Split on the first Power Bomb pickup"),
+    ("allPowerBombs", false, Some("ammoPickups"), "All Power Bombs", "Split on each Power Bomb upgrade"),
+    ("specificBombs", false, Some("ammoPickups"), "Specific Power Bomb Packs", "Split on specific Power Bomb Pack locations"),
+    ("landingSiteBombs", false, Some("specificBombs"), "Crateria Power Bomb Pack", "Split on picking up the Power Bomb Pack in the Crateria Power Bomb Room"),
+    ("etecoonBombs", false, Some("specificBombs"), "Etecoon Power Bomb Pack", "Split on picking up the Power Bomb Pack in the Etecoon Room section of Green Brinstar Main Shaft"),
+    ("pinkBrinstarBombs", false, Some("specificBombs"), "Pink Brinstar Power Bomb Pack", "Split on picking up the Power Bomb Pack in the Pink Brinstar Power Bomb Room"),
+    ("blueBrinstarBombs", false, Some("specificBombs"), "Classic Brinstar Power Bomb Pack", "Split on picking up the Power Bomb Pack in the Morph Ball Room"),
+    ("alphaBombs", false, Some("specificBombs"), "Alpha Power Bomb Pack", "Split on picking up the Power Bomb Pack in the Alpha Power Bomb Room"),
+    ("betaBombs", false, Some("specificBombs"), "Beta Power Bomb Pack", "Split on picking up the Power Bomb Pack in the Beta Power Bomb Room"),
+    ("crocomireBombs", false, Some("specificBombs"), "Crocomire Power Bomb Pack", "Split on picking up the Power Bomb Pack in the Post Crocomire Power Bomb Room"),
+    ("lowerNorfairEscapeBombs", false, Some("specificBombs"), "Lower Norfair Escape Power Bomb Pack", "Split on picking up the Power Bomb Pack in the Lower Norfair Escape Power Bomb Room"),
+    ("shameBombs", false, Some("specificBombs"), "Power Bombs of Shame Pack", "Split on picking up the Power Bomb Pack in Wasteland"),
+    ("rightSandPitBombs", false, Some("specificBombs"), "Maridia Power Bomb Pack", "Split on picking up the Power Bomb Pack in East Sand Hall"),
+
+    ("suitUpgrades", true, None, "Suit Pickups", "Split on Varia and Gravity pickups"),
+    ("variaSuit", true, Some("suitUpgrades"), "Varia Suit", "Split on picking up the Varia Suit"),
+    ("gravSuit", true, Some("suitUpgrades"), "Gravity Suit", "Split on picking up the Gravity Suit"),
+
+    ("beamUpgrades", true, None, "Beam Upgrades", "Split on beam upgrades"),
+    ("chargeBeam", false, Some("beamUpgrades"), "Charge Beam", "Split on picking up the Charge Beam"),
+    ("spazer", false, Some("beamUpgrades"), "Spazer", "Split on picking up the Spazer"),
+    ("wave", true, Some("beamUpgrades"), "Wave Beam", "Split on picking up the Wave Beam"),
+    ("ice", false, Some("beamUpgrades"), "Ice Beam", "Split on picking up the Ice Beam"),
+    ("plasma", false, Some("beamUpgrades"), "Plasma Beam", "Split on picking up the Plasma Beam"),
+
+    ("bootUpgrades", false, None, "Boot Upgrades", "Split on boot upgrades"),
+    ("hiJump", false, Some("bootUpgrades"), "Hi-Jump Boots", "Split on picking up the Hi-Jump Boots"),
+    ("spaceJump", false, Some("bootUpgrades"), "Space Jump", "Split on picking up Space Jump"),
+    ("speedBooster", false, Some("bootUpgrades"), "Speed Booster", "Split on picking up the Speed Booster"),
+
+    ("energyUpgrades", false, None, "Energy Upgrades", "Split on Energy Tanks and Reserve Tanks"),
+    ("firstETank", false, Some("energyUpgrades"), "First Energy Tank", "Split on picking up the first Energy Tank"),
+    ("allETanks", false, Some("energyUpgrades"), "All Energy Tanks", "Split on picking up each Energy Tank"),
+    ("specificETanks", false, Some("energyUpgrades"), "Specific Energy Tanks", "Split on specific Energy Tank locations"),
+    ("gauntletETank", false, Some("specificETanks"), "Gauntlet Energy Tank", "Split on picking up the Energy Tank in the Gauntlet Energy Tank Room"),
+    ("terminatorETank", false, Some("specificETanks"), "Terminator Energy Tank", "Split on picking up the Energy Tank in the Terminator Room"),
+    ("ceilingETank", false, Some("specificETanks"), "Classic Brinstar Energy Tank", "Split on picking up the Energy Tank in the Blue Brinstar Energy Tank Room"),
+    ("etecoonsETank", false, Some("specificETanks"), "Etacoon Energy Tank", "Split on picking up the Energy Tank in the Etecoon Energy Tank Room"),
+    ("waterwayETank", false, Some("specificETanks"), "Waterway Energy Tank", "Split on picking up the Energy Tank in Waterway"),
+    ("waveGateETank", false, Some("specificETanks"), "Pink Brinstar Wave Gate Energy Tank", "Split on picking up the Energy Tank in the Hopper Energy Tank Room"),
+    ("kraidETank", false, Some("specificETanks"), "Warehouse Energy Tank", "Split on picking up the Kraid Energy Tank in the Warehouse Energy Tank Room"),
+    ("crocomireETank", false, Some("specificETanks"), "Crocomire Energy Tank", "Split on picking up the Energy Tank in Crocomire's Room"),
+    ("hiJumpETank", false, Some("specificETanks"), "Hi Jump Energy Tank", "Split on picking up the Energy Tank in the Hi Jump Energy Tank Room"),
+    ("ridleyETank", false, Some("specificETanks"), "Ridley Energy Tank", "Split on picking up the Energy Tank in the Ridley Tank Room"),
+    ("firefleaETank", false, Some("specificETanks"), "Fireflea Energy Tank", "Split on picking up the Energy Tank in the Lower Norfair Fireflea Room"),
+    ("wreckedShipETank", false, Some("specificETanks"), "Wrecked Ship Energy Tank", "Split on picking up the Energy Tank in the Wrecked Ship Energy Tank Room"),
+    ("tatoriETank", false, Some("specificETanks"), "Mama Turtle Energy Tank", "Split on picking up the Energy Tank in the Mama Turtle Room"),
+    ("botwoonETank", false, Some("specificETanks"), "Botwoon Energy Tank", "Split on picking up the Energy Tank in the Botwoon Energy Tank Room"),
+    ("reserveTanks", false, Some("energyUpgrades"), "All Reserve Tanks", "Split on picking up each Reserve Tank"),
+    ("specificRTanks", false, Some("energyUpgrades"), "Specific Reserve Tanks", "Split on specific Reserve Tank locations"),
+    ("brinstarReserve", false, Some("specificRTanks"), "Brinstar Reserve Tank", "Split on picking up the Reserve Tank in the Brinstar Reserve Tank Room"),
+    ("norfairReserve", false, Some("specificRTanks"), "Norfair Reserve Tank", "Split on picking up the Reserve Tank in the Norfair Reserve Tank Room"),
+    ("wreckedShipReserve", false, Some("specificRTanks"), "Wrecked Ship Reserve Tank", "Split on picking up the Reserve Tank in Bowling Alley"),
+    ("maridiaReserve", false, Some("specificRTanks"), "Maridia Reserve Tank", "Split on picking up the Reserve Tank in West Sand Hole"),
+
+    ("miscUpgrades", false, None, "Misc Upgrades", "Split on the miscellaneous upgrades"),
+    ("morphBall", false, Some("miscUpgrades"), "Morphing Ball", "Split on picking up the Morphing Ball"),
+    ("bomb", false, Some("miscUpgrades"), "Bomb", "Split on picking up the Bomb"),
+    ("springBall", false, Some("miscUpgrades"), "Spring Ball", "Split on picking up the Spring Ball"),
+    ("screwAttack", false, Some("miscUpgrades"), "Screw Attack", "Split on picking up the Screw Attack"),
+    ("grapple", false, Some("miscUpgrades"), "Grapple Beam", "Split on picking up the Grapple Beam"),
+    ("xray", false, Some("miscUpgrades"), "X-Ray Scope", "Split on picking up the X-Ray Scope"),
+
+    ("areaTransitions", true, None, "Area Transitions", "Split on transitions between areas"),
+    ("miniBossRooms", false, Some("areaTransitions"), "Miniboss Rooms", "Split on entering miniboss rooms (except Bomb Torizo)"),
+    ("bossRooms", false, Some("areaTransitions"), "Boss Rooms", "Split on entering major boss rooms"),
+    ("elevatorTransitions", false, Some("areaTransitions"), "Elevator transitions", "Split on elevator transitions between areas (except Statue Room to Tourian)"),
+    ("ceresEscape", false, Some("areaTransitions"), "Ceres Escape", "Split on leaving Ceres Station"),
+    ("wreckedShipEntrance", false, Some("areaTransitions"), "Wrecked Ship Entrance", "Split on entering the Wrecked Ship Entrance from the lower door of West Ocean"),
+    ("redTowerMiddleEntrance", false, Some("areaTransitions"), "Red Tower Middle Entrance", "Split on entering Red Tower from Noob Bridge"),
+    ("redTowerBottomEntrance", false, Some("areaTransitions"), "Red Tower Bottom Entrance", "Split on entering Red Tower from Skree Boost room"),
+    ("redTower->batRoom", false, Some("areaTransitions"), "Red Tower to Bat Room", "Split on entering Bat Room from Red Tower"),
+    ("kraidsLair", false, Some("areaTransitions"), "Kraid's Lair", "Split on entering Kraid's Lair"),
+    ("risingTideEntrance", false, Some("areaTransitions"), "Rising Tide Entrance", "Split on entering Rising Tide from Cathedral"),
+    ("frogSavestation->businessCenter", false, Some("areaTransitions"), "Frog Savestation to Business Center", "Split on entering Business center from frog savestation"),
+    ("atticExit", false, Some("areaTransitions"), "Attic Exit", "Split on exiting Attic"),
+    ("tubeBroken", false, Some("areaTransitions"), "Tube Broken", "Split on blowing up the tube to enter Maridia"),
+    ("fishTank->mountEverest", false, Some("areaTransitions"), "Fish Tank to Mount Everest", "Split on entering mount everest from fish tank"),
+    ("crabShaft->aqueduct", false, Some("areaTransitions"), "Crab Shaft to Aqueduct", "Split on entering Aqueduct from crab shaft"),
+    ("cacExit", false, Some("areaTransitions"), "Cacatack Alley Exit", "Split on exiting West Cacattack Alley"),
+    ("toilet", false, Some("areaTransitions"), "Toilet Bowl", "Split on entering Toilet Bowl from either direction"),
+    ("kronicBoost", false, Some("areaTransitions"), "Kronic Boost Room", "Split on entering Kronic Boost room"),
+    ("lowerNorfairEntrance", false, Some("areaTransitions"), "Lower Norfair Entrance", "Split on the elevator down to Lower Norfair"),
+    ("writg", false, Some("areaTransitions"), "Worst Room in the Game", "Split on entering Worst Room in the Game"),
+    ("redKiShaft", false, Some("areaTransitions"), "Red Kihunter Shaft", "Split on entering Red Kihunter Shaft from either Amphitheatre or Wastelands (NOTE: will split twice)"),
+    ("metalPirates", false, Some("areaTransitions"), "Metal Pirates Room", "Split on entering Metal Pirates Room from Wasteland"),
+    ("lowerNorfairSpringMaze", false, Some("areaTransitions"), "Lower Norfair Springball Maze Room", "Split on entering Lower Norfair Springball Maze Room"),
+    ("lowerNorfairExit", false, Some("areaTransitions"), "Lower Norfair Exit", "Split on moving from the Three Musketeers' Room to the Single Chamber"),
+    ("goldenFour", true, Some("areaTransitions"), "Golden Four", "Split on entering the Statues Room with all four major bosses defeated"),
+    ("tourianEntrance", false, Some("areaTransitions"), "Tourian Entrance", "Split on the elevator down to Tourian"),
+    ("metroids", false, Some("areaTransitions"), "Tourian Metroid Rooms", "Split on exiting each of the Metroid rooms in Tourian"),
+    ("babyMetroidRoom", false, Some("areaTransitions"), "Baby Metroid Room", "Split on moving from the Dust Torizo Room to the Big Boy Room"),
+    ("escapeClimb", false, Some("areaTransitions"), "Tourian Exit", "Split on moving from Tourian Escape Room 4 to The Climb"),
+
+    ("miniBosses", false, None, "Minibosses", "Split on defeating minibosses"),
+    ("ceresRidley", false, Some("miniBosses"), "Ceres Ridley", "Split on starting the Ceres Escape"),
+    ("bombTorizo", false, Some("miniBosses"), "Bomb Torizo", "Split on Bomb Torizo's drops appearing"),
+    ("sporeSpawn", false, Some("miniBosses"), "Spore Spawn", "Split on the last hit to Spore Spawn"),
+    ("crocomire", false, Some("miniBosses"), "Crocomire", "Split on Crocomire's drops appearing"),
+    ("botwoon", false, Some("miniBosses"), "Botwoon", "Split on Botwoon's vertical column being fully destroyed"),
+    ("goldenTorizo", false, Some("miniBosses"), "Golden Torizo", "Split on Golden Torizo's drops appearing"),
+
+    ("bosses", true, None, "Bosses", "Split on defeating major bosses"),
+    ("kraid", false, Some("bosses"), "Kraid", "Split shortly after Kraid's drops appear"),
+    ("phantoon", false, Some("bosses"), "Phantoon", "Split on Phantoon's drops appearing"),
+    ("draygon", false, Some("bosses"), "Draygon", "Split on Draygon's drops appearing"),
+    ("ridley", true, Some("bosses"), "Ridley", "Split on Ridley's drops appearing"),
+    ("mb1", false, Some("bosses"), "Mother Brain 1", "Split on Mother Brain's head hitting the ground at the end of the first phase"),
+    ("mb1_0hp", false, Some("bosses"), "Mother Brain 1 at 0 HP", "Split on Mother Brain reaching 0hp in the first phase"),
+    ("mb2", true, Some("bosses"), "Mother Brain 2", "Split on the Baby Metroid detaching from Mother Brain's head"),
+    ("mb3", false, Some("bosses"), "Mother Brain 3", "Split on the start of the Zebes Escape"),
+
+    ("rtaFinish", true, None, "RTA Finish", "Split on facing forward at the end of Zebes Escape"),
+
+    ("igtFinish", false, None, "IGT Finish", "Split on In-Game Time finalizing, when the end cutscene starts"),
+
+    ("sporeSpawnRTAFinish", false, None, "Spore Spawn RTA Finish", "Split on the end of a Spore Spawn RTA run, when the text box clears after collecting the Super Missiles"),
+
+    ("hundredMissileRTAFinish", false, None, "100 Missile RTA Finish", "Split on the end of a 100 Missile RTA run, when the text box clears after collecting the hundredth missile"),
+];
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Settings {
     data: HashMap<std::sync::Arc<str>, (bool, Option<String>)>,
+    /// Files saved before split plans existed split in any order.
+    #[serde(default)]
+    plan: SplitPlan,
+    /// Not saved; scripts declare theirs again when they're loaded.
+    #[serde(skip, default = "Schema::super_metroid")]
+    schema: Schema,
     #[serde(skip)]
     modified_after_creation: bool,
+    /// Changes whenever a value, parent or the plan might have, and is
+    /// never shared by settings with different contents, so copies made
+    /// from these settings can be kept until it moves.
+    #[serde(skip, default = "next_revision")]
+    revision: u64,
+    /// Where `get` notes the keys it's asked for, see `keys_read_by`.
+    #[serde(skip)]
+    reads: Option<Arc<Mutex<BTreeSet<String>>>>,
+}
+
+fn next_revision() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
 }
 
 impl Settings {
     pub fn new() -> Self {
         let mut settings = Settings {
             data: HashMap::new(),
+            plan: SplitPlan::default(),
+            schema: Schema::super_metroid(),
             modified_after_creation: false,
+            revision: next_revision(),
+            reads: None,
         };
-        // Split on Missiles, Super Missiles, and Power Bombs
-        settings.insert("ammoPickups", true);
-        // Split on the first Missile pickup
-        settings.insert_with_parent("firstMissile", false, "ammoPickups");
-        // Split on each Missile upgrade
-        settings.insert_with_parent("allMissiles", false, "ammoPickups");
-        // Split on specific Missile Pack locations
-        settings.insert_with_parent("specificMissiles", false, "ammoPickups");
-        // Split on Crateria Missile Pack locations
-        settings.insert_with_parent("crateriaMissiles", false, "specificMissiles");
-        // Split on picking up the Missile Pack located at the bottom left of the West Ocean
-        settings.insert_with_parent("oceanBottomMissiles", false, "crateriaMissiles");
-        // Split on picking up the Missile Pack located in the ceiling tile in West Ocean
-        settings.insert_with_parent("oceanTopMissiles", false, "crateriaMissiles");
-        // Split on picking up the Missile Pack located in the Morphball maze section of West Ocean
-        settings.insert_with_parent("oceanMiddleMissiles", false, "crateriaMissiles");
-        // Split on picking up the Missile Pack in The Moat, also known as The Lake
-        settings.insert_with_parent("moatMissiles", false, "crateriaMissiles");
-        // Split on picking up the Missile Pack in the Pit Room
-        settings.insert_with_parent("oldTourianMissiles", false, "crateriaMissiles");
-        // Split on picking up the right side Missile Pack at the end of Gauntlet(Green Pirates Shaft)
-        settings.insert_with_parent("gauntletRightMissiles", false, "crateriaMissiles");
-        // Split on picking up the left side Missile Pack at the end of Gauntlet(Green Pirates Shaft)
-        settings.insert_with_parent("gauntletLeftMissiles", false, "crateriaMissiles");
-        // Split on picking up the Missile Pack located in The Final Missile
-        settings.insert_with_parent("dentalPlan", false, "crateriaMissiles");
-        // Split on Brinstar Missile Pack locations
-        settings.insert_with_parent("brinstarMissiles", false, "specificMissiles");
-        // Split on picking up the Missile Pack located below the crumble bridge in the Early Supers Room
-        settings.insert_with_parent("earlySuperBridgeMissiles", false, "brinstarMissiles");
-        // Split on picking up the first Missile Pack behind the Brinstar Reserve Tank
-        settings.insert_with_parent("greenBrinstarReserveMissiles", false, "brinstarMissiles");
-        // Split on picking up the second Missile Pack behind the Brinstar Reserve Tank Room
-        settings.insert_with_parent(
-            "greenBrinstarExtraReserveMissiles",
-            false,
-            "brinstarMissiles",
-        );
-        // Split on picking up the Missile Pack located left of center in Big Pink
-        settings.insert_with_parent("bigPinkTopMissiles", false, "brinstarMissiles");
-        // Split on picking up the Missile Pack located at the bottom left of Big Pink
-        settings.insert_with_parent("chargeMissiles", false, "brinstarMissiles");
-        // Split on picking up the Missile Pack in Green Hill Zone
-        settings.insert_with_parent("greenHillsMissiles", false, "brinstarMissiles");
-        // Split on picking up the Missile Pack in the Blue Brinstar Energy Tank Room
-        settings.insert_with_parent("blueBrinstarETankMissiles", false, "brinstarMissiles");
-        // Split on picking up the first Missile Pack of the game(First Missile Room)
-        settings.insert_with_parent("alphaMissiles", false, "brinstarMissiles");
-        // Split on picking up the Missile Pack located on the pedestal in Billy Mays' Room
-        settings.insert_with_parent("billyMaysMissiles", false, "brinstarMissiles");
-        // Split on picking up the Missile Pack located in the floor of Billy Mays' Room
-        settings.insert_with_parent("butWaitTheresMoreMissiles", false, "brinstarMissiles");
-        // Split on picking up the Missile Pack in the Alpha Power Bombs Room
-        settings.insert_with_parent("redBrinstarMissiles", false, "brinstarMissiles");
-        // Split on picking up the Missile Pack in the Warehouse Kihunter Room
-        settings.insert_with_parent("warehouseMissiles", false, "brinstarMissiles");
-        // Split on Norfair Missile Pack locations
-        settings.insert_with_parent("norfairMissiles", false, "specificMissiles");
-        // Split on picking up the Missile Pack in Cathedral
-        settings.insert_with_parent("cathedralMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in Crumble Shaft
-        settings.insert_with_parent("crumbleShaftMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in Crocomire Escape
-        settings.insert_with_parent("crocomireEscapeMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the Hi Jump Energy Tank Room
-        settings.insert_with_parent("hiJumpMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the Post Crocomire Missile Room, also known as Cosine Room
-        settings.insert_with_parent("postCrocomireMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the Post Crocomire Jump Room
-        settings.insert_with_parent("grappleMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the Norfair Reserve Tank Room
-        settings.insert_with_parent("norfairReserveMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the Green Bubbles Missile Room
-        settings.insert_with_parent("greenBubblesMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in Bubble Mountain
-        settings.insert_with_parent("bubbleMountainMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in Speed Booster Hall
-        settings.insert_with_parent("speedBoostMissiles", false, "norfairMissiles");
-        // Split on picking up the Wave Missile Pack in Double Chamber
-        settings.insert_with_parent("waveMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the Golden Torizo's Room
-        settings.insert_with_parent("goldTorizoMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the Mickey Mouse Room
-        settings.insert_with_parent("mickeyMouseMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the Lower Norfair Springball Maze Room
-        settings.insert_with_parent("lowerNorfairSpringMazeMissiles", false, "norfairMissiles");
-        // Split on picking up the Missile Pack in the The Musketeers' Room
-        settings.insert_with_parent("threeMusketeersMissiles", false, "norfairMissiles");
-        // Split on Wrecked Ship Missile Pack locations
-        settings.insert_with_parent("wreckedShipMissiles", false, "specificMissiles");
-        // Split on picking up the Missile Pack in Wrecked Ship Main Shaft
-        settings.insert_with_parent("wreckedShipMainShaftMissiles", false, "wreckedShipMissiles");
-        // Split on picking up the Missile Pack in Bowling Alley
-        settings.insert_with_parent("bowlingMissiles", false, "wreckedShipMissiles");
-        // Split on picking up the Missile Pack in the Wrecked Ship East Missile Room
-        settings.insert_with_parent("atticMissiles", false, "wreckedShipMissiles");
-        // Split on Maridia Missile Pack locations
-        settings.insert_with_parent("maridiaMissiles", false, "specificMissiles");
-        // Split on picking up the Missile Pack in Main Street
-        settings.insert_with_parent("mainStreetMissiles", false, "maridiaMissiles");
-        // Split on picking up the Missile Pack in the Mama Turtle Room
-        settings.insert_with_parent("mamaTurtleMissiles", false, "maridiaMissiles");
-        // Split on picking up the Missile Pack in Watering Hole
-        settings.insert_with_parent("wateringHoleMissiles", false, "maridiaMissiles");
-        // Split on picking up the Missile Pack in the Pseudo Plasma Spark Room
-        settings.insert_with_parent("beachMissiles", false, "maridiaMissiles");
-        // Split on picking up the Missile Pack in West Sand Hole
-        settings.insert_with_parent("leftSandPitMissiles", false, "maridiaMissiles");
-        // Split on picking up the Missile Pack in East Sand Hole
-        settings.insert_with_parent("rightSandPitMissiles", false, "maridiaMissiles");
-        // Split on picking up the Missile Pack in Aqueduct
-        settings.insert_with_parent("aqueductMissiles", false, "maridiaMissiles");
-        // Split on picking up the Missile Pack in The Precious Room
-        settings.insert_with_parent("preDraygonMissiles", false, "maridiaMissiles");
-        // Split on the first Super Missile pickup
-        settings.insert_with_parent("firstSuper", false, "ammoPickups");
-        // Split on each Super Missile upgrade
-        settings.insert_with_parent("allSupers", false, "ammoPickups");
-        // Split on specific Super Missile Pack locations
-        settings.insert_with_parent("specificSupers", false, "ammoPickups");
-        // Split on picking up the Super Missile Pack in the Crateria Super Room
-        settings.insert_with_parent("climbSupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in the Spore Spawn Super Room (NOTE: SSTRA splits when the dialogue box disappears, not on touch. Use Spore Spawn RTA Finish for SSTRA runs.)
-        settings.insert_with_parent("sporeSpawnSupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in the Early Supers Room
-        settings.insert_with_parent("earlySupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in the Etecoon Super Room
-        settings.insert_with_parent("etecoonSupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in the Golden Torizo's Room
-        settings.insert_with_parent("goldTorizoSupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in the Wrecked Ship West Super Room
-        settings.insert_with_parent("wreckedShipLeftSupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in the Wrecked Ship East Super Room
-        settings.insert_with_parent("wreckedShipRightSupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in Main Street
-        settings.insert_with_parent("crabSupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in Watering Hole
-        settings.insert_with_parent("wateringHoleSupers", false, "specificSupers");
-        // Split on picking up the Super Missile Pack in Aqueduct
-        settings.insert_with_parent("aqueductSupers", false, "specificSupers");
-        // Split on the first Power Bomb pickup
-        settings.insert_with_parent("firstPowerBomb", true, "ammoPickups");
-        // Split on each Power Bomb upgrade
-        settings.insert_with_parent("allPowerBombs", false, "ammoPickups");
-        // Split on specific Power Bomb Pack locations
-        settings.insert_with_parent("specificBombs", false, "ammoPickups");
-        // Split on picking up the Power Bomb Pack in the Crateria Power Bomb Room
-        settings.insert_with_parent("landingSiteBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in the Etecoon Room section of Green Brinstar Main Shaft
-        settings.insert_with_parent("etecoonBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in the Pink Brinstar Power Bomb Room
-        settings.insert_with_parent("pinkBrinstarBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in the Morph Ball Room
-        settings.insert_with_parent("blueBrinstarBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in the Alpha Power Bomb Room
-        settings.insert_with_parent("alphaBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in the Beta Power Bomb Room
-        settings.insert_with_parent("betaBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in the Post Crocomire Power Bomb Room
-        settings.insert_with_parent("crocomireBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in the Lower Norfair Escape Power Bomb Room
-        settings.insert_with_parent("lowerNorfairEscapeBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in Wasteland
-        settings.insert_with_parent("shameBombs", false, "specificBombs");
-        // Split on picking up the Power Bomb Pack in East Sand Hall
-        settings.insert_with_parent("rightSandPitBombs", false, "specificBombs");
-
-        // Split on Varia and Gravity pickups
-        settings.insert("suitUpgrades", true);
-        // Split on picking up the Varia Suit
-        settings.insert_with_parent("variaSuit", true, "suitUpgrades");
-        // Split on picking up the Gravity Suit
-        settings.insert_with_parent("gravSuit", true, "suitUpgrades");
-
-        // Split on beam upgrades
-        settings.insert("beamUpgrades", true);
-        // Split on picking up the Charge Beam
-        settings.insert_with_parent("chargeBeam", false, "beamUpgrades");
-        // Split on picking up the Spazer
-        settings.insert_with_parent("spazer", false, "beamUpgrades");
-        // Split on picking up the Wave Beam
-        settings.insert_with_parent("wave", true, "beamUpgrades");
-        // Split on picking up the Ice Beam
-        settings.insert_with_parent("ice", false, "beamUpgrades");
-        // Split on picking up the Plasma Beam
-        settings.insert_with_parent("plasma", false, "beamUpgrades");
-
-        // Split on boot upgrades
-        settings.insert("bootUpgrades", false);
-        // Split on picking up the Hi-Jump Boots
-        settings.insert_with_parent("hiJump", false, "bootUpgrades");
-        // Split on picking up Space Jump
-        settings.insert_with_parent("spaceJump", false, "bootUpgrades");
-        // Split on picking up the Speed Booster
-        settings.insert_with_parent("speedBooster", false, "bootUpgrades");
-
-        // Split on Energy Tanks and Reserve Tanks
-        settings.insert("energyUpgrades", false);
-        // Split on picking up the first Energy Tank
-        settings.insert_with_parent("firstETank", false, "energyUpgrades");
-        // Split on picking up each Energy Tank
-        settings.insert_with_parent("allETanks", false, "energyUpgrades");
-        // Split on specific Energy Tank locations
-        settings.insert_with_parent("specificETanks", false, "energyUpgrades");
-        // Split on picking up the Energy Tank in the Gauntlet Energy Tank Room
-        settings.insert_with_parent("gauntletETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Terminator Room
-        settings.insert_with_parent("terminatorETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Blue Brinstar Energy Tank Room
-        settings.insert_with_parent("ceilingETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Etecoon Energy Tank Room
-        settings.insert_with_parent("etecoonsETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in Waterway
-        settings.insert_with_parent("waterwayETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Hopper Energy Tank Room
-        settings.insert_with_parent("waveGateETank", false, "specificETanks");
-        // Split on picking up the Kraid Energy Tank in the Warehouse Energy Tank Room
-        settings.insert_with_parent("kraidETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in Crocomire's Room
-        settings.insert_with_parent("crocomireETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Hi Jump Energy Tank Room
-        settings.insert_with_parent("hiJumpETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Ridley Tank Room
-        settings.insert_with_parent("ridleyETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Lower Norfair Fireflea Room
-        settings.insert_with_parent("firefleaETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Wrecked Ship Energy Tank Room
-        settings.insert_with_parent("wreckedShipETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Mama Turtle Room
-        settings.insert_with_parent("tatoriETank", false, "specificETanks");
-        // Split on picking up the Energy Tank in the Botwoon Energy Tank Room
-        settings.insert_with_parent("botwoonETank", false, "specificETanks");
-        // Split on picking up each Reserve Tank
-        settings.insert_with_parent("reserveTanks", false, "energyUpgrades");
-        // Split on specific Reserve Tank locations
-        settings.insert_with_parent("specificRTanks", false, "energyUpgrades");
-        // Split on picking up the Reserve Tank in the Brinstar Reserve Tank Room
-        settings.insert_with_parent("brinstarReserve", false, "specificRTanks");
-        // Split on picking up the Reserve Tank in the Norfair Reserve Tank Room
-        settings.insert_with_parent("norfairReserve", false, "specificRTanks");
-        // Split on picking up the Reserve Tank in Bowling Alley
-        settings.insert_with_parent("wreckedShipReserve", false, "specificRTanks");
-        // Split on picking up the Reserve Tank in West Sand Hole
-        settings.insert_with_parent("maridiaReserve", false, "specificRTanks");
-
-        // Split on the miscellaneous upgrades
-        settings.insert("miscUpgrades", false);
-        // Split on picking up the Morphing Ball
-        settings.insert_with_parent("morphBall", false, "miscUpgrades");
-        // Split on picking up the Bomb
-        settings.insert_with_parent("bomb", false, "miscUpgrades");
-        // Split on picking up the Spring Ball
-        settings.insert_with_parent("springBall", false, "miscUpgrades");
-        // Split on picking up the Screw Attack
-        settings.insert_with_parent("screwAttack", false, "miscUpgrades");
-        // Split on picking up the Grapple Beam
-        settings.insert_with_parent("grapple", false, "miscUpgrades");
-        // Split on picking up the X-Ray Scope
-        settings.insert_with_parent("xray", false, "miscUpgrades");
-
-        // Split on transitions between areas
-        settings.insert("areaTransitions", true);
-        // Split on entering miniboss rooms (except Bomb Torizo)
-        settings.insert_with_parent("miniBossRooms", false, "areaTransitions");
-        // Split on entering major boss rooms
-        settings.insert_with_parent("bossRooms", false, "areaTransitions");
-        // Split on elevator transitions between areas (except Statue Room to Tourian)
-        settings.insert_with_parent("elevatorTransitions", false, "areaTransitions");
-        // Split on leaving Ceres Station
-        settings.insert_with_parent("ceresEscape", false, "areaTransitions");
-        // Split on entering the Wrecked Ship Entrance from the lower door of West Ocean
-        settings.insert_with_parent("wreckedShipEntrance", false, "areaTransitions");
-        // Split on entering Red Tower from Noob Bridge
-        settings.insert_with_parent("redTowerMiddleEntrance", false, "areaTransitions");
-        // Split on entering Red Tower from Skree Boost room
-        settings.insert_with_parent("redTowerBottomEntrance", false, "areaTransitions");
-        // Split on entering Bat Room from Red Tower
-        settings.insert_with_parent("redTower->batRoom", false, "areaTransitions");
-        // Split on entering Kraid's Lair
-        settings.insert_with_parent("kraidsLair", false, "areaTransitions");
-        // Split on entering Rising Tide from Cathedral
-        settings.insert_with_parent("risingTideEntrance", false, "areaTransitions");
-        // Split on entering Business center from frog savestation
-        settings.insert_with_parent("frogSavestation->businessCenter", false, "areaTransitions");
-        // Split on exiting Attic
-        settings.insert_with_parent("atticExit", false, "areaTransitions");
-        // Split on blowing up the tube to enter Maridia
-        settings.insert_with_parent("tubeBroken", false, "areaTransitions");
-        // Split on entering mount everest from fish tank
-        settings.insert_with_parent("fishTank->mountEverest", false, "areaTransitions");
-        // Split on entering Aqueduct from crab shaft
-        settings.insert_with_parent("crabShaft->aqueduct", false, "areaTransitions");
-        // Split on exiting West Cacattack Alley
-        settings.insert_with_parent("cacExit", false, "areaTransitions");
-        // Split on entering Toilet Bowl from either direction
-        settings.insert_with_parent("toilet", false, "areaTransitions");
-        // Split on entering Kronic Boost room
-        settings.insert_with_parent("kronicBoost", false, "areaTransitions");
-        // Split on the elevator down to Lower Norfair
-        settings.insert_with_parent("lowerNorfairEntrance", false, "areaTransitions");
-        // Split on entering Worst Room in the Game
-        settings.insert_with_parent("writg", false, "areaTransitions");
-        // Split on entering Red Kihunter Shaft from either Amphitheatre or Wastelands (NOTE: will split twice)
-        settings.insert_with_parent("redKiShaft", false, "areaTransitions");
-        // Split on entering Metal Pirates Room from Wasteland
-        settings.insert_with_parent("metalPirates", false, "areaTransitions");
-        // Split on entering Lower Norfair Springball Maze Room
-        settings.insert_with_parent("lowerNorfairSpringMaze", false, "areaTransitions");
-        // Split on moving from the Three Musketeers' Room to the Single Chamber
-        settings.insert_with_parent("lowerNorfairExit", false, "areaTransitions");
-        // Split on entering the Statues Room with all four major bosses defeated
-        settings.insert_with_parent("goldenFour", true, "areaTransitions");
-        // Split on the elevator down to Tourian
-        settings.insert_with_parent("tourianEntrance", false, "areaTransitions");
-        // Split on exiting each of the Metroid rooms in Tourian
-        settings.insert_with_parent("metroids", false, "areaTransitions");
-        // Split on moving from the Dust Torizo Room to the Big Boy Room
-        settings.insert_with_parent("babyMetroidRoom", false, "areaTransitions");
-        // Split on moving from Tourian Escape Room 4 to The Climb
-        settings.insert_with_parent("escapeClimb", false, "areaTransitions");
-
-        // Split on defeating minibosses
-        settings.insert("miniBosses", false);
-        // Split on starting the Ceres Escape
-        settings.insert_with_parent("ceresRidley", false, "miniBosses");
-        // Split on Bomb Torizo's drops appearing
-        settings.insert_with_parent("bombTorizo", false, "miniBosses");
-        // Split on the last hit to Spore Spawn
-        settings.insert_with_parent("sporeSpawn", false, "miniBosses");
-        // Split on Crocomire's drops appearing
-        settings.insert_with_parent("crocomire", false, "miniBosses");
-        // Split on Botwoon's vertical column being fully destroyed
-        settings.insert_with_parent("botwoon", false, "miniBosses");
-        // Split on Golden Torizo's drops appearing
-        settings.insert_with_parent("goldenTorizo", false, "miniBosses");
-
-        // Split on defeating major bosses
-        settings.insert("bosses", true);
-        // Split shortly after Kraid's drops appear
-        settings.insert_with_parent("kraid", false, "bosses");
-        // Split on Phantoon's drops appearing
-        settings.insert_with_parent("phantoon", false, "bosses");
-        // Split on Draygon's drops appearing
-        settings.insert_with_parent("draygon", false, "bosses");
-        // Split on Ridley's drops appearing
-        settings.insert_with_parent("ridley", true, "bosses");
-        // Split on Mother Brain's head hitting the ground at the end of the first phase
-        settings.insert_with_parent("mb1", false, "bosses");
-        // Split on Mother Brain reaching 0hp in the first phase
-        settings.insert_with_parent("mb1_0hp", false, "bosses");
-        // Split on the Baby Metroid detaching from Mother Brain's head
-        settings.insert_with_parent("mb2", true, "bosses");
-        // Split on the start of the Zebes Escape
-        settings.insert_with_parent("mb3", false, "bosses");
-
-        // Split on facing forward at the end of Zebes Escape
-        settings.insert("rtaFinish", true);
-        // Split on In-Game Time finalizing, when the end cutscene starts
-        settings.insert("igtFinish", false);
-        // Split on the end of a Spore Spawn RTA run, when the text box clears after collecting the Super Missiles
-        settings.insert("sporeSpawnRTAFinish", false);
-        // Split on the end of a 100 Missile RTA run, when the text box clears after collecting the hundredth missile
-        settings.insert("hundredMissileRTAFinish", false);
+        for &(key, value, parent, _, _) in SUPER_METROID_SETTINGS {
+            match parent {
+                Some(parent) => settings.insert_with_parent(key, value, parent),
+                None => settings.insert(key, value),
+            }
+        }
         settings.modified_after_creation = false;
         settings
     }
 
     fn insert(&mut self, name: &str, value: bool) {
         self.modified_after_creation = true;
+        self.revision = next_revision();
         self.data.insert(name.into(), (value, None));
     }
 
     fn insert_with_parent(&mut self, name: &str, value: bool, parent: &str) {
         self.modified_after_creation = true;
+        self.revision = next_revision();
         self.data
             .insert(name.into(), (value, Some(parent.to_owned())));
     }
 
-    #[allow(dead_code)]
-    fn contains(&self, var: &str) -> bool {
+    pub fn contains(&self, var: &str) -> bool {
         self.data.contains_key(var)
     }
 
+    /// Adds a setting declared by a script, such as an ASL `settings.Add`,
+    /// unless it's already there, and labels it. The saved value wins over
+    /// the script's default, and declaring doesn't count as a modification.
+    pub fn declare(&mut self, name: &str, value: bool, parent: Option<&str>, label: &str) {
+        if !self.contains(name) {
+            self.data
+                .insert(name.into(), (value, parent.map(str::to_owned)));
+            self.revision = next_revision();
+        }
+        self.schema.declare(name, label, None);
+    }
+
+    /// Takes the values and split plan from `saved`, such as a settings
+    /// file, keeping the labels and whatever settings `saved` doesn't
+    /// have, like those a loaded script declared.
+    pub fn load(&mut self, saved: Settings) {
+        let mut data = saved.data;
+        for (key, entry) in self.data.drain() {
+            data.entry(key).or_insert(entry);
+        }
+        self.data = data;
+        self.plan = saved.plan;
+        self.modified_after_creation = saved.modified_after_creation;
+        self.revision = next_revision();
+    }
+
+    /// Adds the settings `live` has and these don't, with their labels,
+    /// such as ones a script declared after these were copied from it.
+    pub fn adopt_new(&mut self, live: &Settings) {
+        for (key, entry) in live.data.iter() {
+            if !self.data.contains_key(key) {
+                self.data.insert(key.clone(), entry.clone());
+                self.revision = next_revision();
+            }
+            if let (None, Some(schema)) = (self.schema.get(key), live.schema.get(key)) {
+                self.schema
+                    .declare(key, &schema.label, schema.tooltip.as_deref());
+            }
+        }
+    }
+
+    pub fn set_tooltip(&mut self, name: &str, tooltip: &str) {
+        let label = self.label(name).to_owned();
+        self.schema.declare(name, &label, Some(tooltip));
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// The label to show for `var`, or `var` itself if it has none.
+    pub fn label<'a>(&'a self, var: &'a str) -> &'a str {
+        self.schema.get(var).map_or(var, |s| s.label.as_str())
+    }
+
+    pub fn tooltip(&self, var: &str) -> Option<&str> {
+        self.schema.get(var)?.tooltip.as_deref()
+    }
+
     pub fn get(&self, var: &str) -> bool {
+        if let Some(reads) = &self.reads {
+            reads.lock().insert(var.to_owned());
+        }
         match self.data.get(var) {
             None => false,
             Some((b, None)) => *b,
@@ -665,9 +715,21 @@ impl Settings {
             Some((_, x)) => (value, x.clone()),
         };
         self.data.insert(std::sync::Arc::from(var), val);
+        self.revision = next_revision();
     }
 
-    /// The keys which have no parent defined
+    /// Every key `f` asks a copy of these settings for with `get`,
+    /// including parents and keys that don't exist, such as misspellings.
+    pub fn keys_read_by(&self, f: impl FnOnce(&Settings)) -> BTreeSet<String> {
+        let reads = Arc::new(Mutex::new(BTreeSet::new()));
+        let mut recording = self.clone();
+        recording.reads = Some(reads.clone());
+        f(&recording);
+        let keys = reads.lock().clone();
+        keys
+    }
+
+    /// The keys which have no parent defined, in declaration order
     pub fn roots(&self) -> Vec<String> {
         let mut rs = vec![];
         for (key, (_, parent)) in self.data.iter() {
@@ -675,10 +737,11 @@ impl Settings {
                 rs.push(key.to_string());
             }
         }
+        self.schema.sort(&mut rs);
         rs
     }
 
-    /// The immediate childern (if any)
+    /// The immediate childern (if any), in declaration order
     pub fn children(&self, key: &str) -> Vec<String> {
         let mut rs = vec![];
         for (k, (_, parent)) in self.data.iter() {
@@ -688,6 +751,7 @@ impl Settings {
                 }
             }
         }
+        self.schema.sort(&mut rs);
         rs
     }
 
@@ -702,6 +766,7 @@ impl Settings {
         // TODO: this is a conservative overapproximation. We don't actually
         // know if the caller wrote to the &mut bool we gave them.
         self.modified_after_creation = true;
+        self.revision = next_revision();
         match self.data.get_mut(var) {
             None => panic!(),
             Some((b, _)) => b,
@@ -712,6 +777,99 @@ impl Settings {
         self.modified_after_creation
     }
 
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Every key, sorted, for choosing split conditions.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.data.keys().map(|k| k.to_string()).collect();
+        keys.sort();
+        keys
+    }
+
+    pub fn plan(&self) -> &SplitPlan {
+        &self.plan
+    }
+
+    pub fn plan_mut(&mut self) -> &mut SplitPlan {
+        self.modified_after_creation = true;
+        self.revision = next_revision();
+        &mut self.plan
+    }
+
+    /// The settings to split with while the timer is on segment `index`.
+    /// In order, that is only the segment's conditions (with whatever
+    /// parents they need); nothing splits when the timer isn't running or
+    /// is past the plan.
+    pub fn for_segment(&self, index: Option<usize>) -> Cow<'_, Settings> {
+        match self.plan.order {
+            SplitOrder::AnyOrder => Cow::Borrowed(self),
+            SplitOrder::InOrder => {
+                let conditions = index.map_or(&[][..], |i| self.plan.conditions(i));
+                Cow::Owned(self.restricted_to(conditions))
+            }
+        }
+    }
+
+    /// The enabled setting that `splits` holds for when it's the only one
+    /// on, for naming a split after the fact. When a group and its
+    /// children would all do, the deepest wins, then the first declared.
+    fn responsible(&self, mut splits: impl FnMut(&Settings) -> bool) -> Option<String> {
+        let depth = |key: &str| {
+            let mut depth = 0;
+            let mut parent = self.data.get(key).and_then(|(_, parent)| parent.as_deref());
+            while let Some(p) = parent {
+                depth += 1;
+                parent = self.data.get(p).and_then(|(_, parent)| parent.as_deref());
+            }
+            depth
+        };
+        let mut keys: Vec<String> = self
+            .data
+            .keys()
+            .filter(|key| self.get(key))
+            .map(|key| key.to_string())
+            .collect();
+        self.schema.sort(&mut keys);
+        keys.into_iter()
+            .filter(|key| splits(&self.restricted_to(std::slice::from_ref(key))))
+            .rev()
+            .max_by_key(|key| depth(key))
+    }
+
+    /// A copy with everything off except `keys`, their parents, and the
+    /// children of `keys` that are enabled here.
+    fn restricted_to(&self, keys: &[String]) -> Settings {
+        let under_a_key = |key: &str| {
+            let mut key = Some(key);
+            while let Some(k) = key {
+                if keys.iter().any(|c| c == k) {
+                    return true;
+                }
+                key = self.data.get(k).and_then(|(_, parent)| parent.as_deref());
+            }
+            false
+        };
+        let mut restricted = self.clone();
+        for (key, (value, _)) in restricted.data.iter_mut() {
+            *value = *value && under_a_key(key);
+        }
+        for key in keys {
+            let mut key = Some(key.clone());
+            while let Some(k) = key {
+                key = match restricted.data.get_mut(k.as_str()) {
+                    Some((value, parent)) => {
+                        *value = true;
+                        parent.clone()
+                    }
+                    None => None,
+                };
+            }
+        }
+        restricted
+    }
+
     pub fn split_on_misc_upgrades(&mut self) {
         self.set("miscUpgrades", true);
         self.set("morphBall", true);
@@ -1642,24 +1800,46 @@ pub enum Width {
     Word,
 }
 
+impl Width {
+    pub fn bytes(self) -> usize {
+        match self {
+            Width::Byte => 1,
+            Width::Word => 2,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct MemoryWatcher {
+    /// Offset from the start of `domain`.
     pub address: u32,
     pub current: u32,
     pub old: u32,
+    pub domain: MemoryDomain,
     width: Width,
 }
 
 impl MemoryWatcher {
+    /// Watches WRAM.
     pub fn new(address: u32, width: Width) -> MemoryWatcher {
+        MemoryWatcher::in_domain(MemoryDomain::Wram, address, width)
+    }
+
+    pub fn in_domain(domain: MemoryDomain, address: u32, width: Width) -> MemoryWatcher {
         MemoryWatcher {
             address,
             current: 0,
             old: 0,
+            domain,
             width,
         }
     }
 
+    /// The offset just past the watched bytes.
+    pub fn end(&self) -> usize {
+        self.address as usize + self.width.bytes()
+    }
+
     pub fn update_value(&mut self, memory: &[u8]) {
         match self.width {
             Width::Byte => {
@@ -1676,29 +1856,82 @@ impl MemoryWatcher {
     }
 }
 
+/// Bitfields whose bits only get set during a run: upgrades, bosses
+/// beaten, and items collected.
+const FLAG_WATCHERS: [&str; 26] = [
+    "unlockedEquips2",
+    "unlockedEquips",
+    "unlockedBeams",
+    "unlockedCharge",
+    "crateriaBosses",
+    "brinstarBosses",
+    "norfairBosses",
+    "wreckedShipBosses",
+    "maridiaBosses",
+    "tourianBosses",
+    "ceresBosses",
+    "crateriaItems",
+    "brinteriaItems",
+    "brinstarItems2",
+    "brinstarItems3",
+    "brinstarItems4",
+    "brinstarItems5",
+    "norfairItems1",
+    "norfairItems2",
+    "norfairItems3",
+    "norfairItems4",
+    "norfairItems5",
+    "wreckedShipItems",
+    "maridiaItems1",
+    "maridiaItems2",
+    "maridiaItems3",
+];
+
+/// Capacities that only grow during a run, with what one pickup adds.
+const COUNTER_WATCHERS: [(&str, u32); 5] = [
+    ("maxMissiles", 5),
+    ("maxSupers", 5),
+    ("maxPowerBombs", 5),
+    ("maxEnergy", 100),
+    ("maxReserve", 100),
+];
+
 #[allow(non_snake_case)]
 #[derive(Clone)]
 pub struct SNESState {
     vars: HashMap<&'static str, MemoryWatcher>,
     pickedUpHundredthMissile: bool,
     pickedUpSporeSpawnSuper: bool,
+    /// The segment the run is on, as far as this state knows: set by its
+    /// own starts and splits, and by the timer when it changes otherwise.
+    pub current_split: Option<usize>,
+    /// WRAM, indexed by offset from `$7E:0000`.
     pub data: Vec<u8>,
+    /// Buffers for the other domains, each just long enough for the
+    /// watchers in it.
+    domains: BTreeMap<MemoryDomain, Vec<u8>>,
     // The MemoryWatchers are not in a good
     // state until they've been updated
     // twice, due to having both old and current
     // fields. So the first time we update, we
     // need to do it twice.
     do_extra_update: bool,
+    /// The in-order settings last split with, by settings revision and
+    /// segment, so they're only worked out again when either changes.
+    segment_settings: Option<((u64, Option<usize>), Settings)>,
 }
 
 impl SNESState {
     pub fn new() -> SNESState {
-        let data = vec![0; 0x10000];
+        let data = vec![0; WRAM_SIZE as usize];
         SNESState {
             do_extra_update: true,
+            segment_settings: None,
             data,
+            domains: BTreeMap::new(),
             pickedUpHundredthMissile: false,
             pickedUpSporeSpawnSuper: false,
+            current_split: None,
             vars: HashMap::from([
                 // Word
                 ("controller", MemoryWatcher::new(0x008B, Width::Word)),
@@ -1751,25 +1984,58 @@ impl SNESState {
         }
     }
 
+    /// Adds (or replaces) a watcher, growing its domain's buffer to fit.
+    pub fn watch(&mut self, name: &'static str, watcher: MemoryWatcher) {
+        if watcher.domain != MemoryDomain::Wram {
+            let memory = self.domains.entry(watcher.domain).or_default();
+            if memory.len() < watcher.end() {
+                memory.resize(watcher.end(), 0);
+            }
+        }
+        self.vars.insert(name, watcher);
+    }
+
+    /// The buffer for `domain`, which is empty if nothing watches it.
+    pub fn memory(&self, domain: MemoryDomain) -> &[u8] {
+        match domain {
+            MemoryDomain::Wram => &self.data,
+            _ => self.domains.get(&domain).map_or(&[], Vec::as_slice),
+        }
+    }
+
+    pub fn memory_mut(&mut self, domain: MemoryDomain) -> &mut [u8] {
+        match domain {
+            MemoryDomain::Wram => &mut self.data,
+            _ => self
+                .domains
+                .get_mut(&domain)
+                .map_or(&mut [], Vec::as_mut_slice),
+        }
+    }
+
     pub fn update(&mut self) {
         for watcher in self.vars.iter_mut() {
+            let memory = match watcher.1.domain {
+                MemoryDomain::Wram => &self.data,
+                domain => &self.domains[&domain],
+            };
             if self.do_extra_update {
-                watcher.1.update_value(&self.data);
-                self.do_extra_update = false;
+                watcher.1.update_value(memory);
             }
-            watcher.1.update_value(&self.data);
+            watcher.1.update_value(memory);
         }
+        self.do_extra_update = false;
     }
 
     pub fn fetch_all(
         &mut self,
-        client: &mut crate::usb2snes::SyncClient,
+        client: &mut dyn MemorySource,
         settings: &Settings,
     ) -> Result<SNESSummary> {
-        // We're only allowed to read 8 regions in a single request
-        // This is currently 8 regions, so things will need to be
-        // merged if you want to add more. That increases read latency.
-        // Basically, we should avoid adding anything more to this.
+        // The client merges these and splits them across requests as
+        // needed. This list fits in one usb2snes request (8 regions of at
+        // most 255 bytes); going past that costs an extra round trip per
+        // poll, so keep it tight.
         let regions = [
             (0x008B, 2),  // Controller 1 Input
             (0x079B, 5),  // ROOM ID + ROOM # for region + Region Number
@@ -1780,16 +2046,51 @@ impl SNESState {
             (0x178C, 2),  // MB Phase hack
             (0xD821, 99), // Event array + Items collected
         ];
-        let snes_data =
-            client.get_addresses(&regions.map(|(base, size)| (0xF50000 + base, size)))?;
-        for ((base, size), snes_data) in regions.into_iter().zip(snes_data.iter()) {
+        let mut reads: Vec<(MemoryDomain, u32, usize)> = regions
+            .into_iter()
+            .map(|(base, size)| (MemoryDomain::Wram, base, size))
+            .collect();
+        // Watchers outside WRAM are read individually; the client merges
+        // neighbours. Sorted so each poll asks for the same thing.
+        let mut others: Vec<(MemoryDomain, u32, usize)> = self
+            .vars
+            .values()
+            .filter(|w| w.domain != MemoryDomain::Wram)
+            .map(|w| (w.domain, w.address, w.width.bytes()))
+            .collect();
+        others.sort_unstable();
+        others.dedup();
+        reads.extend(others);
+        let snes_data = read_domains(client, &reads)?;
+        for ((domain, base, size), snes_data) in reads.into_iter().zip(snes_data.iter()) {
             let base = base as usize;
-            self.data[base..base + size].copy_from_slice(&snes_data[..size]);
+            self.memory_mut(domain)[base..base + size].copy_from_slice(&snes_data[..size]);
         }
         self.update();
         let start = self.start();
         let reset = self.reset();
-        let split = split(settings, self);
+        let split = match settings.plan().order {
+            SplitOrder::AnyOrder => split(settings, self),
+            SplitOrder::InOrder => {
+                let key = (settings.revision(), self.current_split);
+                let segment = match self.segment_settings.take() {
+                    Some((cached, segment)) if cached == key => segment,
+                    _ => settings.for_segment(self.current_split).into_owned(),
+                };
+                let split = split(&segment, self);
+                self.segment_settings = Some((key, segment));
+                split
+            }
+        };
+        if start && self.current_split.is_none() {
+            self.current_split = Some(0);
+        }
+        if reset {
+            self.current_split = None;
+        }
+        if split {
+            self.current_split = self.current_split.map(|i| i + 1);
+        }
         Ok(SNESSummary {
             start,
             reset,
@@ -1820,6 +2121,160 @@ impl SNESState {
         let gametime = hours + minutes + seconds;
         TimeSpan::from_seconds(gametime.as_seconds_f64())
     }
+
+    /// The current values of the flag and counter watchers.
+    pub fn progress(&self) -> Progress {
+        let names = FLAG_WATCHERS
+            .into_iter()
+            .chain(COUNTER_WATCHERS.into_iter().map(|(name, _)| name));
+        Progress(names.map(|name| (name, self[name].current)).collect())
+    }
+
+    /// Splits that happened between `before` and the current state, each
+    /// named after the setting that split.
+    ///
+    /// Each newly set flag bit is one event, checked against the split
+    /// rules on its own. Most item and boss rules also check the room the
+    /// item is in, which can't be known after the fact, so each event is
+    /// tried in every room; only the room it happened in can match. That
+    /// assumes items are where the vanilla game puts them: an upgrade found
+    /// elsewhere, as in a randomizer, is caught up as if it were in its own
+    /// room. The room is held still while probing, so splits on entering a
+    /// room are never caught up, even when the route passed through it.
+    /// Ammo and tank counters are checked a pickup at a time, but only
+    /// count for pickups that didn't split on their own, since live both
+    /// change on the same frame and split once. When splitting in order,
+    /// each event is checked against the segment after the splits found so
+    /// far, which is only right if they happened in the order they are
+    /// checked.
+    pub fn missed_splits(&self, before: &Progress, settings: &Settings) -> Vec<MissedSplit> {
+        let now = self.progress();
+        let went_back = before.0.iter().any(|(name, &old)| {
+            let new = now.0.get(name).copied().unwrap_or(old);
+            if FLAG_WATCHERS.contains(name) {
+                old & !new != 0
+            } else {
+                new < old
+            }
+        });
+        if went_back {
+            return vec![];
+        }
+        let game_time = Some(self.gametime_to_seconds());
+        let missed = |reason: String| MissedSplit { reason, game_time };
+
+        let mut probe = self.clone();
+        for watcher in probe.vars.values_mut() {
+            watcher.old = watcher.current;
+        }
+        let here = self["roomID"].current;
+        let mut rooms: Vec<u32> = roomIDEnum.values().copied().collect();
+        rooms.sort_unstable();
+        rooms.dedup();
+        rooms.retain(|&room| room != here);
+        rooms.insert(0, here);
+
+        let mut splits = vec![];
+        let mut unsplit_pickups = 0;
+        for name in FLAG_WATCHERS {
+            let old = before.0.get(name).copied().unwrap_or(now.0[name]);
+            let new = now.0[name];
+            let mut value = old;
+            for bit in (0..32)
+                .map(|b| 1u32 << b)
+                .filter(|bit| new & !old & bit != 0)
+            {
+                let settings = settings.for_segment(self.segment_after(splits.len()));
+                if let Some(room) = probe.splits_on(&settings, name, value, value | bit, &rooms) {
+                    let label = probe.split_label(&settings, name, value, value | bit, room);
+                    splits.push(missed(label));
+                } else if name.contains("Items") {
+                    unsplit_pickups += 1;
+                }
+                value |= bit;
+            }
+        }
+        let mut counter_splits = vec![];
+        for (name, pickup) in COUNTER_WATCHERS {
+            let old = before.0.get(name).copied().unwrap_or(now.0[name]);
+            let new = now.0[name];
+            let mut value = old;
+            while value < new {
+                let next = if value + pickup <= new {
+                    value + pickup
+                } else {
+                    new
+                };
+                let found = splits.len() + counter_splits.len();
+                let settings = settings.for_segment(self.segment_after(found));
+                if probe
+                    .splits_on(&settings, name, value, next, &[here])
+                    .is_some()
+                {
+                    let label = probe.split_label(&settings, name, value, next, here);
+                    counter_splits.push(missed(label));
+                }
+                value = next;
+            }
+        }
+        splits.extend(counter_splits.into_iter().take(unsplit_pickups));
+        splits
+    }
+
+    /// The segment `splits` splits after the current one.
+    fn segment_after(&self, splits: usize) -> Option<usize> {
+        self.current_split.map(|i| i + splits)
+    }
+
+    /// The first of `rooms` in which `name` changing from `old` to `new`
+    /// splits, with every other watcher held still. Leaves `name` at `new`.
+    fn splits_on(
+        &mut self,
+        settings: &Settings,
+        name: &'static str,
+        old: u32,
+        new: u32,
+        rooms: &[u32],
+    ) -> Option<u32> {
+        let saved = self["roomID"].current;
+        let mut fired = None;
+        for &room in rooms {
+            let watcher = self.vars.get_mut(name).expect("progress watchers exist");
+            watcher.old = old;
+            watcher.current = new;
+            let room_id = self.vars.get_mut("roomID").expect("roomID exists");
+            room_id.old = room;
+            room_id.current = room;
+            let splits = split(settings, self);
+            self.pickedUpHundredthMissile = false;
+            self.pickedUpSporeSpawnSuper = false;
+            if splits {
+                fired = Some(room);
+                break;
+            }
+        }
+        let watcher = self.vars.get_mut(name).expect("progress watchers exist");
+        watcher.old = new;
+        let room_id = self.vars.get_mut("roomID").expect("roomID exists");
+        room_id.old = saved;
+        room_id.current = saved;
+        fired
+    }
+
+    /// The label of the setting that splits on `name` changing from `old`
+    /// to `new` in `room`, falling back to the watcher's name.
+    fn split_label(
+        &mut self,
+        settings: &Settings,
+        name: &'static str,
+        old: u32,
+        new: u32,
+        room: u32,
+    ) -> String {
+        settings
+            .responsible(|only| self.splits_on(only, name, old, new, &[room]).is_some())
+            .map_or_else(|| name.to_owned(), |key| settings.label(&key).to_owned())
+    }
 }
 
 impl Default for SNESState {
@@ -1855,7 +2310,7 @@ impl SuperMetroidAutoSplitter {
 impl AutoSplitter for SuperMetroidAutoSplitter {
     fn update(
         &mut self,
-        client: &mut crate::usb2snes::SyncClient,
+        client: &mut dyn MemorySource,
     ) -> Result<crate::autosplitters::SNESSummary> {
         self.snes.fetch_all(client, &self.settings.read())
     }
@@ -1867,4 +2322,16 @@ impl AutoSplitter for SuperMetroidAutoSplitter {
     fn reset_game_tracking(&mut self) {
         self.snes = SNESState::new();
     }
+
+    fn set_current_split(&mut self, index: Option<usize>) {
+        self.snes.current_split = index;
+    }
+
+    fn progress(&self) -> Option<Progress> {
+        Some(self.snes.progress())
+    }
+
+    fn missed_splits(&self, before: &Progress) -> Vec<MissedSplit> {
+        self.snes.missed_splits(before, &self.settings.read())
+    }
 }
@@ -0,0 +1,862 @@
+//! Runs autosplitters compiled to WebAssembly, the kind LiveSplit One
+//! loads, against the memory read over usb2snes.
+//!
+//! Modules use the auto-splitting ABI from livesplit-core (the imports the
+//! `asr` crate wraps). usb2snes has no processes, so any process a module
+//! attaches to is the SNES: one memory range, and one module, named like
+//! the process, holding WRAM at [`WRAM_ADDRESS`], where it sits in the SNES
+//! address space. Modules that search an emulator's memory for WRAM won't
+//! find it; read from `WRAM_ADDRESS` instead.
+//!
+//! Each poll fetches everything the module has read before in one request
+//! before it runs. A read of a location that isn't in that request yet
+//! suspends the module while it's sent to the device, and joins the
+//! request from the next poll on. Reads made outside `update`, such as
+//! from `_initialize`, fail.
+//!
+//! Settings added with `user_settings_add_bool` join the autosplitter
+//! settings, under the last title added at a lower heading level. Game time
+//! is passed on at each split; pausing it, skipping and undoing splits, and
+//! timer variables are ignored. Imports that aren't listed here, such as
+//! ones from a newer runtime, return zero.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+use std::path::Path;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll, Waker};
+
+use anyhow::{anyhow, Context, Result};
+use livesplit_core::TimeSpan;
+use parking_lot::{Mutex, RwLock};
+use wasmtime::{
+    Caller, Config, Engine, Extern, ExternType, Linker, Memory, Module, Store, TypedFunc, Val,
+};
+
+use super::supermetroid::Settings;
+use super::{AutoSplitter, SNESSummary};
+use crate::memory_source::{MemoryDomain, MemorySource};
+
+/// Where WRAM is in the process a module attaches to.
+pub const WRAM_ADDRESS: u64 = 0x7E0000;
+
+/// The only process there is.
+const PROCESS: u64 = 1;
+
+/// How many instructions a module may run per poll before it's stopped.
+const FUEL_PER_UPDATE: u64 = 100_000_000;
+
+/// Reads closer together than this are fetched as one region.
+const READ_GAP: usize = 32;
+
+// `MemoryRangeFlags`
+const RANGE_READ: u64 = 1 << 1;
+const RANGE_WRITE: u64 = 1 << 2;
+
+// `TimerState`
+const TIMER_NOT_RUNNING: u32 = 0;
+const TIMER_RUNNING: u32 = 1;
+
+// `SettingValueType`
+const SETTING_BOOL: u32 = 3;
+
+/// A setting or title added by the module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WasmSetting {
+    pub key: String,
+    pub default: bool,
+    pub label: String,
+    pub tooltip: Option<String>,
+    pub parent: Option<String>,
+}
+
+/// A read of WRAM the module is suspended on, handed from `process_read`
+/// to `update`, which has the source to answer it with.
+#[derive(Default)]
+struct PendingRead {
+    /// `(offset, size)`, set by the module.
+    request: Option<(usize, usize)>,
+    /// Set by `update`.
+    response: Option<Result<Vec<u8>>>,
+}
+
+/// Waits for `update` to answer the request in a `PendingRead`.
+struct ReadResponse(Arc<Mutex<PendingRead>>);
+
+impl Future for ReadResponse {
+    type Output = Result<Vec<u8>>;
+
+    fn poll(self: std::pin::Pin<&mut Self>, _: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        match self.0.lock().response.take() {
+            Some(response) => Poll::Ready(response),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// What the module's imports see and change.
+struct Host {
+    settings: Arc<RwLock<Settings>>,
+    module_settings: Vec<WasmSetting>,
+    /// Titles that new settings go under, with their heading levels.
+    headings: Vec<(u32, String)>,
+    wram: Vec<u8>,
+    /// Which bytes of `wram` were read this poll.
+    fresh: Vec<bool>,
+    /// Reads this poll of WRAM that wasn't fetched up front, by offset.
+    missed: Vec<(usize, usize)>,
+    /// The read the module is suspended on, if any.
+    pending: Arc<Mutex<PendingRead>>,
+    /// Set only while `update` runs the module, which is when reads can
+    /// go to the device.
+    polling: bool,
+    /// Why a read the module made couldn't be fetched, to fail the poll
+    /// with once the module returns.
+    source_error: Option<anyhow::Error>,
+    /// The name of the process the module attached to.
+    process: Option<String>,
+    summary: SNESSummary,
+    current_split: Option<usize>,
+    game_time: Option<TimeSpan>,
+    /// Settings maps handed to the module, and values taken from them.
+    maps: HashMap<u64, BTreeMap<String, bool>>,
+    values: HashMap<u64, bool>,
+    next_handle: u64,
+    /// Imports that were called but do nothing here, so each is only
+    /// logged once.
+    ignored: BTreeSet<String>,
+}
+
+impl Host {
+    fn new(settings: Arc<RwLock<Settings>>) -> Self {
+        Host {
+            settings,
+            module_settings: vec![],
+            headings: vec![],
+            wram: vec![0; MemoryDomain::Wram.size() as usize],
+            fresh: vec![false; MemoryDomain::Wram.size() as usize],
+            missed: vec![],
+            pending: Arc::default(),
+            polling: false,
+            source_error: None,
+            process: None,
+            summary: no_summary(),
+            current_split: None,
+            game_time: None,
+            maps: HashMap::new(),
+            values: HashMap::new(),
+            next_handle: 1,
+            ignored: BTreeSet::new(),
+        }
+    }
+
+    /// The offset into WRAM of `len` bytes at `address`, if they're all in
+    /// it.
+    fn wram_offset(&self, address: u64, len: usize) -> Option<usize> {
+        let offset = usize::try_from(address.checked_sub(WRAM_ADDRESS)?).ok()?;
+        (offset.checked_add(len)? <= self.wram.len()).then_some(offset)
+    }
+
+    /// Whether `name` is the module named like the attached process, the
+    /// only one there is.
+    fn is_main_module(&self, name: &str) -> bool {
+        self.process.as_deref() == Some(name)
+    }
+
+    /// Declares a setting, or a title when `heading` is given, and returns
+    /// whether it's on.
+    fn add_setting(
+        &mut self,
+        key: String,
+        label: String,
+        default: bool,
+        heading: Option<u32>,
+    ) -> bool {
+        if let Some(level) = heading {
+            while self.headings.last().is_some_and(|(l, _)| *l >= level) {
+                self.headings.pop();
+            }
+        }
+        let parent = self.headings.last().map(|(_, k)| k.clone());
+        let mut settings = self.settings.write();
+        settings.declare(&key, default, parent.as_deref(), &label);
+        if let Some(level) = heading {
+            self.headings.push((level, key.clone()));
+        }
+        let value = settings.get(&key);
+        self.module_settings.push(WasmSetting {
+            key,
+            default,
+            label,
+            tooltip: None,
+            parent,
+        });
+        value
+    }
+
+    fn handle(&mut self) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        handle
+    }
+
+    fn ignore(&mut self, import: &str) {
+        if self.ignored.insert(import.to_owned()) {
+            tracing::debug!("The WASM autosplitter called {import}, which Annelid ignores");
+        }
+    }
+}
+
+fn no_summary() -> SNESSummary {
+    SNESSummary {
+        start: false,
+        reset: false,
+        split: false,
+    }
+}
+
+fn guest_memory(caller: &mut Caller<'_, Host>) -> Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| anyhow!("the module doesn't export its memory"))
+}
+
+fn guest_bytes(caller: &mut Caller<'_, Host>, ptr: u32, len: u32) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; len as usize];
+    guest_memory(caller)?.read(&*caller, ptr as usize, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn guest_str(caller: &mut Caller<'_, Host>, ptr: u32, len: u32) -> Result<String> {
+    Ok(String::from_utf8(guest_bytes(caller, ptr, len)?)?)
+}
+
+fn write_guest(caller: &mut Caller<'_, Host>, ptr: u32, data: &[u8]) -> Result<()> {
+    guest_memory(caller)?.write(&mut *caller, ptr as usize, data)?;
+    Ok(())
+}
+
+/// The WRAM at `address`, suspending the module to fetch it if it wasn't
+/// this poll, in which case it's also remembered so it's fetched from now
+/// on.
+async fn read_wram(caller: &mut Caller<'_, Host>, address: u64, len: usize) -> Option<Vec<u8>> {
+    let host = caller.data_mut();
+    let offset = host.wram_offset(address, len)?;
+    let range = offset..offset + len;
+    if !host.fresh[range.clone()].iter().all(|&fresh| fresh) {
+        if !host.polling || host.source_error.is_some() {
+            return None;
+        }
+        host.pending.lock().request = Some((offset, len));
+        match ReadResponse(host.pending.clone()).await {
+            Ok(bytes) => {
+                let end = offset + bytes.len();
+                host.wram[offset..end].copy_from_slice(&bytes);
+                host.fresh[offset..end].fill(true);
+                host.missed.push((offset, len));
+            }
+            Err(e) => {
+                host.source_error = Some(e);
+                return None;
+            }
+        }
+    }
+    Some(host.wram[range].to_vec())
+}
+
+/// Writes `text` to the buffer at `ptr` whose length is at `len_ptr`, and
+/// sets that length to how long `text` is. Returns 0 if it didn't fit.
+fn write_guest_str(
+    caller: &mut Caller<'_, Host>,
+    ptr: u32,
+    len_ptr: u32,
+    text: &str,
+) -> Result<u32> {
+    let len = u32::from_le_bytes(
+        guest_bytes(caller, len_ptr, 4)?
+            .try_into()
+            .expect("4 bytes"),
+    );
+    write_guest(caller, len_ptr, &(text.len() as u32).to_le_bytes())?;
+    if text.len() > len as usize {
+        return Ok(0);
+    }
+    write_guest(caller, ptr, text.as_bytes())?;
+    Ok(1)
+}
+
+fn define_imports(linker: &mut Linker<Host>) -> Result<()> {
+    linker.func_wrap(
+        "env",
+        "timer_get_state",
+        |caller: Caller<'_, Host>| match caller.data().current_split {
+            Some(_) => TIMER_RUNNING,
+            None => TIMER_NOT_RUNNING,
+        },
+    )?;
+    linker.func_wrap("env", "timer_start", |mut caller: Caller<'_, Host>| {
+        let host = caller.data_mut();
+        if host.current_split.is_none() {
+            host.summary.start = true;
+            host.current_split = Some(0);
+        }
+    })?;
+    linker.func_wrap("env", "timer_split", |mut caller: Caller<'_, Host>| {
+        let host = caller.data_mut();
+        if let Some(index) = host.current_split {
+            host.summary.split = true;
+            host.current_split = Some(index + 1);
+        }
+    })?;
+    linker.func_wrap("env", "timer_reset", |mut caller: Caller<'_, Host>| {
+        let host = caller.data_mut();
+        if host.current_split.is_some() {
+            host.summary.reset = true;
+            host.current_split = None;
+        }
+    })?;
+    linker.func_wrap(
+        "env",
+        "timer_set_game_time",
+        |mut caller: Caller<'_, Host>, secs: i64, nanos: i32| {
+            caller.data_mut().game_time =
+                Some(TimeSpan::from_seconds(secs as f64 + nanos as f64 / 1e9));
+        },
+    )?;
+    for import in [
+        "timer_skip_split",
+        "timer_undo_split",
+        "timer_pause_game_time",
+        "timer_resume_game_time",
+    ] {
+        linker.func_wrap("env", import, move |mut caller: Caller<'_, Host>| {
+            caller.data_mut().ignore(import);
+        })?;
+    }
+    linker.func_wrap(
+        "env",
+        "timer_set_variable",
+        |mut caller: Caller<'_, Host>, _: u32, _: u32, _: u32, _: u32| {
+            caller.data_mut().ignore("timer_set_variable");
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "process_attach",
+        |mut caller: Caller<'_, Host>, name_ptr: u32, name_len: u32| -> Result<u64> {
+            let name = guest_str(&mut caller, name_ptr, name_len)?;
+            caller.data_mut().process = Some(name);
+            Ok(PROCESS)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_detach",
+        |mut caller: Caller<'_, Host>, _process: u64| {
+            caller.data_mut().process = None;
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_is_open",
+        |caller: Caller<'_, Host>, process: u64| {
+            (process == PROCESS && caller.data().process.is_some()) as u32
+        },
+    )?;
+    linker.func_wrap_async(
+        "env",
+        "process_read",
+        |mut caller: Caller<'_, Host>, (process, address, ptr, len): (u64, u64, u32, u32)| {
+            Box::new(async move {
+                if process != PROCESS {
+                    return Ok(0);
+                }
+                match read_wram(&mut caller, address, len as usize).await {
+                    Some(data) => {
+                        write_guest(&mut caller, ptr, &data)?;
+                        Ok(1)
+                    }
+                    None => Ok(0),
+                }
+            })
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_get_module_address",
+        |mut caller: Caller<'_, Host>, process: u64, name_ptr: u32, name_len: u32| {
+            let name = guest_str(&mut caller, name_ptr, name_len)?;
+            if process == PROCESS && caller.data().is_main_module(&name) {
+                Ok(WRAM_ADDRESS)
+            } else {
+                Ok(0)
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_get_module_size",
+        |mut caller: Caller<'_, Host>, process: u64, name_ptr: u32, name_len: u32| {
+            let name = guest_str(&mut caller, name_ptr, name_len)?;
+            if process == PROCESS && caller.data().is_main_module(&name) {
+                Ok(MemoryDomain::Wram.size() as u64)
+            } else {
+                Ok(0)
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_get_path",
+        |mut caller: Caller<'_, Host>, process: u64, ptr: u32, len_ptr: u32| -> Result<u32> {
+            if process != PROCESS {
+                return Ok(0);
+            }
+            write_guest_str(&mut caller, ptr, len_ptr, "usb2snes")
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_get_memory_range_count",
+        |_: Caller<'_, Host>, process: u64| (process == PROCESS) as u64,
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_get_memory_range_address",
+        |_: Caller<'_, Host>, process: u64, index: u64| {
+            if process == PROCESS && index == 0 {
+                WRAM_ADDRESS
+            } else {
+                0
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_get_memory_range_size",
+        |_: Caller<'_, Host>, process: u64, index: u64| {
+            if process == PROCESS && index == 0 {
+                MemoryDomain::Wram.size() as u64
+            } else {
+                0
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "process_get_memory_range_flags",
+        |_: Caller<'_, Host>, process: u64, index: u64| {
+            if process == PROCESS && index == 0 {
+                RANGE_READ | RANGE_WRITE
+            } else {
+                0
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "runtime_set_tick_rate",
+        |mut caller: Caller<'_, Host>, _ticks_per_second: f64| {
+            // Annelid polls at its own rate.
+            caller.data_mut().ignore("runtime_set_tick_rate");
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "runtime_print_message",
+        |mut caller: Caller<'_, Host>, ptr: u32, len: u32| -> Result<()> {
+            let message = guest_str(&mut caller, ptr, len)?;
+            tracing::info!("WASM autosplitter: {message}");
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "runtime_get_os",
+        |mut caller: Caller<'_, Host>, ptr: u32, len_ptr: u32| {
+            write_guest_str(&mut caller, ptr, len_ptr, std::env::consts::OS)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "runtime_get_arch",
+        |mut caller: Caller<'_, Host>, ptr: u32, len_ptr: u32| {
+            write_guest_str(&mut caller, ptr, len_ptr, std::env::consts::ARCH)
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "user_settings_add_bool",
+        |mut caller: Caller<'_, Host>,
+         key_ptr: u32,
+         key_len: u32,
+         label_ptr: u32,
+         label_len: u32,
+         default: u32|
+         -> Result<u32> {
+            let key = guest_str(&mut caller, key_ptr, key_len)?;
+            let label = guest_str(&mut caller, label_ptr, label_len)?;
+            let value = caller
+                .data_mut()
+                .add_setting(key, label, default != 0, None);
+            Ok(value as u32)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "user_settings_add_title",
+        |mut caller: Caller<'_, Host>,
+         key_ptr: u32,
+         key_len: u32,
+         label_ptr: u32,
+         label_len: u32,
+         heading_level: u32|
+         -> Result<()> {
+            let key = guest_str(&mut caller, key_ptr, key_len)?;
+            let label = guest_str(&mut caller, label_ptr, label_len)?;
+            caller
+                .data_mut()
+                .add_setting(key, label, true, Some(heading_level));
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "user_settings_set_tooltip",
+        |mut caller: Caller<'_, Host>,
+         key_ptr: u32,
+         key_len: u32,
+         ptr: u32,
+         len: u32|
+         -> Result<()> {
+            let key = guest_str(&mut caller, key_ptr, key_len)?;
+            let tooltip = guest_str(&mut caller, ptr, len)?;
+            let host = caller.data_mut();
+            match host.module_settings.iter_mut().find(|s| s.key == key) {
+                Some(setting) => {
+                    host.settings.write().set_tooltip(&key, &tooltip);
+                    setting.tooltip = Some(tooltip);
+                }
+                None => {
+                    tracing::warn!("The WASM autosplitter set a tooltip on unknown setting {key}")
+                }
+            }
+            Ok(())
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "settings_map_load",
+        |mut caller: Caller<'_, Host>| {
+            let host = caller.data_mut();
+            let settings = host.settings.read();
+            let map = host
+                .module_settings
+                .iter()
+                .map(|s| (s.key.clone(), settings.get(&s.key)))
+                .collect();
+            drop(settings);
+            let handle = host.handle();
+            host.maps.insert(handle, map);
+            handle
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "settings_map_free",
+        |mut caller: Caller<'_, Host>, map: u64| {
+            caller.data_mut().maps.remove(&map);
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "settings_map_get",
+        |mut caller: Caller<'_, Host>, map: u64, key_ptr: u32, key_len: u32| -> Result<u64> {
+            let key = guest_str(&mut caller, key_ptr, key_len)?;
+            let host = caller.data_mut();
+            let Some(&value) = host.maps.get(&map).and_then(|m| m.get(&key)) else {
+                return Ok(0);
+            };
+            let handle = host.handle();
+            host.values.insert(handle, value);
+            Ok(handle)
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "setting_value_free",
+        |mut caller: Caller<'_, Host>, value: u64| {
+            caller.data_mut().values.remove(&value);
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "setting_value_get_type",
+        |caller: Caller<'_, Host>, value: u64| {
+            if caller.data().values.contains_key(&value) {
+                SETTING_BOOL
+            } else {
+                0
+            }
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "setting_value_get_bool",
+        |mut caller: Caller<'_, Host>, value: u64, ptr: u32| -> Result<u32> {
+            let Some(&value) = caller.data().values.get(&value) else {
+                return Ok(0);
+            };
+            write_guest(&mut caller, ptr, &[value as u8])?;
+            Ok(1)
+        },
+    )?;
+    Ok(())
+}
+
+/// Stands in for the imports `define_imports` doesn't provide: they do
+/// nothing and return zero, and are logged the first time they're called.
+fn define_unknown_imports(
+    linker: &mut Linker<Host>,
+    store: &mut Store<Host>,
+    module: &Module,
+) -> Result<()> {
+    for import in module.imports() {
+        let ExternType::Func(ty) = import.ty() else {
+            continue;
+        };
+        if linker.get_by_import(&mut *store, &import).is_some() {
+            continue;
+        }
+        let name = format!("{}::{}", import.module(), import.name());
+        let results = ty
+            .results()
+            .map(|ty| Val::default_for_ty(&ty).ok_or_else(|| anyhow!("{name} returns a {ty}")))
+            .collect::<Result<Vec<_>>>()?;
+        linker.func_new(
+            import.module(),
+            import.name(),
+            ty,
+            move |mut caller, _, out| {
+                caller.data_mut().ignore(&name);
+                out.clone_from_slice(&results);
+                Ok(())
+            },
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs `call` to the end on this thread, answering each read it's
+/// suspended on with `fetch`.
+fn run<T>(
+    call: impl Future<Output = T>,
+    pending: &Mutex<PendingRead>,
+    mut fetch: impl FnMut(usize, usize) -> Result<Vec<u8>>,
+) -> Result<T> {
+    let mut call = pin!(call);
+    let mut cx = TaskContext::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(result) = call.as_mut().poll(&mut cx) {
+            return Ok(result);
+        }
+        let (offset, len) = pending
+            .lock()
+            .request
+            .take()
+            .ok_or_else(|| anyhow!("the module is waiting on something other than a read"))?;
+        let response = fetch(offset, len);
+        pending.lock().response = Some(response);
+    }
+}
+
+/// Adds the WRAM from `offset` to the regions in `reads`, merging it with
+/// any that are close.
+fn add_read(reads: &mut Vec<(usize, usize)>, offset: usize, len: usize) {
+    reads.push((offset, len));
+    reads.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(reads.len());
+    for &(offset, len) in reads.iter() {
+        match merged.last_mut() {
+            Some((start, size)) if offset <= *start + *size + READ_GAP => {
+                *size = (*size).max(offset + len - *start);
+            }
+            _ => merged.push((offset, len)),
+        }
+    }
+    *reads = merged;
+}
+
+/// An instantiated module and its `update` export.
+struct Instance {
+    store: Store<Host>,
+    update: TypedFunc<(), ()>,
+}
+
+/// Runs a WebAssembly autosplitter module. Its `update` export is called
+/// once per poll.
+///
+/// Errors in `update`, including running out of fuel, are logged and the
+/// poll counts as doing nothing, so a bug in a module doesn't drop the
+/// connection. The default has no module and never does anything.
+#[derive(Default)]
+pub struct WasmAutoSplitter {
+    instance: Option<Instance>,
+    /// The WRAM regions the module has read, by offset, fetched at the
+    /// start of each poll.
+    reads: Vec<(usize, usize)>,
+    /// The last error from `update`, so it's only logged once.
+    error: Option<String>,
+}
+
+impl WasmAutoSplitter {
+    /// Compiles and instantiates `wasm`, which may also be in the text
+    /// format, adding the module's settings to `settings` as it adds them.
+    pub fn new(wasm: &[u8], settings: Arc<RwLock<Settings>>) -> Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, wasm).context("failed to compile the module")?;
+        let mut linker = Linker::new(&engine);
+        define_imports(&mut linker)?;
+        let mut store = Store::new(&engine, Host::new(settings));
+        define_unknown_imports(&mut linker, &mut store, &module)?;
+        store.set_fuel(FUEL_PER_UPDATE)?;
+        let pending = store.data().pending.clone();
+        let no_device = |_, _| Err(anyhow!("reads only reach the device during update"));
+        let instance = run(
+            linker.instantiate_async(&mut store, &module),
+            &pending,
+            no_device,
+        )?
+        .context("failed to instantiate the module")?;
+        if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+            run(initialize.call_async(&mut store, ()), &pending, no_device)?
+                .context("_initialize failed")?;
+        }
+        let update = instance
+            .get_typed_func::<(), ()>(&mut store, "update")
+            .context("the module has no update function")?;
+        Ok(WasmAutoSplitter {
+            instance: Some(Instance { store, update }),
+            ..WasmAutoSplitter::default()
+        })
+    }
+
+    /// Loads a module from a `.wasm` file.
+    pub fn from_file<P>(path: P, settings: Arc<RwLock<Settings>>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let wasm =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        WasmAutoSplitter::new(&wasm, settings)
+            .with_context(|| format!("failed to load {}", path.display()))
+    }
+
+    /// The settings and titles the module added, in the order it added
+    /// them.
+    pub fn settings(&self) -> &[WasmSetting] {
+        self.instance
+            .as_ref()
+            .map_or(&[][..], |i| &i.store.data().module_settings)
+    }
+
+    /// The WRAM regions fetched each poll, as `(offset, size)`.
+    pub fn reads(&self) -> &[(usize, usize)] {
+        &self.reads
+    }
+}
+
+impl AutoSplitter for WasmAutoSplitter {
+    fn update(&mut self, client: &mut dyn MemorySource) -> Result<SNESSummary> {
+        let Some(instance) = self.instance.as_mut() else {
+            return Ok(no_summary());
+        };
+        let host = instance.store.data_mut();
+        if let Some(e) = host.source_error.take() {
+            return Err(e);
+        }
+        host.fresh.fill(false);
+        if !self.reads.is_empty() {
+            let pairs: Vec<(u32, usize)> = self
+                .reads
+                .iter()
+                .map(|&(offset, len)| (MemoryDomain::Wram.base() + offset as u32, len))
+                .collect();
+            let data = client.get_addresses(&pairs)?;
+            for (&(offset, _), bytes) in self.reads.iter().zip(data) {
+                let range = offset..offset + bytes.len();
+                host.wram[range.clone()].copy_from_slice(&bytes);
+                host.fresh[range].fill(true);
+            }
+        }
+        host.summary = no_summary();
+        let pending = host.pending.clone();
+        host.polling = true;
+        instance.store.set_fuel(FUEL_PER_UPDATE)?;
+        let result = run(
+            instance.update.call_async(&mut instance.store, ()),
+            &pending,
+            |offset, len| {
+                let address = MemoryDomain::Wram.base() + offset as u32;
+                let mut data = client.get_addresses(&[(address, len)])?;
+                Ok(data.swap_remove(0))
+            },
+        );
+        let host = instance.store.data_mut();
+        host.polling = false;
+        let result = result?;
+        if host.source_error.is_some() {
+            // The device went away mid-poll. Anything the module did
+            // before that still counts; the error fails the next poll.
+            host.missed.clear();
+            let summary = host.summary;
+            if !(summary.start || summary.split || summary.reset) {
+                return Err(host.source_error.take().expect("checked above"));
+            }
+        }
+        match result {
+            Ok(()) => self.error = None,
+            Err(e) => {
+                let message = format!("{e:#}");
+                if self.error.as_ref() != Some(&message) {
+                    tracing::warn!("WASM autosplitter update failed: {message}");
+                    self.error = Some(message);
+                }
+            }
+        }
+
+        let host = instance.store.data_mut();
+        for (offset, len) in std::mem::take(&mut host.missed) {
+            add_read(&mut self.reads, offset, len);
+        }
+        Ok(host.summary)
+    }
+
+    fn gametime_to_seconds(&self) -> Option<TimeSpan> {
+        self.instance.as_ref()?.store.data().game_time
+    }
+
+    fn reset_game_tracking(&mut self) {
+        if let Some(instance) = self.instance.as_mut() {
+            let host = instance.store.data_mut();
+            host.current_split = None;
+            host.game_time = None;
+        }
+    }
+
+    fn set_current_split(&mut self, index: Option<usize>) {
+        if let Some(instance) = self.instance.as_mut() {
+            instance.store.data_mut().current_split = index;
+        }
+    }
+}
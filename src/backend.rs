@@ -0,0 +1,131 @@
+//! The transports Annelid can read SNES memory through.
+
+use anyhow::{Context, Result};
+use std::rc::Rc;
+
+use crate::config::app_config::{AppConfig, BackendType};
+use crate::memory_source::MemorySource;
+use crate::retroarch::{RetroArchClient, DEFAULT_RETROARCH_PORT};
+use crate::sni::{SniClient, DEFAULT_SNI_PORT};
+use crate::usb2snes::{
+    select_device, ClientOptions, Infos, Space, SyncClient, DEFAULT_HOST, DEFAULT_PORT,
+};
+
+pub enum Backend {
+    /// QUsb2snes, or SNI's usb2snes compatibility server, over WebSocket.
+    Usb2Snes(SyncClient),
+    /// SNI's native gRPC API.
+    Sni(SniClient),
+    /// RetroArch's UDP network commands. Only WRAM can be read.
+    RetroArch(RetroArchClient),
+}
+
+impl Backend {
+    /// Connects to the server selected in `config` and attaches to a device.
+    /// `on_devices` is given every device the server reported, so the UI
+    /// can offer them even when none of them could be selected.
+    pub fn connect(
+        config: &AppConfig,
+        options: ClientOptions,
+        on_devices: impl FnOnce(&[Rc<str>]),
+    ) -> Result<Backend> {
+        let host = config
+            .usb2snes_host
+            .clone()
+            .unwrap_or_else(|| DEFAULT_HOST.to_owned());
+        let preferred = config.preferred_device.as_deref();
+        let fallback = config.device_fallback.unwrap_or_default();
+        match config.backend.unwrap_or_default() {
+            BackendType::Usb2Snes => {
+                let port = config.usb2snes_port.unwrap_or(DEFAULT_PORT);
+                let mut client = SyncClient::connect_to_server(&host, port, options)
+                    .context("creating usb2snes connection")?;
+                client.set_name("annelid")?;
+                tracing::info!("Server version is {:?}", client.app_version()?);
+                let devices = client.list_device()?;
+                on_devices(&devices);
+                let device = select_device(&devices, preferred, fallback)?;
+                tracing::info!("Using device: {device}");
+                client.attach(&device)?;
+                tracing::info!("Connected.");
+                tracing::debug!("{:#?}", client.info()?);
+                Ok(Backend::Usb2Snes(client))
+            }
+            BackendType::Sni => {
+                let port = config.sni_port.unwrap_or(DEFAULT_SNI_PORT);
+                let mut client =
+                    SniClient::connect(&host, port, options).context("creating SNI connection")?;
+                let devices = client.list_devices()?;
+                let names: Vec<Rc<str>> = devices
+                    .iter()
+                    .map(|d| Rc::from(d.display_name.as_str()))
+                    .collect();
+                on_devices(&names);
+                let name = select_device(&names, preferred, fallback)?;
+                let device = devices
+                    .into_iter()
+                    .find(|d| *d.display_name == *name)
+                    .context("selected SNI device vanished")?;
+                tracing::info!("Using SNI device: {device:#?}");
+                client.attach(device);
+                tracing::info!("Connected.");
+                tracing::debug!("{:#?}", client.info()?);
+                Ok(Backend::Sni(client))
+            }
+            BackendType::RetroArch => {
+                let port = config.retroarch_port.unwrap_or(DEFAULT_RETROARCH_PORT);
+                let client = RetroArchClient::connect(&host, port, options)
+                    .context("connecting to RetroArch")?;
+                // There is only ever the one emulator, so the preferred
+                // device does not apply.
+                on_devices(&[Rc::from(client.device_name())]);
+                tracing::info!("Connected to {}", client.device_name());
+                Ok(Backend::RetroArch(client))
+            }
+        }
+    }
+
+    fn source(&mut self) -> &mut dyn MemorySource {
+        match self {
+            Backend::Usb2Snes(client) => client,
+            Backend::Sni(client) => client,
+            Backend::RetroArch(client) => client,
+        }
+    }
+
+    /// The name of the attached device, as offered in the device picker.
+    pub fn device_name(&self) -> String {
+        match self {
+            Backend::Usb2Snes(client) => client.device().map(str::to_owned),
+            Backend::Sni(client) => client.device().map(|d| d.display_name.clone()),
+            Backend::RetroArch(client) => Some(client.device_name()),
+        }
+        .unwrap_or_else(|| "unknown device".to_owned())
+    }
+}
+
+impl MemorySource for Backend {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        self.source().get_addresses(pairs)
+    }
+
+    fn get_space_addresses(
+        &mut self,
+        space: Space,
+        pairs: &[(u32, usize)],
+    ) -> Result<Vec<Vec<u8>>> {
+        self.source().get_space_addresses(space, pairs)
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        self.source().info()
+    }
+
+    fn write_address(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.source().write_address(address, data)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.source().reset()
+    }
+}
@@ -0,0 +1,493 @@
+//! `annelid-cli`: the usb2snes client without the timer, for inspecting a
+//! device and developing autosplitters from a terminal or a script.
+//!
+//! Everything writes to the `out` it is given rather than stdout, so the
+//! commands can be run against the fake server in tests.
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use parking_lot::RwLock;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::autosplitters::registry::{find_autosplitter, load_script, AutoSplitterEntry};
+use crate::autosplitters::supermetroid::Settings;
+use crate::autosplitters::AutoSplitter;
+use crate::backend::Backend;
+use crate::config::app_config::{AppConfig, BackendType};
+use crate::memory_source::{read_domains, MemoryDomain, MemorySource};
+use crate::trace::{format_time, TraceEventKind, TraceMeta, TraceRecorder};
+use crate::usb2snes::{
+    Capability, ClientOptions, SyncClient, USB2SnesError, USB2SnesFileType, SRAM_SIZE, SRAM_START,
+    WRAM_SIZE, WRAM_START,
+};
+
+#[derive(Parser, Debug)]
+#[clap(
+    name = "annelid-cli",
+    author,
+    version,
+    about = "Inspect a usb2snes device and run autosplitters without the timer"
+)]
+pub struct Cli {
+    /// Protocol used to talk to the QUsb2snes/SNI server or RetroArch
+    #[clap(long, value_parser, global = true)]
+    pub backend: Option<BackendType>,
+    /// Host name or IP address of the server
+    #[clap(long, value_parser, global = true)]
+    pub host: Option<String>,
+    /// Port of the server; defaults to the usual port for the backend
+    #[clap(long, value_parser, global = true)]
+    pub port: Option<u16>,
+    /// Device name or `*`/`?` pattern to attach to when several are present
+    #[clap(long, short = 'd', value_parser, global = true)]
+    pub device: Option<String>,
+    #[clap(subcommand)]
+    pub command: CliCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// List the devices the server reports
+    Devices,
+    /// Show what the attached device reports about itself
+    Info,
+    /// Hexdump a memory range, e.g. `peek wram:09A4 61`
+    Peek {
+        location: Location,
+        /// Bytes to read, decimal or 0x-prefixed hex
+        #[clap(default_value = "16", value_parser = parse_number::<usize>)]
+        size: usize,
+    },
+    /// Print a memory range every time it changes
+    Watch {
+        location: Location,
+        #[clap(default_value = "2", value_parser = parse_number::<usize>)]
+        size: usize,
+        /// Reads per second
+        #[clap(long, short = 'r', default_value_t = 60.0)]
+        rate: f32,
+        /// Stop after this many reads
+        #[clap(long)]
+        polls: Option<usize>,
+    },
+    /// List a directory on the SD card
+    Ls {
+        #[clap(default_value = "/")]
+        path: String,
+    },
+    /// Download a file from the SD card
+    Get {
+        remote: String,
+        /// Where to save it; defaults to the file's name in the current directory
+        local: Option<PathBuf>,
+    },
+    /// Upload a file to the SD card
+    Put { local: PathBuf, remote: String },
+    /// Record the autosplitter's reads to a trace file in `dir`
+    Record {
+        #[clap(default_value = ".")]
+        dir: PathBuf,
+        #[clap(flatten)]
+        run: RunOptions,
+    },
+    /// Run the autosplitter for the loaded game and print what it decides
+    Run {
+        /// Autosplitter settings saved from Annelid; the defaults otherwise
+        #[clap(long, short = 'a')]
+        autosplitter: Option<PathBuf>,
+        /// QUsb2snes-style JSON split definitions to run instead
+        #[clap(long, conflicts_with = "autosplitter")]
+        json: Option<PathBuf>,
+        /// LiveSplit ASL script to run instead; `-a` settings apply to it
+        #[clap(long, conflicts_with = "json")]
+        asl: Option<PathBuf>,
+        /// WebAssembly autosplitter module to run instead
+        #[clap(long, conflicts_with_all = ["json", "asl"])]
+        wasm: Option<PathBuf>,
+        /// Also record a trace into this directory
+        #[clap(long)]
+        record: Option<PathBuf>,
+        #[clap(flatten)]
+        run: RunOptions,
+    },
+}
+
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct RunOptions {
+    /// Polls per second
+    #[clap(long, short = 'r', default_value_t = 60.0)]
+    pub rate: f32,
+    /// Stop after this many polls; runs until interrupted otherwise
+    #[clap(long)]
+    pub polls: Option<usize>,
+}
+
+/// A place in memory: a domain and an offset into it. Written either as
+/// `domain:offset` (`wram:0998`, `sram:10`, `rom:7FC0`, `cmd:2C00`) or as a
+/// plain usb2snes address (`F50998`), with offsets in hex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub domain: MemoryDomain,
+    pub offset: u32,
+}
+
+impl FromStr for Location {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Location> {
+        let hex = |digits: &str| {
+            let digits = digits.trim_start_matches("0x").trim_start_matches('$');
+            u32::from_str_radix(digits, 16).with_context(|| format!("{s:?} is not a hex address"))
+        };
+        if let Some((domain, offset)) = s.split_once(':') {
+            let domain = MemoryDomain::ALL
+                .into_iter()
+                .find(|d| d.to_string().eq_ignore_ascii_case(domain))
+                .ok_or_else(|| anyhow!("unknown memory domain {domain:?}"))?;
+            return Ok(Location {
+                domain,
+                offset: hex(offset)?,
+            });
+        }
+        let address = hex(s)?;
+        let (domain, base) = if (WRAM_START..WRAM_START + WRAM_SIZE).contains(&address) {
+            (MemoryDomain::Wram, WRAM_START)
+        } else if (SRAM_START..SRAM_START + SRAM_SIZE).contains(&address) {
+            (MemoryDomain::Sram, SRAM_START)
+        } else if address < SRAM_START {
+            (MemoryDomain::Rom, 0)
+        } else {
+            Err(anyhow!(
+                "{address:#X} is not in WRAM, SRAM or ROM; give a domain, e.g. cmd:2C00"
+            ))?
+        };
+        Ok(Location {
+            domain,
+            offset: address - base,
+        })
+    }
+}
+
+impl Location {
+    /// The usb2snes address, in the domain's space.
+    pub fn address(&self) -> u32 {
+        self.domain.base() + self.offset
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hex number.
+pub fn parse_number<T: TryFrom<u64>>(s: &str) -> Result<T, String> {
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| format!("{s:?}: {e}"))?;
+    T::try_from(value).map_err(|_| format!("{s} is too large"))
+}
+
+/// Writes `data` as lines of 16 bytes, each labelled with its address and
+/// followed by the printable characters.
+pub fn hexdump(out: &mut dyn Write, address: u32, data: &[u8]) -> std::io::Result<()> {
+    for (i, line) in data.chunks(16).enumerate() {
+        write!(out, "{:06X} ", address as usize + i * 16)?;
+        for byte in line {
+            write!(out, " {byte:02X}")?;
+        }
+        let text: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(
+            out,
+            "{:width$}  |{text}|",
+            "",
+            width = (16 - line.len()) * 3
+        )?;
+    }
+    Ok(())
+}
+
+impl Cli {
+    fn app_config(&self) -> AppConfig {
+        let backend = self.backend.unwrap_or_default();
+        let mut config = AppConfig {
+            backend: Some(backend),
+            usb2snes_host: self.host.clone(),
+            preferred_device: self.device.clone(),
+            ..AppConfig::default()
+        };
+        match backend {
+            BackendType::Usb2Snes => config.usb2snes_port = self.port,
+            BackendType::Sni => config.sni_port = self.port,
+            BackendType::RetroArch => config.retroarch_port = self.port,
+        }
+        config
+    }
+
+    fn connect(&self) -> Result<Backend> {
+        Backend::connect(&self.app_config(), ClientOptions::default(), |_| {})
+    }
+
+    pub fn run(&self, out: &mut dyn Write) -> Result<()> {
+        match &self.command {
+            CliCommand::Devices => {
+                let mut devices: Vec<Rc<str>> = vec![];
+                let connected =
+                    Backend::connect(&self.app_config(), ClientOptions::default(), |d| {
+                        devices = d.to_vec()
+                    });
+                if devices.is_empty() {
+                    connected?;
+                }
+                for device in devices {
+                    writeln!(out, "{device}")?;
+                }
+            }
+            CliCommand::Info => {
+                let mut backend = self.connect()?;
+                let info = backend.info()?;
+                writeln!(out, "Device:   {}", backend.device_name())?;
+                writeln!(out, "Type:     {}", info.dev_type)?;
+                writeln!(out, "Firmware: {}", info.version)?;
+                writeln!(out, "ROM:      {}", info.game)?;
+                let flags: Vec<&str> = info.flags.iter().map(|f| &**f).collect();
+                writeln!(out, "Flags:    {}", flags.join(" "))?;
+                let game = backend.game_info()?;
+                writeln!(out, "Game:     {}", game.display_name())?;
+                let autosplitter = find_autosplitter(&game).map_or("none", |e| e.name);
+                writeln!(out, "Autosplitter: {autosplitter}")?;
+            }
+            CliCommand::Peek { location, size } => {
+                let mut backend = self.connect()?;
+                let data = read(&mut backend, location, *size)?;
+                hexdump(out, location.address(), &data)?;
+            }
+            CliCommand::Watch {
+                location,
+                size,
+                rate,
+                polls,
+            } => {
+                let mut backend = self.connect()?;
+                watch(&mut backend, location, *size, *rate, *polls, out)?;
+            }
+            CliCommand::Ls { path } => {
+                let mut backend = self.connect()?;
+                let client = sd_card(&mut backend)?;
+                for entry in client.ls(path)? {
+                    let suffix = match entry.file_type {
+                        USB2SnesFileType::Dir => "/",
+                        USB2SnesFileType::File => "",
+                    };
+                    writeln!(out, "{}{suffix}", entry.name)?;
+                }
+            }
+            CliCommand::Get { remote, local } => {
+                let mut backend = self.connect()?;
+                let data = sd_card(&mut backend)?.get_file(remote)?;
+                let local = match local {
+                    Some(local) => local.clone(),
+                    None => PathBuf::from(remote.rsplit('/').next().unwrap_or(remote)),
+                };
+                std::fs::write(&local, &data)
+                    .with_context(|| format!("writing {}", local.display()))?;
+                writeln!(out, "{} bytes to {}", data.len(), local.display())?;
+            }
+            CliCommand::Put { local, remote } => {
+                let data =
+                    std::fs::read(local).with_context(|| format!("reading {}", local.display()))?;
+                let mut backend = self.connect()?;
+                let client = sd_card(&mut backend)?;
+                client.send_file(remote, &data)?;
+                // PutFile has no reply; this one tells us the upload finished.
+                client.info()?;
+                writeln!(out, "{} bytes to {remote}", data.len())?;
+            }
+            CliCommand::Record { dir, run } => {
+                let mut backend = self.connect()?;
+                let settings = Arc::new(RwLock::new(Settings::new()));
+                run_autosplitter(&mut backend, settings, None, Some(dir), *run, out)?;
+            }
+            CliCommand::Run {
+                autosplitter,
+                json,
+                asl,
+                wasm,
+                record,
+                run,
+            } => {
+                let settings = match autosplitter {
+                    Some(path) => load_settings(path)?,
+                    None => Settings::new(),
+                };
+                let settings = Arc::new(RwLock::new(settings));
+                let script =
+                    load_script(json.as_deref(), asl.as_deref(), wasm.as_deref(), &settings)?;
+                let mut backend = self.connect()?;
+                run_autosplitter(&mut backend, settings, script, record.as_deref(), *run, out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read(source: &mut dyn MemorySource, location: &Location, size: usize) -> Result<Vec<u8>> {
+    let mut data = read_domains(source, &[(location.domain, location.offset, size)])?;
+    Ok(data.remove(0))
+}
+
+/// The SD card commands only exist in the usb2snes protocol.
+fn sd_card(backend: &mut Backend) -> Result<&mut SyncClient> {
+    let Backend::Usb2Snes(client) = backend else {
+        Err(anyhow!("SD card access needs the usb2snes backend"))?
+    };
+    if !client.info()?.supports(Capability::FileCommands) {
+        Err(USB2SnesError::Unsupported(
+            Capability::FileCommands.to_string(),
+        ))?;
+    }
+    Ok(client)
+}
+
+fn load_settings(path: &Path) -> Result<Settings> {
+    let file = std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .with_context(|| format!("reading autosplitter settings from {}", path.display()))
+}
+
+fn poll_interval(rate: f32) -> Result<Duration> {
+    if !(rate > 0.0 && rate.is_finite()) {
+        Err(anyhow!(
+            "the rate must be a positive number of polls per second"
+        ))?;
+    }
+    Ok(Duration::from_secs_f32(1.0 / rate))
+}
+
+fn watch(
+    source: &mut dyn MemorySource,
+    location: &Location,
+    size: usize,
+    rate: f32,
+    polls: Option<usize>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let interval = poll_interval(rate)?;
+    let started = Instant::now();
+    let mut last: Option<Vec<u8>> = None;
+    let mut poll = 0;
+    while polls.is_none_or(|n| poll < n) {
+        let next = Instant::now() + interval;
+        let data = read(source, location, size)?;
+        if last.as_ref() != Some(&data) {
+            let bytes: Vec<String> = data.iter().map(|b| format!("{b:02X}")).collect();
+            write!(
+                out,
+                "{}  {}",
+                format_time(started.elapsed()),
+                bytes.join(" ")
+            )?;
+            if size <= 4 {
+                let value = data
+                    .iter()
+                    .rev()
+                    .fold(0u32, |value, &b| value << 8 | b as u32);
+                write!(out, "  = {value}")?;
+            }
+            writeln!(out)?;
+            out.flush()?;
+            last = Some(data);
+        }
+        poll += 1;
+        std::thread::sleep(next.saturating_duration_since(Instant::now()));
+    }
+    Ok(())
+}
+
+/// Detects the game, then runs its autosplitter, or `script` if given, at
+/// `options.rate`, printing each start, split and reset with the game time.
+/// With `record` the reads are also saved to a trace in that directory.
+pub fn run_autosplitter(
+    backend: &mut Backend,
+    settings: Arc<RwLock<Settings>>,
+    script: Option<(&'static AutoSplitterEntry, Box<dyn AutoSplitter>)>,
+    record: Option<&Path>,
+    options: RunOptions,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let interval = poll_interval(options.rate)?;
+    let game = backend.game_info()?;
+    let (entry, script) = match script {
+        Some((entry, autosplitter)) => (entry, Some(autosplitter)),
+        None => (
+            find_autosplitter(&game)
+                .ok_or_else(|| anyhow!("No autosplitter for {}", game.display_name()))?,
+            None,
+        ),
+    };
+    writeln!(
+        out,
+        "Detected {}, using the {} autosplitter",
+        game.display_name(),
+        entry.name
+    )?;
+    let mut recorder = match record {
+        Some(dir) => {
+            let meta = TraceMeta {
+                game: game.display_name().to_owned(),
+                rom_name: game.rom_name.clone(),
+                autosplitter: entry.name.to_owned(),
+                device: backend.device_name(),
+                polling_rate: options.rate,
+                recorded_at: String::new(),
+            };
+            std::fs::create_dir_all(dir)?;
+            let (recorder, path) = TraceRecorder::create(dir, &meta)?;
+            writeln!(out, "Recording to {}", path.display())?;
+            Some(recorder)
+        }
+        None => None,
+    };
+    out.flush()?;
+
+    let mut autosplitter = script.unwrap_or_else(|| (entry.create)(settings));
+    let started = Instant::now();
+    let mut poll = 0;
+    while options.polls.is_none_or(|n| poll < n) {
+        let next = Instant::now() + interval;
+        let summary = match recorder.as_mut() {
+            Some(recorder) => autosplitter.update(&mut recorder.wrap(backend))?,
+            None => autosplitter.update(backend)?,
+        };
+        for kind in TraceEventKind::from_summary(&summary) {
+            write!(
+                out,
+                "{}  poll {poll:>7}  {kind}",
+                format_time(started.elapsed())
+            )?;
+            if let Some(time) = autosplitter.gametime_to_seconds() {
+                let time = Duration::from_secs_f64(time.total_seconds().max(0.0));
+                write!(out, "  game time {}", format_time(time))?;
+            }
+            writeln!(out)?;
+            out.flush()?;
+        }
+        poll += 1;
+        std::thread::sleep(next.saturating_duration_since(Instant::now()));
+    }
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+    Ok(())
+}
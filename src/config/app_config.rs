@@ -12,6 +12,15 @@ pub struct AppConfig {
     pub recent_layout: Option<String>,
     #[clap(name = "load-autosplitter", short = 'a', long, value_parser)]
     pub recent_autosplitter: Option<String>,
+    /// QUsb2snes-style JSON split definitions to run instead of a built-in autosplitter
+    #[clap(name = "load-json-splits", long, value_parser)]
+    pub json_splits: Option<String>,
+    /// LiveSplit ASL script to run instead of a built-in autosplitter
+    #[clap(name = "load-asl-script", long, value_parser)]
+    pub asl_script: Option<String>,
+    /// WebAssembly autosplitter module to run instead of a built-in autosplitter
+    #[clap(name = "load-wasm-autosplitter", long, value_parser)]
+    pub wasm_autosplitter: Option<String>,
     #[clap(name = "use-autosplitter", long, action)]
     pub use_autosplitter: Option<YesOrNo>,
     #[clap(name = "polling-rate", long, short = 'p', value_parser)]
@@ -26,6 +35,32 @@ pub struct AppConfig {
     pub global_hotkeys: Option<YesOrNo>,
     #[clap(name = "renderer", long, short = 'r', value_parser)]
     pub renderer: Option<RendererType>,
+    /// Protocol used to talk to the QUsb2snes/SNI server or RetroArch
+    #[clap(name = "backend", long, value_parser)]
+    pub backend: Option<BackendType>,
+    /// Host name or IP address of the QUsb2snes/SNI server or RetroArch
+    #[clap(name = "usb2snes-host", long, value_parser)]
+    pub usb2snes_host: Option<String>,
+    /// Port of the QUsb2snes/SNI server; the legacy port 8080 is tried if it fails
+    #[clap(name = "usb2snes-port", long, value_parser)]
+    pub usb2snes_port: Option<u16>,
+    /// Port of SNI's gRPC server, used with `--backend sni`
+    #[clap(name = "sni-port", long, value_parser)]
+    pub sni_port: Option<u16>,
+    /// RetroArch's network command port, used with `--backend retroarch`
+    #[clap(name = "retroarch-port", long, value_parser)]
+    pub retroarch_port: Option<u16>,
+    /// Device name or `*`/`?` pattern to attach to when several are present
+    #[clap(name = "device", long, short = 'd', value_parser)]
+    pub preferred_device: Option<String>,
+    #[clap(name = "device-fallback", long, value_parser)]
+    pub device_fallback: Option<DeviceFallback>,
+    /// Record every autosplitter session to a trace file, for bug reports
+    #[clap(name = "record-traces", long, value_parser)]
+    pub record_traces: Option<YesOrNo>,
+    /// What to do with splits missed while the device was disconnected
+    #[clap(name = "catch-up-splits", long, value_parser)]
+    pub catch_up_splits: Option<CatchUpSplits>,
     #[clap(skip)]
     pub transparent_window: Option<YesOrNo>,
     #[clap(skip)]
@@ -58,6 +93,45 @@ pub enum RendererType {
     Gpu,
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum BackendType {
+    /// The usb2snes WebSocket protocol (QUsb2snes, or SNI's compatibility server)
+    #[default]
+    #[value(name = "usb2snes")]
+    Usb2Snes,
+    /// SNI's native gRPC API
+    Sni,
+    /// RetroArch's UDP network commands (WRAM only)
+    #[value(name = "retroarch")]
+    RetroArch,
+}
+
+/// What to do when the preferred device is not in the device list, or when
+/// no preference is set and more than one device is present.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum DeviceFallback {
+    /// Use the only device present, otherwise wait for the preferred one.
+    #[default]
+    Single,
+    /// Use the first device in the list.
+    First,
+    /// Never pick a device other than the preferred one.
+    Wait,
+}
+
+/// What to do when, after a reconnect, the game has moved past splits the
+/// autosplitter never saw.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum CatchUpSplits {
+    /// Show the missed splits in the control panel and let the runner decide.
+    #[default]
+    Ask,
+    /// Split for each of them right away.
+    Split,
+    /// Only log them.
+    Off,
+}
+
 pub const DEFAULT_FRAME_RATE: f32 = 30.0;
 pub const DEFAULT_POLLING_RATE: f32 = 20.0;
 
@@ -69,6 +143,9 @@ impl AppConfig {
             recent_splits: None,
             recent_layout: None,
             recent_autosplitter: None,
+            json_splits: None,
+            asl_script: None,
+            wasm_autosplitter: None,
             hot_key_start: Some(HotKey {
                 key: KeyCode::Num1,
                 modifiers,
@@ -104,6 +181,15 @@ impl AppConfig {
             reset_game_on_timer_reset: Some(YesOrNo::No),
             global_hotkeys: Some(YesOrNo::Yes),
             renderer: Some(RendererType::Gpu),
+            backend: Some(BackendType::Usb2Snes),
+            usb2snes_host: Some(crate::usb2snes::DEFAULT_HOST.to_owned()),
+            usb2snes_port: Some(crate::usb2snes::DEFAULT_PORT),
+            sni_port: Some(crate::sni::DEFAULT_SNI_PORT),
+            retroarch_port: Some(crate::retroarch::DEFAULT_RETROARCH_PORT),
+            preferred_device: None,
+            device_fallback: Some(DeviceFallback::Single),
+            record_traces: Some(YesOrNo::No),
+            catch_up_splits: Some(CatchUpSplits::Ask),
             transparent_window: None,
         }
     }
@@ -0,0 +1,149 @@
+//! The state of the autosplitter's connection to the SNES, shared between
+//! the polling thread and the UI.
+
+use parking_lot::RwLock;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+pub const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Left-hand text of a layout Text component that Annelid fills in with the
+/// connection state.
+pub const STATUS_COMPONENT_LABEL: &str = "Autosplitter";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not connected, and not trying to be (the autosplitter is off, or we
+    /// are about to reconnect after a settings change).
+    #[default]
+    Disconnected,
+    Connecting,
+    /// Attached to `device`. `game` is filled in once it has been
+    /// identified; staying here means there is no autosplitter for it.
+    Attached {
+        device: String,
+        game: Option<String>,
+    },
+    /// The autosplitter is reading memory. `warnings` lists anything the
+    /// device can't do that the autosplitter or settings ask for.
+    Polling {
+        device: String,
+        game: String,
+        warnings: Vec<String>,
+    },
+    /// The last attempt failed; the next one starts after `retry_in`.
+    Error {
+        reason: String,
+        retry_in: Duration,
+    },
+}
+
+impl ConnectionState {
+    /// True while splits are being driven by the autosplitter.
+    pub fn is_live(&self) -> bool {
+        matches!(self, ConnectionState::Polling { .. })
+    }
+
+    /// A short form for the layout component.
+    pub fn summary(&self) -> String {
+        match self {
+            ConnectionState::Disconnected => "Off".to_owned(),
+            ConnectionState::Connecting => "Connecting".to_owned(),
+            ConnectionState::Attached { game: None, .. } => "Detecting game".to_owned(),
+            ConnectionState::Attached { game: Some(_), .. } => "Unsupported game".to_owned(),
+            ConnectionState::Polling { .. } => "Live".to_owned(),
+            ConnectionState::Error { .. } => "Error".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Disconnected => write!(f, "Disconnected"),
+            ConnectionState::Connecting => write!(f, "Connecting…"),
+            ConnectionState::Attached { device, game: None } => {
+                write!(f, "Attached to {device}")
+            }
+            ConnectionState::Attached {
+                device,
+                game: Some(game),
+            } => write!(f, "Attached to {device}, no autosplitter for {game}"),
+            ConnectionState::Polling {
+                device,
+                game,
+                warnings,
+            } => {
+                write!(f, "Live: {game} on {device}")?;
+                for warning in warnings {
+                    write!(f, "\n{warning}")?;
+                }
+                Ok(())
+            }
+            ConnectionState::Error { reason, retry_in } => {
+                write!(f, "{reason} (retrying in {:.1}s)", retry_in.as_secs_f32())
+            }
+        }
+    }
+}
+
+/// A `ConnectionState` shared between threads. Clones refer to the same
+/// state.
+#[derive(Clone, Debug, Default)]
+pub struct SharedConnectionState(Arc<RwLock<ConnectionState>>);
+
+impl SharedConnectionState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> ConnectionState {
+        self.0.read().clone()
+    }
+
+    pub fn set(&self, state: ConnectionState) {
+        let mut current = self.0.write();
+        if *current != state {
+            tracing::debug!("Connection state: {state}");
+            *current = state;
+        }
+    }
+}
+
+/// Exponential backoff between reconnect attempts: each failure doubles the
+/// delay up to `max`, and a successful connection starts over from `min`.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Backoff {
+            min,
+            max,
+            next: min,
+        }
+    }
+
+    /// The delay before the next attempt.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(MIN_RECONNECT_DELAY, MAX_RECONNECT_DELAY)
+    }
+}
@@ -1,12 +1,20 @@
 #[macro_use]
 extern crate lazy_static;
 pub mod autosplitters;
+pub mod backend;
+pub mod cli;
 pub mod config;
+pub mod connection;
 pub mod hotkey;
 pub mod livesplit_renderer;
 pub mod logging;
+pub mod memory_source;
 pub mod platform;
+pub mod poll_stats;
+pub mod retroarch;
 pub mod routes;
+pub mod sni;
+pub mod trace;
 pub mod ui;
 pub mod usb2snes;
 pub mod utils;
@@ -1,12 +1,20 @@
+use crate::autosplitters::registry::{
+    capability_warnings, find_autosplitter, load_script, AutoSplitterEntry, DetectedGame,
+};
 use crate::autosplitters::supermetroid::Settings;
-use crate::autosplitters::supermetroid::SuperMetroidAutoSplitter;
-use crate::autosplitters::AutoSplitter;
-use anyhow::{anyhow, Context};
+use crate::autosplitters::{AutoSplitter, MissedSplit, Progress, SNESSummary};
+use crate::connection::{Backoff, ConnectionState, SharedConnectionState};
+use crate::memory_source::MemorySource;
+use crate::poll_stats::{PollSample, PollStats, TimedSource};
+use crate::trace::{TraceMeta, TraceRecorder};
+use crate::usb2snes::Capability;
+use anyhow::anyhow;
 use eframe::egui;
 use glow::HasContext;
-use livesplit_core::{Layout, SharedTimer};
+use livesplit_core::{Layout, SharedTimer, TimerPhase};
 use livesplit_hotkey::Hook;
 use parking_lot::RwLock;
+use std::path::Path;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use thread_priority::ThreadBuilder;
@@ -35,6 +43,10 @@ pub(crate) struct UiState {
         Arc<parking_lot::Mutex<Option<crate::ui::layout_editor::LayoutEditorState>>>,
     pub layout_editor_preview: Arc<parking_lot::Mutex<Option<livesplit_core::layout::LayoutState>>>,
     pub log_viewer_open: Arc<AtomicBool>,
+    pub file_manager_open: Arc<AtomicBool>,
+    pub diagnostics_open: Arc<AtomicBool>,
+    pub session_replay_open: Arc<AtomicBool>,
+    pub file_manager: crate::ui::file_manager::FileManager,
     pub show_settings_editor: Arc<AtomicBool>,
     pub autosplitter_settings_snapshot: Arc<parking_lot::Mutex<Option<Settings>>>,
     pub splits_editor_preview: Arc<parking_lot::Mutex<Option<livesplit_core::Run>>>,
@@ -54,6 +66,10 @@ impl UiState {
             layout_editor_state: Arc::new(parking_lot::Mutex::new(None)),
             layout_editor_preview: Arc::new(parking_lot::Mutex::new(None)),
             log_viewer_open: Arc::new(AtomicBool::new(false)),
+            file_manager_open: Arc::new(AtomicBool::new(false)),
+            diagnostics_open: Arc::new(AtomicBool::new(false)),
+            session_replay_open: Arc::new(AtomicBool::new(false)),
+            file_manager: crate::ui::file_manager::FileManager::new(),
             show_settings_editor: Arc::new(AtomicBool::new(false)),
             autosplitter_settings_snapshot: Arc::new(parking_lot::Mutex::new(None)),
             splits_editor_preview: Arc::new(parking_lot::Mutex::new(None)),
@@ -83,6 +99,24 @@ pub struct LiveSplitCoreRenderer {
     /// Used to detect whether the user has moved/resized the window.
     pub(crate) saved_layout_meta: Option<crate::config::layout_meta::LayoutMeta>,
     pub(crate) log_buffer: crate::logging::LogBuffer,
+    /// Devices reported by the usb2snes server on the last connection attempt.
+    pub(crate) snes_devices: Arc<parking_lot::Mutex<Vec<String>>>,
+    /// Cancelling this makes the polling thread drop its connection and
+    /// reconnect, e.g. after the preferred device changes.
+    pub(crate) snes_cancel: crate::usb2snes::CancelHandle,
+    /// The game detected on the last connection, and whether we have an
+    /// autosplitter for it.
+    pub(crate) snes_game: Arc<parking_lot::Mutex<Option<DetectedGame>>>,
+    /// Where the polling thread is in connecting, detecting and polling.
+    pub(crate) snes_connection: SharedConnectionState,
+    /// Timing of the polling loop, for the diagnostics window.
+    pub(crate) poll_stats: Arc<parking_lot::Mutex<PollStats>>,
+    /// A recorded session being played into the timer. The polling thread
+    /// leaves the timer alone while one is active.
+    pub(crate) session_replay: crate::ui::session_replay::SessionReplay,
+    /// Splits found on reconnecting that are waiting for the runner to
+    /// apply or dismiss them in the control panel.
+    pub(crate) missed_splits: Arc<parking_lot::Mutex<Vec<MissedSplit>>>,
     pub(crate) ui: UiState,
 }
 
@@ -117,11 +151,44 @@ impl LiveSplitCoreRenderer {
             load_errors: vec![],
             saved_layout_meta: None,
             log_buffer,
+            snes_devices: Arc::new(parking_lot::Mutex::new(Vec::new())),
+            snes_cancel: crate::usb2snes::CancelHandle::new(),
+            snes_game: Arc::new(parking_lot::Mutex::new(None)),
+            snes_connection: SharedConnectionState::new(),
+            poll_stats: Arc::new(parking_lot::Mutex::new(PollStats::new())),
+            session_replay: crate::ui::session_replay::SessionReplay::default(),
+            missed_splits: Arc::new(parking_lot::Mutex::new(Vec::new())),
             ui: UiState::new(),
         }
     }
 }
 
+impl LiveSplitCoreRenderer {
+    /// Where session traces are recorded, and where replays look first.
+    pub(crate) fn trace_dir(&self) -> std::path::PathBuf {
+        self.project_dirs.data_dir().join("traces")
+    }
+
+    /// Fills in the right-hand side of any split Text component whose left
+    /// side reads `STATUS_COMPONENT_LABEL`, so the connection state can be
+    /// shown on the layout itself. Only the computed state is touched; the
+    /// layout keeps the user's text, so saving it never saves the status.
+    fn show_connection_status(&self, layout_state: &mut livesplit_core::layout::LayoutState) {
+        use livesplit_core::component::text::TextState;
+        use livesplit_core::layout::ComponentState;
+        let status = self.snes_connection.get().summary();
+        for component in layout_state.components.iter_mut() {
+            if let ComponentState::Text(text) = component {
+                if let TextState::Split(left, right) = &mut text.text {
+                    if &**left == crate::connection::STATUS_COMPONENT_LABEL {
+                        *right = status.as_str().into();
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl eframe::App for LiveSplitCoreRenderer {
     fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
         if self.app_config.read().transparent_window == Some(YesOrNo::Yes) {
@@ -236,6 +303,9 @@ impl eframe::App for LiveSplitCoreRenderer {
                         );
                     }
                 }
+                if let Some(ls) = layout_state.as_mut() {
+                    self.show_connection_status(ls);
+                }
             }
             // else: editor is open but no preview yet (first frame) — keep
             // showing the stale layout_state until the editor produces one
@@ -354,6 +424,9 @@ impl eframe::App for LiveSplitCoreRenderer {
         self.show_splits_editor(ctx);
         self.show_layout_editor(ctx);
         self.show_log_viewer(ctx);
+        self.show_file_manager(ctx);
+        self.show_diagnostics(ctx);
+        self.show_session_replay(ctx);
         self.process_ui_actions(ctx);
         ctx.input(|i| {
             let scroll_delta = i.raw_scroll_delta;
@@ -379,6 +452,230 @@ fn panic_payload_to_string(payload: &Box<dyn std::any::Any + Send>) -> String {
     }
 }
 
+/// Like `print_on_error`, but calls out a stalled usb2snes server so it is
+/// clear from the log why the polling thread is reconnecting. The error is
+/// passed on so the caller can decide how long to wait.
+fn log_polling_error<F>(f: F) -> anyhow::Result<()>
+where
+    F: FnOnce() -> anyhow::Result<()>,
+{
+    use crate::usb2snes::USB2SnesError;
+    let result = f();
+    if let Err(e) = &result {
+        match USB2SnesError::from_anyhow(e) {
+            Some(USB2SnesError::Timeout(_)) => {
+                tracing::warn!("usb2snes server stopped responding, reconnecting: {e:#}")
+            }
+            Some(USB2SnesError::Cancelled) => tracing::info!("Reconnecting to usb2snes server"),
+            Some(USB2SnesError::Closed) => {
+                tracing::info!("usb2snes server closed the connection, reconnecting")
+            }
+            Some(USB2SnesError::NoDevice) => {
+                tracing::info!("No devices connected to the usb2snes server yet")
+            }
+            Some(err) if err.is_protocol_error() => {
+                tracing::warn!("Dropping the usb2snes connection after a bad reply: {e:#}")
+            }
+            _ => tracing::warn!("{e}"),
+        }
+    }
+    result
+}
+
+/// Waits out `delay` before the next connection attempt, returning early
+/// if the settings change in the meantime.
+fn wait_to_reconnect(delay: std::time::Duration, cancel: &crate::usb2snes::CancelHandle) {
+    let until = std::time::Instant::now() + delay;
+    while !cancel.is_cancelled() {
+        let now = std::time::Instant::now();
+        if now >= until {
+            break;
+        }
+        std::thread::sleep((until - now).min(std::time::Duration::from_millis(100)));
+    }
+}
+
+/// Blocks until the device runs a game we have an autosplitter for, then
+/// creates that autosplitter. Unsupported games are reported once and
+/// checked again every second in case the user loads something else.
+fn wait_for_supported_game(
+    client: &mut crate::backend::Backend,
+    settings: &Arc<RwLock<Settings>>,
+    config: &AppConfig,
+    snes_game: &parking_lot::Mutex<Option<DetectedGame>>,
+    connection: &SharedConnectionState,
+    cancel: &crate::usb2snes::CancelHandle,
+) -> anyhow::Result<(&'static AutoSplitterEntry, Box<dyn AutoSplitter>)> {
+    let script = load_script(
+        config.json_splits.as_deref().map(Path::new),
+        config.asl_script.as_deref().map(Path::new),
+        config.wasm_autosplitter.as_deref().map(Path::new),
+        settings,
+    )?;
+    if let Some((entry, autosplitter)) = script {
+        let game = client.game_info()?;
+        tracing::info!("Detected {}, using the {}", game.display_name(), entry.name);
+        connection.set(ConnectionState::Attached {
+            device: client.device_name(),
+            game: Some(game.display_name().to_owned()),
+        });
+        *snes_game.lock() = Some(DetectedGame {
+            game,
+            autosplitter: Some(entry.name),
+        });
+        return Ok((entry, autosplitter));
+    }
+    let mut warned_about = None;
+    loop {
+        let game = client.game_info()?;
+        let entry = find_autosplitter(&game);
+        *snes_game.lock() = Some(DetectedGame {
+            game: game.clone(),
+            autosplitter: entry.map(|e| e.name),
+        });
+        connection.set(ConnectionState::Attached {
+            device: client.device_name(),
+            game: Some(game.display_name().to_owned()),
+        });
+        if let Some(entry) = entry {
+            tracing::info!(
+                "Detected {}, using the {} autosplitter",
+                game.display_name(),
+                entry.name
+            );
+            return Ok((entry, (entry.create)(settings.clone())));
+        }
+        if warned_about.as_ref() != Some(&game) {
+            tracing::warn!("No autosplitter for {}: {game:?}", game.display_name());
+            warned_about = Some(game);
+        }
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        if cancel.is_cancelled() {
+            Err(crate::usb2snes::USB2SnesError::Cancelled)?;
+        }
+    }
+}
+
+/// Acts on one autosplitter update: starts, resets or splits the timer.
+/// `update_splits` is passed to `Timer::reset`, so replays can reset without
+/// adding attempts to the run's history.
+pub(crate) fn apply_summary(
+    timer: &SharedTimer,
+    app_config: &RwLock<AppConfig>,
+    autosplitter: &dyn AutoSplitter,
+    summary: SNESSummary,
+    update_splits: bool,
+) -> anyhow::Result<()> {
+    let reset_on_game_reset = app_config.read().reset_timer_on_game_reset == Some(YesOrNo::Yes);
+    let mut timer = timer
+        .write()
+        .map_err(|e| anyhow!("failed to acquire write lock on timer: {e}"))?;
+    if summary.start {
+        timer.start().ok();
+    }
+    if summary.reset && reset_on_game_reset {
+        timer.reset(update_splits).ok();
+    }
+    if summary.split {
+        if let Some(t) = autosplitter.gametime_to_seconds() {
+            timer.set_game_time(t).ok();
+        }
+        timer.split().ok();
+    }
+    Ok(())
+}
+
+/// Passes the timer's current segment on to `autosplitter`.
+pub(crate) fn sync_current_split(
+    timer: &SharedTimer,
+    autosplitter: &mut dyn AutoSplitter,
+) -> anyhow::Result<()> {
+    let index = timer
+        .read()
+        .map_err(|e| anyhow!("failed to acquire read lock on timer: {e}"))?
+        .current_split_index();
+    autosplitter.set_current_split(index);
+    Ok(())
+}
+
+/// Splits once for each missed split, in order, at the game time it was
+/// noticed.
+pub(crate) fn apply_missed_splits(
+    timer: &SharedTimer,
+    missed: &[MissedSplit],
+) -> anyhow::Result<()> {
+    let mut timer = timer
+        .write()
+        .map_err(|e| anyhow!("failed to acquire write lock on timer: {e}"))?;
+    for split in missed {
+        tracing::info!("Splitting for {} missed while disconnected", split.reason);
+        if let Some(t) = split.game_time {
+            timer.set_game_time(t).ok();
+        }
+        timer.split().ok();
+    }
+    Ok(())
+}
+
+/// Looks for splits missed while disconnected, if a run is going, and
+/// applies, offers or logs them as configured.
+fn catch_up(
+    timer: &SharedTimer,
+    app_config: &RwLock<AppConfig>,
+    autosplitter: &dyn AutoSplitter,
+    before: &Progress,
+    pending: &parking_lot::Mutex<Vec<MissedSplit>>,
+) -> anyhow::Result<()> {
+    let phase = timer
+        .read()
+        .map_err(|e| anyhow!("failed to acquire read lock on timer: {e}"))?
+        .current_phase();
+    if phase != TimerPhase::Running {
+        return Ok(());
+    }
+    let missed = autosplitter.missed_splits(before);
+    if missed.is_empty() {
+        return Ok(());
+    }
+    for split in missed.iter() {
+        tracing::warn!("Missed a split while disconnected: {}", split.reason);
+    }
+    match app_config.read().catch_up_splits.unwrap_or_default() {
+        CatchUpSplits::Split => apply_missed_splits(timer, &missed)?,
+        CatchUpSplits::Ask => pending.lock().extend(missed),
+        CatchUpSplits::Off => {}
+    }
+    Ok(())
+}
+
+/// Starts recording a trace of this session, if it can be created.
+fn start_trace(
+    dir: &std::path::Path,
+    client: &crate::backend::Backend,
+    snes_game: &parking_lot::Mutex<Option<DetectedGame>>,
+    polling_rate: f32,
+) -> Option<TraceRecorder<std::io::BufWriter<std::fs::File>>> {
+    let detected = snes_game.lock().clone()?;
+    let meta = TraceMeta {
+        game: detected.game.display_name().to_owned(),
+        rom_name: detected.game.rom_name.clone(),
+        autosplitter: detected.autosplitter.unwrap_or_default().to_owned(),
+        device: client.device_name(),
+        polling_rate,
+        recorded_at: String::new(),
+    };
+    match TraceRecorder::create(dir, &meta) {
+        Ok((recorder, path)) => {
+            tracing::info!("Recording trace to {}", path.display());
+            Some(recorder)
+        }
+        Err(e) => {
+            tracing::warn!("Could not start a trace: {e:#}");
+            None
+        }
+    }
+}
+
 pub fn app_init(
     app: &mut LiveSplitCoreRenderer,
     sync_receiver: std::sync::mpsc::Receiver<ThreadEvent>,
@@ -442,6 +739,14 @@ pub fn app_init(
     let timer = app.timer.clone();
     let settings = app.settings.clone();
     let app_config = app.app_config.clone();
+    let snes_devices = app.snes_devices.clone();
+    let snes_cancel = app.snes_cancel.clone();
+    let snes_game = app.snes_game.clone();
+    let snes_connection = app.snes_connection.clone();
+    let poll_stats = app.poll_stats.clone();
+    let session_replay = app.session_replay.clone();
+    let missed_splits = app.missed_splits.clone();
+    let trace_dir = app.trace_dir();
     // This thread deals with polling the SNES at a fixed rate.
     if app_config.read().use_autosplitter == Some(YesOrNo::Yes) {
         match ThreadBuilder::default()
@@ -450,106 +755,161 @@ pub fn app_init(
             // should leave it at the default to make sure we get timely
             // polling of SNES state
             .spawn(move |_| {
-                if let Err(panic) =
-                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                        if polling_rate > 0.0 {
-                            loop {
-                                let period = std::time::Duration::from_secs_f32(1.0 / polling_rate);
-                                print_on_error(|| -> anyhow::Result<()> {
-                                    let mut client = crate::usb2snes::SyncClient::connect()
-                                        .context("creating usb2snes connection")?;
-                                    client.set_name("annelid")?;
-                                    tracing::info!("Server version is {:?}", client.app_version()?);
-                                    let mut devices = client.list_device()?.to_vec();
-                                    if devices.len() != 1 {
-                                        if devices.is_empty() {
-                                            Err(anyhow!("No devices present"))?;
-                                        } else {
-                                            Err(anyhow!(
-                                                "You need to select a device: {:#?}",
-                                                devices
-                                            ))?;
-                                        }
+                if let Err(panic) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    if polling_rate > 0.0 {
+                        let mut backoff = Backoff::default();
+                        // What the autosplitter had seen when the connection
+                        // dropped, to catch up on splits after reconnecting.
+                        let mut progress: Option<(&'static str, Progress)> = None;
+                        loop {
+                            let period = std::time::Duration::from_secs_f32(1.0 / polling_rate);
+                            let result = log_polling_error(|| -> anyhow::Result<()> {
+                                snes_cancel.reset();
+                                snes_connection.set(ConnectionState::Connecting);
+                                let config = app_config.read().clone();
+                                let mut client = crate::backend::Backend::connect(
+                                    &config,
+                                    crate::usb2snes::ClientOptions {
+                                        cancel: snes_cancel.clone(),
+                                        ..crate::usb2snes::ClientOptions::default()
+                                    },
+                                    |devices| {
+                                        *snes_devices.lock() =
+                                            devices.iter().map(|d| d.to_string()).collect();
+                                    },
+                                )?;
+                                snes_connection.set(ConnectionState::Attached {
+                                    device: client.device_name(),
+                                    game: None,
+                                });
+                                let (entry, mut autosplitter) = wait_for_supported_game(
+                                    &mut client,
+                                    &settings,
+                                    &config,
+                                    &snes_game,
+                                    &snes_connection,
+                                    &snes_cancel,
+                                )?;
+                                let infos = client.info()?;
+                                let can_reset = infos.supports(Capability::ControlCommands);
+                                let warnings = capability_warnings(
+                                    entry,
+                                    &infos,
+                                    config.reset_game_on_timer_reset == Some(YesOrNo::Yes),
+                                );
+                                for warning in warnings.iter() {
+                                    tracing::warn!("{warning}");
+                                }
+                                snes_connection.set(ConnectionState::Polling {
+                                    device: client.device_name(),
+                                    game: snes_game
+                                        .lock()
+                                        .as_ref()
+                                        .map(|g| g.game.display_name().to_owned())
+                                        .unwrap_or_default(),
+                                    warnings,
+                                });
+                                backoff.reset();
+                                let mut catching_up = progress
+                                    .clone()
+                                    .filter(|(name, _)| *name == entry.name)
+                                    .map(|(_, before)| before);
+                                let mut recorder = if config.record_traces == Some(YesOrNo::Yes) {
+                                    start_trace(&trace_dir, &client, &snes_game, polling_rate)
+                                } else {
+                                    None
+                                };
+                                poll_stats.lock().period = period;
+                                let mut scheduled = std::time::Instant::now();
+                                let mut next = scheduled + period;
+                                loop {
+                                    if !session_replay.is_active() {
+                                        sync_current_split(&timer, autosplitter.as_mut())?;
                                     }
-                                    let device =
-                                        devices.pop().ok_or(anyhow!("Device list was empty"))?;
-                                    tracing::info!("Using device: {device}");
-                                    client.attach(&device)?;
-                                    tracing::info!("Connected.");
-                                    tracing::debug!("{:#?}", client.info()?);
-                                    let mut autosplitter: Box<dyn AutoSplitter> =
-                                        Box::new(SuperMetroidAutoSplitter::new(settings.clone()));
-                                    let mut next = std::time::Instant::now() + period;
-                                    loop {
-                                        let summary = autosplitter.update(&mut client)?;
-                                        if summary.start {
-                                            timer
-                                        .write()
-                                        .map_err(|e| {
-                                            anyhow!("failed to acquire write lock on timer: {e}")
-                                        })?
-                                        .start()
-                                        .ok();
-                                        }
-                                        if summary.reset
-                                            && app_config.read().reset_timer_on_game_reset
-                                                == Some(YesOrNo::Yes)
-                                        {
-                                            timer
-                                        .write()
-                                        .map_err(|e| {
-                                            anyhow!("failed to acquire write lock on timer: {e}")
-                                        })?
-                                        .reset(true)
-                                        .ok();
+                                    let poll_start = std::time::Instant::now();
+                                    let mut timed = TimedSource::new(&mut client);
+                                    let summary = match recorder.as_mut() {
+                                        Some(recorder) => {
+                                            autosplitter.update(&mut recorder.wrap(&mut timed))?
                                         }
-                                        if summary.split {
-                                            if let Some(t) = autosplitter.gametime_to_seconds() {
-                                                timer
-                                                    .write()
-                                                    .map_err(|e| {
-                                                        anyhow!(
-                                                    "failed to acquire write lock on timer: {e}"
-                                                )
-                                                    })?
-                                                    .set_game_time(t)
-                                                    .ok();
-                                            }
-                                            timer
-                                        .write()
-                                        .map_err(|e| {
-                                            anyhow!("failed to acquire write lock on timer: {e}")
-                                        })?
-                                        .split()
-                                        .ok();
-                                        }
-                                        // If the timer gets reset, we need to make a fresh snes state
-                                        if let Ok(ThreadEvent::TimerReset) =
-                                            sync_receiver.try_recv()
+                                        None => autosplitter.update(&mut timed)?,
+                                    };
+                                    let sample = PollSample {
+                                        round_trip: timed.elapsed,
+                                        lateness: poll_start.saturating_duration_since(scheduled),
+                                    };
+                                    if let Some(before) =
+                                        catching_up.take().filter(|_| !session_replay.is_active())
+                                    {
+                                        catch_up(
+                                            &timer,
+                                            &app_config,
+                                            autosplitter.as_ref(),
+                                            &before,
+                                            &missed_splits,
+                                        )?;
+                                    }
+                                    // The session replay window owns the timer while it plays
+                                    if !session_replay.is_active() {
+                                        apply_summary(
+                                            &timer,
+                                            &app_config,
+                                            autosplitter.as_ref(),
+                                            summary,
+                                            true,
+                                        )?;
+                                    }
+                                    progress = if summary.reset {
+                                        None
+                                    } else {
+                                        autosplitter.progress().map(|p| (entry.name, p))
+                                    };
+                                    // If the timer gets reset, we need to make a fresh snes state
+                                    if let Ok(ThreadEvent::TimerReset) = sync_receiver.try_recv() {
+                                        autosplitter.reset_game_tracking();
+                                        progress = None;
+                                        missed_splits.lock().clear();
+                                        //Reset the snes
+                                        if app_config.read().reset_game_on_timer_reset
+                                            == Some(YesOrNo::Yes)
+                                            && can_reset
+                                            && !session_replay.is_active()
                                         {
-                                            autosplitter.reset_game_tracking();
-                                            //Reset the snes
-                                            if app_config.read().reset_game_on_timer_reset
-                                                == Some(YesOrNo::Yes)
-                                            {
-                                                client.reset()?;
-                                            }
-                                        }
-                                        let now = std::time::Instant::now();
-                                        if now < next {
-                                            std::thread::sleep(next - now);
-                                            next += period;
-                                        } else {
-                                            // skip sleep; we are late
-                                            next = now + period;
+                                            client.reset()?;
                                         }
                                     }
-                                });
-                                std::thread::sleep(std::time::Duration::from_millis(1000));
+                                    let now = std::time::Instant::now();
+                                    poll_stats.lock().record(sample, now >= next);
+                                    if now < next {
+                                        std::thread::sleep(next - now);
+                                        scheduled = next;
+                                        next += period;
+                                    } else {
+                                        // skip sleep; we are late
+                                        scheduled = now;
+                                        next = now + period;
+                                    }
+                                }
+                            });
+                            let Err(e) = result else { continue };
+                            if matches!(
+                                crate::usb2snes::USB2SnesError::from_anyhow(&e),
+                                Some(crate::usb2snes::USB2SnesError::Cancelled)
+                            ) {
+                                // The settings changed; try them right away.
+                                snes_connection.set(ConnectionState::Disconnected);
+                                backoff.reset();
+                                continue;
                             }
+                            let delay = backoff.next_delay();
+                            snes_connection.set(ConnectionState::Error {
+                                reason: e.to_string(),
+                                retry_in: delay,
+                            });
+                            wait_to_reconnect(delay, &snes_cancel);
                         }
-                    }))
-                {
+                    }
+                })) {
                     let msg = panic_payload_to_string(&panic);
                     tracing::error!("SNES polling thread panicked: {msg}");
                 }
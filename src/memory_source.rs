@@ -0,0 +1,419 @@
+//! Where autosplitters get SNES memory from.
+//!
+//! Every transport implements `MemorySource`, so a game module only needs
+//! batched reads and never sees a socket. `InMemorySource` and
+//! `ReplaySource` stand in for a console in tests and when replaying a
+//! recorded session.
+
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use crate::autosplitters::registry::{GameInfo, RomHeader, HEADER_ADDRESSES, HEADER_SIZE};
+use crate::retroarch::RetroArchClient;
+use crate::sni::SniClient;
+use crate::usb2snes::{
+    Capability, Infos, Space, SyncClient, USB2SnesError, SRAM_SIZE, SRAM_START, WRAM_SIZE,
+    WRAM_START,
+};
+
+/// Size of the sd2snes command area.
+pub const CMD_SIZE: u32 = 0x10000;
+
+/// A region of memory that watchers can read from. Offsets within a domain
+/// start at zero, so a WRAM watcher at `0x079B` reads `$7E:079B`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MemoryDomain {
+    #[default]
+    Wram,
+    /// Cartridge save RAM.
+    Sram,
+    /// The ROM as the sd2snes maps it, from `0x000000` up to SRAM.
+    Rom,
+    /// The sd2snes command area, read from the `CMD` space.
+    Cmd,
+}
+
+impl MemoryDomain {
+    pub const ALL: [MemoryDomain; 4] = [
+        MemoryDomain::Wram,
+        MemoryDomain::Sram,
+        MemoryDomain::Rom,
+        MemoryDomain::Cmd,
+    ];
+
+    pub fn space(self) -> Space {
+        match self {
+            MemoryDomain::Cmd => Space::CMD,
+            _ => Space::SNES,
+        }
+    }
+
+    /// Where the domain starts in its space.
+    pub fn base(self) -> u32 {
+        match self {
+            MemoryDomain::Wram => WRAM_START,
+            MemoryDomain::Sram => SRAM_START,
+            MemoryDomain::Rom | MemoryDomain::Cmd => 0,
+        }
+    }
+
+    pub fn size(self) -> u32 {
+        match self {
+            MemoryDomain::Wram => WRAM_SIZE,
+            MemoryDomain::Sram => SRAM_SIZE,
+            MemoryDomain::Rom => SRAM_START,
+            MemoryDomain::Cmd => CMD_SIZE,
+        }
+    }
+
+    /// The address in `space()` of `size` bytes at `offset`, or an error
+    /// if they don't fit in the domain.
+    pub fn address(self, offset: u32, size: usize) -> Result<u32> {
+        if offset as u64 + size as u64 > self.size() as u64 {
+            Err(anyhow!(
+                "{size} byte read at offset {offset:#x} runs past the end of {self} ({:#x} bytes)",
+                self.size()
+            ))?;
+        }
+        Ok(self.base() + offset)
+    }
+}
+
+impl std::fmt::Display for MemoryDomain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            MemoryDomain::Wram => "WRAM",
+            MemoryDomain::Sram => "SRAM",
+            MemoryDomain::Rom => "ROM",
+            MemoryDomain::Cmd => "CMD",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Reads `(domain, offset, size)` regions, returning one buffer per region.
+/// Everything in the `SNES` space goes out in a single `get_addresses`
+/// call, so adding SRAM or ROM watchers doesn't add a round trip when the
+/// device can batch them.
+pub fn read_domains(
+    source: &mut dyn MemorySource,
+    regions: &[(MemoryDomain, u32, usize)],
+) -> Result<Vec<Vec<u8>>> {
+    let mut snes = vec![];
+    let mut cmd = vec![];
+    for (i, &(domain, offset, size)) in regions.iter().enumerate() {
+        let read = (i, (domain.address(offset, size)?, size));
+        match domain.space() {
+            Space::CMD => cmd.push(read),
+            _ => snes.push(read),
+        }
+    }
+    let mut result = vec![vec![]; regions.len()];
+    for (space, reads) in [(Space::SNES, snes), (Space::CMD, cmd)] {
+        if reads.is_empty() {
+            continue;
+        }
+        let pairs: Vec<(u32, usize)> = reads.iter().map(|&(_, pair)| pair).collect();
+        let data = source.get_space_addresses(space, &pairs)?;
+        for ((i, _), data) in reads.into_iter().zip(data) {
+            result[i] = data;
+        }
+    }
+    Ok(result)
+}
+
+/// Addresses are in the usb2snes address space throughout, e.g. WRAM
+/// starts at `0xF50000`.
+pub trait MemorySource {
+    /// Reads each `(address, size)` region, returning one buffer per region.
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>>;
+
+    fn info(&mut self) -> Result<Infos>;
+
+    /// Reads regions from another memory space, such as the sd2snes `CMD`
+    /// area. Only devices that have one need to override this.
+    fn get_space_addresses(
+        &mut self,
+        space: Space,
+        pairs: &[(u32, usize)],
+    ) -> Result<Vec<Vec<u8>>> {
+        match space {
+            Space::SNES => self.get_addresses(pairs),
+            _ => Err(USB2SnesError::Unsupported(format!("reading {space} space")))?,
+        }
+    }
+
+    /// Writes `data` at `address`. Sources that can't write say so.
+    fn write_address(&mut self, _address: u32, _data: &[u8]) -> Result<()> {
+        Err(USB2SnesError::Unsupported("writing memory".to_owned()))?
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        Err(USB2SnesError::Unsupported("resetting".to_owned()))?
+    }
+
+    /// Identifies the running game from `Info` and, when the device allows
+    /// ROM reads, the cartridge header.
+    fn game_info(&mut self) -> Result<GameInfo> {
+        let info = self.info()?;
+        let header = if !info.supports(Capability::RomRead) {
+            None
+        } else {
+            let candidates = self.get_addresses(&HEADER_ADDRESSES.map(|a| (a, HEADER_SIZE)))?;
+            RomHeader::find(&candidates)
+        };
+        Ok(GameInfo {
+            rom_name: info.game.to_string(),
+            header,
+        })
+    }
+}
+
+impl MemorySource for SyncClient {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        SyncClient::get_addresses(self, pairs)
+    }
+
+    fn get_space_addresses(
+        &mut self,
+        space: Space,
+        pairs: &[(u32, usize)],
+    ) -> Result<Vec<Vec<u8>>> {
+        SyncClient::get_space_addresses(self, space, pairs)
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        SyncClient::info(self)
+    }
+
+    fn write_address(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.put_address(Space::SNES, address, data)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        SyncClient::reset(self)
+    }
+}
+
+impl MemorySource for SniClient {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        SniClient::get_addresses(self, pairs)
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        SniClient::info(self)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        SniClient::reset(self)
+    }
+}
+
+impl MemorySource for RetroArchClient {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        RetroArchClient::get_addresses(self, pairs)
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        RetroArchClient::info(self)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        RetroArchClient::reset(self)
+    }
+}
+
+/// Memory held in a map, for tests. Bytes that were never written read as
+/// zero.
+#[derive(Clone, Debug)]
+pub struct InMemorySource {
+    memory: HashMap<u32, u8>,
+    cmd: HashMap<u32, u8>,
+    infos: Infos,
+    reads: Vec<Vec<(u32, usize)>>,
+    resets: usize,
+}
+
+impl Default for InMemorySource {
+    fn default() -> Self {
+        InMemorySource {
+            memory: HashMap::new(),
+            cmd: HashMap::new(),
+            infos: Infos {
+                version: Rc::from("1.0"),
+                dev_type: Rc::from("InMemory"),
+                game: Rc::from(""),
+                flags: vec![],
+            },
+            reads: vec![],
+            resets: 0,
+        }
+    }
+}
+
+impl InMemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, address: u32, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.memory.insert(address + i as u32, *byte);
+        }
+    }
+
+    pub fn read(&self, address: u32, size: usize) -> Vec<u8> {
+        (0..size as u32)
+            .map(|i| self.memory.get(&(address + i)).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /// Writes to the `CMD` space, which is separate from the rest.
+    pub fn write_cmd(&mut self, address: u32, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.cmd.insert(address + i as u32, *byte);
+        }
+    }
+
+    /// Sets what `info` reports as the loaded ROM.
+    pub fn set_game(&mut self, rom_name: &str) {
+        self.infos.game = Rc::from(rom_name);
+    }
+
+    pub fn set_flags(&mut self, flags: &[&str]) {
+        self.infos.flags = flags.iter().map(|f| Rc::from(*f)).collect();
+    }
+
+    /// The regions asked for by each read so far, in any space.
+    pub fn reads(&self) -> &[Vec<(u32, usize)>] {
+        &self.reads
+    }
+
+    pub fn resets(&self) -> usize {
+        self.resets
+    }
+}
+
+impl MemorySource for InMemorySource {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        self.reads.push(pairs.to_vec());
+        Ok(pairs
+            .iter()
+            .map(|&(address, size)| self.read(address, size))
+            .collect())
+    }
+
+    fn get_space_addresses(
+        &mut self,
+        space: Space,
+        pairs: &[(u32, usize)],
+    ) -> Result<Vec<Vec<u8>>> {
+        if space != Space::CMD {
+            return self.get_addresses(pairs);
+        }
+        self.reads.push(pairs.to_vec());
+        Ok(pairs
+            .iter()
+            .map(|&(address, size)| {
+                (0..size as u32)
+                    .map(|i| self.cmd.get(&(address + i)).copied().unwrap_or(0))
+                    .collect()
+            })
+            .collect())
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        Ok(self.infos.clone())
+    }
+
+    fn write_address(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.write(address, data);
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.resets += 1;
+        Ok(())
+    }
+}
+
+/// Returned by `ReplaySource` once every frame has been read.
+#[derive(Debug)]
+pub struct ReplayFinished;
+
+impl std::fmt::Display for ReplayFinished {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "end of replay")
+    }
+}
+
+impl std::error::Error for ReplayFinished {}
+
+/// The memory changes to apply before one poll.
+pub type Frame = Vec<(u32, Vec<u8>)>;
+
+/// Plays back memory one frame per `get_addresses` call. Each frame's
+/// writes are applied on top of the previous ones, so a frame only needs
+/// what changed. Reads after the last frame fail with `ReplayFinished`.
+#[derive(Clone, Debug, Default)]
+pub struct ReplaySource {
+    memory: InMemorySource,
+    frames: VecDeque<Frame>,
+}
+
+impl ReplaySource {
+    pub fn new(frames: impl IntoIterator<Item = Frame>) -> Self {
+        ReplaySource {
+            memory: InMemorySource::new(),
+            frames: frames.into_iter().collect(),
+        }
+    }
+
+    /// Starts from `memory` rather than all zeroes, e.g. to set up `Info`.
+    pub fn with_memory(memory: InMemorySource, frames: impl IntoIterator<Item = Frame>) -> Self {
+        ReplaySource {
+            memory,
+            frames: frames.into_iter().collect(),
+        }
+    }
+
+    pub fn push_frame(&mut self, frame: Frame) {
+        self.frames.push_back(frame);
+    }
+
+    /// Frames not yet played.
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn memory(&self) -> &InMemorySource {
+        &self.memory
+    }
+}
+
+impl MemorySource for ReplaySource {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        let Some(frame) = self.frames.pop_front() else {
+            Err(ReplayFinished)?
+        };
+        for (address, data) in frame.iter() {
+            self.memory.write(*address, data);
+        }
+        self.memory.get_addresses(pairs)
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        self.memory.info()
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.memory.reset()
+    }
+
+    /// Detection reads the header from the current memory without using up
+    /// a frame.
+    fn game_info(&mut self) -> Result<GameInfo> {
+        self.memory.game_info()
+    }
+}
@@ -0,0 +1,246 @@
+//! Timing of the autosplitter's polling loop: how long each memory read
+//! takes and how closely polls keep to the configured `polling_rate`.
+
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::autosplitters::registry::GameInfo;
+use crate::memory_source::MemorySource;
+use crate::usb2snes::{Infos, Space};
+
+/// How many recent polls the statistics cover.
+pub const HISTORY_LEN: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PollSample {
+    /// Time spent waiting on memory reads during the poll, as measured by
+    /// `TimedSource`.
+    pub round_trip: Duration,
+    /// How long after its scheduled time the poll started.
+    pub lateness: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PollSummary {
+    pub count: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    /// Standard deviation of the round trip.
+    pub jitter: Duration,
+    pub mean_lateness: Duration,
+    pub max_lateness: Duration,
+}
+
+/// Round trips bucketed by `bucket_width`, starting at zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    pub bucket_width: Duration,
+    pub counts: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PollStats {
+    samples: VecDeque<PollSample>,
+    /// Interval between polls implied by `polling_rate`.
+    pub period: Duration,
+    /// Polls since the last reset, including those no longer in the history.
+    pub polls: u64,
+    /// Polls that finished after the next one was due.
+    pub missed_deadlines: u64,
+}
+
+impl PollStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, sample: PollSample, missed_deadline: bool) {
+        if self.samples.len() == HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.polls += 1;
+        if missed_deadline {
+            self.missed_deadlines += 1;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.polls = 0;
+        self.missed_deadlines = 0;
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &PollSample> {
+        self.samples.iter()
+    }
+
+    /// `None` until something has been recorded.
+    pub fn summary(&self) -> Option<PollSummary> {
+        let count = self.samples.len();
+        if count == 0 {
+            return None;
+        }
+        let mut round_trips: Vec<Duration> = self.samples.iter().map(|s| s.round_trip).collect();
+        round_trips.sort();
+        let percentile = |p: usize| round_trips[((count - 1) * p).div_ceil(100)];
+        let total: Duration = round_trips.iter().sum();
+        let mean = total / count as u32;
+        let variance = round_trips
+            .iter()
+            .map(|rt| (rt.as_secs_f64() - mean.as_secs_f64()).powi(2))
+            .sum::<f64>()
+            / count as f64;
+        let lateness: Duration = self.samples.iter().map(|s| s.lateness).sum();
+        Some(PollSummary {
+            count,
+            min: round_trips[0],
+            mean,
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: round_trips[count - 1],
+            jitter: Duration::from_secs_f64(variance.sqrt()),
+            mean_lateness: lateness / count as u32,
+            max_lateness: self
+                .samples
+                .iter()
+                .map(|s| s.lateness)
+                .max()
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Splits the round trips into `buckets` equal buckets covering zero to
+    /// the slowest poll, each at least 100µs wide.
+    pub fn histogram(&self, buckets: usize) -> Histogram {
+        let buckets = buckets.max(1);
+        let max = self
+            .samples
+            .iter()
+            .map(|s| s.round_trip)
+            .max()
+            .unwrap_or_default();
+        let width_us = (max.as_micros() as u64 / buckets as u64 + 1).max(100);
+        let mut counts = vec![0; buckets];
+        for sample in self.samples.iter() {
+            let bucket = (sample.round_trip.as_micros() as u64 / width_us) as usize;
+            counts[bucket.min(buckets - 1)] += 1;
+        }
+        Histogram {
+            bucket_width: Duration::from_micros(width_us),
+            counts,
+        }
+    }
+
+    /// A plain-text summary suitable for pasting into a bug report or a
+    /// run submission.
+    pub fn report(&self) -> String {
+        let ms = |d: Duration| format!("{:.2} ms", d.as_secs_f64() * 1000.0);
+        let mut out = String::new();
+        let _ = writeln!(out, "Poll period: {}", ms(self.period));
+        let _ = writeln!(out, "Polls: {}", self.polls);
+        let _ = writeln!(
+            out,
+            "Missed deadlines: {} ({:.2}%)",
+            self.missed_deadlines,
+            self.missed_percent()
+        );
+        match self.summary() {
+            None => {
+                let _ = writeln!(out, "No samples yet");
+            }
+            Some(s) => {
+                let _ = writeln!(out, "Last {} polls:", s.count);
+                let _ = writeln!(
+                    out,
+                    "  Round trip: min {}, mean {}, max {}",
+                    ms(s.min),
+                    ms(s.mean),
+                    ms(s.max)
+                );
+                let _ = writeln!(
+                    out,
+                    "  Percentiles: p50 {}, p95 {}, p99 {}",
+                    ms(s.p50),
+                    ms(s.p95),
+                    ms(s.p99)
+                );
+                let _ = writeln!(out, "  Jitter (std dev): {}", ms(s.jitter));
+                let _ = writeln!(
+                    out,
+                    "  Start lateness: mean {}, max {}",
+                    ms(s.mean_lateness),
+                    ms(s.max_lateness)
+                );
+            }
+        }
+        out
+    }
+
+    pub fn missed_percent(&self) -> f64 {
+        if self.polls == 0 {
+            0.0
+        } else {
+            self.missed_deadlines as f64 * 100.0 / self.polls as f64
+        }
+    }
+}
+
+/// A source that adds the time spent in each read to `elapsed`, so a
+/// poll's round trip leaves out the autosplitter's own work.
+pub struct TimedSource<'a> {
+    inner: &'a mut dyn MemorySource,
+    pub elapsed: Duration,
+}
+
+impl<'a> TimedSource<'a> {
+    pub fn new(inner: &'a mut dyn MemorySource) -> Self {
+        TimedSource {
+            inner,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl MemorySource for TimedSource<'_> {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        let start = Instant::now();
+        let data = self.inner.get_addresses(pairs);
+        self.elapsed += start.elapsed();
+        data
+    }
+
+    fn get_space_addresses(
+        &mut self,
+        space: Space,
+        pairs: &[(u32, usize)],
+    ) -> Result<Vec<Vec<u8>>> {
+        let start = Instant::now();
+        let data = self.inner.get_space_addresses(space, pairs);
+        self.elapsed += start.elapsed();
+        data
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        self.inner.info()
+    }
+
+    fn write_address(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.inner.write_address(address, data)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.inner.reset()
+    }
+
+    fn game_info(&mut self) -> Result<GameInfo> {
+        self.inner.game_info()
+    }
+}
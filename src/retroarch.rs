@@ -0,0 +1,240 @@
+//! A client for RetroArch's network command interface.
+//!
+//! With "Network Commands" enabled, RetroArch listens for plain text
+//! commands on a UDP port. `READ_CORE_MEMORY` reads through the core's
+//! memory map, so addresses are on the SNES bus: WRAM is `$7E0000`.
+//! `get_addresses` takes usb2snes addresses like the other backends and
+//! translates them.
+
+use anyhow::{anyhow, Context, Result};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::usb2snes::{
+    is_timeout, CancelHandle, ClientOptions, Infos, USB2SnesError, FLAG_NO_FILE_CMD,
+    FLAG_NO_ROM_READ, FLAG_NO_ROM_WRITE, READ_POLL_INTERVAL, WRAM_SIZE, WRAM_START,
+};
+
+pub const DEFAULT_RETROARCH_PORT: u16 = 55355;
+/// Where WRAM starts on the SNES bus.
+pub const BUS_WRAM_START: u32 = 0x7E0000;
+/// Largest read sent in one command. Replies spell out each byte as three
+/// characters and must fit in a single datagram.
+pub const MAX_READ_SIZE: usize = 2048;
+const MAX_REPLY_SIZE: usize = 65536;
+
+/// What `GET_STATUS` reports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// `PLAYING`, `PAUSED` or `CONTENTLESS`.
+    pub state: String,
+    /// The core's system id, e.g. `super_nes`.
+    pub system: String,
+    /// The loaded content's name, usually the ROM file name without its
+    /// extension.
+    pub content: String,
+    pub crc32: Option<u32>,
+}
+
+impl Status {
+    pub fn parse(reply: &str) -> Result<Status> {
+        let rest = reply
+            .trim()
+            .strip_prefix("GET_STATUS ")
+            .ok_or_else(|| anyhow!("unexpected reply to GET_STATUS: {reply:?}"))?;
+        let (state, details) = rest.split_once(' ').unwrap_or((rest, ""));
+        let mut fields = details.split(',');
+        let system = fields.next().unwrap_or_default().to_owned();
+        let mut content = vec![];
+        let mut crc32 = None;
+        // The content name may itself contain commas.
+        for field in fields {
+            match field.strip_prefix("crc32=") {
+                Some(hex) => crc32 = u32::from_str_radix(hex, 16).ok(),
+                None => content.push(field),
+            }
+        }
+        Ok(Status {
+            state: state.to_owned(),
+            system,
+            content: content.join(","),
+            crc32,
+        })
+    }
+}
+
+/// Maps a usb2snes address to the SNES bus. Only WRAM is mapped the same
+/// way by every core, so anything else is refused.
+pub fn bus_address(address: u32, size: usize) -> Result<u32> {
+    let end = address as u64 + size as u64;
+    if address >= WRAM_START && end <= (WRAM_START + WRAM_SIZE) as u64 {
+        Ok(address - WRAM_START + BUS_WRAM_START)
+    } else {
+        Err(USB2SnesError::Unsupported(format!(
+            "reading ${address:06X} through RetroArch (only WRAM is mapped)"
+        )))?
+    }
+}
+
+/// The address a `READ_CORE_MEMORY` reply is for, if `reply` is one.
+fn read_reply_address(reply: &str) -> Option<u32> {
+    let mut words = reply.split_ascii_whitespace();
+    if words.next()? != "READ_CORE_MEMORY" {
+        return None;
+    }
+    u32::from_str_radix(words.next()?, 16).ok()
+}
+
+pub struct RetroArchClient {
+    socket: UdpSocket,
+    version: String,
+    read_timeout: std::time::Duration,
+    cancel: CancelHandle,
+    devel: bool,
+}
+
+impl RetroArchClient {
+    /// Opens a socket to RetroArch and checks that it answers `VERSION`.
+    pub fn connect(host: &str, port: u16, options: ClientOptions) -> Result<RetroArchClient> {
+        let address = (host.trim(), port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("could not resolve {host}"))?;
+        let local = if address.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+        socket.set_read_timeout(Some(READ_POLL_INTERVAL))?;
+        let mut client = RetroArchClient {
+            socket,
+            version: String::new(),
+            read_timeout: options.connect_timeout,
+            cancel: options.cancel,
+            devel: options.devel,
+        };
+        let version = client
+            .command("VERSION", |_| true)
+            .context("RetroArch did not answer; is Network Commands enabled?")?;
+        client.version = version.trim().to_owned();
+        client.read_timeout = options.read_timeout;
+        Ok(client)
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The name offered in the device picker.
+    pub fn device_name(&self) -> String {
+        format!("RetroArch {}", self.version)
+    }
+
+    /// Sends `command` and waits for a reply that `is_reply` accepts.
+    /// Anything else is a late answer to an earlier command and is dropped.
+    fn command(&mut self, command: &str, is_reply: impl Fn(&str) -> bool) -> Result<String> {
+        if self.devel {
+            tracing::debug!("Send command : {command}");
+        }
+        self.socket.send(format!("{command}\n").as_bytes())?;
+        let deadline = Instant::now() + self.read_timeout;
+        let mut buf = vec![0; MAX_REPLY_SIZE];
+        loop {
+            if self.cancel.is_cancelled() {
+                Err(USB2SnesError::Cancelled)?;
+            }
+            match self.socket.recv(&mut buf) {
+                Ok(n) => {
+                    let reply = String::from_utf8_lossy(&buf[..n]).into_owned();
+                    if is_reply(&reply) {
+                        return Ok(reply);
+                    }
+                    tracing::debug!("Ignoring stale RetroArch reply: {reply:?}");
+                }
+                Err(e) if is_timeout(&e) => {
+                    if Instant::now() >= deadline {
+                        Err(USB2SnesError::Timeout(self.read_timeout))?;
+                    }
+                }
+                Err(e) => Err(e)?,
+            }
+        }
+    }
+
+    /// Sends a command that has no reply.
+    fn notify(&mut self, command: &str) -> Result<()> {
+        self.socket.send(format!("{command}\n").as_bytes())?;
+        Ok(())
+    }
+
+    pub fn status(&mut self) -> Result<Status> {
+        let reply = self.command("GET_STATUS", |r| r.starts_with("GET_STATUS "))?;
+        Status::parse(&reply)
+    }
+
+    /// Reads `size` bytes at `address` on the SNES bus.
+    pub fn read_core_memory(&mut self, address: u32, size: usize) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(size);
+        while data.len() < size {
+            let chunk_address = address + data.len() as u32;
+            let chunk_size = (size - data.len()).min(MAX_READ_SIZE);
+            let reply = self.command(
+                &format!("READ_CORE_MEMORY {chunk_address:x} {chunk_size}"),
+                |r| read_reply_address(r) == Some(chunk_address),
+            )?;
+            // Skip the command name and address.
+            let mut bytes = reply.split_ascii_whitespace().skip(2).peekable();
+            if bytes.peek() == Some(&"-1") {
+                let error = bytes.by_ref().skip(1).collect::<Vec<_>>().join(" ");
+                Err(anyhow!(
+                    "RetroArch could not read ${chunk_address:06X}: {error}"
+                ))?;
+            }
+            let chunk = bytes
+                .map(|b| u8::from_str_radix(b, 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .with_context(|| format!("malformed READ_CORE_MEMORY reply: {reply:?}"))?;
+            if chunk.len() != chunk_size {
+                Err(anyhow!(
+                    "RetroArch returned {} bytes at ${chunk_address:06X}, expected {chunk_size}",
+                    chunk.len()
+                ))?;
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data)
+    }
+
+    /// Reads each region, given as usb2snes addresses.
+    pub fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        pairs
+            .iter()
+            .map(|&(address, size)| {
+                let address = bus_address(address, size)?;
+                self.read_core_memory(address, size)
+            })
+            .collect()
+    }
+
+    /// RetroArch can't read ROM, write memory or touch files through this
+    /// interface, so `Info` says as much. It can reset the game.
+    pub fn info(&mut self) -> Result<Infos> {
+        let status = self.status()?;
+        Ok(Infos {
+            version: Rc::from(self.version.as_str()),
+            dev_type: Rc::from("RetroArch"),
+            game: Rc::from(status.content.as_str()),
+            flags: [FLAG_NO_ROM_READ, FLAG_NO_ROM_WRITE, FLAG_NO_FILE_CMD]
+                .into_iter()
+                .map(Rc::from)
+                .collect(),
+        })
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        self.notify("RESET")
+    }
+}
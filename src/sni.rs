@@ -0,0 +1,371 @@
+//! A client for SNI's native gRPC API.
+//!
+//! SNI also speaks the usb2snes WebSocket protocol, but going through its
+//! gRPC `DeviceMemory` service directly saves the compatibility shim and
+//! lets us batch reads with `MultiRead`. The messages below mirror the
+//! subset of `sni.proto` we use; they are written out by hand so building
+//! Annelid does not need `protoc`.
+
+use anyhow::{anyhow, Result};
+use std::rc::Rc;
+use std::time::Duration;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Channel;
+
+use crate::usb2snes::{CancelHandle, ClientOptions, Infos, USB2SnesError};
+
+pub const DEFAULT_SNI_PORT: u16 = 8191;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum AddressSpace {
+    /// The address space used by usb2snes, e.g. `$F5_0000` is WRAM.
+    FxPakPro = 0,
+    SnesABus = 1,
+    Raw = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum MemoryMapping {
+    Unknown = 0,
+    HiRom = 1,
+    LoRom = 2,
+    ExHiRom = 3,
+    Sa1 = 4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum DeviceCapability {
+    None = 0,
+    ReadMemory = 1,
+    WriteMemory = 2,
+    ExecuteAsm = 3,
+    ResetSystem = 4,
+    PauseUnpauseEmulation = 5,
+    PauseToggleEmulation = 6,
+    ResetToMenu = 7,
+    FetchFields = 8,
+    ReadDirectory = 10,
+    MakeDirectory = 11,
+    RemoveFile = 12,
+    RenameFile = 13,
+    PutFile = 14,
+    GetFile = 15,
+    BootFile = 16,
+    NwaCommand = 17,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Field {
+    DeviceName = 0,
+    DeviceVersion = 1,
+    DeviceStatus = 2,
+    CoreName = 20,
+    CoreVersion = 21,
+    CorePlatform = 22,
+    RomFileName = 40,
+    RomHashType = 41,
+    RomHashValue = 42,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DevicesRequest {
+    #[prost(string, repeated, tag = "1")]
+    pub kinds: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DevicesResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub devices: Vec<Device>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Device {
+    #[prost(string, tag = "1")]
+    pub uri: String,
+    #[prost(string, tag = "2")]
+    pub display_name: String,
+    #[prost(string, tag = "3")]
+    pub kind: String,
+    #[prost(enumeration = "DeviceCapability", repeated, tag = "4")]
+    pub capabilities: Vec<i32>,
+    #[prost(enumeration = "AddressSpace", tag = "6")]
+    pub default_address_space: i32,
+    #[prost(string, tag = "7")]
+    pub system: String,
+}
+
+impl Device {
+    pub fn has_capability(&self, capability: DeviceCapability) -> bool {
+        self.capabilities.contains(&(capability as i32))
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReadMemoryRequest {
+    #[prost(uint32, tag = "1")]
+    pub request_address: u32,
+    #[prost(enumeration = "AddressSpace", tag = "2")]
+    pub request_address_space: i32,
+    #[prost(enumeration = "MemoryMapping", optional, tag = "4")]
+    pub request_memory_mapping: Option<i32>,
+    #[prost(uint32, tag = "3")]
+    pub size: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ReadMemoryResponse {
+    #[prost(uint32, tag = "1")]
+    pub request_address: u32,
+    #[prost(enumeration = "AddressSpace", tag = "2")]
+    pub request_address_space: i32,
+    #[prost(enumeration = "MemoryMapping", tag = "6")]
+    pub request_memory_mapping: i32,
+    #[prost(uint32, tag = "3")]
+    pub device_address: u32,
+    #[prost(enumeration = "AddressSpace", tag = "4")]
+    pub device_address_space: i32,
+    #[prost(bytes = "vec", tag = "5")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MultiReadMemoryRequest {
+    #[prost(string, tag = "1")]
+    pub uri: String,
+    #[prost(message, repeated, tag = "2")]
+    pub requests: Vec<ReadMemoryRequest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MultiReadMemoryResponse {
+    #[prost(string, tag = "1")]
+    pub uri: String,
+    #[prost(message, repeated, tag = "2")]
+    pub responses: Vec<ReadMemoryResponse>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ResetSystemRequest {
+    #[prost(string, tag = "1")]
+    pub uri: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ResetSystemResponse {
+    #[prost(string, tag = "1")]
+    pub uri: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FieldsRequest {
+    #[prost(string, tag = "1")]
+    pub uri: String,
+    #[prost(enumeration = "Field", repeated, tag = "2")]
+    pub fields: Vec<i32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FieldsResponse {
+    #[prost(string, tag = "1")]
+    pub uri: String,
+    #[prost(enumeration = "Field", repeated, tag = "2")]
+    pub fields: Vec<i32>,
+    #[prost(string, repeated, tag = "3")]
+    pub values: Vec<String>,
+}
+
+/// gRPC method paths. `sni.proto` has no package, so these are bare.
+pub mod paths {
+    pub const LIST_DEVICES: &str = "/Devices/ListDevices";
+    pub const MULTI_READ: &str = "/DeviceMemory/MultiRead";
+    pub const RESET_SYSTEM: &str = "/DeviceControl/ResetSystem";
+    pub const FETCH_FIELDS: &str = "/DeviceInfo/FetchFields";
+}
+
+async fn unary<Req, Resp>(
+    channel: Channel,
+    path: &'static str,
+    request: Req,
+) -> std::result::Result<Resp, tonic::Status>
+where
+    Req: prost::Message + Send + Sync + 'static,
+    Resp: prost::Message + Default + Send + Sync + 'static,
+{
+    let mut grpc = tonic::client::Grpc::new(channel);
+    grpc.ready()
+        .await
+        .map_err(|e| tonic::Status::unavailable(format!("SNI is not ready: {e}")))?;
+    let codec = tonic::codec::ProstCodec::<Req, Resp>::default();
+    let response = grpc
+        .unary(
+            tonic::Request::new(request),
+            PathAndQuery::from_static(path),
+            codec,
+        )
+        .await?;
+    Ok(response.into_inner())
+}
+
+/// A blocking SNI client, so it can be driven from the polling thread the
+/// same way as `usb2snes::SyncClient`.
+pub struct SniClient {
+    runtime: tokio::runtime::Runtime,
+    channel: Channel,
+    device: Option<Device>,
+    read_timeout: Duration,
+    cancel: CancelHandle,
+    devel: bool,
+}
+
+impl SniClient {
+    pub fn connect(host: &str, port: u16, options: ClientOptions) -> Result<SniClient> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let uri = format!("http://{}", crate::usb2snes::server_address(host, port));
+        let endpoint = Channel::from_shared(uri)?
+            .connect_timeout(options.connect_timeout)
+            .tcp_nodelay(true);
+        let channel = runtime
+            .block_on(async {
+                tokio::time::timeout(options.connect_timeout, endpoint.connect()).await
+            })
+            .map_err(|_| USB2SnesError::Timeout(options.connect_timeout))??;
+        Ok(SniClient {
+            runtime,
+            channel,
+            device: None,
+            read_timeout: options.read_timeout,
+            cancel: options.cancel,
+            devel: options.devel,
+        })
+    }
+
+    fn call<Req, Resp>(&mut self, path: &'static str, request: Req) -> Result<Resp>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        if self.cancel.is_cancelled() {
+            Err(USB2SnesError::Cancelled)?;
+        }
+        if self.devel {
+            tracing::debug!("SNI call {path}: {request:?}");
+        }
+        let channel = self.channel.clone();
+        let timeout = self.read_timeout;
+        let response = self
+            .runtime
+            .block_on(async { tokio::time::timeout(timeout, unary(channel, path, request)).await })
+            .map_err(|_| USB2SnesError::Timeout(timeout))?
+            .map_err(|status| anyhow!("SNI call {path} failed: {}", status.message()))?;
+        if self.devel {
+            tracing::debug!("SNI reply: {response:?}");
+        }
+        Ok(response)
+    }
+
+    pub fn list_devices(&mut self) -> Result<Vec<Device>> {
+        let response: DevicesResponse =
+            self.call(paths::LIST_DEVICES, DevicesRequest { kinds: vec![] })?;
+        Ok(response.devices)
+    }
+
+    /// Selects the device that later calls operate on.
+    pub fn attach(&mut self, device: Device) {
+        self.device = Some(device);
+    }
+
+    pub fn device(&self) -> Option<&Device> {
+        self.device.as_ref()
+    }
+
+    fn uri(&self) -> Result<String> {
+        self.device
+            .as_ref()
+            .map(|d| d.uri.clone())
+            .ok_or_else(|| anyhow!("No SNI device attached"))
+    }
+
+    pub fn info(&mut self) -> Result<Infos> {
+        let uri = self.uri()?;
+        let device = self.device.clone().unwrap_or_default();
+        let wanted = [Field::DeviceVersion, Field::RomFileName];
+        let response: FieldsResponse = if device.has_capability(DeviceCapability::FetchFields) {
+            self.call(
+                paths::FETCH_FIELDS,
+                FieldsRequest {
+                    uri,
+                    fields: wanted.iter().map(|f| *f as i32).collect(),
+                },
+            )?
+        } else {
+            FieldsResponse::default()
+        };
+        let value = |field: Field| -> Rc<str> {
+            response
+                .fields
+                .iter()
+                .position(|f| *f == field as i32)
+                .and_then(|i| response.values.get(i))
+                .map(|v| Rc::from(v.as_str()))
+                .unwrap_or_else(|| Rc::from(""))
+        };
+        Ok(Infos {
+            version: value(Field::DeviceVersion),
+            dev_type: Rc::from(device.kind.as_str()),
+            game: value(Field::RomFileName),
+            flags: vec![],
+        })
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        let uri = self.uri()?;
+        let _: ResetSystemResponse = self.call(paths::RESET_SYSTEM, ResetSystemRequest { uri })?;
+        Ok(())
+    }
+
+    /// Reads several regions in one `MultiRead` call. Addresses use the same
+    /// FX Pak Pro address space as usb2snes, e.g. `0xF50000` for WRAM.
+    pub fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        let uri = self.uri()?;
+        let requests = pairs
+            .iter()
+            .map(|&(address, size)| ReadMemoryRequest {
+                request_address: address,
+                request_address_space: AddressSpace::FxPakPro as i32,
+                request_memory_mapping: None,
+                size: size as u32,
+            })
+            .collect();
+        let response: MultiReadMemoryResponse =
+            self.call(paths::MULTI_READ, MultiReadMemoryRequest { uri, requests })?;
+        if response.responses.len() != pairs.len() {
+            Err(anyhow!(
+                "SNI returned {} regions, expected {}",
+                response.responses.len(),
+                pairs.len()
+            ))?;
+        }
+        response
+            .responses
+            .into_iter()
+            .zip(pairs.iter())
+            .map(|(r, &(address, size))| {
+                if r.data.len() < size {
+                    Err(anyhow!(
+                        "SNI returned {} bytes for ${address:06x}, expected {size}",
+                        r.data.len()
+                    ))
+                } else {
+                    Ok(r.data)
+                }
+            })
+            .collect()
+    }
+}
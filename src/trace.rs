@@ -0,0 +1,594 @@
+//! Recording polling sessions to trace files and replaying them.
+//!
+//! A trace starts with `MAGIC`, then one line of JSON `TraceMeta`, then one
+//! binary record per poll (all integers little endian):
+//!
+//! ```text
+//! u8   1 (a poll)
+//! u64  microseconds since recording started
+//! u16  region count
+//! per region:
+//!   u32  address
+//!   u32  size
+//!   u8   0 = same bytes as the last poll of this region, 1 = bytes follow
+//!   [u8; size]
+//! ```
+//!
+//! Most regions don't change from one poll to the next, so the "same as
+//! last time" marker keeps traces small. A trace cut short by a crash is
+//! read up to its last complete poll.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::autosplitters::registry::GameInfo;
+use crate::autosplitters::{AutoSplitter, SNESSummary};
+use crate::memory_source::{InMemorySource, MemorySource, ReplaySource};
+use crate::usb2snes::{Infos, Space, SRAM_SIZE};
+
+pub const MAGIC: &[u8] = b"annelid-trace 1\n";
+pub const TRACE_EXTENSION: &str = "trace";
+const POLL_RECORD: u8 = 1;
+const UNCHANGED: u8 = 0;
+const CHANGED: u8 = 1;
+/// How often the recorder pushes buffered polls to disk.
+/// Largest region a usb2snes read covers, the cartridge's SRAM. Anything
+/// bigger is a corrupt size, not a reason to allocate gigabytes.
+const MAX_REGION_SIZE: usize = SRAM_SIZE as usize;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What was being recorded, stored as JSON at the top of the trace.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TraceMeta {
+    /// The game's display name.
+    pub game: String,
+    /// The ROM name reported by the device, for game detection on replay.
+    pub rom_name: String,
+    pub autosplitter: String,
+    pub device: String,
+    pub polling_rate: f32,
+    pub recorded_at: String,
+}
+
+/// The regions one poll read, and when.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Poll {
+    pub time: Duration,
+    pub regions: Vec<(u32, Vec<u8>)>,
+}
+
+pub struct TraceWriter<W: Write> {
+    out: W,
+    last: HashMap<(u32, usize), Vec<u8>>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut out: W, meta: &TraceMeta) -> Result<Self> {
+        out.write_all(MAGIC)?;
+        serde_json::to_writer(&mut out, meta)?;
+        out.write_all(b"\n")?;
+        Ok(TraceWriter {
+            out,
+            last: HashMap::new(),
+        })
+    }
+
+    pub fn write_poll(&mut self, poll: &Poll) -> Result<()> {
+        let count = u16::try_from(poll.regions.len())
+            .map_err(|_| anyhow!("too many regions in one poll: {}", poll.regions.len()))?;
+        self.out.write_all(&[POLL_RECORD])?;
+        self.out
+            .write_all(&(poll.time.as_micros() as u64).to_le_bytes())?;
+        self.out.write_all(&count.to_le_bytes())?;
+        for (address, data) in poll.regions.iter() {
+            self.out.write_all(&address.to_le_bytes())?;
+            self.out.write_all(&(data.len() as u32).to_le_bytes())?;
+            let key = (*address, data.len());
+            if self.last.get(&key) == Some(data) {
+                self.out.write_all(&[UNCHANGED])?;
+            } else {
+                self.out.write_all(&[CHANGED])?;
+                self.out.write_all(data)?;
+                self.last.insert(key, data.clone());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+
+    pub fn into_inner(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    pub meta: TraceMeta,
+    pub polls: Vec<Poll>,
+    /// The file ended partway through a poll, e.g. because Annelid crashed
+    /// while recording.
+    pub truncated: bool,
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads one poll record after its tag byte.
+fn read_poll(
+    input: &mut impl Read,
+    last: &mut HashMap<(u32, usize), Vec<u8>>,
+) -> Result<Poll, std::io::Error> {
+    let time = Duration::from_micros(u64::from_le_bytes(read_array(input)?));
+    let count = u16::from_le_bytes(read_array(input)?);
+    let mut regions = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let address = u32::from_le_bytes(read_array(input)?);
+        let size = u32::from_le_bytes(read_array(input)?) as usize;
+        if size > MAX_REGION_SIZE {
+            Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("${address:06X} has an impossible size of {size} bytes"),
+            ))?;
+        }
+        let [marker] = read_array(input)?;
+        let key = (address, size);
+        let data = match marker {
+            UNCHANGED => last.get(&key).cloned().ok_or_else(|| {
+                std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("${address:06X} marked unchanged before it was read"),
+                )
+            })?,
+            CHANGED => {
+                let mut data = vec![0; size];
+                input.read_exact(&mut data)?;
+                last.insert(key, data.clone());
+                data
+            }
+            other => Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown region marker {other}"),
+            ))?,
+        };
+        regions.push((address, data));
+    }
+    Ok(Poll { time, regions })
+}
+
+impl Trace {
+    pub fn read(input: impl Read) -> Result<Trace> {
+        let mut input = BufReader::new(input);
+        let mut magic = vec![0; MAGIC.len()];
+        input
+            .read_exact(&mut magic)
+            .context("reading trace header")?;
+        if magic != MAGIC {
+            Err(anyhow!("not an Annelid trace"))?;
+        }
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        let meta: TraceMeta = serde_json::from_str(&line).context("reading trace metadata")?;
+        let mut polls = vec![];
+        let mut last = HashMap::new();
+        let mut truncated = false;
+        loop {
+            let mut tag = [0];
+            match input.read(&mut tag)? {
+                0 => break,
+                _ if tag[0] != POLL_RECORD => Err(anyhow!("unknown trace record {}", tag[0]))?,
+                _ => {}
+            }
+            match read_poll(&mut input, &mut last) {
+                Ok(poll) => polls.push(poll),
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    truncated = true;
+                    break;
+                }
+                Err(e) => Err(e).with_context(|| format!("reading poll {}", polls.len()))?,
+            }
+        }
+        Ok(Trace {
+            meta,
+            polls,
+            truncated,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Trace> {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        Trace::read(file).with_context(|| format!("reading {}", path.display()))
+    }
+
+    /// A source that plays the trace back one poll per read.
+    pub fn replay_source(&self) -> ReplaySource {
+        let mut memory = InMemorySource::new();
+        memory.set_game(&self.meta.rom_name);
+        ReplaySource::with_memory(memory, self.polls.iter().map(|p| p.regions.clone()))
+    }
+}
+
+/// Writes polls to a trace as they happen. Use `wrap` to put it between an
+/// autosplitter and its memory source.
+pub struct TraceRecorder<W: Write> {
+    writer: TraceWriter<W>,
+    started: Instant,
+    last_flush: Instant,
+    /// Set after a write error so one bad disk doesn't stop the autosplitter.
+    failed: bool,
+}
+
+impl TraceRecorder<BufWriter<File>> {
+    /// Starts a new trace in `dir`, named after the time and the game.
+    /// `recorded_at` is filled in if it is empty.
+    pub fn create(dir: &Path, meta: &TraceMeta) -> Result<(Self, PathBuf)> {
+        std::fs::create_dir_all(dir)?;
+        let game: String = meta
+            .game
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let stamp = file_time_stamp();
+        let path = dir.join(format!("{stamp}-{game}.{TRACE_EXTENSION}"));
+        let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+        let mut meta = meta.clone();
+        if meta.recorded_at.is_empty() {
+            meta.recorded_at = stamp;
+        }
+        Ok((TraceRecorder::new(BufWriter::new(file), &meta)?, path))
+    }
+}
+
+impl<W: Write> TraceRecorder<W> {
+    pub fn new(out: W, meta: &TraceMeta) -> Result<Self> {
+        let now = Instant::now();
+        Ok(TraceRecorder {
+            writer: TraceWriter::new(out, meta)?,
+            started: now,
+            last_flush: now,
+            failed: false,
+        })
+    }
+
+    pub fn record(&mut self, pairs: &[(u32, usize)], data: &[Vec<u8>]) -> Result<()> {
+        let poll = Poll {
+            time: self.started.elapsed(),
+            regions: pairs
+                .iter()
+                .map(|&(address, _)| address)
+                .zip(data.iter().cloned())
+                .collect(),
+        };
+        self.writer.write_poll(&poll)?;
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// A source that reads through `inner` and records what it returns.
+    pub fn wrap<'a>(&'a mut self, inner: &'a mut dyn MemorySource) -> Recording<'a, W> {
+        Recording {
+            recorder: self,
+            inner,
+        }
+    }
+
+    pub fn finish(self) -> Result<W> {
+        self.writer.into_inner()
+    }
+}
+
+pub struct Recording<'a, W: Write> {
+    recorder: &'a mut TraceRecorder<W>,
+    inner: &'a mut dyn MemorySource,
+}
+
+impl<W: Write> MemorySource for Recording<'_, W> {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        let data = self.inner.get_addresses(pairs)?;
+        if !self.recorder.failed {
+            if let Err(e) = self.recorder.record(pairs, &data) {
+                tracing::warn!("Stopped recording trace: {e:#}");
+                self.recorder.failed = true;
+            }
+        }
+        Ok(data)
+    }
+
+    /// Traces hold the `SNES` space only; reads from other spaces pass
+    /// through unrecorded.
+    fn get_space_addresses(
+        &mut self,
+        space: Space,
+        pairs: &[(u32, usize)],
+    ) -> Result<Vec<Vec<u8>>> {
+        match space {
+            Space::SNES => self.get_addresses(pairs),
+            _ => self.inner.get_space_addresses(space, pairs),
+        }
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        self.inner.info()
+    }
+
+    fn write_address(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.inner.write_address(address, data)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.inner.reset()
+    }
+
+    fn game_info(&mut self) -> Result<GameInfo> {
+        self.inner.game_info()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceEventKind {
+    Start,
+    Reset,
+    Split,
+}
+
+impl TraceEventKind {
+    /// The decisions in `summary`, in the order the timer applies them.
+    pub fn from_summary(summary: &SNESSummary) -> Vec<TraceEventKind> {
+        [
+            (summary.start, TraceEventKind::Start),
+            (summary.reset, TraceEventKind::Reset),
+            (summary.split, TraceEventKind::Split),
+        ]
+        .into_iter()
+        .filter(|(happened, _)| *happened)
+        .map(|(_, kind)| kind)
+        .collect()
+    }
+}
+
+impl fmt::Display for TraceEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEventKind::Start => write!(f, "start"),
+            TraceEventKind::Reset => write!(f, "reset"),
+            TraceEventKind::Split => write!(f, "split"),
+        }
+    }
+}
+
+/// An autosplitter decision during replay.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// Index of the poll, counting from zero.
+    pub poll: usize,
+    /// When the poll happened in the original session.
+    pub time: Duration,
+    pub kind: TraceEventKind,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub polls: usize,
+    pub events: Vec<TraceEvent>,
+}
+
+impl ReplayReport {
+    pub fn count(&self, kind: TraceEventKind) -> usize {
+        self.events.iter().filter(|e| e.kind == kind).count()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} polls: {} starts, {} splits, {} resets",
+            self.polls,
+            self.count(TraceEventKind::Start),
+            self.count(TraceEventKind::Split),
+            self.count(TraceEventKind::Reset)
+        )?;
+        for event in self.events.iter() {
+            writeln!(
+                f,
+                "poll {:>7}  {}  {}",
+                event.poll,
+                format_time(event.time),
+                event.kind
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats `time` as `h:mm:ss.mmm`.
+pub fn format_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Plays a trace into an autosplitter one poll at a time, so a caller can
+/// pace the polls against the clock or step through them by hand.
+pub struct TracePlayer {
+    times: Vec<Duration>,
+    source: ReplaySource,
+}
+
+impl TracePlayer {
+    pub fn new(trace: &Trace) -> Self {
+        TracePlayer {
+            times: trace.polls.iter().map(|p| p.time).collect(),
+            source: trace.replay_source(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Polls played so far.
+    pub fn position(&self) -> usize {
+        self.times.len() - self.source.remaining()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.source.remaining() == 0
+    }
+
+    /// When the next poll happened in the original session.
+    pub fn next_time(&self) -> Option<Duration> {
+        self.times.get(self.position()).copied()
+    }
+
+    /// Whether the next poll is due once playback reaches `at`.
+    pub fn is_due(&self, at: Duration) -> bool {
+        self.next_time().is_some_and(|time| time <= at)
+    }
+
+    /// Runs one autosplitter update over the next poll, returning what it
+    /// decided along with those decisions as events. Events are attributed
+    /// to the last poll the update read.
+    pub fn step(
+        &mut self,
+        autosplitter: &mut dyn AutoSplitter,
+    ) -> Result<(SNESSummary, Vec<TraceEvent>)> {
+        let summary = autosplitter
+            .update(&mut self.source)
+            .with_context(|| format!("replaying poll {}", self.position()))?;
+        let poll = self.position() - 1;
+        let events = TraceEventKind::from_summary(&summary)
+            .into_iter()
+            .map(|kind| TraceEvent {
+                poll,
+                time: self.times[poll],
+                kind,
+            })
+            .collect();
+        Ok((summary, events))
+    }
+}
+
+/// Maps the wall clock onto a trace's timeline at an adjustable speed.
+/// Every method takes `now` so the caller decides which clock to use.
+#[derive(Clone, Copy, Debug)]
+pub struct PlaybackClock {
+    anchor: Instant,
+    position: Duration,
+    speed: f32,
+    paused: bool,
+}
+
+impl PlaybackClock {
+    /// Starts playing from `position` at normal speed.
+    pub fn new(position: Duration, now: Instant) -> Self {
+        PlaybackClock {
+            anchor: now,
+            position,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    /// How far into the trace playback has reached.
+    pub fn position(&self, now: Instant) -> Duration {
+        if self.paused {
+            self.position
+        } else {
+            self.position
+                + now
+                    .saturating_duration_since(self.anchor)
+                    .mul_f64(self.speed as f64)
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32, now: Instant) {
+        self.seek(self.position(now), now);
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        self.seek(self.position(now), now);
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self, now: Instant) {
+        self.seek(self.position(now), now);
+        self.paused = false;
+    }
+
+    /// Moves playback to `position`, e.g. after stepping while paused.
+    pub fn seek(&mut self, position: Duration, now: Instant) {
+        self.position = position;
+        self.anchor = now;
+    }
+
+    /// Wall time left until playback reaches `at`, or `None` while paused.
+    pub fn until(&self, at: Duration, now: Instant) -> Option<Duration> {
+        if self.paused || self.speed == 0.0 {
+            return None;
+        }
+        Some(
+            at.saturating_sub(self.position(now))
+                .div_f64(self.speed as f64),
+        )
+    }
+}
+
+/// Feeds every poll in `trace` to `autosplitter` and reports which polls
+/// made it start, reset or split, in the order the polling thread acts on
+/// them.
+pub fn replay(trace: &Trace, autosplitter: &mut dyn AutoSplitter) -> Result<ReplayReport> {
+    let mut player = TracePlayer::new(trace);
+    let mut report = ReplayReport {
+        polls: player.len(),
+        events: vec![],
+    };
+    while !player.is_finished() {
+        let (_, events) = player.step(autosplitter)?;
+        report.events.extend(events);
+    }
+    Ok(report)
+}
+
+fn file_time_stamp() -> String {
+    use time::OffsetDateTime;
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    now.format(time::macros::format_description!(
+        "[year][month][day]-[hour][minute][second]"
+    ))
+    .unwrap_or_else(|_| "trace".to_string())
+}
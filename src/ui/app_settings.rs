@@ -31,6 +31,22 @@ fn yes_no_checkbox(ui: &mut egui::Ui, label: &str, value: &mut Option<YesOrNo>)
     }
 }
 
+fn device_fallback_label(fallback: DeviceFallback) -> &'static str {
+    match fallback {
+        DeviceFallback::Single => "Use the only device",
+        DeviceFallback::First => "Use the first device",
+        DeviceFallback::Wait => "Wait for it",
+    }
+}
+
+fn catch_up_label(catch_up: CatchUpSplits) -> &'static str {
+    match catch_up {
+        CatchUpSplits::Ask => "Ask",
+        CatchUpSplits::Split => "Split automatically",
+        CatchUpSplits::Off => "Ignore",
+    }
+}
+
 /// Formats a HotKey for display using egui's built-in shortcut formatting.
 fn format_hotkey(ctx: &egui::Context, hotkey: &Option<HotKey>) -> String {
     match hotkey {
@@ -176,6 +192,110 @@ fn settings_panel_ui(
                         }
                         ui.weak("(requires restart)");
                     });
+                    ui.horizontal(|ui| {
+                        ui.label("Protocol:");
+                        let current = config.backend.unwrap_or_default();
+                        egui::ComboBox::from_id_salt("backend")
+                            .selected_text(match current {
+                                BackendType::Usb2Snes => "usb2snes",
+                                BackendType::Sni => "SNI (gRPC)",
+                                BackendType::RetroArch => "RetroArch",
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    &mut config.backend,
+                                    Some(BackendType::Usb2Snes),
+                                    "usb2snes",
+                                );
+                                ui.selectable_value(
+                                    &mut config.backend,
+                                    Some(BackendType::Sni),
+                                    "SNI (gRPC)",
+                                );
+                                ui.selectable_value(
+                                    &mut config.backend,
+                                    Some(BackendType::RetroArch),
+                                    "RetroArch",
+                                );
+                            });
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Server:");
+                        let mut host = config
+                            .usb2snes_host
+                            .clone()
+                            .unwrap_or_else(|| crate::usb2snes::DEFAULT_HOST.to_owned());
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut host).desired_width(140.0))
+                            .on_hover_text("Host name or IP address of QUsb2snes, SNI or RetroArch")
+                            .changed()
+                        {
+                            config.usb2snes_host = Some(host);
+                        }
+                        ui.label(":");
+                        if config.backend.unwrap_or_default() == BackendType::Sni {
+                            let mut port = config.sni_port.unwrap_or(crate::sni::DEFAULT_SNI_PORT);
+                            if ui
+                                .add(egui::DragValue::new(&mut port).range(1..=u16::MAX))
+                                .on_hover_text("Port of SNI's gRPC server")
+                                .changed()
+                            {
+                                config.sni_port = Some(port);
+                            }
+                        } else if config.backend.unwrap_or_default() == BackendType::RetroArch {
+                            let mut port = config
+                                .retroarch_port
+                                .unwrap_or(crate::retroarch::DEFAULT_RETROARCH_PORT);
+                            if ui
+                                .add(egui::DragValue::new(&mut port).range(1..=u16::MAX))
+                                .on_hover_text("RetroArch's network_cmd_port")
+                                .changed()
+                            {
+                                config.retroarch_port = Some(port);
+                            }
+                        } else {
+                            let mut port = config
+                                .usb2snes_port
+                                .unwrap_or(crate::usb2snes::DEFAULT_PORT);
+                            if ui
+                                .add(egui::DragValue::new(&mut port).range(1..=u16::MAX))
+                                .on_hover_text("Port 8080 is tried too if this one does not answer")
+                                .changed()
+                            {
+                                config.usb2snes_port = Some(port);
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Preferred Device:");
+                        let mut device = config.preferred_device.clone().unwrap_or_default();
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut device).hint_text("Automatic"))
+                            .on_hover_text("Device name, or a pattern using * and ?")
+                            .changed()
+                        {
+                            config.preferred_device = (!device.trim().is_empty()).then_some(device);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("If Missing:");
+                        let current = config.device_fallback.unwrap_or_default();
+                        egui::ComboBox::from_id_salt("device_fallback")
+                            .selected_text(device_fallback_label(current))
+                            .show_ui(ui, |ui| {
+                                for fallback in [
+                                    DeviceFallback::Single,
+                                    DeviceFallback::First,
+                                    DeviceFallback::Wait,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut config.device_fallback,
+                                        Some(fallback),
+                                        device_fallback_label(fallback),
+                                    );
+                                }
+                            });
+                    });
                     yes_no_checkbox(
                         ui,
                         "Reset timer on game reset",
@@ -186,6 +306,65 @@ fn settings_panel_ui(
                         "Reset game on timer reset",
                         &mut config.reset_game_on_timer_reset,
                     );
+                    ui.horizontal(|ui| {
+                        ui.label("JSON Splits:");
+                        let mut path = config.json_splits.clone().unwrap_or_default();
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut path).hint_text("Built-in"))
+                            .on_hover_text(
+                                "Path to QUsb2snes-style JSON split definitions, used for any game",
+                            )
+                            .changed()
+                        {
+                            config.json_splits = (!path.trim().is_empty()).then_some(path);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("ASL Script:");
+                        let mut path = config.asl_script.clone().unwrap_or_default();
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut path).hint_text("Built-in"))
+                            .on_hover_text(
+                                "Path to a LiveSplit ASL script, used for any game; \
+                                 JSON splits take precedence",
+                            )
+                            .changed()
+                        {
+                            config.asl_script = (!path.trim().is_empty()).then_some(path);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("WASM Autosplitter:");
+                        let mut path = config.wasm_autosplitter.clone().unwrap_or_default();
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut path).hint_text("Built-in"))
+                            .on_hover_text(
+                                "Path to a .wasm autosplitter module, used for any game; \
+                                 JSON splits and ASL scripts take precedence",
+                            )
+                            .changed()
+                        {
+                            config.wasm_autosplitter = (!path.trim().is_empty()).then_some(path);
+                        }
+                    });
+                    yes_no_checkbox(ui, "Record polling traces", &mut config.record_traces);
+                    ui.horizontal(|ui| {
+                        ui.label("Splits Missed While Disconnected:");
+                        let current = config.catch_up_splits.unwrap_or_default();
+                        egui::ComboBox::from_id_salt("catch_up_splits")
+                            .selected_text(catch_up_label(current))
+                            .show_ui(ui, |ui| {
+                                for catch_up in
+                                    [CatchUpSplits::Ask, CatchUpSplits::Split, CatchUpSplits::Off]
+                                {
+                                    ui.selectable_value(
+                                        &mut config.catch_up_splits,
+                                        Some(catch_up),
+                                        catch_up_label(catch_up),
+                                    );
+                                }
+                            });
+                    });
                 });
 
             // --- Input ---
@@ -297,7 +476,7 @@ impl LiveSplitCoreRenderer {
             egui::ViewportId::from_hash_of("app_settings"),
             egui::ViewportBuilder::default()
                 .with_title("Annelid Settings")
-                .with_inner_size([400.0, 470.0]),
+                .with_inner_size([400.0, 590.0]),
             move |ctx, _class| {
                 settings_panel_ui(ctx, &state, &actions, &open);
             },
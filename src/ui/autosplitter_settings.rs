@@ -1,24 +1,36 @@
-use crate::autosplitters::supermetroid::Settings;
+use crate::autosplitters::supermetroid::{Settings, SplitOrder};
 use crate::livesplit_renderer::LiveSplitCoreRenderer;
 use crate::ui::control_panel::UiAction;
 use eframe::egui;
+use livesplit_core::SharedTimer;
 use parking_lot::{Mutex, RwLock};
 use std::sync::atomic::{AtomicBool, Ordering};
 
+/// A checkbox for `key`, with its label and tooltip.
+fn setting_checkbox(settings: &mut Settings, ui: &mut egui::Ui, key: &str) {
+    let label = settings.label(key).to_owned();
+    let tooltip = settings.tooltip(key).map(str::to_owned);
+    let response = ui.checkbox(settings.lookup_mut(key), label);
+    if let Some(tooltip) = tooltip {
+        response.on_hover_text(tooltip);
+    }
+}
+
+/// Shows `roots` and everything under them, in the order they were
+/// declared.
 pub fn show_children(
     settings: &mut Settings,
     ui: &mut egui::Ui,
     ctx: &egui::Context,
-    roots: &mut [String],
+    roots: &[String],
 ) {
-    roots.sort();
     roots.iter().for_each(|key| {
-        let mut children = settings.children(key);
+        let children = settings.children(key);
         let id = ui.make_persistent_id(key);
         if !children.is_empty() {
             egui::collapsing_header::CollapsingState::load_with_default_open(ctx, id, false)
                 .show_header(ui, |ui| {
-                    ui.checkbox(settings.lookup_mut(key), key);
+                    setting_checkbox(settings, ui, key);
                 })
                 .body(|ui| {
                     ui.indent(id, |ui| {
@@ -26,16 +38,96 @@ pub fn show_children(
                             if !settings.lookup(key) {
                                 ui.disable();
                             }
-                            show_children(settings, ui, ctx, &mut children);
+                            show_children(settings, ui, ctx, &children);
                         });
                     });
                 });
         } else {
             ui.scope(|ui| {
-                ui.checkbox(settings.lookup_mut(key), key);
+                setting_checkbox(settings, ui, key);
+            });
+        }
+    });
+}
+
+/// Chooses whether to split in order and, if so, which conditions end
+/// each of `segments`, the names of the loaded splits.
+fn split_plan_ui(settings: &mut Settings, ui: &mut egui::Ui, segments: &[String]) {
+    let mut in_order = settings.plan().order == SplitOrder::InOrder;
+    if ui
+        .checkbox(&mut in_order, "Split in order")
+        .on_hover_text("Each segment only ends on its own conditions")
+        .changed()
+    {
+        let plan = settings.plan_mut();
+        plan.order = if in_order {
+            SplitOrder::InOrder
+        } else {
+            SplitOrder::AnyOrder
+        };
+        if in_order && plan.segments.is_empty() {
+            plan.align(segments);
+        }
+    }
+    if !in_order {
+        return;
+    }
+    let planned: Vec<&str> = settings
+        .plan()
+        .segments
+        .iter()
+        .map(|s| s.name.as_str())
+        .collect();
+    if planned != segments {
+        ui.horizontal(|ui| {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "The plan doesn't match the loaded splits",
+            );
+            if ui.button("Match Splits").clicked() {
+                settings.plan_mut().align(segments);
+            }
+        });
+    }
+    let mut keys = settings.keys();
+    settings.schema().sort(&mut keys);
+    let mut changed = None;
+    egui::Grid::new("split_plan").striped(true).show(ui, |ui| {
+        for (i, segment) in settings.plan().segments.iter().enumerate() {
+            ui.label(&segment.name);
+            ui.horizontal_wrapped(|ui| {
+                for (j, condition) in segment.conditions.iter().enumerate() {
+                    if ui
+                        .small_button(format!("{} ×", settings.label(condition)))
+                        .on_hover_text("Remove")
+                        .clicked()
+                    {
+                        changed = Some((i, Err(j)));
+                    }
+                }
+                egui::ComboBox::from_id_salt(("split_plan_add", i))
+                    .selected_text("Add…")
+                    .show_ui(ui, |ui| {
+                        for key in keys.iter() {
+                            if ui.selectable_label(false, settings.label(key)).clicked() {
+                                changed = Some((i, Ok(key.clone())));
+                            }
+                        }
+                    });
             });
+            ui.end_row();
         }
     });
+    if let Some((i, change)) = changed {
+        let conditions = &mut settings.plan_mut().segments[i].conditions;
+        match change {
+            Ok(key) if !conditions.contains(&key) => conditions.push(key),
+            Ok(_) => {}
+            Err(j) => {
+                conditions.remove(j);
+            }
+        }
+    }
 }
 
 #[derive(PartialEq)]
@@ -49,6 +141,7 @@ enum EditorAction {
 /// Renders the autosplitter settings UI in a deferred viewport.
 fn settings_viewport_ui(
     ctx: &egui::Context,
+    timer: &SharedTimer,
     settings: &RwLock<Settings>,
     snapshot: &Mutex<Option<Settings>>,
     actions: &Mutex<Vec<UiAction>>,
@@ -63,13 +156,30 @@ fn settings_viewport_ui(
     // Initialize snapshot on first frame
     let mut guard = snapshot.lock();
     let snap = guard.get_or_insert_with(|| settings.read().clone());
+    // A script loaded while the window is open may declare more
+    snap.adopt_new(&settings.read());
 
     let mut action = EditorAction::None;
 
     egui::CentralPanel::default().show(ctx, |ui| {
         egui::ScrollArea::both().show(ui, |ui| {
-            let mut roots = snap.roots();
-            show_children(snap, ui, ctx, &mut roots);
+            egui::CollapsingHeader::new("Split Order")
+                .default_open(snap.plan().order == SplitOrder::InOrder)
+                .show(ui, |ui| {
+                    let segments: Vec<String> = match timer.read() {
+                        Ok(timer) => timer
+                            .run()
+                            .segments()
+                            .iter()
+                            .map(|s| s.name().to_owned())
+                            .collect(),
+                        Err(_) => vec![],
+                    };
+                    split_plan_ui(snap, ui, &segments);
+                });
+            ui.separator();
+            let roots = snap.roots();
+            show_children(snap, ui, ctx, &roots);
         });
         ui.separator();
         ui.horizontal(|ui| {
@@ -90,13 +200,13 @@ fn settings_viewport_ui(
     match action {
         EditorAction::Update => {
             if let Some(snap) = guard.take() {
-                *settings.write() = snap;
+                settings.write().load(snap);
             }
             open.store(false, Ordering::Relaxed);
         }
         EditorAction::SaveToFile => {
             if let Some(snap) = guard.take() {
-                *settings.write() = snap;
+                settings.write().load(snap);
             }
             actions.lock().push(UiAction::SaveAutosplitterDialog);
             open.store(false, Ordering::Relaxed);
@@ -115,6 +225,7 @@ impl LiveSplitCoreRenderer {
             return;
         }
 
+        let timer = self.timer.clone();
         let settings = self.settings.clone();
         let snapshot = self.ui.autosplitter_settings_snapshot.clone();
         let actions = self.ui.ui_actions.clone();
@@ -126,7 +237,7 @@ impl LiveSplitCoreRenderer {
                 .with_title("Autosplitter Settings")
                 .with_inner_size([400.0, 500.0]),
             move |ctx, _class| {
-                settings_viewport_ui(ctx, &settings, &snapshot, &actions, &open);
+                settings_viewport_ui(ctx, &timer, &settings, &snapshot, &actions, &open);
             },
         );
     }
@@ -1,11 +1,13 @@
 use eframe::egui;
 use livesplit_core::SharedTimer;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::autosplitters::supermetroid::Settings;
+use crate::autosplitters::MissedSplit;
 use crate::config::app_config::*;
-use crate::livesplit_renderer::{LiveSplitCoreRenderer, ThreadEvent};
+use crate::connection::{ConnectionState, SharedConnectionState};
+use crate::livesplit_renderer::{apply_missed_splits, LiveSplitCoreRenderer, ThreadEvent};
 
 pub(crate) enum UiAction {
     // File
@@ -26,6 +28,9 @@ pub(crate) enum UiAction {
     ConfigureAutosplitter,
     OpenAutosplitterDialog,
     SaveAutosplitterDialog,
+    SelectDevice(Option<String>),
+    ApplyMissedSplits,
+    DismissMissedSplits,
     // Splits Editor
     OpenSplitsEditor,
     ApplySplitsEdit(Box<livesplit_core::Run>),
@@ -37,6 +42,14 @@ pub(crate) enum UiAction {
     ApplySettings(AppConfig),
     OpenLogViewer,
     OpenLogDirectory,
+    OpenDiagnostics,
+    OpenSessionReplay,
+    OpenReplayTrace,
+    // SD card
+    OpenFileManager,
+    FileManagerUpload,
+    FileManagerDownload(String),
+    FileManagerDelete(String),
     Quit,
 }
 
@@ -46,6 +59,10 @@ pub(crate) enum UiAction {
 fn control_panel_ui(
     ctx: &egui::Context,
     timer: &SharedTimer,
+    app_config: &RwLock<AppConfig>,
+    devices: &Mutex<Vec<String>>,
+    connection: &SharedConnectionState,
+    missed_splits: &Mutex<Vec<MissedSplit>>,
     actions: &Mutex<Vec<UiAction>>,
     open: &AtomicBool,
 ) {
@@ -129,6 +146,9 @@ fn control_panel_ui(
                             actions.lock().push(UiAction::SaveAutosplitterDialog);
                         }
                     });
+                    device_picker(ui, app_config, devices, actions);
+                    connection_status(ui, connection);
+                    missed_splits_prompt(ui, missed_splits, actions);
                 });
 
             ui.separator();
@@ -137,9 +157,14 @@ fn control_panel_ui(
             }
             ui.separator();
 
-            if ui.button("Settings").clicked() {
-                actions.lock().push(UiAction::OpenSettingsPanel);
-            }
+            ui.horizontal(|ui| {
+                if ui.button("Settings").clicked() {
+                    actions.lock().push(UiAction::OpenSettingsPanel);
+                }
+                if ui.button("SD Card Files").clicked() {
+                    actions.lock().push(UiAction::OpenFileManager);
+                }
+            });
             ui.horizontal(|ui| {
                 if ui.button("View Log").clicked() {
                     actions.lock().push(UiAction::OpenLogViewer);
@@ -148,6 +173,14 @@ fn control_panel_ui(
                     actions.lock().push(UiAction::OpenLogDirectory);
                 }
             });
+            ui.horizontal(|ui| {
+                if ui.button("Polling Diagnostics").clicked() {
+                    actions.lock().push(UiAction::OpenDiagnostics);
+                }
+                if ui.button("Replay Session").clicked() {
+                    actions.lock().push(UiAction::OpenSessionReplay);
+                }
+            });
             if ui.button("Quit").clicked() {
                 actions.lock().push(UiAction::Quit);
             }
@@ -155,6 +188,96 @@ fn control_panel_ui(
     });
 }
 
+/// Lets the user choose which of the devices reported by the usb2snes
+/// server the autosplitter should attach to.
+fn device_picker(
+    ui: &mut egui::Ui,
+    app_config: &RwLock<AppConfig>,
+    devices: &Mutex<Vec<String>>,
+    actions: &Mutex<Vec<UiAction>>,
+) {
+    let preferred = app_config.read().preferred_device.clone();
+    let devices = devices.lock().clone();
+    ui.horizontal(|ui| {
+        ui.label("Device:");
+        egui::ComboBox::from_id_salt("device_picker")
+            .selected_text(preferred.as_deref().unwrap_or("Automatic"))
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(preferred.is_none(), "Automatic")
+                    .clicked()
+                {
+                    actions.lock().push(UiAction::SelectDevice(None));
+                }
+                for device in devices.iter() {
+                    let selected = preferred.as_deref() == Some(device.as_str());
+                    if ui.selectable_label(selected, device).clicked() {
+                        actions
+                            .lock()
+                            .push(UiAction::SelectDevice(Some(device.clone())));
+                    }
+                }
+            });
+    });
+    if devices.is_empty() {
+        ui.weak("No devices found");
+    } else if let Some(pattern) = preferred.as_deref() {
+        if !devices
+            .iter()
+            .any(|d| crate::usb2snes::device_matches(pattern, d))
+        {
+            ui.weak("Preferred device is not connected");
+        }
+    }
+}
+
+/// Shows what the polling thread is doing: connecting, waiting for a game
+/// we have an autosplitter for, polling, or waiting to retry after an error.
+fn connection_status(ui: &mut egui::Ui, connection: &SharedConnectionState) {
+    let state = connection.get();
+    let color = match &state {
+        ConnectionState::Polling { warnings, .. } if !warnings.is_empty() => {
+            ui.visuals().warn_fg_color
+        }
+        ConnectionState::Polling { .. } => egui::Color32::from_rgb(0x40, 0xc0, 0x40),
+        ConnectionState::Attached { game: Some(_), .. } => ui.visuals().warn_fg_color,
+        ConnectionState::Error { .. } => ui.visuals().error_fg_color,
+        _ => ui.visuals().weak_text_color(),
+    };
+    ui.colored_label(color, state.to_string());
+}
+
+/// Offers the splits found on reconnecting, when catching up is set to ask.
+fn missed_splits_prompt(
+    ui: &mut egui::Ui,
+    missed_splits: &Mutex<Vec<MissedSplit>>,
+    actions: &Mutex<Vec<UiAction>>,
+) {
+    let missed = missed_splits.lock().clone();
+    if missed.is_empty() {
+        return;
+    }
+    let count = match missed.len() {
+        1 => "1 split".to_owned(),
+        n => format!("{n} splits"),
+    };
+    ui.colored_label(
+        ui.visuals().warn_fg_color,
+        format!("{count} missed while disconnected"),
+    );
+    for split in missed.iter() {
+        ui.weak(&split.reason);
+    }
+    ui.horizontal(|ui| {
+        if ui.button("Split").clicked() {
+            actions.lock().push(UiAction::ApplyMissedSplits);
+        }
+        if ui.button("Dismiss").clicked() {
+            actions.lock().push(UiAction::DismissMissedSplits);
+        }
+    });
+}
+
 impl LiveSplitCoreRenderer {
     pub(crate) fn show_control_panel(&mut self, ctx: &egui::Context) {
         if !self.ui.control_panel_open.load(Ordering::Relaxed) {
@@ -162,6 +285,10 @@ impl LiveSplitCoreRenderer {
         }
 
         let timer = self.timer.clone();
+        let app_config = self.app_config.clone();
+        let devices = self.snes_devices.clone();
+        let connection = self.snes_connection.clone();
+        let missed_splits = self.missed_splits.clone();
         let actions = self.ui.ui_actions.clone();
         let open = self.ui.control_panel_open.clone();
 
@@ -169,9 +296,18 @@ impl LiveSplitCoreRenderer {
             egui::ViewportId::from_hash_of("control_panel"),
             egui::ViewportBuilder::default()
                 .with_title("Annelid Control Panel")
-                .with_inner_size([300.0, 440.0]),
+                .with_inner_size([300.0, 480.0]),
             move |ctx, _class| {
-                control_panel_ui(ctx, &timer, &actions, &open);
+                control_panel_ui(
+                    ctx,
+                    &timer,
+                    &app_config,
+                    &devices,
+                    &connection,
+                    &missed_splits,
+                    &actions,
+                    &open,
+                );
             },
         );
     }
@@ -270,6 +406,21 @@ impl LiveSplitCoreRenderer {
                         tracing::warn!("Failed to save autosplitter: {e}");
                     }
                 }
+                UiAction::SelectDevice(device) => {
+                    self.app_config.write().preferred_device = device;
+                    self.save_app_config();
+                    // Drop the current connection so the new choice is used
+                    self.snes_cancel.cancel();
+                }
+                UiAction::ApplyMissedSplits => {
+                    let missed = std::mem::take(&mut *self.missed_splits.lock());
+                    if let Err(e) = apply_missed_splits(&self.timer, &missed) {
+                        tracing::warn!("Failed to apply missed splits: {e}");
+                    }
+                }
+                UiAction::DismissMissedSplits => {
+                    self.missed_splits.lock().clear();
+                }
                 UiAction::OpenSplitsEditor => {
                     if !self
                         .ui
@@ -341,8 +492,24 @@ impl LiveSplitCoreRenderer {
                         });
                 }
                 UiAction::ApplySettings(new_config) => {
+                    let connection_changed = {
+                        let old = self.app_config.read();
+                        old.backend != new_config.backend
+                            || old.usb2snes_host != new_config.usb2snes_host
+                            || old.usb2snes_port != new_config.usb2snes_port
+                            || old.sni_port != new_config.sni_port
+                            || old.retroarch_port != new_config.retroarch_port
+                            || old.preferred_device != new_config.preferred_device
+                            || old.device_fallback != new_config.device_fallback
+                            || old.json_splits != new_config.json_splits
+                            || old.asl_script != new_config.asl_script
+                            || old.wasm_autosplitter != new_config.wasm_autosplitter
+                    };
                     *self.app_config.write() = new_config;
                     self.save_app_config();
+                    if connection_changed {
+                        self.snes_cancel.cancel();
+                    }
                 }
                 UiAction::OpenLogViewer => {
                     self.ui
@@ -352,6 +519,38 @@ impl LiveSplitCoreRenderer {
                 UiAction::OpenLogDirectory => {
                     crate::logging::open_log_dir();
                 }
+                UiAction::OpenDiagnostics => {
+                    self.ui
+                        .diagnostics_open
+                        .store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+                UiAction::OpenSessionReplay => {
+                    self.ui
+                        .session_replay_open
+                        .store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+                UiAction::OpenReplayTrace => {
+                    self.open_replay_trace_dialog();
+                }
+                UiAction::OpenFileManager => {
+                    self.ui
+                        .file_manager_open
+                        .store(true, std::sync::atomic::Ordering::Relaxed);
+                    let dir = self.ui.file_manager.dir();
+                    self.ui.file_manager.run(
+                        &self.app_config.read(),
+                        crate::ui::file_manager::Job::List(dir),
+                    );
+                }
+                UiAction::FileManagerUpload => {
+                    self.upload_to_cart_dialog(&document_dir);
+                }
+                UiAction::FileManagerDownload(remote) => {
+                    self.download_from_cart_dialog(&document_dir, remote);
+                }
+                UiAction::FileManagerDelete(remote) => {
+                    self.delete_from_cart(remote);
+                }
                 UiAction::Quit => {
                     ctx.send_viewport_cmd(egui::viewport::ViewportCommand::Close);
                 }
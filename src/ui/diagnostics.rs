@@ -0,0 +1,169 @@
+use eframe::egui;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::connection::SharedConnectionState;
+use crate::livesplit_renderer::LiveSplitCoreRenderer;
+use crate::poll_stats::{Histogram, PollStats};
+
+const HISTOGRAM_BUCKETS: usize = 40;
+
+fn ms(d: Duration) -> String {
+    format!("{:.2} ms", d.as_secs_f64() * 1000.0)
+}
+
+fn diagnostics_ui(
+    ctx: &egui::Context,
+    stats: &Mutex<PollStats>,
+    connection: &SharedConnectionState,
+    open: &AtomicBool,
+) {
+    if ctx.input(|i| i.viewport().close_requested()) {
+        open.store(false, Ordering::Relaxed);
+        return;
+    }
+
+    ctx.request_repaint_after(Duration::from_millis(500));
+
+    // Copy what we need so the polling thread isn't held up while we draw.
+    let (snapshot, summary, histogram) = {
+        let stats = stats.lock();
+        (
+            stats.clone(),
+            stats.summary(),
+            stats.histogram(HISTOGRAM_BUCKETS),
+        )
+    };
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.label(connection.get().to_string());
+        ui.horizontal(|ui| {
+            if ui.button("Reset").clicked() {
+                stats.lock().reset();
+            }
+            if ui.button("Copy Report").clicked() {
+                ctx.copy_text(snapshot.report());
+            }
+        });
+        ui.separator();
+
+        egui::Grid::new("poll_stats").num_columns(2).show(ui, |ui| {
+            ui.label("Poll period");
+            ui.label(ms(snapshot.period));
+            ui.end_row();
+            ui.label("Polls");
+            ui.label(snapshot.polls.to_string());
+            ui.end_row();
+            ui.label("Missed deadlines");
+            ui.label(format!(
+                "{} ({:.2}%)",
+                snapshot.missed_deadlines,
+                snapshot.missed_percent()
+            ));
+            ui.end_row();
+            if let Some(s) = &summary {
+                ui.label("Round trip");
+                ui.label(format!("{} / {} / {}", ms(s.min), ms(s.mean), ms(s.max)))
+                    .on_hover_text("min / mean / max");
+                ui.end_row();
+                ui.label("Percentiles");
+                ui.label(format!("{} / {} / {}", ms(s.p50), ms(s.p95), ms(s.p99)))
+                    .on_hover_text("p50 / p95 / p99");
+                ui.end_row();
+                ui.label("Jitter");
+                ui.label(ms(s.jitter))
+                    .on_hover_text("Standard deviation of the round trip");
+                ui.end_row();
+                ui.label("Start lateness");
+                ui.label(format!("{} / {}", ms(s.mean_lateness), ms(s.max_lateness)))
+                    .on_hover_text("mean / max delay past the scheduled poll time");
+                ui.end_row();
+            }
+        });
+        ui.separator();
+
+        match summary {
+            None => {
+                ui.weak("No polls recorded yet");
+            }
+            Some(s) => {
+                ui.label(format!("Round trip over the last {} polls", s.count));
+                histogram_plot(ui, &histogram, snapshot.period);
+            }
+        }
+    });
+}
+
+/// Draws `histogram` as bars, with a line where the poll period falls if it
+/// is in range: polls to the right of it can't keep up with `polling_rate`.
+fn histogram_plot(ui: &mut egui::Ui, histogram: &Histogram, period: Duration) {
+    let size = egui::vec2(ui.available_width(), 120.0);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 0.0, visuals.extreme_bg_color);
+
+    let tallest = histogram.counts.iter().copied().max().unwrap_or(0).max(1);
+    let bar_width = rect.width() / histogram.counts.len() as f32;
+    for (i, &count) in histogram.counts.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let height = rect.height() * count as f32 / tallest as f32;
+        let left = rect.left() + i as f32 * bar_width;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(left + 1.0, rect.bottom() - height),
+            egui::pos2(left + bar_width - 1.0, rect.bottom()),
+        );
+        painter.rect_filled(bar, 0.0, visuals.selection.bg_fill);
+    }
+
+    let range = histogram.bucket_width * histogram.counts.len() as u32;
+    if period > Duration::ZERO && period < range {
+        let x = rect.left() + rect.width() * period.as_secs_f32() / range.as_secs_f32();
+        painter.vline(
+            x,
+            rect.y_range(),
+            egui::Stroke::new(1.0, visuals.warn_fg_color),
+        );
+    }
+
+    let font = egui::FontId::proportional(11.0);
+    let color = visuals.weak_text_color();
+    painter.text(
+        rect.left_top() + egui::vec2(4.0, 2.0),
+        egui::Align2::LEFT_TOP,
+        format!("{tallest} polls"),
+        font,
+        color,
+    );
+    ui.horizontal(|ui| {
+        ui.weak("0 ms");
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.weak(ms(range));
+        });
+    });
+}
+
+impl LiveSplitCoreRenderer {
+    pub(crate) fn show_diagnostics(&self, ctx: &egui::Context) {
+        if !self.ui.diagnostics_open.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let stats = self.poll_stats.clone();
+        let connection = self.snes_connection.clone();
+        let open = self.ui.diagnostics_open.clone();
+
+        ctx.show_viewport_deferred(
+            egui::ViewportId::from_hash_of("diagnostics"),
+            egui::ViewportBuilder::default()
+                .with_title("Annelid Polling Diagnostics")
+                .with_inner_size([420.0, 420.0]),
+            move |ctx, _class| {
+                diagnostics_ui(ctx, &stats, &connection, &open);
+            },
+        );
+    }
+}
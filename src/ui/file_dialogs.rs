@@ -174,6 +174,42 @@ impl LiveSplitCoreRenderer {
             if cli_config.renderer.is_some() {
                 new_app_config.renderer = cli_config.renderer;
             }
+            if cli_config.backend.is_some() {
+                new_app_config.backend = cli_config.backend;
+            }
+            if cli_config.usb2snes_host.is_some() {
+                new_app_config.usb2snes_host = cli_config.usb2snes_host;
+            }
+            if cli_config.usb2snes_port.is_some() {
+                new_app_config.usb2snes_port = cli_config.usb2snes_port;
+            }
+            if cli_config.sni_port.is_some() {
+                new_app_config.sni_port = cli_config.sni_port;
+            }
+            if cli_config.retroarch_port.is_some() {
+                new_app_config.retroarch_port = cli_config.retroarch_port;
+            }
+            if cli_config.preferred_device.is_some() {
+                new_app_config.preferred_device = cli_config.preferred_device;
+            }
+            if cli_config.device_fallback.is_some() {
+                new_app_config.device_fallback = cli_config.device_fallback;
+            }
+            if cli_config.record_traces.is_some() {
+                new_app_config.record_traces = cli_config.record_traces;
+            }
+            if cli_config.json_splits.is_some() {
+                new_app_config.json_splits = cli_config.json_splits;
+            }
+            if cli_config.asl_script.is_some() {
+                new_app_config.asl_script = cli_config.asl_script;
+            }
+            if cli_config.wasm_autosplitter.is_some() {
+                new_app_config.wasm_autosplitter = cli_config.wasm_autosplitter;
+            }
+            if cli_config.catch_up_splits.is_some() {
+                new_app_config.catch_up_splits = cli_config.catch_up_splits;
+            }
             if cli_config.transparent_window.is_some() {
                 new_app_config.transparent_window = cli_config.transparent_window;
             }
@@ -321,7 +357,8 @@ impl LiveSplitCoreRenderer {
     }
 
     pub fn load_autosplitter(&mut self, f: &std::fs::File) -> Result<()> {
-        *self.settings.write() = serde_json::from_reader(std::io::BufReader::new(f))?;
+        let saved = serde_json::from_reader(std::io::BufReader::new(f))?;
+        self.settings.write().load(saved);
         Ok(())
     }
 
@@ -605,6 +642,18 @@ impl LiveSplitCoreRenderer {
         Ok(())
     }
 
+    /// Picks a recorded trace, starting in the directory sessions are
+    /// recorded to, and replays it into the timer.
+    pub fn open_replay_trace_dialog(&mut self) {
+        let dir = self.trace_dir();
+        let dir = dir.to_str().unwrap_or("").to_owned();
+        self.open_dialog(
+            &dir,
+            &[("Annelid Trace", crate::trace::TRACE_EXTENSION)],
+            |me, path| me.start_session_replay(&path),
+        );
+    }
+
     pub fn open_dialog(
         &mut self,
         default_dir: &str,
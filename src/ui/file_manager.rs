@@ -0,0 +1,411 @@
+use anyhow::{Context, Result};
+use eframe::egui;
+use parking_lot::{Mutex, RwLock};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use thread_priority::ThreadBuilder;
+
+use crate::config::app_config::AppConfig;
+use crate::livesplit_renderer::LiveSplitCoreRenderer;
+use crate::ui::control_panel::UiAction;
+use crate::usb2snes::{
+    select_device, ClientOptions, SyncClient, USB2SnesError, USB2SnesFileType, DEFAULT_HOST,
+    DEFAULT_PORT, FLAG_NO_FILE_CMD,
+};
+
+/// Listing a large directory on the cart can take a while.
+const FILE_READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+const ROM_EXTENSIONS: &[&str] = &["sfc", "smc", "swc", "fig", "bs"];
+
+pub(crate) enum Job {
+    List(String),
+    Upload { local: PathBuf, remote_dir: String },
+    Download { remote: String, local: PathBuf },
+    Delete(String),
+    Boot(String),
+}
+
+#[derive(Clone)]
+pub(crate) struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct Progress {
+    pub label: String,
+    pub done: usize,
+    pub total: usize,
+}
+
+pub(crate) struct FileManagerState {
+    /// The directory on the SD card being shown.
+    pub dir: String,
+    pub entries: Vec<Entry>,
+    pub progress: Option<Progress>,
+    pub busy: bool,
+    /// The outcome of the last job, shown under the listing.
+    pub status: Option<String>,
+}
+
+/// Browses the cart's SD card. Each job runs on its own thread with its own
+/// usb2snes connection, so the autosplitter keeps polling meanwhile.
+#[derive(Clone)]
+pub(crate) struct FileManager {
+    state: Arc<Mutex<FileManagerState>>,
+}
+
+impl FileManager {
+    pub fn new() -> Self {
+        FileManager {
+            state: Arc::new(Mutex::new(FileManagerState {
+                dir: "/".to_owned(),
+                entries: vec![],
+                progress: None,
+                busy: false,
+                status: None,
+            })),
+        }
+    }
+
+    pub fn dir(&self) -> String {
+        self.state.lock().dir.clone()
+    }
+
+    /// Starts `job` unless another one is still running.
+    pub fn run(&self, config: &AppConfig, job: Job) {
+        {
+            let mut state = self.state.lock();
+            if state.busy {
+                return;
+            }
+            state.busy = true;
+            state.status = None;
+        }
+        let config = config.clone();
+        let state = self.state.clone();
+        let spawned = ThreadBuilder::default()
+            .name("SD2SNES File Thread".to_owned())
+            .spawn(move |_| {
+                let result = run_job(&config, job, &state);
+                let mut state = state.lock();
+                state.busy = false;
+                state.progress = None;
+                match result {
+                    Ok(message) => state.status = message,
+                    Err(e) => {
+                        tracing::warn!("File manager: {e:#}");
+                        state.status = Some(format!("{e:#}"));
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            tracing::error!("Failed to spawn file manager thread: {e}");
+            let mut state = self.state.lock();
+            state.busy = false;
+            state.status = Some(format!("{e}"));
+        }
+    }
+}
+
+impl Default for FileManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn connect(config: &AppConfig) -> Result<SyncClient> {
+    let host = config.usb2snes_host.as_deref().unwrap_or(DEFAULT_HOST);
+    let port = config.usb2snes_port.unwrap_or(DEFAULT_PORT);
+    let options = ClientOptions {
+        read_timeout: FILE_READ_TIMEOUT,
+        ..ClientOptions::default()
+    };
+    let mut client = SyncClient::connect_to_server(host, port, options)
+        .context("creating usb2snes connection")?;
+    client.set_name("annelid files")?;
+    let devices = client.list_device()?;
+    let device = select_device(
+        &devices,
+        config.preferred_device.as_deref(),
+        config.device_fallback.unwrap_or_default(),
+    )?;
+    client.attach(&device)?;
+    if client.info()?.has_flag(FLAG_NO_FILE_CMD) {
+        Err(USB2SnesError::Unsupported(format!(
+            "file commands ({device})"
+        )))?;
+    }
+    Ok(client)
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{name}", dir.trim_end_matches('/'))
+}
+
+fn parent(dir: &str) -> String {
+    match dir.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/".to_owned(),
+        Some((parent, _)) => parent.to_owned(),
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn is_rom(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, ext)| {
+        ROM_EXTENSIONS
+            .iter()
+            .any(|rom| rom.eq_ignore_ascii_case(ext))
+    })
+}
+
+/// Runs `job` and, unless it booted a ROM, refreshes the listing. The
+/// refresh doubles as the round trip that confirms an upload finished.
+fn run_job(
+    config: &AppConfig,
+    job: Job,
+    state: &Mutex<FileManagerState>,
+) -> Result<Option<String>> {
+    let set_progress = |label: &str, done: usize, total: usize| {
+        state.lock().progress = Some(Progress {
+            label: label.to_owned(),
+            done,
+            total,
+        });
+    };
+    let mut client = connect(config)?;
+    let mut dir = state.lock().dir.clone();
+    let message = match job {
+        Job::List(path) => {
+            dir = path;
+            None
+        }
+        Job::Upload { local, remote_dir } => {
+            let name = local
+                .file_name()
+                .and_then(|n| n.to_str())
+                .context("upload needs a file name")?
+                .to_owned();
+            let data =
+                std::fs::read(&local).with_context(|| format!("reading {}", local.display()))?;
+            let label = format!("Uploading {name}");
+            client.send_file_with_progress(&join(&remote_dir, &name), &data, |done, total| {
+                set_progress(&label, done, total)
+            })?;
+            dir = remote_dir;
+            Some(format!("Uploaded {name}"))
+        }
+        Job::Download { remote, local } => {
+            let label = format!("Downloading {}", file_name(&remote));
+            let data = client
+                .get_file_with_progress(&remote, |done, total| set_progress(&label, done, total))?;
+            std::fs::write(&local, data).with_context(|| format!("writing {}", local.display()))?;
+            Some(format!("Saved {}", local.display()))
+        }
+        Job::Delete(remote) => {
+            client.remove_path(&remote)?;
+            Some(format!("Deleted {}", file_name(&remote)))
+        }
+        Job::Boot(remote) => {
+            client.boot(&remote)?;
+            return Ok(Some(format!("Booted {}", file_name(&remote))));
+        }
+    };
+    let mut entries: Vec<Entry> = client
+        .ls(&dir)?
+        .into_iter()
+        .filter(|info| &*info.name != "." && &*info.name != "..")
+        .map(|info| Entry {
+            name: info.name.to_string(),
+            is_dir: info.file_type == USB2SnesFileType::Dir,
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+    let mut state = state.lock();
+    state.dir = dir;
+    state.entries = entries;
+    Ok(message)
+}
+
+fn file_manager_ui(
+    ctx: &egui::Context,
+    manager: &FileManager,
+    app_config: &RwLock<AppConfig>,
+    actions: &Mutex<Vec<UiAction>>,
+    open: &AtomicBool,
+) {
+    if ctx.input(|i| i.viewport().close_requested()) {
+        open.store(false, Ordering::Relaxed);
+        return;
+    }
+    let (dir, entries, progress, busy, status) = {
+        let state = manager.state.lock();
+        (
+            state.dir.clone(),
+            state.entries.clone(),
+            state.progress.clone(),
+            state.busy,
+            state.status.clone(),
+        )
+    };
+    if busy {
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+    }
+    let run = |job| manager.run(&app_config.read(), job);
+
+    egui::TopBottomPanel::top("file_manager_toolbar").show(ctx, |ui| {
+        ui.add_enabled_ui(!busy, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(dir != "/", egui::Button::new("⬆ Up"))
+                    .clicked()
+                {
+                    run(Job::List(parent(&dir)));
+                }
+                if ui.button("Refresh").clicked() {
+                    run(Job::List(dir.clone()));
+                }
+                if ui.button("Upload…").clicked() {
+                    actions.lock().push(UiAction::FileManagerUpload);
+                }
+                ui.label(&dir);
+            });
+        });
+    });
+    egui::TopBottomPanel::bottom("file_manager_status").show(ctx, |ui| {
+        if let Some(progress) = progress {
+            let fraction = if progress.total == 0 {
+                1.0
+            } else {
+                progress.done as f32 / progress.total as f32
+            };
+            ui.add(
+                egui::ProgressBar::new(fraction)
+                    .text(progress.label)
+                    .show_percentage(),
+            );
+        } else if busy {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Working…");
+            });
+        } else if let Some(status) = status {
+            ui.label(status);
+        } else {
+            ui.weak(format!("{} entries", entries.len()));
+        }
+    });
+    egui::CentralPanel::default().show(ctx, |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.add_enabled_ui(!busy, |ui| {
+                for entry in entries.iter() {
+                    let path = join(&dir, &entry.name);
+                    ui.horizontal(|ui| {
+                        if entry.is_dir {
+                            if ui
+                                .selectable_label(false, format!("📁 {}", entry.name))
+                                .clicked()
+                            {
+                                run(Job::List(path.clone()));
+                            }
+                        } else {
+                            ui.label(format!("📄 {}", entry.name));
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.small_button("Delete").clicked() {
+                                actions
+                                    .lock()
+                                    .push(UiAction::FileManagerDelete(path.clone()));
+                            }
+                            if entry.is_dir {
+                                return;
+                            }
+                            if ui.small_button("Download").clicked() {
+                                actions
+                                    .lock()
+                                    .push(UiAction::FileManagerDownload(path.clone()));
+                            }
+                            if is_rom(&entry.name) && ui.small_button("Boot").clicked() {
+                                run(Job::Boot(path.clone()));
+                            }
+                        });
+                    });
+                }
+            });
+        });
+    });
+}
+
+impl LiveSplitCoreRenderer {
+    pub(crate) fn show_file_manager(&self, ctx: &egui::Context) {
+        if !self.ui.file_manager_open.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let manager = self.ui.file_manager.clone();
+        let app_config = self.app_config.clone();
+        let actions = self.ui.ui_actions.clone();
+        let open = self.ui.file_manager_open.clone();
+
+        ctx.show_viewport_deferred(
+            egui::ViewportId::from_hash_of("file_manager"),
+            egui::ViewportBuilder::default()
+                .with_title("Annelid SD Card")
+                .with_inner_size([500.0, 450.0]),
+            move |ctx, _class| {
+                file_manager_ui(ctx, &manager, &app_config, &actions, &open);
+            },
+        );
+    }
+
+    pub(crate) fn upload_to_cart_dialog(&mut self, document_dir: &str) {
+        let Some(local) = rfd::FileDialog::new()
+            .set_directory(document_dir)
+            .add_filter("ROMs and patches", &["sfc", "smc", "ips", "bps"])
+            .add_filter("Any file", &["*"])
+            .pick_file()
+        else {
+            return;
+        };
+        let remote_dir = self.ui.file_manager.dir();
+        self.ui
+            .file_manager
+            .run(&self.app_config.read(), Job::Upload { local, remote_dir });
+    }
+
+    pub(crate) fn download_from_cart_dialog(&mut self, document_dir: &str, remote: String) {
+        let Some(local) = rfd::FileDialog::new()
+            .set_directory(document_dir)
+            .set_file_name(file_name(&remote))
+            .save_file()
+        else {
+            return;
+        };
+        self.ui
+            .file_manager
+            .run(&self.app_config.read(), Job::Download { remote, local });
+    }
+
+    pub(crate) fn delete_from_cart(&mut self, remote: String) {
+        use rfd::{MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
+        let confirmed = MessageDialog::new()
+            .set_level(MessageLevel::Warning)
+            .set_title("Delete File")
+            .set_description(format!("Delete {remote} from the SD card?"))
+            .set_buttons(MessageButtons::YesNo)
+            .show();
+        if confirmed == MessageDialogResult::Yes {
+            self.ui
+                .file_manager
+                .run(&self.app_config.read(), Job::Delete(remote));
+        }
+    }
+}
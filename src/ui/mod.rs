@@ -1,8 +1,11 @@
 pub mod app_settings;
 pub mod autosplitter_settings;
 pub mod control_panel;
+pub mod diagnostics;
 pub mod file_dialogs;
+pub mod file_manager;
 pub mod hotkeys;
 pub mod layout_editor;
 pub mod log_viewer;
+pub mod session_replay;
 pub mod splits_editor;
@@ -0,0 +1,411 @@
+use anyhow::{anyhow, Result};
+use eframe::egui;
+use livesplit_core::SharedTimer;
+use parking_lot::{Mutex, RwLock};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thread_priority::ThreadBuilder;
+
+use crate::autosplitters::registry::{
+    find_autosplitter, load_script, AutoSplitterEntry, GameInfo, AUTOSPLITTERS,
+};
+use crate::autosplitters::AutoSplitter;
+use crate::config::app_config::AppConfig;
+use crate::livesplit_renderer::{apply_summary, sync_current_split, LiveSplitCoreRenderer};
+use crate::trace::{format_time, PlaybackClock, Trace, TraceEvent, TraceMeta, TracePlayer};
+use crate::ui::control_panel::UiAction;
+
+/// Playback speeds offered in the window, as multiples of the recorded pace.
+pub const SPEEDS: [f32; 5] = [1.0, 2.0, 4.0, 8.0, 16.0];
+/// Longest the replay thread sleeps before checking the controls again.
+const CONTROL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What the window shows and the replay thread reads its controls from.
+#[derive(Clone, Debug)]
+pub(crate) struct ReplayState {
+    /// True while a replay thread is playing polls into the timer.
+    pub active: bool,
+    /// Bumped for each new replay so a replaced thread knows to stop.
+    generation: u64,
+    pub file_name: String,
+    pub meta: TraceMeta,
+    pub autosplitter: String,
+    pub polls: usize,
+    pub position: usize,
+    pub events: Vec<TraceEvent>,
+    pub error: Option<String>,
+    pub speed: f32,
+    pub paused: bool,
+    steps: usize,
+}
+
+impl Default for ReplayState {
+    fn default() -> Self {
+        ReplayState {
+            active: false,
+            generation: 0,
+            file_name: String::new(),
+            meta: TraceMeta::default(),
+            autosplitter: String::new(),
+            polls: 0,
+            position: 0,
+            events: vec![],
+            error: None,
+            speed: 1.0,
+            paused: false,
+            steps: 0,
+        }
+    }
+}
+
+/// Shared between the UI, the replay thread and the polling thread.
+#[derive(Clone, Default)]
+pub(crate) struct SessionReplay(Arc<Mutex<ReplayState>>);
+
+impl SessionReplay {
+    pub fn is_active(&self) -> bool {
+        self.0.lock().active
+    }
+
+    pub fn snapshot(&self) -> ReplayState {
+        self.0.lock().clone()
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        self.0.lock().speed = speed;
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.0.lock().paused = paused;
+    }
+
+    /// Plays the next poll. Stepping pauses playback first.
+    pub fn step(&self) {
+        let mut state = self.0.lock();
+        state.paused = true;
+        state.steps += 1;
+    }
+
+    /// Ends the replay and gives the timer back to the polling thread.
+    pub fn stop(&self) {
+        let mut state = self.0.lock();
+        state.generation += 1;
+        state.active = false;
+    }
+
+    /// Plays `trace` into `timer` on a new thread, replacing any replay in
+    /// progress.
+    fn start(
+        &self,
+        file_name: String,
+        trace: Trace,
+        autosplitter: (&'static str, Box<dyn AutoSplitter>),
+        timer: SharedTimer,
+        app_config: Arc<RwLock<AppConfig>>,
+    ) -> Result<()> {
+        let generation = {
+            let mut state = self.0.lock();
+            *state = ReplayState {
+                active: true,
+                generation: state.generation + 1,
+                file_name,
+                meta: trace.meta.clone(),
+                autosplitter: autosplitter.0.to_owned(),
+                polls: trace.polls.len(),
+                speed: state.speed,
+                ..ReplayState::default()
+            };
+            state.generation
+        };
+        let replay = self.clone();
+        let mut autosplitter = autosplitter.1;
+        ThreadBuilder::default()
+            .name("Session Replay Thread".to_owned())
+            .spawn(move |_| {
+                let result = replay.play(
+                    generation,
+                    &trace,
+                    autosplitter.as_mut(),
+                    &timer,
+                    &app_config,
+                );
+                let mut state = replay.0.lock();
+                if state.generation == generation {
+                    state.active = false;
+                    if let Err(e) = result {
+                        tracing::warn!("Session replay failed: {e:#}");
+                        state.error = Some(format!("{e:#}"));
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    /// The replay thread: plays polls as the clock reaches them, picking up
+    /// speed, pause and step requests between polls.
+    fn play(
+        &self,
+        generation: u64,
+        trace: &Trace,
+        autosplitter: &mut dyn AutoSplitter,
+        timer: &SharedTimer,
+        app_config: &RwLock<AppConfig>,
+    ) -> Result<()> {
+        let mut player = TracePlayer::new(trace);
+        let mut clock = PlaybackClock::new(player.next_time().unwrap_or_default(), Instant::now());
+        while !player.is_finished() {
+            let now = Instant::now();
+            let mut steps = {
+                let mut state = self.0.lock();
+                if state.generation != generation {
+                    return Ok(());
+                }
+                if clock.speed() != state.speed {
+                    clock.set_speed(state.speed, now);
+                }
+                if clock.is_paused() != state.paused {
+                    if state.paused {
+                        clock.pause(now);
+                    } else {
+                        clock.resume(now);
+                    }
+                }
+                std::mem::take(&mut state.steps)
+            };
+            let mut events = vec![];
+            while steps > 0 || player.is_due(clock.position(now)) {
+                let time = player.next_time().unwrap_or_default();
+                sync_current_split(timer, autosplitter)?;
+                let (summary, new_events) = player.step(autosplitter)?;
+                apply_summary(timer, app_config, autosplitter, summary, false)?;
+                events.extend(new_events);
+                if steps > 0 {
+                    steps -= 1;
+                    clock.seek(time, now);
+                }
+                if player.is_finished() {
+                    break;
+                }
+            }
+            {
+                let mut state = self.0.lock();
+                if state.generation != generation {
+                    return Ok(());
+                }
+                state.position = player.position();
+                state.events.extend(events);
+            }
+            let wait = player
+                .next_time()
+                .and_then(|next| clock.until(next, Instant::now()))
+                .unwrap_or(CONTROL_INTERVAL);
+            std::thread::sleep(wait.min(CONTROL_INTERVAL));
+        }
+        Ok(())
+    }
+}
+
+fn session_replay_ui(
+    ctx: &egui::Context,
+    replay: &SessionReplay,
+    actions: &Mutex<Vec<UiAction>>,
+    open: &AtomicBool,
+) {
+    if ctx.input(|i| i.viewport().close_requested()) {
+        open.store(false, Ordering::Relaxed);
+        return;
+    }
+
+    let state = replay.snapshot();
+    if state.active {
+        ctx.request_repaint_after(Duration::from_millis(100));
+    }
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("Open Trace...").clicked() {
+                actions.lock().push(UiAction::OpenReplayTrace);
+            }
+            if ui
+                .add_enabled(state.active, egui::Button::new("Stop"))
+                .clicked()
+            {
+                replay.stop();
+            }
+        });
+        ui.weak("Turn on \"Record polling traces\" in Settings to record sessions to replay.");
+        ui.separator();
+
+        if state.file_name.is_empty() {
+            ui.weak("No trace loaded");
+            return;
+        }
+
+        egui::Grid::new("replay_meta")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Trace");
+                ui.label(&state.file_name);
+                ui.end_row();
+                ui.label("Game");
+                ui.label(&state.meta.game);
+                ui.end_row();
+                ui.label("Autosplitter");
+                ui.label(&state.autosplitter);
+                ui.end_row();
+                ui.label("Recorded");
+                ui.label(format!(
+                    "{} on {}",
+                    state.meta.recorded_at, state.meta.device
+                ));
+                ui.end_row();
+            });
+        ui.separator();
+
+        let progress = if state.polls == 0 {
+            1.0
+        } else {
+            state.position as f32 / state.polls as f32
+        };
+        ui.add(
+            egui::ProgressBar::new(progress)
+                .text(format!("Poll {} of {}", state.position, state.polls)),
+        );
+
+        ui.add_enabled_ui(state.active, |ui| {
+            ui.horizontal(|ui| {
+                let label = if state.paused { "Play" } else { "Pause" };
+                if ui.button(label).clicked() {
+                    replay.set_paused(!state.paused);
+                }
+                if ui.button("Step").on_hover_text("Play one poll").clicked() {
+                    replay.step();
+                }
+                ui.separator();
+                let mut speed = state.speed;
+                for choice in SPEEDS {
+                    ui.selectable_value(&mut speed, choice, format!("{choice}x"));
+                }
+                if speed != state.speed {
+                    replay.set_speed(speed);
+                }
+            });
+        });
+        if state.speed != 1.0 {
+            ui.weak("Real time only keeps pace at 1x; game time is read from the trace.");
+        }
+        if let Some(error) = &state.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        } else if !state.active && state.position == state.polls {
+            ui.label("Replay finished");
+        }
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                egui::Grid::new("replay_events")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for event in state.events.iter() {
+                            ui.monospace(format!("poll {}", event.poll));
+                            ui.monospace(format_time(event.time));
+                            ui.label(event.kind.to_string());
+                            ui.end_row();
+                        }
+                    });
+            });
+    });
+}
+
+impl LiveSplitCoreRenderer {
+    pub(crate) fn show_session_replay(&self, ctx: &egui::Context) {
+        if !self.ui.session_replay_open.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let replay = self.session_replay.clone();
+        let actions = self.ui.ui_actions.clone();
+        let open = self.ui.session_replay_open.clone();
+
+        ctx.show_viewport_deferred(
+            egui::ViewportId::from_hash_of("session_replay"),
+            egui::ViewportBuilder::default()
+                .with_title("Annelid Session Replay")
+                .with_inner_size([420.0, 460.0]),
+            move |ctx, _class| {
+                session_replay_ui(ctx, &replay, &actions, &open);
+            },
+        );
+    }
+
+    /// Loads the trace at `path` and starts playing it into the timer with
+    /// the autosplitter it was recorded with, using the current settings.
+    /// Traces recorded with JSON definitions, an ASL script or a WASM
+    /// module use the one loaded now.
+    /// The timer is reset first without saving the attempt.
+    pub(crate) fn start_session_replay(&mut self, path: &Path) -> Result<()> {
+        let trace = Trace::load(path)?;
+        let config = self.app_config.read().clone();
+        let script = if AUTOSPLITTERS
+            .iter()
+            .any(|e| e.name == trace.meta.autosplitter)
+        {
+            None
+        } else {
+            load_script(
+                config.json_splits.as_deref().map(Path::new),
+                config.asl_script.as_deref().map(Path::new),
+                config.wasm_autosplitter.as_deref().map(Path::new),
+                &self.settings,
+            )?
+        };
+        let autosplitter: (&'static str, Box<dyn AutoSplitter>) = match script {
+            Some((entry, autosplitter)) if entry.name == trace.meta.autosplitter => {
+                (entry.name, autosplitter)
+            }
+            _ => {
+                let entry = recorded_autosplitter(&trace)?;
+                (entry.name, (entry.create)(self.settings.clone()))
+            }
+        };
+        if trace.truncated {
+            tracing::warn!("{} ends partway through a poll", path.display());
+        }
+        self.timer
+            .write()
+            .map_err(|e| anyhow!("failed to acquire write lock on timer: {e}"))?
+            .reset(false)
+            .ok();
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.session_replay.start(
+            file_name,
+            trace,
+            autosplitter,
+            self.timer.clone(),
+            self.app_config.clone(),
+        )
+    }
+}
+
+/// The registered autosplitter `trace` was recorded with, or failing
+/// that one for its ROM name.
+fn recorded_autosplitter(trace: &Trace) -> Result<&'static AutoSplitterEntry> {
+    AUTOSPLITTERS
+        .iter()
+        .find(|e| e.name == trace.meta.autosplitter)
+        .or_else(|| {
+            find_autosplitter(&GameInfo {
+                rom_name: trace.meta.rom_name.clone(),
+                header: None,
+            })
+        })
+        .ok_or_else(|| anyhow!("No autosplitter for {}", trace.meta.game))
+}
@@ -21,8 +21,9 @@
 #![allow(dead_code)]
 
 use serde::{Deserialize, Serialize};
-use std::net::TcpStream;
+use std::net::{TcpStream, ToSocketAddrs};
 use strum_macros::Display;
+use tungstenite::handshake::HandshakeError;
 use tungstenite::protocol::WebSocket;
 use tungstenite::stream::MaybeTlsStream;
 use tungstenite::Bytes;
@@ -30,9 +31,14 @@ use tungstenite::Message;
 
 use std::borrow::Cow;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 
+use crate::config::app_config::DeviceFallback;
+
 #[derive(Display, Debug)]
 #[allow(dead_code)]
 pub enum Command {
@@ -52,8 +58,9 @@ pub enum Command {
     Remove,
 
     GetAddress,
+    PutAddress,
 }
-#[derive(Display, Debug)]
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum Space {
     None,
@@ -61,7 +68,7 @@ pub enum Space {
     CMD,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Infos {
     pub version: Rc<str>,
     pub dev_type: Rc<str>,
@@ -69,6 +76,78 @@ pub struct Infos {
     pub flags: Vec<Rc<str>>,
 }
 
+/// Feature flags a server may report in `Info` to say what it can't do.
+pub const FLAG_NO_CONTROL_CMD: &str = "NO_CONTROL_CMD";
+pub const FLAG_NO_ROM_WRITE: &str = "NO_ROM_WRITE";
+pub const FLAG_NO_ROM_READ: &str = "NO_ROM_READ";
+pub const FLAG_NO_FILE_CMD: &str = "NO_FILE_CMD";
+
+impl Infos {
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| &**f == flag)
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        !self.has_flag(capability.flag())
+    }
+
+    /// The entries of `needs` this device can't do, in order.
+    pub fn missing(&self, needs: &[Capability]) -> Vec<Capability> {
+        needs
+            .iter()
+            .copied()
+            .filter(|&c| !self.supports(c))
+            .collect()
+    }
+}
+
+/// Something a device can be unable to do, as advertised by a flag in
+/// `Info`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    RomRead,
+    RomWrite,
+    /// Reset, menu and boot, and writes to `CMD` space.
+    ControlCommands,
+    FileCommands,
+}
+
+impl Capability {
+    /// The flag a server reports when the device lacks this.
+    pub fn flag(self) -> &'static str {
+        match self {
+            Capability::RomRead => FLAG_NO_ROM_READ,
+            Capability::RomWrite => FLAG_NO_ROM_WRITE,
+            Capability::ControlCommands => FLAG_NO_CONTROL_CMD,
+            Capability::FileCommands => FLAG_NO_FILE_CMD,
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::RomRead => write!(f, "reading ROM"),
+            Capability::RomWrite => write!(f, "writing ROM"),
+            Capability::ControlCommands => write!(f, "resetting the console"),
+            Capability::FileCommands => write!(f, "SD card access"),
+        }
+    }
+}
+
+/// How `get_addresses` turns its regions into `GetAddress` requests.
+/// Vectored reads are an sd2snes/FXPak firmware feature that most other
+/// devices get through QUsb2snes or SNI too; `SyncClient` falls back to
+/// sequential reads for devices that fail them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadStrategy {
+    /// Up to `MAX_VECTOR_REGIONS` regions per request.
+    #[default]
+    Vectored,
+    /// One region per request.
+    Sequential,
+}
+
 #[derive(Serialize)]
 #[allow(non_snake_case)]
 struct USB2SnesQuery<'a> {
@@ -96,26 +175,371 @@ pub struct USB2SnesFileInfo {
     pub file_type: USB2SnesFileType,
 }
 
+pub const DEFAULT_HOST: &str = "localhost";
+pub const DEFAULT_PORT: u16 = 23074;
+/// Older QUsb2snes releases (and some SNI setups) only listen here.
+pub const LEGACY_PORT: u16 = 8080;
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a single socket read may block before we check for
+/// cancellation and the overall read deadline again.
+pub(crate) const READ_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// A `GetAddress` with several operand pairs becomes a single vectored
+/// read on the sd2snes, which allows at most this many regions...
+pub const MAX_VECTOR_REGIONS: usize = 8;
+/// ...of at most this many bytes each. A single pair may be any size.
+pub const MAX_VECTOR_SIZE: usize = 255;
+/// Where WRAM and cartridge SRAM live in the usb2snes `SNES` space.
+pub const WRAM_START: u32 = 0xF50000;
+pub const WRAM_SIZE: u32 = 0x20000;
+pub const SRAM_START: u32 = 0xE00000;
+pub const SRAM_SIZE: u32 = 0x100000;
+
+#[derive(Debug)]
+pub enum USB2SnesError {
+    /// The server did not answer within the configured timeout.
+    Timeout(Duration),
+    /// The request was abandoned through a `CancelHandle`.
+    Cancelled,
+    /// The device's `Info` flags rule out the operation.
+    Unsupported(String),
+    /// The server's reply didn't make sense for the command, e.g. too few
+    /// fields or JSON we couldn't parse.
+    Protocol(String),
+    /// The server closed the connection.
+    Closed,
+    /// A text frame came where binary data was expected, or the reverse.
+    UnexpectedFrame {
+        expected: &'static str,
+        got: &'static str,
+    },
+    /// The data stopped before the requested number of bytes arrived.
+    ShortRead { expected: usize, got: usize },
+    /// The server has no devices connected.
+    NoDevice,
+}
+
+impl std::fmt::Display for USB2SnesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            USB2SnesError::Timeout(d) => {
+                write!(f, "server did not respond within {d:?}")
+            }
+            USB2SnesError::Cancelled => write!(f, "usb2snes request was cancelled"),
+            USB2SnesError::Unsupported(what) => write!(f, "device does not support {what}"),
+            USB2SnesError::Protocol(what) => write!(f, "malformed usb2snes reply: {what}"),
+            USB2SnesError::Closed => write!(f, "usb2snes server closed the connection"),
+            USB2SnesError::UnexpectedFrame { expected, got } => {
+                write!(f, "expected a {expected} reply from usb2snes, got {got}")
+            }
+            USB2SnesError::ShortRead { expected, got } => {
+                write!(f, "usb2snes sent {got} of {expected} bytes")
+            }
+            USB2SnesError::NoDevice => write!(f, "No devices present"),
+        }
+    }
+}
+
+impl std::error::Error for USB2SnesError {}
+
+impl USB2SnesError {
+    /// Returns the `USB2SnesError` behind an `anyhow::Error`, if there is one.
+    pub fn from_anyhow(e: &anyhow::Error) -> Option<&USB2SnesError> {
+        e.downcast_ref::<USB2SnesError>()
+    }
+
+    /// Whether the server broke the protocol, as opposed to going away or
+    /// being slow. Retrying on the same connection won't help.
+    pub fn is_protocol_error(&self) -> bool {
+        matches!(
+            self,
+            USB2SnesError::Protocol(_)
+                | USB2SnesError::UnexpectedFrame { .. }
+                | USB2SnesError::ShortRead { .. }
+        )
+    }
+}
+
+/// How a frame is described in `UnexpectedFrame`.
+fn frame_kind(message: &Message) -> &'static str {
+    match message {
+        Message::Text(_) => "text",
+        Message::Binary(_) => "binary",
+        Message::Ping(_) => "ping",
+        Message::Pong(_) => "pong",
+        Message::Close(_) => "close",
+        Message::Frame(_) => "raw frame",
+    }
+}
+
+fn is_closed(e: &tungstenite::Error) -> bool {
+    use std::io::ErrorKind;
+    match e {
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => true,
+        tungstenite::Error::Io(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::BrokenPipe
+                | ErrorKind::UnexpectedEof
+        ),
+        tungstenite::Error::Protocol(
+            tungstenite::error::ProtocolError::ResetWithoutClosingHandshake,
+        ) => true,
+        _ => false,
+    }
+}
+
+/// Shared flag that makes any blocked read on a `SyncClient` return
+/// `USB2SnesError::Cancelled`. Clones refer to the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears the flag so the handle can be reused for a new connection.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub cancel: CancelHandle,
+    pub devel: bool,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            cancel: CancelHandle::new(),
+            devel: false,
+        }
+    }
+}
+
 pub struct SyncClient {
     client: WebSocket<MaybeTlsStream<TcpStream>>,
     devel: bool,
+    read_timeout: Duration,
+    cancel: CancelHandle,
+    /// The device passed to the last `attach`.
+    device: Option<Rc<str>>,
+    /// `Info` for the attached device, fetched the first time a write
+    /// needs to check its flags.
+    infos: Option<Infos>,
+    /// Starts out vectored and drops to sequential for the rest of the
+    /// connection once the device fails a vectored read.
+    read_strategy: ReadStrategy,
+}
+
+/// The ports to try, in order, when connecting to `port`.
+pub fn candidate_ports(port: u16) -> Vec<u16> {
+    if port == LEGACY_PORT {
+        vec![port]
+    } else {
+        vec![port, LEGACY_PORT]
+    }
+}
+
+/// Formats `host` and `port` as a socket address, bracketing IPv6 literals.
+pub fn server_address(host: &str, port: u16) -> String {
+    let host = host.trim();
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+pub(crate) fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
 }
 
 impl SyncClient {
     pub fn connect() -> Result<SyncClient> {
-        Ok(SyncClient {
-            client: tungstenite::client::connect("ws://localhost:23074")?.0,
-            devel: false,
-        })
+        Self::connect_with_options(ClientOptions::default())
     }
 
     pub fn connect_with_devel() -> Result<SyncClient> {
-        Ok(SyncClient {
-            client: tungstenite::client::connect("ws://localhost:23074")?.0,
+        Self::connect_with_options(ClientOptions {
             devel: true,
+            ..ClientOptions::default()
+        })
+    }
+
+    pub fn connect_with_options(options: ClientOptions) -> Result<SyncClient> {
+        Self::connect_to_server(DEFAULT_HOST, DEFAULT_PORT, options)
+    }
+
+    /// Connects to the server at `host:port`, falling back to the legacy
+    /// QUsb2snes port if nothing answers on `port`.
+    pub fn connect_to_server(host: &str, port: u16, options: ClientOptions) -> Result<SyncClient> {
+        Self::connect_any(host, &candidate_ports(port), options)
+    }
+
+    /// Tries each port in turn and returns the first successful connection,
+    /// or the error from the first port if none of them work.
+    pub fn connect_any(host: &str, ports: &[u16], options: ClientOptions) -> Result<SyncClient> {
+        let mut first_error = None;
+        for &port in ports {
+            let address = server_address(host, port);
+            match Self::connect_to(&address, options.clone()) {
+                Ok(client) => {
+                    if first_error.is_some() {
+                        tracing::info!("Connected to usb2snes server on fallback port {port}");
+                    }
+                    return Ok(client);
+                }
+                Err(e) => {
+                    tracing::debug!("No usb2snes server at {address}: {e}");
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap_or_else(|| anyhow!("No usb2snes ports to try")))
+    }
+
+    /// Connects to the usb2snes server at `address` (`host:port`).
+    pub fn connect_to(address: &str, options: ClientOptions) -> Result<SyncClient> {
+        let mut last_error = None;
+        let mut stream = None;
+        for addr in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, options.connect_timeout) {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(e) if is_timeout(&e) => {
+                    last_error = Some(USB2SnesError::Timeout(options.connect_timeout).into())
+                }
+                Err(e) => last_error = Some(e.into()),
+            }
+        }
+        let stream = match stream {
+            Some(stream) => stream,
+            None => {
+                return Err(last_error.unwrap_or_else(|| anyhow!("Could not resolve {address}")))
+            }
+        };
+        stream.set_nodelay(true)?;
+        // The handshake is allowed the whole connect timeout. Afterwards we
+        // switch to short reads so blocked calls can notice cancellation.
+        stream.set_read_timeout(Some(options.connect_timeout))?;
+        stream.set_write_timeout(Some(options.read_timeout))?;
+        let url = format!("ws://{address}");
+        let client = match tungstenite::client::client(url, MaybeTlsStream::Plain(stream)) {
+            Ok((client, _response)) => client,
+            Err(HandshakeError::Interrupted(_)) => {
+                Err(USB2SnesError::Timeout(options.connect_timeout))?
+            }
+            Err(HandshakeError::Failure(tungstenite::Error::Io(e))) if is_timeout(&e) => {
+                Err(USB2SnesError::Timeout(options.connect_timeout))?
+            }
+            Err(HandshakeError::Failure(e)) => Err(e)?,
+        };
+        if let MaybeTlsStream::Plain(stream) = client.get_ref() {
+            stream.set_read_timeout(Some(READ_POLL_INTERVAL))?;
+        }
+        Ok(SyncClient {
+            client,
+            devel: options.devel,
+            read_timeout: options.read_timeout,
+            cancel: options.cancel,
+            device: None,
+            infos: None,
+            read_strategy: ReadStrategy::default(),
         })
     }
 
+    /// A handle that can abort reads on this client from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Waits for the next text or binary message from the server, giving up
+    /// after the read timeout or as soon as the client is cancelled.
+    fn read_message(&mut self) -> Result<Message> {
+        let deadline = Instant::now() + self.read_timeout;
+        loop {
+            if self.cancel.is_cancelled() {
+                Err(USB2SnesError::Cancelled)?;
+            }
+            match self.client.read() {
+                // tungstenite answers pings itself.
+                Ok(Message::Ping(_) | Message::Pong(_)) => {}
+                Ok(Message::Close(_)) => Err(USB2SnesError::Closed)?,
+                Ok(message) => return Ok(message),
+                Err(tungstenite::Error::Io(e)) if is_timeout(&e) => {
+                    if Instant::now() >= deadline {
+                        Err(USB2SnesError::Timeout(self.read_timeout))?;
+                    }
+                }
+                Err(e) if is_closed(&e) => Err(USB2SnesError::Closed)?,
+                Err(e) => Err(e)?,
+            }
+        }
+    }
+
+    /// Reads binary messages until `size` bytes have arrived, calling
+    /// `progress` with the running total. A connection that closes, stalls
+    /// or switches to text partway through is a short read.
+    fn read_binary(&mut self, size: usize, mut progress: impl FnMut(usize)) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = Vec::with_capacity(size);
+        while data.len() < size {
+            let message = match self.read_message() {
+                Ok(message) => message,
+                Err(e) if !data.is_empty() => match USB2SnesError::from_anyhow(&e) {
+                    Some(USB2SnesError::Closed | USB2SnesError::Timeout(_)) => {
+                        Err(USB2SnesError::ShortRead {
+                            expected: size,
+                            got: data.len(),
+                        })?
+                    }
+                    _ => return Err(e),
+                },
+                Err(e) => return Err(e),
+            };
+            match message {
+                Message::Binary(msgdata) => data.extend(&msgdata),
+                other if data.is_empty() => Err(USB2SnesError::UnexpectedFrame {
+                    expected: "binary",
+                    got: frame_kind(&other),
+                })?,
+                _ => Err(USB2SnesError::ShortRead {
+                    expected: size,
+                    got: data.len(),
+                })?,
+            }
+            progress(data.len());
+        }
+        if data.len() != size {
+            Err(USB2SnesError::Protocol(format!(
+                "expected {size} bytes, got {}",
+                data.len()
+            )))?;
+        }
+        Ok(data)
+    }
+
     fn send_command(&mut self, command: Command, args: &[Cow<str>]) -> Result<()> {
         self.send_command_with_space(command, None, args)
     }
@@ -142,23 +566,40 @@ impl SyncClient {
             tracing::debug!("{json}");
         }
         let message = Message::text(json);
-        Ok(self.client.send(message)?)
+        match self.client.send(message) {
+            Ok(()) => Ok(()),
+            Err(e) if is_closed(&e) => Err(USB2SnesError::Closed)?,
+            Err(e) => Err(e)?,
+        }
     }
 
     fn get_reply(&mut self) -> Result<USB2SnesResult> {
-        let reply = self.client.read()?;
-        let mut textreply: String = String::from("");
-        match reply {
-            Message::Text(value) => {
-                textreply = value.to_string();
-            }
-            _ => Err(anyhow!("Error getting a reply"))?,
+        let textreply = match self.read_message()? {
+            Message::Text(value) => value.to_string(),
+            other => Err(USB2SnesError::UnexpectedFrame {
+                expected: "text",
+                got: frame_kind(&other),
+            })?,
         };
         if self.devel {
             tracing::debug!("Reply:");
             tracing::debug!("{textreply}");
         }
-        Ok(serde_json::from_str(&textreply)?)
+        let reply = serde_json::from_str(&textreply)
+            .map_err(|e| USB2SnesError::Protocol(format!("{e}: {textreply:?}")))?;
+        Ok(reply)
+    }
+
+    /// Gets a reply and checks it has at least `min` results.
+    fn get_results(&mut self, command: Command, min: usize) -> Result<Rc<[Rc<str>]>> {
+        let results = self.get_reply()?.Results;
+        if results.len() < min {
+            Err(USB2SnesError::Protocol(format!(
+                "{command} reply has {} fields, expected at least {min}",
+                results.len()
+            )))?;
+        }
+        Ok(results)
     }
 
     pub fn set_name(&mut self, name: &str) -> Result<()> {
@@ -167,8 +608,8 @@ impl SyncClient {
 
     pub fn app_version(&mut self) -> Result<String> {
         self.send_command(Command::AppVersion, &[])?;
-        let usbreply = self.get_reply()?;
-        Ok(usbreply.Results[0].to_string())
+        let results = self.get_results(Command::AppVersion, 1)?;
+        Ok(results[0].to_string())
     }
 
     pub fn list_device(&mut self) -> Result<Rc<[Rc<str>]>> {
@@ -178,19 +619,47 @@ impl SyncClient {
     }
 
     pub fn attach(&mut self, device: &str) -> Result<()> {
+        self.infos = None;
+        self.read_strategy = ReadStrategy::default();
+        self.device = Some(Rc::from(device));
         self.send_command(Command::Attach, &[Cow::Borrowed(device)])
     }
 
+    pub fn device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+
     pub fn info(&mut self) -> Result<Infos> {
         self.send_command(Command::Info, &[])?;
-        let usbreply = self.get_reply()?;
-        let info = usbreply.Results;
-        Ok(Infos {
+        let info = self.get_results(Command::Info, 3)?;
+        let infos = Infos {
             version: info[0].clone(),
             dev_type: info[1].clone(),
             game: info[2].clone(),
             flags: info[3..].to_owned(),
-        })
+        };
+        if self.infos.is_none() {
+            tracing::info!(
+                "{} {}: flags {:?}",
+                infos.dev_type,
+                infos.version,
+                infos.flags
+            );
+        }
+        self.infos = Some(infos.clone());
+        Ok(infos)
+    }
+
+    /// How reads are currently batched for the attached device.
+    pub fn read_strategy(&self) -> ReadStrategy {
+        self.read_strategy
+    }
+
+    fn cached_info(&mut self) -> Result<&Infos> {
+        if self.infos.is_none() {
+            self.info()?;
+        }
+        Ok(self.infos.as_ref().expect("info() caches its result"))
     }
 
     pub fn reset(&mut self) -> Result<()> {
@@ -207,62 +676,70 @@ impl SyncClient {
 
     pub fn ls(&mut self, path: &str) -> Result<Vec<USB2SnesFileInfo>> {
         self.send_command(Command::List, &[Cow::Borrowed(path)])?;
-        let usbreply = self.get_reply()?;
-        let vec_info = usbreply.Results;
-        let mut toret: Vec<USB2SnesFileInfo> = vec![];
-        let mut i = 0;
-        while i < vec_info.len() {
-            let info: USB2SnesFileInfo = USB2SnesFileInfo {
-                file_type: if &*vec_info[i] == "1" {
+        let vec_info = self.get_reply()?.Results;
+        // Entries come as (type, name) pairs.
+        if vec_info.len() % 2 != 0 {
+            Err(USB2SnesError::Protocol(format!(
+                "List reply has {} fields, expected type and name pairs",
+                vec_info.len()
+            )))?;
+        }
+        Ok(vec_info
+            .chunks_exact(2)
+            .map(|entry| USB2SnesFileInfo {
+                file_type: if &*entry[0] == "1" {
                     USB2SnesFileType::File
                 } else {
                     USB2SnesFileType::Dir
                 },
-                name: vec_info[i + 1].clone(),
-            };
-            toret.push(info);
-            i += 2;
-        }
-        Ok(toret)
+                name: entry[1].clone(),
+            })
+            .collect())
     }
 
     pub fn send_file(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        self.send_file_with_progress(path, data, |_, _| {})
+    }
+
+    /// Like `send_file`, calling `progress(sent, total)` after each chunk.
+    /// The server does not acknowledge `PutFile`, so the upload is only
+    /// known to be complete after the next command that gets a reply.
+    pub fn send_file_with_progress(
+        &mut self,
+        path: &str,
+        data: &[u8],
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<()> {
         self.send_command(
             Command::PutFile,
             &[Cow::Borrowed(path), Cow::Owned(format!("{:x}", data.len()))],
         )?;
-        let mut start = 0;
-        let mut stop = 1024;
-        while start < data.len() {
+        let mut sent = 0;
+        for chunk in data.chunks(1024) {
             self.client
-                .send(Message::binary(Bytes::copy_from_slice(&data[start..stop])))?;
-            start += 1024;
-            stop += 1024;
-            if stop > data.len() {
-                stop = data.len();
-            }
+                .send(Message::binary(Bytes::copy_from_slice(chunk)))?;
+            sent += chunk.len();
+            progress(sent, data.len());
         }
         Ok(())
     }
 
     pub fn get_file(&mut self, path: &str) -> Result<Vec<u8>> {
+        self.get_file_with_progress(path, |_, _| {})
+    }
+
+    /// Like `get_file`, calling `progress(received, total)` as data arrives.
+    pub fn get_file_with_progress(
+        &mut self,
+        path: &str,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<u8>> {
         self.send_command(Command::GetFile, &[Cow::Borrowed(path)])?;
-        let string_hex = self.get_reply()?.Results[0].to_string();
-        let size = usize::from_str_radix(&string_hex, 16)?;
-        let mut data: Vec<u8> = Vec::with_capacity(size);
-        loop {
-            let reply = self.client.read()?;
-            match reply {
-                Message::Binary(msgdata) => {
-                    data.extend(&msgdata);
-                }
-                _ => Err(anyhow!("Error getting a reply"))?,
-            }
-            if data.len() == size {
-                break;
-            }
-        }
-        Ok(data)
+        let results = self.get_results(Command::GetFile, 1)?;
+        let size = usize::from_str_radix(&results[0], 16).map_err(|_| {
+            USB2SnesError::Protocol(format!("GetFile size {:?} is not hex", results[0]))
+        })?;
+        self.read_binary(size, |received| progress(received, size))
     }
 
     pub fn remove_path(&mut self, path: &str) -> Result<()> {
@@ -278,23 +755,102 @@ impl SyncClient {
                 Cow::Owned(format!("{size:x}")),
             ],
         )?;
-        let mut data: Vec<u8> = Vec::with_capacity(size);
-        loop {
-            let reply = self.client.read()?;
-            match reply {
-                Message::Binary(msgdata) => {
-                    data.extend(&msgdata);
-                }
-                _ => Err(anyhow!("Error getting a reply"))?,
-            }
-            if data.len() == size {
-                break;
+        self.read_binary(size, |_| {})
+    }
+
+    /// Writes `data` at `address` in `space`, after checking the device's
+    /// flags allow it. The server sends no reply to `PutAddress`.
+    pub fn put_address(&mut self, space: Space, address: u32, data: &[u8]) -> Result<()> {
+        let info = self.cached_info()?;
+        match space {
+            Space::CMD if !info.supports(Capability::ControlCommands) => {
+                Err(USB2SnesError::Unsupported("writes to CMD space".to_owned()))?
             }
+            Space::SNES if address < SRAM_START && !info.supports(Capability::RomWrite) => Err(
+                USB2SnesError::Unsupported(format!("ROM writes (address {address:#x})")),
+            )?,
+            Space::None => Err(anyhow!("PutAddress needs a memory space"))?,
+            _ => {}
         }
-        Ok(data)
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.send_command_with_space(
+            Command::PutAddress,
+            Some(space),
+            &[
+                Cow::Owned(format!("{address:x}")),
+                Cow::Owned(format!("{:x}", data.len())),
+            ],
+        )?;
+        for chunk in data.chunks(1024) {
+            self.client
+                .send(Message::binary(Bytes::copy_from_slice(chunk)))?;
+        }
+        Ok(())
+    }
+
+    /// Writes to WRAM; `offset` is relative to `$7E:0000`.
+    pub fn write_wram(&mut self, offset: u32, data: &[u8]) -> Result<()> {
+        check_bounds("WRAM", offset, data.len(), WRAM_SIZE)?;
+        self.put_address(Space::SNES, WRAM_START + offset, data)
+    }
+
+    /// Writes to cartridge SRAM; `offset` is relative to the start of SRAM.
+    pub fn write_sram(&mut self, offset: u32, data: &[u8]) -> Result<()> {
+        check_bounds("SRAM", offset, data.len(), SRAM_SIZE)?;
+        self.put_address(Space::SNES, SRAM_START + offset, data)
+    }
+
+    /// Writes to the sd2snes command area, e.g. to upload code for the
+    /// firmware's NMI hook.
+    pub fn write_cmd(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        self.put_address(Space::CMD, address, data)
     }
 
+    /// Reads any number of regions, merging overlapping or adjacent ones
+    /// and spreading the rest over as few requests as the device allows.
+    /// The result has one entry per pair, in the order given.
     pub fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        self.get_space_addresses(Space::SNES, pairs)
+    }
+
+    /// Like `get_addresses`, for any memory space. The `CMD` space is read
+    /// one region at a time.
+    pub fn get_space_addresses(
+        &mut self,
+        space: Space,
+        pairs: &[(u32, usize)],
+    ) -> Result<Vec<Vec<u8>>> {
+        let strategy = match space {
+            Space::SNES => self.read_strategy,
+            Space::CMD if !self.cached_info()?.supports(Capability::ControlCommands) => {
+                Err(USB2SnesError::Unsupported("reading CMD space".to_owned()))?
+            }
+            Space::CMD => ReadStrategy::Sequential,
+            Space::None => Err(anyhow!("GetAddress needs a memory space"))?,
+        };
+        let plan = ReadPlan::with_strategy(pairs, strategy);
+        let mut replies = Vec::with_capacity(plan.requests().len());
+        for request in plan.requests() {
+            match self.get_vector(space, request) {
+                Ok(reply) => replies.push(reply),
+                // Not every device behind usb2snes can do vectored reads,
+                // and the ones that can't answer them short or garbled.
+                Err(e) if request.len() > 1 && is_failed_vector(&e) => {
+                    tracing::warn!("Vectored read failed, reading one region at a time: {e}");
+                    self.read_strategy = ReadStrategy::Sequential;
+                    return self.get_space_addresses(space, pairs);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(plan.extract(pairs, &replies))
+    }
+
+    /// Sends one `GetAddress` and returns the bytes of every region
+    /// concatenated. `pairs` must already respect the vectored read limits.
+    fn get_vector(&mut self, space: Space, pairs: &[(u32, usize)]) -> Result<Vec<u8>> {
         let mut args = Vec::with_capacity(pairs.len() * 2);
         let mut total_size = 0;
         for &(address, size) in pairs.iter() {
@@ -302,26 +858,176 @@ impl SyncClient {
             args.push(Cow::Owned(format!("{size:x}")));
             total_size += size;
         }
-        self.send_command_with_space(Command::GetAddress, Some(Space::SNES), &args)?;
-        let mut data: Vec<u8> = Vec::with_capacity(total_size);
-        let mut ret: Vec<Vec<u8>> = Vec::with_capacity(pairs.len());
-        loop {
-            let reply = self.client.read()?;
-            match reply {
-                Message::Binary(msgdata) => {
-                    data.extend(&msgdata);
-                }
-                _ => Err(anyhow!("Error getting a reply"))?,
+        self.send_command_with_space(Command::GetAddress, Some(space), &args)?;
+        self.read_binary(total_size, |_| {})
+    }
+}
+
+fn is_failed_vector(e: &anyhow::Error) -> bool {
+    matches!(
+        USB2SnesError::from_anyhow(e),
+        Some(USB2SnesError::ShortRead { .. } | USB2SnesError::Protocol(_))
+    )
+}
+
+fn check_bounds(region: &str, offset: u32, len: usize, size: u32) -> Result<()> {
+    if offset as u64 + len as u64 > size as u64 {
+        Err(anyhow!(
+            "{len} byte write at offset {offset:#x} runs past the end of {region} ({size:#x} bytes)"
+        ))?;
+    }
+    Ok(())
+}
+
+/// How a list of `(address, size)` reads is turned into `GetAddress`
+/// requests.
+///
+/// Overlapping and adjacent ranges are merged first. Merged ranges that fit
+/// in [`MAX_VECTOR_REGIONS`] slots of [`MAX_VECTOR_SIZE`] bytes are cut into
+/// slots and packed into vectored requests; anything larger is read on its
+/// own, since a single-region request has no size limit. With
+/// [`ReadStrategy::Sequential`] every merged range is its own request.
+#[derive(Debug)]
+pub struct ReadPlan {
+    requests: Vec<Vec<(u32, usize)>>,
+}
+
+impl ReadPlan {
+    pub fn new(pairs: &[(u32, usize)]) -> ReadPlan {
+        Self::with_strategy(pairs, ReadStrategy::Vectored)
+    }
+
+    pub fn with_strategy(pairs: &[(u32, usize)], strategy: ReadStrategy) -> ReadPlan {
+        let mut ranges: Vec<(u64, u64)> = pairs
+            .iter()
+            .filter(|&&(_, size)| size > 0)
+            .map(|&(address, size)| (address as u64, address as u64 + size as u64))
+            .collect();
+        ranges.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        if strategy == ReadStrategy::Sequential {
+            let requests = merged
+                .into_iter()
+                .map(|(start, end)| vec![(start as u32, (end - start) as usize)])
+                .collect();
+            return ReadPlan { requests };
+        }
+
+        let max_split = (MAX_VECTOR_REGIONS * MAX_VECTOR_SIZE) as u64;
+        let mut requests = vec![];
+        let mut slots = vec![];
+        for (start, end) in merged {
+            if end - start > max_split {
+                requests.push(vec![(start as u32, (end - start) as usize)]);
+                continue;
             }
-            if data.len() == total_size {
-                break;
+            let mut address = start;
+            while address < end {
+                let size = (end - address).min(MAX_VECTOR_SIZE as u64);
+                slots.push((address as u32, size as usize));
+                address += size;
+            }
+        }
+        requests.extend(slots.chunks(MAX_VECTOR_REGIONS).map(<[_]>::to_vec));
+        ReadPlan { requests }
+    }
+
+    /// The operand pairs of each `GetAddress` to send.
+    pub fn requests(&self) -> &[Vec<(u32, usize)>] {
+        &self.requests
+    }
+
+    /// Cuts the original `pairs` back out of `replies`, where `replies[i]`
+    /// holds the concatenated bytes returned for `requests()[i]`.
+    pub fn extract(&self, pairs: &[(u32, usize)], replies: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let mut pieces: Vec<(u64, &[u8])> = vec![];
+        for (request, reply) in self.requests.iter().zip(replies) {
+            let mut consumed = 0;
+            for &(address, size) in request {
+                pieces.push((address as u64, &reply[consumed..consumed + size]));
+                consumed += size;
             }
         }
-        let mut consumed = 0;
-        for &(_address, size) in pairs.iter() {
-            ret.push(data[consumed..consumed + size].into());
-            consumed += size;
+        pieces.sort_unstable_by_key(|&(address, _)| address);
+
+        pairs
+            .iter()
+            .map(|&(address, size)| {
+                let mut data = Vec::with_capacity(size);
+                let mut address = address as u64;
+                // The piece holding `address` is the last one starting at
+                // or before it; merged ranges make the following pieces
+                // contiguous for as long as this pair needs.
+                let mut index = pieces.partition_point(|&(start, _)| start <= address);
+                while data.len() < size && index > 0 && index <= pieces.len() {
+                    let (start, bytes) = pieces[index - 1];
+                    let offset = (address - start) as usize;
+                    let take = (size - data.len()).min(bytes.len() - offset);
+                    data.extend_from_slice(&bytes[offset..offset + take]);
+                    address += take as u64;
+                    index += 1;
+                }
+                data
+            })
+            .collect()
+    }
+}
+
+/// Returns true if `name` matches `pattern`. Patterns may use `*` for any
+/// run of characters and `?` for a single character; matching ignores case.
+pub fn device_matches(pattern: &str, name: &str) -> bool {
+    fn glob(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| glob(rest, &name[i..])),
+            Some(('?', rest)) => !name.is_empty() && glob(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && glob(rest, &name[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.trim().to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    glob(&pattern, &name)
+}
+
+/// Picks the device to attach to from the server's device list.
+///
+/// The first device matching `preferred` wins. Otherwise `fallback`
+/// decides whether another device may be used.
+pub fn select_device(
+    devices: &[Rc<str>],
+    preferred: Option<&str>,
+    fallback: DeviceFallback,
+) -> Result<Rc<str>> {
+    let preferred = preferred.filter(|p| !p.trim().is_empty());
+    if devices.is_empty() {
+        Err(USB2SnesError::NoDevice)?;
+    }
+    if let Some(pattern) = preferred {
+        if let Some(device) = devices.iter().find(|d| device_matches(pattern, d)) {
+            return Ok(device.clone());
+        }
+    }
+    match (fallback, devices) {
+        (DeviceFallback::First, [first, ..]) => Ok(first.clone()),
+        (DeviceFallback::Single | DeviceFallback::Wait, [only]) if preferred.is_none() => {
+            Ok(only.clone())
+        }
+        (DeviceFallback::Single, [only]) => {
+            tracing::info!("Preferred device is missing, using the only device present");
+            Ok(only.clone())
         }
-        Ok(ret)
+        _ => match preferred {
+            Some(pattern) => Err(anyhow!(
+                "Preferred device \"{pattern}\" is not present: {devices:#?}"
+            )),
+            None => Err(anyhow!("You need to select a device: {devices:#?}")),
+        },
     }
 }
@@ -1,5 +1,6 @@
 use annelid::config::app_config::{
-    AppConfig, RendererType, YesOrNo, DEFAULT_FRAME_RATE, DEFAULT_POLLING_RATE,
+    AppConfig, CatchUpSplits, DeviceFallback, RendererType, YesOrNo, DEFAULT_FRAME_RATE,
+    DEFAULT_POLLING_RATE,
 };
 use annelid::hotkey::KeyCode;
 
@@ -25,6 +26,9 @@ fn default_config_has_expected_defaults() {
     assert_eq!(config.renderer, Some(RendererType::Gpu));
     assert_eq!(config.reset_timer_on_game_reset, Some(YesOrNo::No));
     assert_eq!(config.reset_game_on_timer_reset, Some(YesOrNo::No));
+    assert!(config.preferred_device.is_none());
+    assert_eq!(config.device_fallback, Some(DeviceFallback::Single));
+    assert_eq!(config.catch_up_splits, Some(CatchUpSplits::Ask));
 }
 
 #[test]
@@ -61,6 +65,7 @@ fn config_toml_round_trip() {
     assert_eq!(config.use_autosplitter, deserialized.use_autosplitter);
     assert_eq!(config.global_hotkeys, deserialized.global_hotkeys);
     assert_eq!(config.renderer, deserialized.renderer);
+    assert_eq!(config.device_fallback, deserialized.device_fallback);
 }
 
 #[test]
@@ -85,3 +90,14 @@ use_autosplitter = "Yes"
     assert_eq!(config.use_autosplitter, Some(YesOrNo::Yes));
     assert!(config.hot_key_start.is_none());
 }
+
+#[test]
+fn config_preferred_device_round_trip() {
+    let toml_str = r#"
+preferred_device = "SD2SNES*"
+device_fallback = "Wait"
+"#;
+    let config: AppConfig = toml::from_str(toml_str).expect("should deserialize device fields");
+    assert_eq!(config.preferred_device.as_deref(), Some("SD2SNES*"));
+    assert_eq!(config.device_fallback, Some(DeviceFallback::Wait));
+}
@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use annelid::autosplitters::asl::{AslAutoSplitter, AslScript, Value};
+use annelid::autosplitters::supermetroid::{split, SNESState, Settings};
+use annelid::autosplitters::{AutoSplitter, SNESSummary};
+use annelid::memory_source::InMemorySource;
+use annelid::usb2snes::WRAM_START;
+use parking_lot::RwLock;
+
+const SUPER_METROID: &str = include_str!("../data/SuperMetroid.asl");
+
+const ROOM_KRAID: u16 = 0xA59F;
+const ROOM_LANDING_SITE: u16 = 0x91F8;
+
+fn load(source: &str, settings: Settings) -> AslAutoSplitter {
+    let script = AslScript::parse(source).expect("parse");
+    AslAutoSplitter::new(script, Arc::new(RwLock::new(settings))).expect("startup and init")
+}
+
+fn poll(autosplitter: &mut AslAutoSplitter, source: &mut InMemorySource) -> SNESSummary {
+    autosplitter.update(source).expect("update")
+}
+
+fn wram(source: &mut InMemorySource, offset: u32, data: &[u8]) {
+    source.write(WRAM_START + offset, data);
+}
+
+/// Memory that changes between two polls, by Super Metroid watcher name.
+struct Scenario {
+    settings: &'static [&'static str],
+    before: &'static [(&'static str, u16)],
+    after: &'static [(&'static str, u16)],
+}
+
+/// Writes `value` to the watcher `name` in both the Rust port's state and
+/// the memory the script reads.
+fn write(snes: &mut SNESState, source: &mut InMemorySource, name: &str, value: u16) {
+    let offset = snes[name].address;
+    let width = match name {
+        "roomID" | "maxEnergy" | "maxReserve" | "enemyHP" | "shipAI" | "motherBrainHP" => 2,
+        _ => 1,
+    };
+    let bytes = &value.to_le_bytes()[..width];
+    snes.data[offset as usize..offset as usize + width].copy_from_slice(bytes);
+    wram(source, offset, bytes);
+}
+
+/// Whether the Rust port and the script split for `scenario`.
+fn both_split(scenario: &Scenario) -> (bool, bool) {
+    let mut settings = Settings::new();
+    for key in scenario.settings {
+        settings.set(key, true);
+    }
+    let mut snes = SNESState::new();
+    let mut source = InMemorySource::new();
+    for &(name, value) in scenario.before {
+        write(&mut snes, &mut source, name, value);
+    }
+    snes.update();
+    let mut asl = load(SUPER_METROID, settings.clone());
+    asl.set_current_split(Some(0));
+    poll(&mut asl, &mut source);
+    for &(name, value) in scenario.after {
+        write(&mut snes, &mut source, name, value);
+    }
+    snes.update();
+    (
+        split(&settings, &mut snes),
+        poll(&mut asl, &mut source).split,
+    )
+}
+
+#[test]
+fn super_metroid_script_loads() {
+    let mut asl = load(SUPER_METROID, Settings::new());
+    let setting = asl
+        .settings()
+        .iter()
+        .find(|s| s.key == "oceanBottomMissiles")
+        .expect("setting");
+    assert_eq!(setting.label, "Ocean Bottom Missile Pack");
+    assert_eq!(setting.parent.as_deref(), Some("crateriaMissiles"));
+    assert!(setting.tooltip.as_deref().unwrap().contains("West Ocean"));
+    assert!(matches!(asl.var("frameRate"), Some(Value::Float(f)) if *f == 60.0));
+
+    // Every watcher from init, read in one request.
+    let mut source = InMemorySource::new();
+    poll(&mut asl, &mut source);
+    let reads = source.reads();
+    assert_eq!(reads.len(), 1);
+    assert_eq!(reads[0].len(), 43);
+    assert_eq!(reads[0][0], (WRAM_START + 0x079B, 2));
+}
+
+#[test]
+fn super_metroid_script_starts_and_resets() {
+    let mut asl = load(SUPER_METROID, Settings::new());
+    let mut source = InMemorySource::new();
+    wram(&mut source, 0x0998, &[0x02]);
+    assert!(!poll(&mut asl, &mut source).start);
+    wram(&mut source, 0x0998, &[0x1F]);
+    assert!(poll(&mut asl, &mut source).start);
+
+    wram(&mut source, 0x079B, &ROOM_LANDING_SITE.to_le_bytes());
+    assert!(!poll(&mut asl, &mut source).reset);
+    wram(&mut source, 0x079B, &[0, 0]);
+    assert!(poll(&mut asl, &mut source).reset);
+    assert!(!poll(&mut asl, &mut source).start);
+}
+
+#[test]
+fn super_metroid_script_splits_like_the_rust_port() {
+    let scenarios = [
+        (
+            true,
+            Scenario {
+                settings: &["kraid"],
+                before: &[("roomID", ROOM_KRAID)],
+                after: &[("brinstarBosses", 1)],
+            },
+        ),
+        (
+            false,
+            Scenario {
+                settings: &["kraid"],
+                before: &[("roomID", ROOM_LANDING_SITE)],
+                after: &[("brinstarBosses", 1)],
+            },
+        ),
+        (
+            true,
+            Scenario {
+                settings: &["firstMissile"],
+                before: &[],
+                after: &[("maxMissiles", 5)],
+            },
+        ),
+        (
+            true,
+            Scenario {
+                settings: &["allMissiles"],
+                before: &[("maxMissiles", 5)],
+                after: &[("maxMissiles", 10)],
+            },
+        ),
+        (
+            true,
+            Scenario {
+                settings: &["rtaFinish"],
+                before: &[("eventFlags", 0x40), ("shipAI", 0)],
+                after: &[("shipAI", 0xAA4F)],
+            },
+        ),
+        (
+            false,
+            Scenario {
+                settings: &[],
+                before: &[],
+                after: &[("maxMissiles", 5)],
+            },
+        ),
+    ];
+    for (expected, scenario) in scenarios {
+        let (rust, asl) = both_split(&scenario);
+        assert_eq!(rust, expected, "Rust port, {:?}", scenario.after);
+        assert_eq!(asl, expected, "script, {:?}", scenario.after);
+    }
+}
+
+#[test]
+fn super_metroid_game_time_comes_from_the_clock() {
+    let mut asl = load(SUPER_METROID, Settings::new());
+    let mut source = InMemorySource::new();
+    asl.set_current_split(Some(0));
+    wram(&mut source, 0x09DA, &[30]);
+    wram(&mut source, 0x09DC, &[5]);
+    wram(&mut source, 0x09DE, &[2]);
+    wram(&mut source, 0x09E0, &[1]);
+    poll(&mut asl, &mut source);
+    let time = asl.gametime_to_seconds().expect("game time");
+    assert_eq!(time.total_seconds(), 3725.5);
+}
+
+const SCRIPT: &str = r#"
+state("snes9x") { byte gameState : "snes9x.exe", 0x2EFBA4, 0x0998; }
+state("usb2snes") {
+    byte gameState : 0x0998;
+    ushort roomID : 0x7E079B;
+}
+
+startup
+{
+    settings.Add("rooms", true, "Rooms");
+    settings.CurrentDefaultParent = "rooms";
+    settings.Add("landingSite", false, "Landing Site");
+    settings.SetToolTip("landingSite", "Split on entering the Landing Site");
+    vars.polls = 0;
+    vars.rooms = new Dictionary<string, int> { { "landingSite", 0x91F8 } };
+}
+
+update
+{
+    vars.polls++;
+}
+
+start
+{
+    return old.gameState != 0x1F && current.gameState == 0x1F;
+}
+
+split
+{
+    var room = vars.rooms["landingSite"];
+    return settings["landingSite"] && old.roomID != room && current.roomID == room;
+}
+"#;
+
+#[test]
+fn state_blocks_and_settings_drive_the_actions() {
+    let settings = Arc::new(RwLock::new(Settings::new()));
+    let script = AslScript::parse(SCRIPT).expect("parse");
+    let mut asl = AslAutoSplitter::new(script, settings.clone()).expect("load");
+    let mut source = InMemorySource::new();
+    // The usb2snes block is used; `$7E` addresses are WRAM offsets.
+    poll(&mut asl, &mut source);
+    assert_eq!(
+        source.reads()[0],
+        [(WRAM_START + 0x079B, 2), (WRAM_START + 0x0998, 1)]
+    );
+    assert_eq!(
+        asl.settings()[1].tooltip.as_deref(),
+        Some("Split on entering the Landing Site")
+    );
+    assert_eq!(settings.read().label("landingSite"), "Landing Site");
+    assert_eq!(
+        settings.read().tooltip("landingSite"),
+        Some("Split on entering the Landing Site")
+    );
+    assert_eq!(settings.read().roots().last().unwrap(), "rooms");
+
+    wram(&mut source, 0x0998, &[0x1F]);
+    assert!(poll(&mut asl, &mut source).start);
+    wram(&mut source, 0x079B, &ROOM_LANDING_SITE.to_le_bytes());
+    assert!(!poll(&mut asl, &mut source).split, "the setting is off");
+
+    // Script settings can be changed like any other.
+    settings.write().set("landingSite", true);
+    wram(&mut source, 0x079B, &[0, 0]);
+    poll(&mut asl, &mut source);
+    wram(&mut source, 0x079B, &ROOM_LANDING_SITE.to_le_bytes());
+    assert!(poll(&mut asl, &mut source).split);
+    assert!(matches!(asl.var("polls"), Some(Value::Int(5))));
+
+    // A disabled parent disables its children.
+    settings.write().set("rooms", false);
+    wram(&mut source, 0x079B, &[0, 0]);
+    poll(&mut asl, &mut source);
+    wram(&mut source, 0x079B, &ROOM_LANDING_SITE.to_le_bytes());
+    assert!(!poll(&mut asl, &mut source).split);
+}
+
+#[test]
+fn expressions_follow_csharp_rules() {
+    let script = r#"
+        startup
+        {
+            Func<int, int> twice = x => x * 2;
+            vars.twice = twice;
+            vars.list = new List<int> { 1, 2 };
+            vars.list.Add(3);
+        }
+        start
+        {
+            var text = "Room " + 0x79B.ToString("X4") + " " + 1.5;
+            double ratio = 7;
+            return 1 + 2 * 3 == 7
+                && 7 / 2 == 3
+                && ratio / 2 == 3.5
+                && (0xF0 | 0x0F) == 0xFF
+                && 1 << 4 == 16
+                && (byte)0x1FF == 0xFF
+                && -7 % 3 == -1
+                && text == "Room 079B 1.5"
+                && (true ? vars.twice(4) : 0) == 8
+                && vars.list.Count == 3 && vars.list.Contains(3)
+                && TimeSpan.FromMinutes(1.5).TotalSeconds == 90
+                && (null ?? 5) == 5;
+        }
+    "#;
+    let mut asl = load(script, Settings::new());
+    assert!(poll(&mut asl, &mut InMemorySource::new()).start);
+}
+
+#[test]
+fn statements_using_the_emulator_are_skipped() {
+    let script = r#"
+        init
+        {
+            IntPtr offset = IntPtr.Zero;
+            if (memory.ProcessName.ToLower().Contains("snes9x")) {
+                offset = memory.ReadPointer((IntPtr)0x789414);
+            }
+            if (offset == IntPtr.Zero && modules.First().ModuleMemorySize == 0) {
+                throw new InvalidOperationException("Unsupported emulator version");
+            }
+            vars.watchers = new MemoryWatcherList {
+                new MemoryWatcher<ushort>(offset + 0x0010) { Name = "counter" },
+            };
+        }
+        update { vars.watchers.UpdateAll(game); }
+        start { return vars.watchers["counter"].Changed; }
+    "#;
+    let mut asl = load(script, Settings::new());
+    let mut source = InMemorySource::new();
+    assert!(!poll(&mut asl, &mut source).start);
+    assert_eq!(source.reads()[0], [(WRAM_START + 0x10, 2)]);
+    wram(&mut source, 0x10, &[1, 0]);
+    assert!(poll(&mut asl, &mut source).start);
+}
+
+#[test]
+fn errors_in_an_action_count_as_false() {
+    let script = r#"
+        start { return vars.missing; }
+        split { return true; }
+    "#;
+    let mut asl = load(script, Settings::new());
+    let mut source = InMemorySource::new();
+    assert!(!poll(&mut asl, &mut source).start);
+    asl.set_current_split(Some(0));
+    assert!(poll(&mut asl, &mut source).split);
+}
+
+#[test]
+fn scripts_that_cannot_run_are_rejected() {
+    let error = AslScript::parse("startup\n{\n    var x = ;\n}").unwrap_err();
+    assert!(error.to_string().contains("line 3"), "{error}");
+    let error = AslScript::parse("splt { return true; }").unwrap_err();
+    assert!(error.to_string().contains("unknown action splt"), "{error}");
+    let error = AslScript::parse("state(\"usb2snes\") { byte x : 0x20000; }").unwrap_err();
+    assert!(format!("{error:#}").contains("WRAM"), "{error:#}");
+    let script = AslScript::parse("startup { settings.Add(\"a\", true, \"A\", \"b\"); }").unwrap();
+    assert!(AslAutoSplitter::new(script, Arc::default()).is_err());
+}
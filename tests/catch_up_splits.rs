@@ -0,0 +1,139 @@
+use annelid::autosplitters::supermetroid::{SNESState, Settings};
+use annelid::autosplitters::Progress;
+use annelid::memory_source::InMemorySource;
+use annelid::usb2snes::WRAM_START;
+
+// --- Helpers ---
+
+fn addr(name: &str) -> u32 {
+    SNESState::new()[name].address
+}
+
+fn write_byte(source: &mut InMemorySource, name: &str, val: u8) {
+    source.write(WRAM_START + addr(name), &[val]);
+}
+
+fn write_word_le(source: &mut InMemorySource, name: &str, val: u16) {
+    source.write(WRAM_START + addr(name), &val.to_le_bytes());
+}
+
+fn settings_with(keys: &[&str]) -> Settings {
+    let mut s = Settings::new();
+    for key in keys {
+        s.set(key, true);
+    }
+    s
+}
+
+const ROOM_LANDING_SITE: u16 = 0x91F8;
+const ROOM_MARIDIA_ELEVATOR: u16 = 0x94CC;
+const ROOM_KRAID_EYE_DOOR: u16 = 0xA56B;
+const ROOM_KRAID: u16 = 0xA59F;
+
+/// The progress seen before the drop, and the memory of a different room
+/// to write what happened while disconnected into.
+fn disconnect(setup: impl Fn(&mut InMemorySource)) -> (Progress, InMemorySource) {
+    disconnect_between(ROOM_LANDING_SITE, ROOM_MARIDIA_ELEVATOR, setup)
+}
+
+fn disconnect_between(
+    from: u16,
+    to: u16,
+    setup: impl Fn(&mut InMemorySource),
+) -> (Progress, InMemorySource) {
+    let mut before = InMemorySource::new();
+    write_word_le(&mut before, "roomID", from);
+    setup(&mut before);
+    let mut snes = SNESState::new();
+    snes.fetch_all(&mut before, &Settings::new()).expect("poll");
+    let mut after = InMemorySource::new();
+    write_word_le(&mut after, "roomID", to);
+    setup(&mut after);
+    (snes.progress(), after)
+}
+
+/// The first poll of the new connection, which must not split on its own:
+/// anything that happened while disconnected is up to catch-up.
+fn reconnect(source: &mut InMemorySource, settings: &Settings) -> SNESState {
+    let mut snes = SNESState::new();
+    let summary = snes.fetch_all(source, settings).expect("poll");
+    assert!(!summary.split, "the first poll split");
+    snes
+}
+
+#[test]
+fn boss_beaten_in_another_room_is_missed() {
+    let settings = settings_with(&["bosses", "kraid"]);
+    let (before, mut source) = disconnect(|_| {});
+    write_byte(&mut source, "brinstarBosses", 1);
+    let snes = reconnect(&mut source, &settings);
+
+    let missed = snes.missed_splits(&before, &settings);
+    assert_eq!(missed.len(), 1, "{missed:?}");
+    assert_eq!(missed[0].reason, "Kraid");
+}
+
+#[test]
+fn entering_a_room_while_disconnected_is_not_missed() {
+    let settings = settings_with(&["bosses", "kraid", "areaTransitions", "bossRooms"]);
+    let (before, mut source) = disconnect_between(ROOM_KRAID_EYE_DOOR, ROOM_KRAID, |_| {});
+    write_byte(&mut source, "brinstarBosses", 1);
+    let snes = reconnect(&mut source, &settings);
+
+    let missed = snes.missed_splits(&before, &settings);
+    assert_eq!(missed.len(), 1, "{missed:?}");
+    assert_eq!(missed[0].reason, "Kraid");
+}
+
+#[test]
+fn item_and_its_ammo_split_once() {
+    let settings = settings_with(&[
+        "ammoPickups",
+        "firstMissile",
+        "specificMissiles",
+        "crateriaMissiles",
+        "oceanBottomMissiles",
+    ]);
+    let (before, mut source) = disconnect(|_| {});
+    write_byte(&mut source, "crateriaItems", 2);
+    write_byte(&mut source, "maxMissiles", 5);
+    let snes = reconnect(&mut source, &settings);
+
+    let missed = snes.missed_splits(&before, &settings);
+    assert_eq!(missed.len(), 1, "{missed:?}");
+    assert_eq!(missed[0].reason, "Ocean Bottom Missile Pack");
+}
+
+#[test]
+fn ammo_splits_count_for_items_without_their_own_split() {
+    let settings = settings_with(&["ammoPickups", "allMissiles"]);
+    let (before, mut source) = disconnect(|source| write_byte(source, "maxMissiles", 5));
+    write_byte(&mut source, "crateriaItems", 2 | 4);
+    write_byte(&mut source, "maxMissiles", 15);
+    let snes = reconnect(&mut source, &settings);
+
+    let missed = snes.missed_splits(&before, &settings);
+    assert_eq!(missed.len(), 2, "{missed:?}");
+    assert_eq!(missed[0].reason, "All Missiles");
+}
+
+#[test]
+fn lost_progress_means_no_catch_up() {
+    let settings = settings_with(&["bosses", "kraid", "ammoPickups", "allMissiles"]);
+    let (before, mut source) = disconnect(|source| write_byte(source, "maxMissiles", 10));
+    write_byte(&mut source, "brinstarBosses", 1);
+    write_byte(&mut source, "maxMissiles", 5);
+    let snes = reconnect(&mut source, &settings);
+
+    assert!(snes.missed_splits(&before, &settings).is_empty());
+}
+
+#[test]
+fn nothing_changed_means_nothing_missed() {
+    let settings = Settings::new();
+    let (before, mut source) = disconnect(|source| write_byte(source, "brinstarBosses", 1));
+    let snes = reconnect(&mut source, &settings);
+
+    assert_eq!(snes.progress(), before);
+    assert!(snes.missed_splits(&before, &settings).is_empty());
+}
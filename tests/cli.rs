@@ -0,0 +1,211 @@
+mod common;
+
+use annelid::cli::{hexdump, parse_number, Cli, CliCommand, Location};
+use annelid::memory_source::MemoryDomain;
+use annelid::trace::Trace;
+use clap::Parser;
+use common::FakeUsb2Snes;
+
+fn cli(server: &FakeUsb2Snes, args: &[&str]) -> Cli {
+    let port = server.port.to_string();
+    let mut argv = vec!["annelid-cli", "--host", &server.host, "--port", &port];
+    argv.extend_from_slice(args);
+    Cli::try_parse_from(argv).expect("arguments")
+}
+
+fn run(server: &FakeUsb2Snes, args: &[&str]) -> String {
+    let mut out = vec![];
+    cli(server, args).run(&mut out).expect("run");
+    String::from_utf8(out).expect("utf-8")
+}
+
+#[test]
+fn locations_accept_domains_and_plain_addresses() {
+    let at = |s: &str| s.parse::<Location>().expect(s);
+    let location = |domain, offset| Location { domain, offset };
+    assert_eq!(at("wram:0998"), location(MemoryDomain::Wram, 0x0998));
+    assert_eq!(at("SRAM:0x10"), location(MemoryDomain::Sram, 0x10));
+    assert_eq!(at("cmd:2C00"), location(MemoryDomain::Cmd, 0x2C00));
+    assert_eq!(at("F50998"), location(MemoryDomain::Wram, 0x0998));
+    assert_eq!(at("0xE00010"), location(MemoryDomain::Sram, 0x10));
+    assert_eq!(at("$7FC0"), location(MemoryDomain::Rom, 0x7FC0));
+    assert_eq!(at("F50998").address(), 0xF50998);
+    assert!("F00000".parse::<Location>().is_err());
+    assert!("vram:0".parse::<Location>().is_err());
+    assert!("wram:xyz".parse::<Location>().is_err());
+}
+
+#[test]
+fn numbers_are_decimal_or_hex() {
+    assert_eq!(parse_number::<usize>("16"), Ok(16));
+    assert_eq!(parse_number::<usize>("0x10"), Ok(16));
+    assert!(parse_number::<u8>("0x100").is_err());
+    assert!(parse_number::<usize>("ten").is_err());
+}
+
+#[test]
+fn hexdump_labels_each_line() {
+    let mut out = vec![];
+    hexdump(&mut out, 0xF50000, b"Super Metroid\x00\x01\x02\x03\x04").expect("dump");
+    let text = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "F50000  53 75 70 65 72 20 4D 65 74 72 6F 69 64 00 01 02  |Super Metroid...|",
+            "F50010  03 04                                            |..|",
+        ]
+    );
+}
+
+#[test]
+fn subcommands_parse() {
+    let cli = Cli::try_parse_from(["annelid-cli", "peek", "wram:09A4", "0x3D", "-d", "SD2SNES*"])
+        .expect("parse");
+    assert_eq!(cli.device.as_deref(), Some("SD2SNES*"));
+    let CliCommand::Peek { location, size } = cli.command else {
+        panic!("expected peek, got {:?}", cli.command);
+    };
+    assert_eq!(location.offset, 0x09A4);
+    assert_eq!(size, 0x3D);
+    assert!(Cli::try_parse_from(["annelid-cli", "peek"]).is_err());
+}
+
+#[test]
+fn devices_and_info_describe_the_server() {
+    let server = FakeUsb2Snes::start();
+    server.set_devices(&["SD2SNES COM3", "SD2SNES COM4"]);
+    assert_eq!(run(&server, &["devices"]), "SD2SNES COM3\nSD2SNES COM4\n");
+    let info = run(&server, &["info", "-d", "*COM4"]);
+    assert!(info.contains("Type:     SD2SNES"), "{info}");
+    assert!(info.contains("Autosplitter: Super Metroid"), "{info}");
+}
+
+#[test]
+fn peek_and_watch_read_memory() {
+    let server = FakeUsb2Snes::start();
+    server.write_wram(0x0998, &[0x1F, 0x00]);
+    let dump = run(&server, &["peek", "wram:0998", "2"]);
+    assert!(dump.starts_with("F50998  1F 00"), "{dump}");
+    let watched = run(&server, &["watch", "F50998", "--polls", "3"]);
+    let lines: Vec<&str> = watched.lines().collect();
+    assert_eq!(lines.len(), 1, "{watched}");
+    assert!(lines[0].ends_with("1F 00  = 31"), "{watched}");
+}
+
+#[test]
+fn files_round_trip_through_the_sd_card() {
+    let server = FakeUsb2Snes::start();
+    let dir = tempfile::tempdir().expect("temp dir");
+    let local = dir.path().join("patch.ips");
+    std::fs::write(&local, b"PATCH").unwrap();
+    run(
+        &server,
+        &["put", local.to_str().unwrap(), "/roms/patch.ips"],
+    );
+    assert_eq!(run(&server, &["ls", "/roms"]), "patch.ips\n");
+    let copy = dir.path().join("copy.ips");
+    run(&server, &["get", "/roms/patch.ips", copy.to_str().unwrap()]);
+    assert_eq!(std::fs::read(copy).unwrap(), b"PATCH");
+}
+
+#[test]
+fn record_saves_each_poll() {
+    let server = FakeUsb2Snes::start();
+    let dir = tempfile::tempdir().expect("temp dir");
+    let output = run(
+        &server,
+        &[
+            "record",
+            dir.path().to_str().unwrap(),
+            "--polls",
+            "3",
+            "--rate",
+            "1000",
+        ],
+    );
+    assert!(
+        output.starts_with("Detected sm.sfc, using the Super Metroid autosplitter"),
+        "{output}"
+    );
+    let trace = std::fs::read_dir(dir.path())
+        .unwrap()
+        .next()
+        .expect("a trace")
+        .unwrap()
+        .path();
+    let trace = Trace::load(&trace).expect("load");
+    assert_eq!(trace.polls.len(), 3);
+    assert_eq!(trace.meta.autosplitter, "Super Metroid");
+}
+
+#[test]
+fn run_uses_json_definitions() {
+    let server = FakeUsb2Snes::start();
+    server.write_wram(0x09C8, &[0x05]);
+    let dir = tempfile::tempdir().expect("temp dir");
+    let json = dir.path().join("splits.json");
+    std::fs::write(
+        &json,
+        r#"{
+            "game": "Test Game",
+            "definitions": [
+                { "name": "missiles", "address": "0x09C8", "value": "0x05", "type": "eq" }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let output = run(
+        &server,
+        &[
+            "run",
+            "--json",
+            json.to_str().unwrap(),
+            "--polls",
+            "2",
+            "--rate",
+            "1000",
+        ],
+    );
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2, "{output}");
+    assert!(
+        lines[0].ends_with("using the JSON definitions autosplitter"),
+        "{output}"
+    );
+    assert!(lines[1].contains("poll       0  split"), "{output}");
+}
+
+#[test]
+fn run_uses_asl_scripts() {
+    let server = FakeUsb2Snes::start();
+    server.write_wram(0x09C8, &[0x05]);
+    let dir = tempfile::tempdir().expect("temp dir");
+    let asl = dir.path().join("Test.asl");
+    std::fs::write(
+        &asl,
+        r#"state("usb2snes") { byte missiles : 0x7E09C8; }
+           start { return current.missiles == 5; }"#,
+    )
+    .unwrap();
+    let output = run(
+        &server,
+        &[
+            "run",
+            "--asl",
+            asl.to_str().unwrap(),
+            "--polls",
+            "2",
+            "--rate",
+            "1000",
+        ],
+    );
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2, "{output}");
+    assert!(
+        lines[0].ends_with("using the ASL script autosplitter"),
+        "{output}"
+    );
+    assert!(lines[1].contains("poll       0  start"), "{output}");
+    assert!(Cli::try_parse_from(["annelid-cli", "run", "--json", "a", "--asl", "b"]).is_err());
+}
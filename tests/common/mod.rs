@@ -0,0 +1,457 @@
+//! A local usb2snes server for integration tests.
+//!
+//! `FakeUsb2Snes` speaks enough of the QUsb2snes WebSocket protocol for
+//! `SyncClient` and the autosplitters: `Name`, `AppVersion`, `DeviceList`,
+//! `Attach`, `Info`, `Reset`, `PutAddress` and `GetAddress` with up to
+//! eight operand pairs, enforcing the same limits as an sd2snes. Memory is
+//! a 16 MiB buffer indexed by the same addresses the client sends, so WRAM
+//! lives at `0xF50000`. `CMD` space reads come from a separate 64 KiB
+//! buffer set with `write_cmd`; writes to it are only logged. The SD card
+//! is a map from absolute paths to contents, with directories implied by
+//! the paths, and supports `List`, `PutFile`, `GetFile`, `Remove` and
+//! `Boot`.
+#![allow(dead_code)]
+
+use annelid::usb2snes::{MAX_VECTOR_REGIONS, MAX_VECTOR_SIZE};
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+pub const MEMORY_SIZE: usize = 0x1000000;
+pub const CMD_MEMORY_SIZE: usize = 0x10000;
+pub const WRAM: u32 = 0xF50000;
+/// QUsb2snes sends binary replies in frames of at most this many bytes.
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// One command as the server received it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub opcode: String,
+    pub space: Option<String>,
+    pub operands: Vec<String>,
+}
+
+struct State {
+    memory: Vec<u8>,
+    cmd_memory: Vec<u8>,
+    devices: Vec<String>,
+    info: Vec<String>,
+    chunk_size: usize,
+    /// When false, multi-region `GetAddress` requests are answered with the
+    /// first region only, like a device without vectored reads.
+    vectored_reads: bool,
+    requests: Vec<Request>,
+    resets: usize,
+    cmd_writes: Vec<(u32, Vec<u8>)>,
+    files: BTreeMap<String, Vec<u8>>,
+    booted: Vec<String>,
+}
+
+enum WriteTarget {
+    Memory { space: String, address: usize },
+    File(String),
+}
+
+/// A `PutAddress` or `PutFile` whose binary payload is still arriving.
+struct PendingWrite {
+    target: WriteTarget,
+    size: usize,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Connection {
+    attached: bool,
+    pending: Option<PendingWrite>,
+}
+
+#[derive(Clone)]
+pub struct FakeUsb2Snes {
+    pub host: String,
+    pub port: u16,
+    state: Arc<Mutex<State>>,
+    /// Bumped by `disconnect_all`; connections from an older generation
+    /// close themselves.
+    generation: Arc<AtomicUsize>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl FakeUsb2Snes {
+    /// Starts a server with a single attached-ready SD2SNES device.
+    pub fn start() -> FakeUsb2Snes {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let port = listener.local_addr().expect("local addr").port();
+        let server = FakeUsb2Snes {
+            host: "127.0.0.1".to_owned(),
+            port,
+            state: Arc::new(Mutex::new(State {
+                memory: vec![0; MEMORY_SIZE],
+                cmd_memory: vec![0; CMD_MEMORY_SIZE],
+                devices: vec!["SD2SNES COM3".to_owned()],
+                info: vec![
+                    "1.11.0".to_owned(),
+                    "SD2SNES".to_owned(),
+                    "/sm.sfc".to_owned(),
+                    "NO_CONTROL_CMD".to_owned(),
+                ],
+                chunk_size: DEFAULT_CHUNK_SIZE,
+                vectored_reads: true,
+                requests: vec![],
+                resets: 0,
+                cmd_writes: vec![],
+                files: BTreeMap::new(),
+                booted: vec![],
+            })),
+            generation: Arc::new(AtomicUsize::new(0)),
+            connections: Arc::new(AtomicUsize::new(0)),
+        };
+        let accepting = server.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { break };
+                let connection = accepting.clone();
+                std::thread::spawn(move || connection.serve(stream));
+            }
+        });
+        server
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn set_devices(&self, devices: &[&str]) {
+        self.state.lock().devices = devices.iter().map(|d| d.to_string()).collect();
+    }
+
+    /// Sets the device type reported by `Info`.
+    pub fn set_dev_type(&self, dev_type: &str) {
+        self.state.lock().info[1] = dev_type.to_owned();
+    }
+
+    /// Sets the ROM path reported by `Info`.
+    pub fn set_rom_name(&self, name: &str) {
+        self.state.lock().info[2] = name.to_owned();
+    }
+
+    /// Replaces the feature flags reported by `Info`.
+    pub fn set_flags(&self, flags: &[&str]) {
+        let mut state = self.state.lock();
+        state.info.truncate(3);
+        state.info.extend(flags.iter().map(|f| f.to_string()));
+    }
+
+    pub fn set_vectored_reads(&self, vectored_reads: bool) {
+        self.state.lock().vectored_reads = vectored_reads;
+    }
+
+    pub fn set_chunk_size(&self, chunk_size: usize) {
+        assert!(chunk_size > 0);
+        self.state.lock().chunk_size = chunk_size;
+    }
+
+    pub fn write(&self, address: u32, data: &[u8]) {
+        let address = address as usize;
+        self.state.lock().memory[address..address + data.len()].copy_from_slice(data);
+    }
+
+    pub fn write_wram(&self, offset: u32, data: &[u8]) {
+        self.write(WRAM + offset, data);
+    }
+
+    /// Sets what `CMD` space reads return.
+    pub fn write_cmd(&self, address: u32, data: &[u8]) {
+        let address = address as usize;
+        self.state.lock().cmd_memory[address..address + data.len()].copy_from_slice(data);
+    }
+
+    pub fn read(&self, address: u32, size: usize) -> Vec<u8> {
+        let address = address as usize;
+        self.state.lock().memory[address..address + size].to_vec()
+    }
+
+    /// Every command received so far, across all connections.
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().requests.clone()
+    }
+
+    /// Every completed write to `CMD` space, as `(address, data)`.
+    pub fn cmd_writes(&self) -> Vec<(u32, Vec<u8>)> {
+        self.state.lock().cmd_writes.clone()
+    }
+
+    pub fn put_file(&self, path: &str, data: &[u8]) {
+        self.state
+            .lock()
+            .files
+            .insert(path.to_owned(), data.to_vec());
+    }
+
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        self.state.lock().files.get(path).cloned()
+    }
+
+    /// Every ROM booted so far.
+    pub fn booted(&self) -> Vec<String> {
+        self.state.lock().booted.clone()
+    }
+
+    pub fn resets(&self) -> usize {
+        self.state.lock().resets
+    }
+
+    /// How many WebSocket connections have completed the handshake.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Drops every open connection, like QUsb2snes does when the device
+    /// goes away. New connections are still accepted.
+    pub fn disconnect_all(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn serve(&self, stream: TcpStream) {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let Ok(mut socket) = tungstenite::accept(stream) else {
+            return;
+        };
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        socket
+            .get_ref()
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .expect("set read timeout");
+        let mut connection = Connection::default();
+        while self.generation.load(Ordering::SeqCst) == generation {
+            let text = match socket.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Binary(data)) => match self.receive(&mut connection, &data) {
+                    Ok(()) => continue,
+                    Err(()) => break,
+                },
+                Ok(Message::Close(_)) => break,
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    continue
+                }
+                Err(_) => break,
+            };
+            if self.generation.load(Ordering::SeqCst) != generation {
+                break;
+            }
+            let Some(request) = parse_request(&text) else {
+                break;
+            };
+            self.state.lock().requests.push(request.clone());
+            // Like QUsb2snes, anything the server does not understand, or
+            // a device command before Attach, closes the connection.
+            if self.handle(&mut socket, &request, &mut connection).is_err() {
+                break;
+            }
+        }
+        let _ = socket.close(None);
+        let _ = socket.flush();
+    }
+
+    fn handle(
+        &self,
+        socket: &mut WebSocket<TcpStream>,
+        request: &Request,
+        connection: &mut Connection,
+    ) -> Result<(), ()> {
+        let attached = &mut connection.attached;
+        if connection.pending.is_some() {
+            // A new command before the payload finished is a protocol error.
+            return Err(());
+        }
+        let results = |results: Vec<String>| {
+            Message::text(serde_json::json!({ "Results": results }).to_string())
+        };
+        match request.opcode.as_str() {
+            "Name" => Ok(()),
+            "AppVersion" => send(socket, results(vec!["FakeUsb2Snes-1.0".to_owned()])),
+            "DeviceList" => {
+                let devices = self.state.lock().devices.clone();
+                send(socket, results(devices))
+            }
+            "Attach" => {
+                let wanted = request.operands.first().ok_or(())?;
+                *attached = self.state.lock().devices.contains(wanted);
+                Ok(())
+            }
+            "Info" if *attached => {
+                let info = self.state.lock().info.clone();
+                send(socket, results(info))
+            }
+            "Reset" if *attached => {
+                self.state.lock().resets += 1;
+                Ok(())
+            }
+            "GetAddress" if *attached => {
+                let regions = request.operands.len() / 2;
+                if !request.operands.len().is_multiple_of(2) || regions > MAX_VECTOR_REGIONS {
+                    return Err(());
+                }
+                let (data, chunk_size) = {
+                    let state = self.state.lock();
+                    let memory = match request.space.as_deref() {
+                        Some("SNES") => &state.memory,
+                        Some("CMD") => &state.cmd_memory,
+                        _ => return Err(()),
+                    };
+                    let mut data = vec![];
+                    for pair in request.operands.chunks(2) {
+                        let address = usize::from_str_radix(&pair[0], 16).map_err(|_| ())?;
+                        let size = usize::from_str_radix(&pair[1], 16).map_err(|_| ())?;
+                        // The sd2snes vectored read has a one byte length.
+                        if regions > 1 && size > MAX_VECTOR_SIZE {
+                            return Err(());
+                        }
+                        data.extend_from_slice(memory.get(address..address + size).ok_or(())?);
+                        if !state.vectored_reads {
+                            break;
+                        }
+                    }
+                    (data, state.chunk_size)
+                };
+                for chunk in data.chunks(chunk_size) {
+                    send(socket, Message::binary(chunk.to_vec()))?;
+                }
+                Ok(())
+            }
+            "PutAddress" if *attached => {
+                let [address, size] = request.operands.as_slice() else {
+                    return Err(());
+                };
+                let size = usize::from_str_radix(size, 16).map_err(|_| ())?;
+                connection.pending = Some(PendingWrite {
+                    target: WriteTarget::Memory {
+                        space: request.space.clone().ok_or(())?,
+                        address: usize::from_str_radix(address, 16).map_err(|_| ())?,
+                    },
+                    size,
+                    data: Vec::with_capacity(size),
+                });
+                Ok(())
+            }
+            "List" if *attached => {
+                let dir = request.operands.first().ok_or(())?;
+                let prefix = format!("{}/", dir.trim_end_matches('/'));
+                let mut listing = vec![];
+                let mut seen_dirs = vec![];
+                for path in self.state.lock().files.keys() {
+                    let Some(rest) = path.strip_prefix(&prefix) else {
+                        continue;
+                    };
+                    match rest.split_once('/') {
+                        Some((subdir, _)) if !seen_dirs.contains(&subdir.to_owned()) => {
+                            seen_dirs.push(subdir.to_owned());
+                            listing.extend(["0".to_owned(), subdir.to_owned()]);
+                        }
+                        Some(_) => {}
+                        None => listing.extend(["1".to_owned(), rest.to_owned()]),
+                    }
+                }
+                send(socket, results(listing))
+            }
+            "PutFile" if *attached => {
+                let [path, size] = request.operands.as_slice() else {
+                    return Err(());
+                };
+                let size = usize::from_str_radix(size, 16).map_err(|_| ())?;
+                if size == 0 {
+                    self.put_file(path, &[]);
+                    return Ok(());
+                }
+                connection.pending = Some(PendingWrite {
+                    target: WriteTarget::File(path.clone()),
+                    size,
+                    data: Vec::with_capacity(size),
+                });
+                Ok(())
+            }
+            "GetFile" if *attached => {
+                let path = request.operands.first().ok_or(())?;
+                let (data, chunk_size) = {
+                    let state = self.state.lock();
+                    (state.files.get(path).cloned().ok_or(())?, state.chunk_size)
+                };
+                send(socket, results(vec![format!("{:x}", data.len())]))?;
+                for chunk in data.chunks(chunk_size) {
+                    send(socket, Message::binary(chunk.to_vec()))?;
+                }
+                Ok(())
+            }
+            "Remove" if *attached => {
+                let path = request.operands.first().ok_or(())?;
+                self.state.lock().files.remove(path).ok_or(())?;
+                Ok(())
+            }
+            "Boot" if *attached => {
+                let path = request.operands.first().ok_or(())?;
+                self.state.lock().booted.push(path.clone());
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+
+    fn receive(&self, connection: &mut Connection, data: &[u8]) -> Result<(), ()> {
+        let pending = connection.pending.as_mut().ok_or(())?;
+        pending.data.extend_from_slice(data);
+        if pending.data.len() < pending.size {
+            return Ok(());
+        }
+        let write = connection.pending.take().expect("pending write");
+        if write.data.len() > write.size {
+            return Err(());
+        }
+        let mut state = self.state.lock();
+        match write.target {
+            WriteTarget::Memory { space, address } => match space.as_str() {
+                "SNES" => state
+                    .memory
+                    .get_mut(address..address + write.size)
+                    .ok_or(())?
+                    .copy_from_slice(&write.data),
+                "CMD" => state.cmd_writes.push((address as u32, write.data)),
+                _ => return Err(()),
+            },
+            WriteTarget::File(path) => {
+                state.files.insert(path, write.data);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, message: Message) -> Result<(), ()> {
+    socket.send(message).map_err(|_| ())
+}
+
+fn parse_request(text: &str) -> Option<Request> {
+    let value: Value = serde_json::from_str(text).ok()?;
+    let operands = match value.get("Operands") {
+        Some(Value::Array(operands)) => operands
+            .iter()
+            .map(|o| o.as_str().map(str::to_owned))
+            .collect::<Option<Vec<_>>>()?,
+        _ => vec![],
+    };
+    Some(Request {
+        opcode: value.get("Opcode")?.as_str()?.to_owned(),
+        space: value
+            .get("Space")
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        operands,
+    })
+}
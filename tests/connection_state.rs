@@ -0,0 +1,66 @@
+use annelid::connection::{Backoff, ConnectionState, SharedConnectionState};
+use std::time::Duration;
+
+#[test]
+fn backoff_doubles_up_to_the_cap() {
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(3));
+    let delays: Vec<u64> = (0..5)
+        .map(|_| backoff.next_delay().as_millis() as u64)
+        .collect();
+    assert_eq!(delays, [500, 1000, 2000, 3000, 3000]);
+}
+
+#[test]
+fn backoff_starts_over_after_reset() {
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+    backoff.next_delay();
+    backoff.next_delay();
+    backoff.reset();
+    assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+}
+
+#[test]
+fn states_describe_themselves() {
+    let attached = ConnectionState::Attached {
+        device: "SD2SNES COM3".to_owned(),
+        game: Some("ZELDA".to_owned()),
+    };
+    assert_eq!(
+        attached.to_string(),
+        "Attached to SD2SNES COM3, no autosplitter for ZELDA"
+    );
+    assert_eq!(attached.summary(), "Unsupported game");
+    let polling = ConnectionState::Polling {
+        device: "SD2SNES COM3".to_owned(),
+        game: "Super Metroid".to_owned(),
+        warnings: vec![],
+    };
+    assert!(polling.is_live());
+    assert_eq!(polling.to_string(), "Live: Super Metroid on SD2SNES COM3");
+    let limited = ConnectionState::Polling {
+        device: "RetroArch 1.19.1".to_owned(),
+        game: "Super Metroid".to_owned(),
+        warnings: vec!["RetroArch doesn't support reading ROM".to_owned()],
+    };
+    assert!(limited.is_live());
+    assert_eq!(limited.summary(), "Live");
+    assert_eq!(
+        limited.to_string(),
+        "Live: Super Metroid on RetroArch 1.19.1\nRetroArch doesn't support reading ROM"
+    );
+    let error = ConnectionState::Error {
+        reason: "connection refused".to_owned(),
+        retry_in: Duration::from_secs(2),
+    };
+    assert_eq!(error.to_string(), "connection refused (retrying in 2.0s)");
+    assert!(!error.is_live());
+}
+
+#[test]
+fn shared_state_is_seen_by_clones() {
+    let state = SharedConnectionState::new();
+    let ui = state.clone();
+    assert_eq!(ui.get(), ConnectionState::Disconnected);
+    state.set(ConnectionState::Connecting);
+    assert_eq!(ui.get(), ConnectionState::Connecting);
+}
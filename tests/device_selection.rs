@@ -0,0 +1,98 @@
+use annelid::config::app_config::DeviceFallback;
+use annelid::usb2snes::{device_matches, select_device};
+use std::rc::Rc;
+
+fn devices(names: &[&str]) -> Vec<Rc<str>> {
+    names.iter().map(|n| Rc::from(*n)).collect()
+}
+
+// --- device_matches ---
+
+#[test]
+fn exact_name_matches() {
+    assert!(device_matches("SD2SNES COM3", "SD2SNES COM3"));
+    assert!(!device_matches("SD2SNES COM3", "SD2SNES COM4"));
+}
+
+#[test]
+fn matching_ignores_case_and_surrounding_whitespace() {
+    assert!(device_matches("  sd2snes com3 ", "SD2SNES COM3"));
+}
+
+#[test]
+fn star_matches_any_run() {
+    assert!(device_matches("SD2SNES*", "SD2SNES COM3"));
+    assert!(device_matches("*snes9x*", "EMUNWA Snes9x 1.62"));
+    assert!(device_matches("*", "anything"));
+    assert!(!device_matches("SD2SNES*", "EMUNWA bsnes"));
+}
+
+#[test]
+fn question_mark_matches_one_character() {
+    assert!(device_matches("SD2SNES COM?", "SD2SNES COM3"));
+    assert!(!device_matches("SD2SNES COM?", "SD2SNES COM12"));
+}
+
+// --- select_device ---
+
+#[test]
+fn no_devices_is_an_error() {
+    assert!(select_device(&[], None, DeviceFallback::First).is_err());
+}
+
+#[test]
+fn single_device_is_used_without_preference() {
+    let list = devices(&["SD2SNES COM3"]);
+    for fallback in [
+        DeviceFallback::Single,
+        DeviceFallback::First,
+        DeviceFallback::Wait,
+    ] {
+        let device = select_device(&list, None, fallback).expect("only device");
+        assert_eq!(&*device, "SD2SNES COM3");
+    }
+}
+
+#[test]
+fn preferred_device_wins_over_order() {
+    let list = devices(&["EMUNWA Snes9x", "SD2SNES COM3"]);
+    let device = select_device(&list, Some("SD2SNES*"), DeviceFallback::First).unwrap();
+    assert_eq!(&*device, "SD2SNES COM3");
+}
+
+#[test]
+fn several_devices_without_preference_need_a_choice() {
+    let list = devices(&["EMUNWA Snes9x", "SD2SNES COM3"]);
+    assert!(select_device(&list, None, DeviceFallback::Single).is_err());
+    assert!(select_device(&list, None, DeviceFallback::Wait).is_err());
+    let device = select_device(&list, None, DeviceFallback::First).unwrap();
+    assert_eq!(&*device, "EMUNWA Snes9x");
+}
+
+#[test]
+fn blank_preference_is_ignored() {
+    let list = devices(&["SD2SNES COM3"]);
+    let device = select_device(&list, Some("  "), DeviceFallback::Wait).unwrap();
+    assert_eq!(&*device, "SD2SNES COM3");
+}
+
+#[test]
+fn missing_preferred_device_follows_fallback() {
+    let one = devices(&["EMUNWA Snes9x"]);
+    let two = devices(&["EMUNWA Snes9x", "EMUNWA bsnes"]);
+    let preferred = Some("SD2SNES*");
+
+    assert_eq!(
+        &*select_device(&one, preferred, DeviceFallback::Single).unwrap(),
+        "EMUNWA Snes9x"
+    );
+    assert!(select_device(&two, preferred, DeviceFallback::Single).is_err());
+
+    assert_eq!(
+        &*select_device(&two, preferred, DeviceFallback::First).unwrap(),
+        "EMUNWA Snes9x"
+    );
+
+    assert!(select_device(&one, preferred, DeviceFallback::Wait).is_err());
+    assert!(select_device(&two, preferred, DeviceFallback::Wait).is_err());
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use annelid::autosplitters::registry::{
+    capability_warnings, find_autosplitter, GameInfo, RomHeader, AUTOSPLITTERS, HEADER_SIZE,
+};
+use annelid::backend::Backend;
+use annelid::config::app_config::AppConfig;
+use annelid::memory_source::MemorySource;
+use annelid::usb2snes::{Capability, ClientOptions, Infos, FLAG_NO_CONTROL_CMD, FLAG_NO_ROM_READ};
+use common::FakeUsb2Snes;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Builds a header with a valid checksum/complement pair.
+fn header(title: &str, checksum: u16) -> Vec<u8> {
+    let mut bytes = vec![b' '; HEADER_SIZE];
+    bytes[..title.len()].copy_from_slice(title.as_bytes());
+    bytes[0x15] = 0x20; // LoROM
+    bytes[0x1C..0x1E].copy_from_slice(&(!checksum).to_le_bytes());
+    bytes[0x1E..0x20].copy_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+fn game(rom_name: &str, header: Option<(&str, u16)>) -> GameInfo {
+    GameInfo {
+        rom_name: rom_name.to_owned(),
+        header: header.map(|(title, checksum)| RomHeader {
+            title: title.to_owned(),
+            checksum,
+        }),
+    }
+}
+
+fn connect(server: &FakeUsb2Snes) -> Backend {
+    let config = AppConfig {
+        usb2snes_host: Some(server.host.clone()),
+        usb2snes_port: Some(server.port),
+        ..AppConfig::default()
+    };
+    let options = ClientOptions {
+        read_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    };
+    Backend::connect(&config, options, |_| {}).expect("connect")
+}
+
+fn detect(server: &FakeUsb2Snes) -> GameInfo {
+    connect(server).game_info().expect("game info")
+}
+
+#[test]
+fn header_is_parsed_when_checksum_is_consistent() {
+    let parsed = RomHeader::parse(&header("Super Metroid", 0xF8DF)).expect("valid header");
+    assert_eq!(parsed.title, "Super Metroid");
+    assert_eq!(parsed.checksum, 0xF8DF);
+}
+
+#[test]
+fn header_with_bad_complement_is_rejected() {
+    let mut bytes = header("Super Metroid", 0xF8DF);
+    bytes[0x1C] ^= 1;
+    assert_eq!(RomHeader::parse(&bytes), None);
+    assert_eq!(RomHeader::parse(&[0; 4]), None);
+}
+
+#[test]
+fn first_valid_candidate_wins() {
+    let lorom = vec![0; HEADER_SIZE];
+    let hirom = header("ZELDANODENSETSU", 0x1234);
+    let found = RomHeader::find(&[lorom, hirom]).expect("HiROM header");
+    assert_eq!(found.title, "ZELDANODENSETSU");
+}
+
+#[test]
+fn checksum_match_finds_super_metroid() {
+    let entry = find_autosplitter(&game("", Some(("", 0xF8DF)))).expect("match");
+    assert_eq!(entry.name, "Super Metroid");
+}
+
+#[test]
+fn title_match_covers_rom_hacks() {
+    let hack = game("/hacks/practice.sfc", Some(("SUPER METROID", 0x1234)));
+    assert_eq!(
+        find_autosplitter(&hack).map(|e| e.name),
+        Some("Super Metroid")
+    );
+}
+
+#[test]
+fn rom_name_is_the_last_resort() {
+    let by_name = game("C:\\roms\\Super Metroid (JU).sfc", None);
+    assert_eq!(
+        find_autosplitter(&by_name).map(|e| e.name),
+        Some("Super Metroid")
+    );
+    assert!(find_autosplitter(&game("", None)).is_none());
+}
+
+#[test]
+fn unknown_games_have_no_autosplitter() {
+    let zelda = game("/roms/zelda.sfc", Some(("THE LEGEND OF ZELDA", 0xA0F9)));
+    assert!(find_autosplitter(&zelda).is_none());
+    assert_eq!(zelda.display_name(), "THE LEGEND OF ZELDA");
+    assert_eq!(game("/roms/zelda.sfc", None).display_name(), "zelda.sfc");
+}
+
+#[test]
+fn backend_reads_the_rom_header() {
+    let server = FakeUsb2Snes::start();
+    server.set_rom_name("/roms/hack.sfc");
+    server.write(0x7FC0, &header("Super Metroid", 0xF8DF));
+    let detected = detect(&server);
+    assert_eq!(detected.rom_name, "/roms/hack.sfc");
+    assert_eq!(
+        detected.header,
+        Some(RomHeader {
+            title: "Super Metroid".to_owned(),
+            checksum: 0xF8DF
+        })
+    );
+}
+
+#[test]
+fn backend_skips_the_header_without_rom_access() {
+    let server = FakeUsb2Snes::start();
+    server.set_flags(&[FLAG_NO_ROM_READ]);
+    server.write(0x7FC0, &header("Super Metroid", 0xF8DF));
+    let detected = detect(&server);
+    assert_eq!(detected.header, None);
+    assert!(!server.requests().iter().any(|r| r.opcode == "GetAddress"));
+    // The fake reports /sm.sfc, which is enough to pick an autosplitter.
+    assert!(find_autosplitter(&detected).is_some());
+}
+
+#[test]
+fn backend_reports_the_attached_device() {
+    let server = FakeUsb2Snes::start();
+    server.set_devices(&["EMUNWA bsnes"]);
+    assert_eq!(connect(&server).device_name(), "EMUNWA bsnes");
+}
+
+fn infos(dev_type: &str, flags: &[&str]) -> Infos {
+    Infos {
+        version: Rc::from("1.0"),
+        dev_type: Rc::from(dev_type),
+        game: Rc::from("sm.sfc"),
+        flags: flags.iter().map(|f| Rc::from(*f)).collect(),
+    }
+}
+
+#[test]
+fn flags_decide_capabilities() {
+    let fxpak = infos("SD2SNES", &[]);
+    assert!(fxpak.missing(&[Capability::RomRead]).is_empty());
+    let emulator = infos("RETROARCH", &[FLAG_NO_ROM_READ, FLAG_NO_CONTROL_CMD]);
+    assert_eq!(
+        emulator.missing(&[
+            Capability::RomRead,
+            Capability::FileCommands,
+            Capability::ControlCommands
+        ]),
+        [Capability::RomRead, Capability::ControlCommands]
+    );
+}
+
+#[test]
+fn missing_capabilities_are_explained() {
+    let entry = &AUTOSPLITTERS[0];
+    let emulator = infos("SNES9X", &[FLAG_NO_CONTROL_CMD]);
+    assert!(capability_warnings(entry, &emulator, false).is_empty());
+    let warnings = capability_warnings(entry, &emulator, true);
+    assert_eq!(warnings.len(), 1);
+    assert!(
+        warnings[0].starts_with("SNES9X doesn't support resetting the console"),
+        "{warnings:?}"
+    );
+    assert!(capability_warnings(entry, &infos("SD2SNES", &[]), true).is_empty());
+}
@@ -0,0 +1,206 @@
+use annelid::autosplitters::json::{CheckDetails, CheckType, JsonAutoSplitter, Splits};
+use annelid::autosplitters::{AutoSplitter, SNESSummary};
+use annelid::memory_source::InMemorySource;
+use annelid::usb2snes::WRAM_START;
+
+const SPLITS: &str = r#"{
+    "game": "Test Game",
+    "autostart": {
+        "active": "1",
+        "address": "0x0998",
+        "value": "0x1F",
+        "type": "eq"
+    },
+    "definitions": [
+        {
+            "name": "kraid",
+            "address": "0xD829",
+            "value": "0x01",
+            "type": "bit",
+            "more": [
+                { "address": "0x079B", "value": "0xA59F", "type": "weq" }
+            ]
+        },
+        {
+            "name": "missiles",
+            "address": "0x09C8",
+            "value": "0x05",
+            "type": "eq",
+            "next": [
+                { "address": "0x09C8", "value": "0x0A", "type": "eq" }
+            ]
+        }
+    ]
+}"#;
+
+fn check(typ: CheckType, address: u32, value: u32) -> CheckDetails {
+    CheckDetails {
+        note: None,
+        address,
+        value,
+        typ,
+    }
+}
+
+fn autosplitter(json: &str) -> JsonAutoSplitter {
+    JsonAutoSplitter::new(Splits::parse(json).expect("parse")).expect("valid definitions")
+}
+
+fn poll(autosplitter: &mut JsonAutoSplitter, source: &mut InMemorySource) -> SNESSummary {
+    autosplitter.update(source).expect("update")
+}
+
+fn wram(source: &mut InMemorySource, offset: u32, data: &[u8]) {
+    source.write(WRAM_START + offset, data);
+}
+
+#[test]
+fn checks_compare_bytes_and_words() {
+    assert!(check(CheckType::Bit, 0, 0x04).test(0x06));
+    assert!(!check(CheckType::Bit, 0, 0x01).test(0x06));
+    assert!(check(CheckType::Eq, 0, 5).test(5));
+    assert!(check(CheckType::Gt, 0, 5).test(6));
+    assert!(!check(CheckType::Lt, 0, 5).test(5));
+    assert!(check(CheckType::Gte, 0, 5).test(5));
+    assert!(check(CheckType::Lte, 0, 5).test(4));
+    assert!(check(CheckType::Wbit, 0, 0x0100).test(0x0100));
+    assert!(check(CheckType::Weq, 0, 0xA59F).test(0xA59F));
+    assert_eq!(check(CheckType::Weq, 0, 0).width(), 2);
+    assert_eq!(check(CheckType::Eq, 0, 0).width(), 1);
+}
+
+#[test]
+fn bank_addresses_are_wram_offsets() {
+    assert_eq!(check(CheckType::Eq, 0x7ED829, 0).offset(), 0xD829);
+    assert_eq!(check(CheckType::Eq, 0x7F0010, 0).offset(), 0x10010);
+    assert_eq!(check(CheckType::Eq, 0x0998, 0).offset(), 0x0998);
+}
+
+#[test]
+fn addresses_outside_wram_are_rejected() {
+    let json = SPLITS.replace("0xD829", "0x20000");
+    let splits = Splits::parse(&json).expect("parse");
+    assert!(JsonAutoSplitter::new(splits).is_err());
+}
+
+#[test]
+fn every_address_is_read_in_one_request() {
+    let mut splitter = autosplitter(SPLITS);
+    let mut source = InMemorySource::new();
+    poll(&mut splitter, &mut source);
+    assert_eq!(
+        source.reads(),
+        [vec![
+            (WRAM_START + 0x079B, 2),
+            (WRAM_START + 0x0998, 1),
+            (WRAM_START + 0x09C8, 1),
+            (WRAM_START + 0xD829, 1),
+        ]]
+    );
+}
+
+#[test]
+fn autostart_fires_when_its_check_starts_passing() {
+    let mut splitter = autosplitter(SPLITS);
+    let mut source = InMemorySource::new();
+    // Already passing when we connect: not a start.
+    wram(&mut source, 0x0998, &[0x1F]);
+    assert!(!poll(&mut splitter, &mut source).start);
+    wram(&mut source, 0x0998, &[0x06]);
+    assert!(!poll(&mut splitter, &mut source).start);
+    wram(&mut source, 0x0998, &[0x1F]);
+    assert!(poll(&mut splitter, &mut source).start);
+    assert!(!poll(&mut splitter, &mut source).start);
+}
+
+#[test]
+fn definitions_split_in_order_after_the_start() {
+    let mut splitter = autosplitter(SPLITS);
+    let mut source = InMemorySource::new();
+    // Kraid is dead in the save file, but the run hasn't started.
+    wram(&mut source, 0xD829, &[0x01]);
+    wram(&mut source, 0x079B, &[0x9F, 0xA5]);
+    assert!(!poll(&mut splitter, &mut source).split);
+    wram(&mut source, 0x0998, &[0x1F]);
+    assert!(poll(&mut splitter, &mut source).start);
+    assert!(poll(&mut splitter, &mut source).split);
+    assert_eq!(splitter.next_split().map(|c| &*c.name), Some("missiles"));
+
+    // `next` steps pass one per poll, in order.
+    wram(&mut source, 0x09C8, &[0x0A]);
+    assert!(!poll(&mut splitter, &mut source).split);
+    wram(&mut source, 0x09C8, &[0x05]);
+    assert!(!poll(&mut splitter, &mut source).split);
+    wram(&mut source, 0x09C8, &[0x0A]);
+    assert!(poll(&mut splitter, &mut source).split);
+    assert!(splitter.next_split().is_none());
+    assert!(!poll(&mut splitter, &mut source).split);
+}
+
+#[test]
+fn more_checks_must_all_pass() {
+    let json = SPLITS.replace(r#""active": "1""#, r#""active": "0""#);
+    let mut splitter = autosplitter(&json);
+    let mut source = InMemorySource::new();
+    wram(&mut source, 0xD829, &[0x01]);
+    wram(&mut source, 0x079B, &[0xF8, 0x91]);
+    assert!(!poll(&mut splitter, &mut source).split);
+    wram(&mut source, 0x079B, &[0x9F, 0xA5]);
+    assert!(poll(&mut splitter, &mut source).split);
+}
+
+#[test]
+fn timer_reset_goes_back_to_the_first_definition() {
+    let json = SPLITS.replace(r#""active": "1""#, r#""active": "0""#);
+    let mut splitter = autosplitter(&json);
+    let mut source = InMemorySource::new();
+    wram(&mut source, 0xD829, &[0x01]);
+    wram(&mut source, 0x079B, &[0x9F, 0xA5]);
+    assert!(poll(&mut splitter, &mut source).split);
+    splitter.reset_game_tracking();
+    assert_eq!(splitter.next_split().map(|c| &*c.name), Some("kraid"));
+}
+
+#[test]
+fn manual_splits_skips_and_undos_are_followed() {
+    let json = SPLITS.replace(r#""active": "1""#, r#""active": "0""#);
+    let mut splitter = autosplitter(&json);
+    let mut source = InMemorySource::new();
+    wram(&mut source, 0x09C8, &[0x05]);
+    // Split Kraid by hand; the first missiles step then passes.
+    splitter.set_current_split(Some(1));
+    assert!(!poll(&mut splitter, &mut source).split);
+    // The same index again, as before every poll, keeps that step.
+    splitter.set_current_split(Some(1));
+    wram(&mut source, 0x09C8, &[0x0A]);
+    assert!(poll(&mut splitter, &mut source).split);
+
+    // Undo back to the missiles, whose steps start over.
+    splitter.set_current_split(Some(1));
+    assert_eq!(splitter.next_split().map(|c| &*c.name), Some("missiles"));
+    assert!(!poll(&mut splitter, &mut source).split);
+    splitter.set_current_split(Some(0));
+    assert_eq!(splitter.next_split().map(|c| &*c.name), Some("kraid"));
+    splitter.set_current_split(Some(2));
+    assert!(splitter.next_split().is_none());
+}
+
+#[test]
+fn a_timer_started_by_hand_splits() {
+    let mut splitter = autosplitter(SPLITS);
+    let mut source = InMemorySource::new();
+    // The autostart check already passes, so it never sees an edge.
+    wram(&mut source, 0x0998, &[0x1F]);
+    assert!(!poll(&mut splitter, &mut source).split);
+    splitter.set_current_split(Some(0));
+    wram(&mut source, 0xD829, &[0x01]);
+    wram(&mut source, 0x079B, &[0x9F, 0xA5]);
+    assert!(poll(&mut splitter, &mut source).split);
+
+    // After a reset, starting by hand again works too.
+    splitter.reset_game_tracking();
+    splitter.set_current_split(None);
+    assert!(!poll(&mut splitter, &mut source).split);
+    splitter.set_current_split(Some(0));
+    assert!(poll(&mut splitter, &mut source).split);
+}
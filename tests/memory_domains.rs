@@ -0,0 +1,142 @@
+mod common;
+
+use annelid::autosplitters::supermetroid::{MemoryWatcher, SNESState, Settings, Width};
+use annelid::memory_source::{read_domains, InMemorySource, MemoryDomain, ReplaySource};
+use annelid::usb2snes::{ClientOptions, Space, SyncClient, USB2SnesError, SRAM_START, WRAM_START};
+use common::FakeUsb2Snes;
+use std::time::Duration;
+
+fn attached_client(server: &FakeUsb2Snes) -> SyncClient {
+    let options = ClientOptions {
+        read_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    };
+    let mut client = SyncClient::connect_to(&server.address(), options).expect("connect");
+    let devices = client.list_device().expect("device list");
+    client.attach(&devices[0]).expect("attach");
+    client
+}
+
+#[test]
+fn domain_offsets_map_to_usb2snes_addresses() {
+    assert_eq!(
+        MemoryDomain::Wram.address(0x079B, 2).unwrap(),
+        WRAM_START + 0x079B
+    );
+    assert_eq!(
+        MemoryDomain::Sram.address(0x10, 2).unwrap(),
+        SRAM_START + 0x10
+    );
+    assert_eq!(MemoryDomain::Rom.address(0x7FC0, 21).unwrap(), 0x7FC0);
+    assert_eq!(MemoryDomain::Cmd.address(0x2C00, 1).unwrap(), 0x2C00);
+    assert_eq!(MemoryDomain::Cmd.space(), Space::CMD);
+    for domain in MemoryDomain::ALL {
+        assert!(domain.address(domain.size() - 1, 2).is_err(), "{domain}");
+    }
+}
+
+#[test]
+fn snes_space_domains_share_one_read() {
+    let mut source = InMemorySource::new();
+    source.write(WRAM_START + 0x0998, &[0x1F]);
+    source.write(SRAM_START + 0x20, &[0xAB, 0xCD]);
+    source.write(0x7FDC, &[0x12]);
+    source.write_cmd(0x2C00, &[0x60]);
+    let data = read_domains(
+        &mut source,
+        &[
+            (MemoryDomain::Cmd, 0x2C00, 1),
+            (MemoryDomain::Wram, 0x0998, 1),
+            (MemoryDomain::Sram, 0x20, 2),
+            (MemoryDomain::Rom, 0x7FDC, 1),
+        ],
+    )
+    .expect("read");
+    assert_eq!(data, [vec![0x60], vec![0x1F], vec![0xAB, 0xCD], vec![0x12]]);
+    assert_eq!(
+        source.reads(),
+        [
+            vec![
+                (WRAM_START + 0x0998, 1),
+                (SRAM_START + 0x20, 2),
+                (0x7FDC, 1)
+            ],
+            vec![(0x2C00, 1)],
+        ]
+    );
+}
+
+#[test]
+fn watchers_read_from_their_domain() {
+    let mut snes = SNESState::new();
+    snes.watch(
+        "saveSlot",
+        MemoryWatcher::in_domain(MemoryDomain::Sram, 0x1FEC, Width::Word),
+    );
+    snes.watch(
+        "romVersion",
+        MemoryWatcher::in_domain(MemoryDomain::Rom, 0x7FDB, Width::Byte),
+    );
+    assert_eq!(snes.memory(MemoryDomain::Sram).len(), 0x1FEE);
+    assert!(snes.memory(MemoryDomain::Cmd).is_empty());
+
+    let mut source = InMemorySource::new();
+    source.write(SRAM_START + 0x1FEC, &[0x02, 0x00]);
+    source.write(0x7FDB, &[0x01]);
+    // The same offset in WRAM must not leak into the SRAM watcher.
+    source.write(WRAM_START + 0x1FEC, &[0x55, 0x55]);
+    snes.fetch_all(&mut source, &Settings::new())
+        .expect("fetch");
+    assert_eq!(snes["saveSlot"].current, 2);
+    assert_eq!(snes["romVersion"].current, 1);
+    assert_eq!(source.reads().len(), 1);
+}
+
+#[test]
+fn sources_without_cmd_space_say_so() {
+    let mut snes = SNESState::new();
+    snes.watch(
+        "nmiHook",
+        MemoryWatcher::in_domain(MemoryDomain::Cmd, 0x2C00, Width::Byte),
+    );
+    let mut source = ReplaySource::new(vec![vec![]]);
+    let err = snes
+        .fetch_all(&mut source, &Settings::new())
+        .expect_err("replays have no CMD space");
+    assert!(matches!(
+        USB2SnesError::from_anyhow(&err),
+        Some(USB2SnesError::Unsupported(_))
+    ));
+}
+
+#[test]
+fn cmd_space_is_read_from_the_device() {
+    let server = FakeUsb2Snes::start();
+    server.set_flags(&[]);
+    server.write_cmd(0x2C00, &[0x60, 0xEA]);
+    server.write(0x2C00, &[0xFF, 0xFF]);
+    let mut client = attached_client(&server);
+    let data = client
+        .get_space_addresses(Space::CMD, &[(0x2C00, 2)])
+        .expect("read");
+    assert_eq!(data, [vec![0x60, 0xEA]]);
+    let request = server
+        .requests()
+        .into_iter()
+        .find(|r| r.opcode == "GetAddress")
+        .expect("GetAddress");
+    assert_eq!(request.space.as_deref(), Some("CMD"));
+}
+
+#[test]
+fn cmd_reads_respect_no_control_cmd() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    let err = client
+        .get_space_addresses(Space::CMD, &[(0x2C00, 1)])
+        .expect_err("CMD disabled");
+    assert!(matches!(
+        USB2SnesError::from_anyhow(&err),
+        Some(USB2SnesError::Unsupported(_))
+    ));
+}
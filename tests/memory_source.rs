@@ -0,0 +1,103 @@
+use annelid::autosplitters::registry::{find_autosplitter, HEADER_SIZE};
+use annelid::autosplitters::supermetroid::{Settings, SuperMetroidAutoSplitter};
+use annelid::autosplitters::AutoSplitter;
+use annelid::memory_source::{InMemorySource, MemorySource, ReplayFinished, ReplaySource};
+use annelid::usb2snes::{USB2SnesError, FLAG_NO_ROM_READ, WRAM_START};
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+const GAME_STATE: u32 = WRAM_START + 0x0998;
+const ROOM_ID: u32 = WRAM_START + 0x079B;
+
+fn autosplitter() -> SuperMetroidAutoSplitter {
+    SuperMetroidAutoSplitter::new(Arc::new(RwLock::new(Settings::new())))
+}
+
+#[test]
+fn in_memory_reads_what_was_written() {
+    let mut source = InMemorySource::new();
+    source.write(0xF50010, &[1, 2, 3]);
+    let data = source
+        .get_addresses(&[(0xF5000F, 5), (0xF60000, 2)])
+        .expect("read");
+    assert_eq!(data, [vec![0, 1, 2, 3, 0], vec![0, 0]]);
+    assert_eq!(source.reads(), [vec![(0xF5000F, 5), (0xF60000, 2)]]);
+    source.write_address(0xF60000, &[9]).expect("write");
+    assert_eq!(source.read(0xF60000, 1), [9]);
+}
+
+#[test]
+fn autosplitter_runs_against_in_memory_source() {
+    let mut source = InMemorySource::new();
+    let mut splitter = autosplitter();
+    let summary = splitter.update(&mut source).expect("update");
+    assert!(!summary.start && !summary.split && !summary.reset);
+    // One batched read, all of it in WRAM.
+    assert_eq!(source.reads().len(), 1);
+    assert!(source.reads()[0]
+        .iter()
+        .all(|&(address, _)| address >= WRAM_START));
+}
+
+#[test]
+fn replay_drives_start_and_reset() {
+    let frames = vec![
+        vec![(GAME_STATE, vec![0x02]), (ROOM_ID, vec![0xF8, 0x91])],
+        vec![(GAME_STATE, vec![0x1F])],
+        vec![],
+        vec![(ROOM_ID, vec![0x00, 0x00])],
+    ];
+    let mut source = ReplaySource::new(frames);
+    let mut splitter = autosplitter();
+    let starts: Vec<(bool, bool)> = (0..4)
+        .map(|_| {
+            let summary = splitter.update(&mut source).expect("update");
+            (summary.start, summary.reset)
+        })
+        .collect();
+    assert_eq!(
+        starts,
+        [(false, false), (true, false), (false, false), (false, true)]
+    );
+    assert_eq!(source.remaining(), 0);
+    let err = splitter.update(&mut source).unwrap_err();
+    assert!(err.downcast_ref::<ReplayFinished>().is_some());
+}
+
+#[test]
+fn game_detection_works_on_any_source() {
+    let mut header = vec![b' '; HEADER_SIZE];
+    header[..13].copy_from_slice(b"Super Metroid");
+    header[0x1C..0x1E].copy_from_slice(&(!0xF8DFu16).to_le_bytes());
+    header[0x1E..0x20].copy_from_slice(&0xF8DFu16.to_le_bytes());
+    let mut source = InMemorySource::new();
+    source.write(0x7FC0, &header);
+    let game = source.game_info().expect("game info");
+    assert_eq!(game.header.as_ref().map(|h| h.checksum), Some(0xF8DF));
+    assert!(find_autosplitter(&game).is_some());
+
+    source.set_flags(&[FLAG_NO_ROM_READ]);
+    source.set_game("/roms/zelda.sfc");
+    let game = source.game_info().expect("game info");
+    assert_eq!(game.header, None);
+    assert_eq!(game.display_name(), "zelda.sfc");
+}
+
+#[test]
+fn replay_detection_does_not_use_up_frames() {
+    let mut memory = InMemorySource::new();
+    memory.set_game("sm.sfc");
+    let mut source = ReplaySource::with_memory(memory, vec![vec![]]);
+    source.game_info().expect("game info");
+    assert_eq!(source.remaining(), 1);
+}
+
+#[test]
+fn replay_is_read_only() {
+    let mut source = ReplaySource::new(vec![]);
+    let err = source.write_address(WRAM_START, &[1]).unwrap_err();
+    assert!(matches!(
+        USB2SnesError::from_anyhow(&err),
+        Some(USB2SnesError::Unsupported(_))
+    ));
+}
@@ -0,0 +1,127 @@
+use annelid::memory_source::{InMemorySource, MemorySource};
+use annelid::poll_stats::{PollSample, PollStats, TimedSource, HISTORY_LEN};
+use annelid::usb2snes::Infos;
+use anyhow::Result;
+use std::time::Duration;
+
+fn sample(round_trip_ms: u64) -> PollSample {
+    PollSample {
+        round_trip: Duration::from_millis(round_trip_ms),
+        lateness: Duration::ZERO,
+    }
+}
+
+#[test]
+fn empty_stats_have_no_summary() {
+    let stats = PollStats::new();
+    assert_eq!(stats.summary(), None);
+    assert_eq!(stats.missed_percent(), 0.0);
+    assert!(stats.report().contains("No samples yet"));
+}
+
+#[test]
+fn summary_covers_round_trip_and_lateness() {
+    let mut stats = PollStats::new();
+    for ms in 1..=100 {
+        stats.record(
+            PollSample {
+                round_trip: Duration::from_millis(ms),
+                lateness: Duration::from_millis(ms % 3),
+            },
+            ms > 98,
+        );
+    }
+    let s = stats.summary().expect("summary");
+    assert_eq!(s.count, 100);
+    assert_eq!(s.min, Duration::from_millis(1));
+    assert_eq!(s.max, Duration::from_millis(100));
+    assert_eq!(s.p50, Duration::from_millis(51));
+    assert_eq!(s.p95, Duration::from_millis(96));
+    assert_eq!(s.p99, Duration::from_millis(100));
+    assert_eq!(s.mean, Duration::from_micros(50_500));
+    assert_eq!(s.max_lateness, Duration::from_millis(2));
+    assert_eq!(stats.missed_deadlines, 2);
+    assert_eq!(stats.missed_percent(), 2.0);
+}
+
+#[test]
+fn constant_round_trips_have_no_jitter() {
+    let mut stats = PollStats::new();
+    for _ in 0..10 {
+        stats.record(sample(4), false);
+    }
+    assert_eq!(stats.summary().expect("summary").jitter, Duration::ZERO);
+    stats.record(sample(6), false);
+    assert!(stats.summary().expect("summary").jitter > Duration::ZERO);
+}
+
+#[test]
+fn history_is_bounded_but_counters_are_not() {
+    let mut stats = PollStats::new();
+    for _ in 0..HISTORY_LEN + 10 {
+        stats.record(sample(1), true);
+    }
+    assert_eq!(stats.samples().count(), HISTORY_LEN);
+    assert_eq!(stats.polls, (HISTORY_LEN + 10) as u64);
+    assert_eq!(stats.missed_deadlines, (HISTORY_LEN + 10) as u64);
+    stats.reset();
+    assert_eq!(stats.polls, 0);
+    assert_eq!(stats.summary(), None);
+}
+
+#[test]
+fn histogram_buckets_span_the_slowest_poll() {
+    let mut stats = PollStats::new();
+    for ms in [1, 1, 2, 9] {
+        stats.record(sample(ms), false);
+    }
+    let histogram = stats.histogram(10);
+    assert_eq!(histogram.counts.len(), 10);
+    assert_eq!(histogram.counts.iter().sum::<usize>(), 4);
+    // 9ms / 10 buckets, rounded up past the slowest sample.
+    assert_eq!(histogram.bucket_width, Duration::from_micros(901));
+    assert_eq!(histogram.counts[1], 2);
+    assert_eq!(histogram.counts[2], 1);
+    assert_eq!(histogram.counts[9], 1);
+}
+
+#[test]
+fn report_lists_the_key_numbers() {
+    let mut stats = PollStats::new();
+    stats.period = Duration::from_millis(16);
+    stats.record(sample(5), false);
+    let report = stats.report();
+    assert!(report.contains("Poll period: 16.00 ms"), "{report}");
+    assert!(report.contains("Polls: 1"), "{report}");
+    assert!(report.contains("min 5.00 ms"), "{report}");
+}
+
+struct SlowSource {
+    inner: InMemorySource,
+    delay: Duration,
+}
+
+impl MemorySource for SlowSource {
+    fn get_addresses(&mut self, pairs: &[(u32, usize)]) -> Result<Vec<Vec<u8>>> {
+        std::thread::sleep(self.delay);
+        self.inner.get_addresses(pairs)
+    }
+
+    fn info(&mut self) -> Result<Infos> {
+        self.inner.info()
+    }
+}
+
+#[test]
+fn timed_source_counts_only_reads() {
+    let mut slow = SlowSource {
+        inner: InMemorySource::new(),
+        delay: Duration::from_millis(5),
+    };
+    let mut timed = TimedSource::new(&mut slow);
+    timed.get_addresses(&[(0xF50000, 2)]).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    timed.get_addresses(&[(0xF50010, 2)]).unwrap();
+    assert!(timed.elapsed >= Duration::from_millis(10));
+    assert!(timed.elapsed < Duration::from_millis(100));
+}
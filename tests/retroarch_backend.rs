@@ -0,0 +1,220 @@
+use annelid::backend::Backend;
+use annelid::config::app_config::{AppConfig, BackendType};
+use annelid::memory_source::MemorySource;
+use annelid::retroarch::{bus_address, RetroArchClient, Status, MAX_READ_SIZE};
+use annelid::usb2snes::{ClientOptions, USB2SnesError, FLAG_NO_ROM_READ};
+use parking_lot::Mutex;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Answers RetroArch network commands over UDP from a fake 128KiB WRAM.
+struct FakeRetroArch {
+    port: u16,
+    state: Arc<Mutex<FakeState>>,
+}
+
+struct FakeState {
+    wram: Vec<u8>,
+    status: String,
+    commands: Vec<String>,
+    /// Sent before the real reply to the next `READ_CORE_MEMORY`.
+    stale_reply: Option<String>,
+    silent: bool,
+}
+
+impl FakeRetroArch {
+    fn start() -> FakeRetroArch {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("bind");
+        let port = socket.local_addr().expect("local addr").port();
+        let state = Arc::new(Mutex::new(FakeState {
+            wram: vec![0; 0x20000],
+            status:
+                "GET_STATUS PLAYING super_nes,Super Metroid (Japan, USA) (En,Ja),crc32=d63ed5f8"
+                    .to_owned(),
+            commands: vec![],
+            stale_reply: None,
+            silent: false,
+        }));
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 1024];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                let command = String::from_utf8_lossy(&buf[..n]).trim().to_owned();
+                let mut state = shared.lock();
+                state.commands.push(command.clone());
+                if state.silent {
+                    continue;
+                }
+                let words: Vec<&str> = command.split_whitespace().collect();
+                let reply = match words.as_slice() {
+                    ["VERSION"] => Some("1.19.1\n".to_owned()),
+                    ["GET_STATUS"] => Some(format!("{}\n", state.status)),
+                    ["READ_CORE_MEMORY", address, size] => {
+                        if let Some(stale) = state.stale_reply.take() {
+                            let _ = socket.send_to(stale.as_bytes(), peer);
+                        }
+                        let address = u32::from_str_radix(address, 16).unwrap();
+                        let size: usize = size.parse().unwrap();
+                        let offset = address.wrapping_sub(0x7E0000) as usize;
+                        Some(match state.wram.get(offset..offset + size) {
+                            Some(bytes) => {
+                                let hex: Vec<String> =
+                                    bytes.iter().map(|b| format!("{b:02X}")).collect();
+                                format!("READ_CORE_MEMORY {address:x} {}\n", hex.join(" "))
+                            }
+                            None => {
+                                format!("READ_CORE_MEMORY {address:x} -1 address out of range\n")
+                            }
+                        })
+                    }
+                    _ => None,
+                };
+                if let Some(reply) = reply {
+                    let _ = socket.send_to(reply.as_bytes(), peer);
+                }
+            }
+        });
+        FakeRetroArch { port, state }
+    }
+
+    fn write_wram(&self, offset: usize, data: &[u8]) {
+        self.state.lock().wram[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    fn commands(&self) -> Vec<String> {
+        self.state.lock().commands.clone()
+    }
+}
+
+fn options() -> ClientOptions {
+    ClientOptions {
+        connect_timeout: Duration::from_millis(300),
+        read_timeout: Duration::from_millis(300),
+        ..ClientOptions::default()
+    }
+}
+
+fn client(fake: &FakeRetroArch) -> RetroArchClient {
+    RetroArchClient::connect("127.0.0.1", fake.port, options()).expect("connect")
+}
+
+#[test]
+fn connect_reads_the_version() {
+    let fake = FakeRetroArch::start();
+    let client = client(&fake);
+    assert_eq!(client.version(), "1.19.1");
+    assert_eq!(client.device_name(), "RetroArch 1.19.1");
+}
+
+#[test]
+fn wram_reads_are_translated_to_the_snes_bus() {
+    let fake = FakeRetroArch::start();
+    fake.write_wram(0x0998, &[0x08, 0x00]);
+    fake.write_wram(0x1FFFE, &[0xAA, 0xBB]);
+    let mut client = client(&fake);
+    let data = client
+        .get_addresses(&[(0xF50998, 2), (0xF6FFFE, 2)])
+        .expect("read");
+    assert_eq!(data, [vec![0x08, 0x00], vec![0xAA, 0xBB]]);
+    let commands = fake.commands();
+    assert!(commands.contains(&"READ_CORE_MEMORY 7e0998 2".to_owned()));
+    assert!(commands.contains(&"READ_CORE_MEMORY 7ffffe 2".to_owned()));
+}
+
+#[test]
+fn large_reads_are_chunked() {
+    let fake = FakeRetroArch::start();
+    let pattern: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+    fake.write_wram(0, &pattern);
+    let mut client = client(&fake);
+    let data = client.read_core_memory(0x7E0000, 5000).expect("read");
+    assert_eq!(data, pattern);
+    let reads = fake
+        .commands()
+        .into_iter()
+        .filter(|c| c.starts_with("READ_CORE_MEMORY"))
+        .count();
+    assert_eq!(reads, 5000_usize.div_ceil(MAX_READ_SIZE));
+}
+
+#[test]
+fn addresses_outside_wram_are_unsupported() {
+    assert_eq!(bus_address(0xF50000, 0x20000).unwrap(), 0x7E0000);
+    let err = bus_address(0xE00000, 2).unwrap_err();
+    assert!(matches!(
+        USB2SnesError::from_anyhow(&err),
+        Some(USB2SnesError::Unsupported(_))
+    ));
+    assert!(bus_address(0xF6FFFF, 2).is_err());
+}
+
+#[test]
+fn stale_replies_are_skipped() {
+    let fake = FakeRetroArch::start();
+    fake.write_wram(0x10, &[0x42]);
+    fake.state.lock().stale_reply = Some("READ_CORE_MEMORY 7e0998 01 02\n".to_owned());
+    let mut client = client(&fake);
+    assert_eq!(client.read_core_memory(0x7E0010, 1).expect("read"), [0x42]);
+}
+
+#[test]
+fn read_errors_are_reported() {
+    let fake = FakeRetroArch::start();
+    let mut client = client(&fake);
+    let err = client.read_core_memory(0x7E0000, 0x30000).unwrap_err();
+    assert!(err.to_string().contains("address out of range"), "{err}");
+}
+
+#[test]
+fn silence_times_out() {
+    let fake = FakeRetroArch::start();
+    let mut client = client(&fake);
+    fake.state.lock().silent = true;
+    let err = client.status().unwrap_err();
+    assert!(matches!(
+        USB2SnesError::from_anyhow(&err),
+        Some(USB2SnesError::Timeout(_))
+    ));
+}
+
+#[test]
+fn status_parsing() {
+    let status = Status::parse(
+        "GET_STATUS PLAYING super_nes,Super Metroid (Japan, USA) (En,Ja),crc32=d63ed5f8\n",
+    )
+    .expect("status");
+    assert_eq!(status.state, "PLAYING");
+    assert_eq!(status.system, "super_nes");
+    assert_eq!(status.content, "Super Metroid (Japan, USA) (En,Ja)");
+    assert_eq!(status.crc32, Some(0xD63ED5F8));
+    let idle = Status::parse("GET_STATUS CONTENTLESS").expect("status");
+    assert_eq!(idle.state, "CONTENTLESS");
+    assert_eq!(idle.content, "");
+}
+
+#[test]
+fn backend_detects_the_game_from_the_content_name() {
+    let fake = FakeRetroArch::start();
+    let config = AppConfig {
+        backend: Some(BackendType::RetroArch),
+        usb2snes_host: Some("127.0.0.1".to_owned()),
+        retroarch_port: Some(fake.port),
+        ..AppConfig::default()
+    };
+    let mut devices = vec![];
+    let mut backend = Backend::connect(&config, options(), |d| {
+        devices = d.iter().map(|d| d.to_string()).collect()
+    })
+    .expect("connect");
+    assert_eq!(devices, ["RetroArch 1.19.1"]);
+    assert!(backend.info().expect("info").has_flag(FLAG_NO_ROM_READ));
+    let game = backend.game_info().expect("game info");
+    assert_eq!(game.header, None);
+    assert_eq!(game.display_name(), "Super Metroid (Japan, USA) (En,Ja)");
+    assert_eq!(
+        annelid::autosplitters::registry::find_autosplitter(&game).map(|e| e.name),
+        Some("Super Metroid")
+    );
+    backend.reset().expect("reset");
+}
@@ -0,0 +1,344 @@
+// tonic::Status is large, but it is what the handlers must return.
+#![allow(clippy::result_large_err)]
+
+use annelid::sni::*;
+use annelid::usb2snes::{ClientOptions, USB2SnesError};
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::body::BoxBody;
+use tonic::codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{NamedService, UnaryService};
+use tonic::Status;
+
+const WRAM: u32 = 0xF50000;
+
+/// A stand-in for SNI's gRPC server with one device and 128 KiB of WRAM.
+#[derive(Clone, Default)]
+struct FakeSni {
+    wram: Arc<Mutex<Vec<u8>>>,
+    resets: Arc<AtomicUsize>,
+    /// Delay before answering MultiRead, to simulate a stalled server.
+    read_delay: Arc<Mutex<Duration>>,
+}
+
+impl FakeSni {
+    fn new() -> Self {
+        FakeSni {
+            wram: Arc::new(Mutex::new(vec![0; 0x20000])),
+            ..FakeSni::default()
+        }
+    }
+
+    fn device() -> Device {
+        Device {
+            uri: "fxpakpro://./dev/ttyACM0".to_owned(),
+            display_name: "FX Pak Pro".to_owned(),
+            kind: "fxpakpro".to_owned(),
+            capabilities: vec![
+                DeviceCapability::ReadMemory as i32,
+                DeviceCapability::ResetSystem as i32,
+                DeviceCapability::FetchFields as i32,
+            ],
+            default_address_space: AddressSpace::FxPakPro as i32,
+            system: "snes".to_owned(),
+        }
+    }
+
+    fn read(&self, request: &ReadMemoryRequest) -> Result<ReadMemoryResponse, Status> {
+        let wram = self.wram.lock();
+        let start = request
+            .request_address
+            .checked_sub(WRAM)
+            .ok_or_else(|| Status::invalid_argument("only WRAM is readable"))?
+            as usize;
+        let end = start + request.size as usize;
+        let data = wram
+            .get(start..end)
+            .ok_or_else(|| Status::out_of_range("read past end of WRAM"))?
+            .to_vec();
+        Ok(ReadMemoryResponse {
+            request_address: request.request_address,
+            request_address_space: request.request_address_space,
+            device_address: request.request_address,
+            device_address_space: AddressSpace::FxPakPro as i32,
+            data,
+            ..ReadMemoryResponse::default()
+        })
+    }
+
+    async fn route<B>(self, req: http::Request<B>) -> http::Response<BoxBody>
+    where
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        match req.uri().path() {
+            paths::LIST_DEVICES => {
+                unary(req, |_: DevicesRequest| {
+                    Ok(DevicesResponse {
+                        devices: vec![FakeSni::device()],
+                    })
+                })
+                .await
+            }
+            paths::MULTI_READ => {
+                let delay = *self.read_delay.lock();
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                unary(req, move |r: MultiReadMemoryRequest| {
+                    let responses = r
+                        .requests
+                        .iter()
+                        .map(|request| self.read(request))
+                        .collect::<Result<_, _>>()?;
+                    Ok(MultiReadMemoryResponse {
+                        uri: r.uri,
+                        responses,
+                    })
+                })
+                .await
+            }
+            paths::RESET_SYSTEM => {
+                unary(req, move |r: ResetSystemRequest| {
+                    self.resets.fetch_add(1, Ordering::Relaxed);
+                    Ok(ResetSystemResponse { uri: r.uri })
+                })
+                .await
+            }
+            paths::FETCH_FIELDS => {
+                unary(req, |r: FieldsRequest| {
+                    let values = r
+                        .fields
+                        .iter()
+                        // Field numbers from sni.proto, not from our enum.
+                        .map(|f| match f {
+                            1 => "1.11.0".to_owned(),
+                            40 => "/roms/sm.sfc".to_owned(),
+                            _ => String::new(),
+                        })
+                        .collect();
+                    Ok(FieldsResponse {
+                        uri: r.uri,
+                        fields: r.fields,
+                        values,
+                    })
+                })
+                .await
+            }
+            _ => Status::unimplemented("not part of the fake").into_http(),
+        }
+    }
+}
+
+struct Unary<F>(F);
+
+impl<Req, Resp, F> UnaryService<Req> for Unary<F>
+where
+    F: FnMut(Req) -> Result<Resp, Status>,
+    Resp: Send + 'static,
+{
+    type Response = Resp;
+    type Future = BoxFuture<tonic::Response<Resp>, Status>;
+
+    fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+        let result = (self.0)(request.into_inner()).map(tonic::Response::new);
+        Box::pin(async move { result })
+    }
+}
+
+async fn unary<B, Req, Resp, F>(req: http::Request<B>, f: F) -> http::Response<BoxBody>
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+    Req: prost::Message + Default + Send + 'static,
+    Resp: prost::Message + Send + 'static,
+    F: FnMut(Req) -> Result<Resp, Status>,
+{
+    let codec = tonic::codec::ProstCodec::<Resp, Req>::default();
+    tonic::server::Grpc::new(codec).unary(Unary(f), req).await
+}
+
+/// SNI splits its API across several services; each one needs its own
+/// `NamedService` so the router can dispatch on the path prefix.
+macro_rules! fake_service {
+    ($name:ident, $service:literal) => {
+        #[derive(Clone)]
+        struct $name(FakeSni);
+
+        impl NamedService for $name {
+            const NAME: &'static str = $service;
+        }
+
+        impl<B> Service<http::Request<B>> for $name
+        where
+            B: Body + Send + 'static,
+            B::Error: Into<StdError> + Send + 'static,
+        {
+            type Response = http::Response<BoxBody>;
+            type Error = std::convert::Infallible;
+            type Future = BoxFuture<Self::Response, Self::Error>;
+
+            fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, req: http::Request<B>) -> Self::Future {
+                let fake = self.0.clone();
+                Box::pin(async move { Ok(fake.route(req).await) })
+            }
+        }
+    };
+}
+
+fake_service!(DevicesService, "Devices");
+fake_service!(DeviceMemoryService, "DeviceMemory");
+fake_service!(DeviceControlService, "DeviceControl");
+fake_service!(DeviceInfoService, "DeviceInfo");
+
+/// Starts the fake server on its own thread and returns its port.
+fn start_server(fake: FakeSni) -> u16 {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+    let port = listener.local_addr().expect("local addr").port();
+    listener.set_nonblocking(true).expect("nonblocking");
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("runtime");
+        runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).expect("tokio listener");
+            tonic::transport::Server::builder()
+                .add_service(DevicesService(fake.clone()))
+                .add_service(DeviceMemoryService(fake.clone()))
+                .add_service(DeviceControlService(fake.clone()))
+                .add_service(DeviceInfoService(fake))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+                .await
+                .expect("serve");
+        });
+    });
+    port
+}
+
+fn connect(port: u16) -> SniClient {
+    let options = ClientOptions {
+        read_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    };
+    let mut client = SniClient::connect("127.0.0.1", port, options).expect("connect");
+    let device = client
+        .list_devices()
+        .expect("list devices")
+        .pop()
+        .expect("one device");
+    client.attach(device);
+    client
+}
+
+#[test]
+fn lists_devices_with_capabilities() {
+    let port = start_server(FakeSni::new());
+    let options = ClientOptions::default();
+    let mut client = SniClient::connect("127.0.0.1", port, options).expect("connect");
+    let devices = client.list_devices().expect("list devices");
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].display_name, "FX Pak Pro");
+    assert!(devices[0].has_capability(DeviceCapability::ReadMemory));
+    assert!(!devices[0].has_capability(DeviceCapability::WriteMemory));
+}
+
+#[test]
+fn multi_read_returns_each_region() {
+    let fake = FakeSni::new();
+    {
+        let mut wram = fake.wram.lock();
+        wram[0x079B] = 0xF8;
+        wram[0x079C] = 0x91;
+        wram[0x0998] = 0x08;
+    }
+    let port = start_server(fake);
+    let mut client = connect(port);
+    let data = client
+        .get_addresses(&[(WRAM + 0x079B, 2), (WRAM + 0x0998, 1)])
+        .expect("read");
+    assert_eq!(data, vec![vec![0xF8, 0x91], vec![0x08]]);
+}
+
+#[test]
+fn reads_require_an_attached_device() {
+    let port = start_server(FakeSni::new());
+    let mut client =
+        SniClient::connect("127.0.0.1", port, ClientOptions::default()).expect("connect");
+    assert!(client.get_addresses(&[(WRAM, 1)]).is_err());
+}
+
+#[test]
+fn server_errors_are_reported() {
+    let port = start_server(FakeSni::new());
+    let mut client = connect(port);
+    let err = client
+        .get_addresses(&[(0x000000, 1)])
+        .expect_err("ROM is not readable on the fake");
+    assert!(err.to_string().contains("only WRAM"), "{err}");
+}
+
+#[test]
+fn info_uses_fetch_fields() {
+    let port = start_server(FakeSni::new());
+    let mut client = connect(port);
+    let info = client.info().expect("info");
+    assert_eq!(&*info.version, "1.11.0");
+    assert_eq!(&*info.game, "/roms/sm.sfc");
+    assert_eq!(&*info.dev_type, "fxpakpro");
+}
+
+#[test]
+fn fields_are_encoded_with_the_proto_numbers() {
+    let request = FieldsRequest {
+        uri: String::new(),
+        fields: [
+            Field::DeviceName,
+            Field::DeviceVersion,
+            Field::DeviceStatus,
+            Field::CoreName,
+            Field::CoreVersion,
+            Field::CorePlatform,
+            Field::RomFileName,
+            Field::RomHashType,
+            Field::RomHashValue,
+        ]
+        .map(|f| f as i32)
+        .to_vec(),
+    };
+    let encoded = prost::Message::encode_to_vec(&request);
+    // Tag 2, packed, then the numbers `enum Field` has in sni.proto.
+    assert_eq!(encoded, [0x12, 9, 0, 1, 2, 20, 21, 22, 40, 41, 42]);
+}
+
+#[test]
+fn reset_calls_device_control() {
+    let fake = FakeSni::new();
+    let resets = fake.resets.clone();
+    let port = start_server(fake);
+    let mut client = connect(port);
+    client.reset().expect("reset");
+    assert_eq!(resets.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn stalled_read_times_out() {
+    let fake = FakeSni::new();
+    let delay = fake.read_delay.clone();
+    let port = start_server(fake);
+    let mut client = connect(port);
+    *delay.lock() = Duration::from_secs(5);
+    let err = client
+        .get_addresses(&[(WRAM, 1)])
+        .expect_err("should time out");
+    assert!(matches!(
+        USB2SnesError::from_anyhow(&err),
+        Some(USB2SnesError::Timeout(_))
+    ));
+}
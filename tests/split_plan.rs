@@ -0,0 +1,145 @@
+use annelid::autosplitters::supermetroid::{
+    split, PlannedSegment, SNESState, Settings, SplitOrder, SplitPlan,
+};
+use annelid::memory_source::InMemorySource;
+use annelid::usb2snes::WRAM_START;
+
+const ROOM_KRAID: u16 = 0xA59F;
+
+fn segment(name: &str, conditions: &[&str]) -> PlannedSegment {
+    PlannedSegment {
+        name: name.to_owned(),
+        conditions: conditions.iter().map(|c| c.to_string()).collect(),
+    }
+}
+
+/// Any% settings, split in order on Kraid then Phantoon.
+fn in_order() -> Settings {
+    let mut settings = Settings::new();
+    settings.split_on_anypercent();
+    *settings.plan_mut() = SplitPlan {
+        order: SplitOrder::InOrder,
+        segments: vec![
+            segment("Kraid", &["kraid"]),
+            segment("Phantoon", &["phantoon"]),
+        ],
+    };
+    settings
+}
+
+/// A state where Kraid has just died in his room.
+fn kraid_dies() -> SNESState {
+    let mut snes = SNESState::new();
+    let room = snes["roomID"].address as usize;
+    let bosses = snes["brinstarBosses"].address as usize;
+    snes.data[room..room + 2].copy_from_slice(&ROOM_KRAID.to_le_bytes());
+    snes.update();
+    snes.data[bosses] = 1;
+    snes.update();
+    snes
+}
+
+#[test]
+fn any_order_is_the_default() {
+    let mut settings = Settings::new();
+    settings.split_on_anypercent();
+    assert_eq!(settings.plan().order, SplitOrder::AnyOrder);
+    assert!(split(&settings.for_segment(Some(5)), &mut kraid_dies()));
+    assert!(split(&settings.for_segment(None), &mut kraid_dies()));
+}
+
+#[test]
+fn in_order_only_the_current_segment_splits() {
+    let settings = in_order();
+    assert!(split(&settings.for_segment(Some(0)), &mut kraid_dies()));
+    assert!(!split(&settings.for_segment(Some(1)), &mut kraid_dies()));
+    assert!(!split(&settings.for_segment(Some(2)), &mut kraid_dies()));
+    assert!(!split(&settings.for_segment(None), &mut kraid_dies()));
+}
+
+#[test]
+fn segment_settings_keep_conditions_and_their_parents() {
+    let mut settings = in_order();
+    settings.plan_mut().segments[1] = segment("Bosses", &["bosses"]);
+    settings.set("draygon", false);
+
+    let kraid = settings.for_segment(Some(0));
+    assert!(kraid.get("kraid"));
+    assert!(!kraid.get("phantoon"));
+    assert!(!kraid.get("firstMissile") && !kraid.get("morphBall"));
+
+    // A parent condition allows its children that are enabled.
+    let bosses = settings.for_segment(Some(1));
+    assert!(bosses.get("kraid") && bosses.get("phantoon"));
+    assert!(!bosses.get("draygon"));
+    assert!(!bosses.get("morphBall"));
+}
+
+#[test]
+fn fetching_follows_the_segment() {
+    let settings = in_order();
+    let mut snes = SNESState::new();
+    let mut source = InMemorySource::new();
+    let room = snes["roomID"].address;
+    let bosses = snes["brinstarBosses"].address;
+    source.write(WRAM_START + room, &ROOM_KRAID.to_le_bytes());
+    snes.fetch_all(&mut source, &settings).expect("fetch");
+    snes.current_split = Some(0);
+    source.write(WRAM_START + bosses, &[1]);
+    assert!(snes.fetch_all(&mut source, &settings).expect("fetch").split);
+    assert_eq!(snes.current_split, Some(1));
+}
+
+#[test]
+fn segment_settings_follow_edits_made_mid_segment() {
+    let mut settings = in_order();
+    settings.plan_mut().segments[0] = segment("Phantoon", &["phantoon"]);
+    let mut snes = SNESState::new();
+    let mut source = InMemorySource::new();
+    let room = snes["roomID"].address;
+    let bosses = snes["brinstarBosses"].address;
+    source.write(WRAM_START + room, &ROOM_KRAID.to_le_bytes());
+    snes.current_split = Some(0);
+    let revision = settings.revision();
+    snes.fetch_all(&mut source, &settings).expect("fetch");
+
+    settings.plan_mut().segments[0] = segment("Kraid", &["kraid"]);
+    assert_ne!(settings.revision(), revision);
+    source.write(WRAM_START + bosses, &[1]);
+    assert!(snes.fetch_all(&mut source, &settings).expect("fetch").split);
+}
+
+#[test]
+fn aligning_keeps_conditions_by_name_then_position() {
+    let mut plan = SplitPlan {
+        order: SplitOrder::InOrder,
+        segments: vec![
+            segment("Kraid", &["kraid"]),
+            segment("Phantom", &["phantoon"]),
+            segment("Draygon", &["draygon"]),
+        ],
+    };
+    let names = ["Bombs", "Kraid", "Phantoon"].map(String::from);
+    plan.align(&names);
+    assert_eq!(
+        plan.segments,
+        [
+            segment("Bombs", &[]),
+            segment("Kraid", &["kraid"]),
+            segment("Phantoon", &["draygon"]),
+        ]
+    );
+}
+
+#[test]
+fn settings_without_a_plan_still_load() {
+    let settings = in_order();
+    let mut json = serde_json::to_value(&settings).expect("serialize");
+    let loaded: Settings = serde_json::from_value(json.clone()).expect("deserialize");
+    assert_eq!(loaded.plan(), settings.plan());
+
+    json.as_object_mut().unwrap().remove("plan");
+    let old: Settings = serde_json::from_value(json).expect("deserialize");
+    assert_eq!(old.plan(), &SplitPlan::default());
+    assert!(old.get("kraid"));
+}
@@ -1,4 +1,5 @@
-use annelid::autosplitters::supermetroid::Settings;
+use annelid::autosplitters::supermetroid::{split, SNESState, Schema, Settings};
+use std::collections::BTreeSet;
 
 #[test]
 fn default_settings_have_roots() {
@@ -78,3 +79,151 @@ fn settings_serde_round_trip() {
     assert_eq!(s.get("ammoPickups"), s2.get("ammoPickups"));
     assert_eq!(s.get("firstMissile"), s2.get("firstMissile"));
 }
+
+#[test]
+fn roots_and_children_follow_declaration_order() {
+    let s = Settings::new();
+    assert_eq!(
+        s.roots(),
+        [
+            "ammoPickups",
+            "suitUpgrades",
+            "beamUpgrades",
+            "bootUpgrades",
+            "energyUpgrades",
+            "miscUpgrades",
+            "areaTransitions",
+            "miniBosses",
+            "bosses",
+            "rtaFinish",
+            "igtFinish",
+            "sporeSpawnRTAFinish",
+            "hundredMissileRTAFinish",
+        ]
+    );
+    assert_eq!(
+        s.children("bosses"),
+        ["kraid", "phantoon", "draygon", "ridley", "mb1", "mb1_0hp", "mb2", "mb3"]
+    );
+}
+
+#[test]
+fn every_setting_has_a_label_and_tooltip() {
+    let s = Settings::new();
+    for key in s.keys() {
+        let schema = s.schema().get(&key).expect("schema");
+        assert!(!schema.label.is_empty(), "{key}");
+        assert!(schema.tooltip.is_some(), "{key}");
+    }
+    assert_eq!(s.label("oceanBottomMissiles"), "Ocean Bottom Missile Pack");
+    assert!(s
+        .tooltip("oceanBottomMissiles")
+        .unwrap()
+        .contains("West Ocean"));
+    assert_eq!(s.label("thisKeyDoesNotExist"), "thisKeyDoesNotExist");
+    assert_eq!(s.tooltip("thisKeyDoesNotExist"), None);
+}
+
+#[test]
+fn loaded_settings_keep_the_schema() {
+    let json = serde_json::to_string(&Settings::new()).expect("serialize");
+    let s: Settings = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(s.label("kraid"), "Kraid");
+    assert_eq!(s.roots(), Settings::new().roots());
+}
+
+#[test]
+fn declared_settings_come_after_the_built_in_ones() {
+    let mut s = Settings::new();
+    s.set("saved", true);
+    s.declare("custom", false, Some("bosses"), "Custom Boss");
+    s.set_tooltip("custom", "Split on a boss of your own");
+    assert_eq!(s.children("bosses").last().unwrap(), "custom");
+    assert_eq!(s.label("custom"), "Custom Boss");
+    assert_eq!(s.tooltip("custom"), Some("Split on a boss of your own"));
+    // Keys nobody declared, like one from a saved file, go last.
+    assert_eq!(s.roots().last().unwrap(), "saved");
+    assert!(!s.has_been_modified());
+}
+
+#[test]
+fn loading_a_file_keeps_declared_settings() {
+    let mut saved = Settings::new();
+    saved.set("kraid", true);
+    let saved: Settings =
+        serde_json::from_str(&serde_json::to_string(&saved).expect("serialize")).expect("load");
+    let mut s = Settings::new();
+    s.declare("custom", true, Some("bosses"), "Custom Boss");
+    s.load(saved);
+    assert!(s.get("kraid"));
+    assert!(s.get("custom"));
+    assert_eq!(s.label("custom"), "Custom Boss");
+}
+
+#[test]
+fn copies_adopt_settings_declared_since() {
+    let mut live = Settings::new();
+    let mut copy = live.clone();
+    copy.set("kraid", true);
+    live.declare("custom", true, Some("bosses"), "Custom Boss");
+    copy.adopt_new(&live);
+    assert_eq!(copy.children("bosses").last().unwrap(), "custom");
+    assert_eq!(copy.label("custom"), "Custom Boss");
+    assert!(copy.get("kraid"));
+}
+
+/// Earlier spellings `split` still reads next to the declared key.
+const OLD_SPELLINGS: [&str; 3] = ["etacoonBombs", "etacoonSupers", "etacoonsETank"];
+
+#[test]
+fn split_reads_every_declared_setting_and_nothing_else() {
+    let mut settings = Settings::new();
+    let keys = settings.schema().keys();
+    for key in &keys {
+        settings.set(key, true);
+    }
+    let mut snes = SNESState::new();
+    let read = settings.keys_read_by(|settings| {
+        split(settings, &mut snes);
+    });
+    let declared: BTreeSet<String> = keys.into_iter().collect();
+    let unread: Vec<_> = declared.difference(&read).collect();
+    assert!(unread.is_empty(), "split() never reads {unread:?}");
+    let undeclared: Vec<_> = read
+        .difference(&declared)
+        .filter(|key| !OLD_SPELLINGS.contains(&key.as_str()))
+        .collect();
+    assert!(
+        undeclared.is_empty(),
+        "split() reads undeclared {undeclared:?}"
+    );
+}
+
+/// Settings SuperMetroid.asl doesn't have.
+const ANNELID_ONLY: [&str; 5] = [
+    "crabShaft->aqueduct",
+    "fishTank->mountEverest",
+    "frogSavestation->businessCenter",
+    "mb1_0hp",
+    "redTower->batRoom",
+];
+
+/// The keys `settings.Add` declares in SuperMetroid.asl, spelled the way
+/// Annelid spells them.
+fn asl_keys() -> BTreeSet<String> {
+    include_str!("../data/SuperMetroid.asl")
+        .split("settings.Add(\"")
+        .skip(1)
+        .map(|rest| rest[..rest.find('"').expect("closing quote")].replace("etacoon", "etecoon"))
+        .collect()
+}
+
+#[test]
+fn the_schema_settings_and_script_declare_the_same_keys() {
+    let schema: BTreeSet<String> = Schema::super_metroid().keys().into_iter().collect();
+    let settings: BTreeSet<String> = Settings::new().keys().into_iter().collect();
+    assert_eq!(schema, settings);
+    let mut asl = asl_keys();
+    asl.extend(ANNELID_ONLY.map(str::to_owned));
+    assert_eq!(schema, asl);
+}
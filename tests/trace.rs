@@ -0,0 +1,228 @@
+use annelid::autosplitters::supermetroid::{Settings, SuperMetroidAutoSplitter};
+use annelid::autosplitters::AutoSplitter;
+use annelid::memory_source::{MemorySource, ReplaySource};
+use annelid::trace::{
+    replay, PlaybackClock, Poll, Trace, TraceEventKind, TraceMeta, TracePlayer, TraceRecorder,
+    TraceWriter, MAGIC,
+};
+use annelid::usb2snes::WRAM_START;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const GAME_STATE: u32 = WRAM_START + 0x0998;
+const ROOM_ID: u32 = WRAM_START + 0x079B;
+
+fn meta() -> TraceMeta {
+    TraceMeta {
+        game: "Super Metroid".to_owned(),
+        rom_name: "sm.sfc".to_owned(),
+        autosplitter: "Super Metroid".to_owned(),
+        device: "SD2SNES COM3".to_owned(),
+        polling_rate: 60.0,
+        recorded_at: "20260101-120000".to_owned(),
+    }
+}
+
+fn autosplitter() -> SuperMetroidAutoSplitter {
+    SuperMetroidAutoSplitter::new(Arc::new(RwLock::new(Settings::new())))
+}
+
+/// A session that boots into a file, starts, then resets to the title.
+fn session() -> ReplaySource {
+    ReplaySource::new(vec![
+        vec![(GAME_STATE, vec![0x02]), (ROOM_ID, vec![0xF8, 0x91])],
+        vec![],
+        vec![(GAME_STATE, vec![0x1F])],
+        vec![],
+        vec![(ROOM_ID, vec![0x00, 0x00])],
+    ])
+}
+
+/// Records a live run of the autosplitter over `source`.
+fn record(mut source: ReplaySource) -> Vec<u8> {
+    let mut recorder = TraceRecorder::new(vec![], &meta()).expect("recorder");
+    let mut splitter = autosplitter();
+    while source.remaining() > 0 {
+        splitter
+            .update(&mut recorder.wrap(&mut source))
+            .expect("update");
+    }
+    recorder.finish().expect("finish")
+}
+
+#[test]
+fn polls_round_trip() {
+    let polls = vec![
+        Poll {
+            time: Duration::from_micros(0),
+            regions: vec![(0xF50000, vec![1, 2]), (0xF51000, vec![3])],
+        },
+        Poll {
+            time: Duration::from_micros(16_667),
+            regions: vec![(0xF50000, vec![1, 2]), (0xF51000, vec![4])],
+        },
+    ];
+    let mut writer = TraceWriter::new(vec![], &meta()).expect("writer");
+    for poll in polls.iter() {
+        writer.write_poll(poll).expect("write");
+    }
+    let bytes = writer.into_inner().expect("bytes");
+    assert!(bytes.starts_with(MAGIC));
+    let trace = Trace::read(&bytes[..]).expect("read");
+    assert_eq!(trace.meta, meta());
+    assert_eq!(trace.polls, polls);
+    assert!(!trace.truncated);
+}
+
+#[test]
+fn unchanged_regions_are_not_repeated() {
+    let poll = |time| Poll {
+        time: Duration::from_millis(time),
+        regions: vec![(0xF50000, vec![0xAA; 200])],
+    };
+    let mut writer = TraceWriter::new(vec![], &meta()).expect("writer");
+    writer.write_poll(&poll(0)).expect("write");
+    let after_first = writer.into_inner().expect("bytes").len();
+    let mut writer = TraceWriter::new(vec![], &meta()).expect("writer");
+    for time in 0..10 {
+        writer.write_poll(&poll(time)).expect("write");
+    }
+    let after_ten = writer.into_inner().expect("bytes").len();
+    // Each repeat costs its header only: tag, time, count, address, size, marker.
+    assert_eq!(after_ten - after_first, 9 * (1 + 8 + 2 + 4 + 4 + 1));
+}
+
+#[test]
+fn truncated_traces_keep_complete_polls() {
+    let bytes = record(session());
+    let full = Trace::read(&bytes[..]).expect("read");
+    assert_eq!(full.polls.len(), 5);
+    let cut = Trace::read(&bytes[..bytes.len() - 3]).expect("read");
+    assert!(cut.truncated);
+    assert_eq!(cut.polls[..], full.polls[..4]);
+}
+
+#[test]
+fn oversized_regions_are_rejected() {
+    let poll = Poll {
+        time: Duration::ZERO,
+        regions: vec![(0xF50000, vec![1, 2])],
+    };
+    let mut writer = TraceWriter::new(vec![], &meta()).expect("writer");
+    writer.write_poll(&poll).expect("write");
+    let mut bytes = writer.into_inner().expect("bytes");
+    // The size sits before the marker and the two data bytes.
+    let size = bytes.len() - 7;
+    bytes[size..size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    let err = Trace::read(&bytes[..]).expect_err("oversized region");
+    assert!(format!("{err:#}").contains("impossible size"), "{err:#}");
+}
+
+#[test]
+fn other_files_are_rejected() {
+    assert!(Trace::read(&b"{\"not\": \"a trace\"}"[..]).is_err());
+}
+
+#[test]
+fn replay_reports_the_polls_that_acted() {
+    let trace = Trace::read(&record(session())[..]).expect("read");
+    let report = replay(&trace, &mut autosplitter()).expect("replay");
+    assert_eq!(report.polls, 5);
+    let events: Vec<(usize, TraceEventKind)> =
+        report.events.iter().map(|e| (e.poll, e.kind)).collect();
+    assert_eq!(
+        events,
+        [(2, TraceEventKind::Start), (4, TraceEventKind::Reset)]
+    );
+    let text = report.to_string();
+    assert!(
+        text.starts_with("5 polls: 1 starts, 0 splits, 1 resets"),
+        "{text}"
+    );
+}
+
+#[test]
+fn replay_source_reports_the_recorded_game() {
+    let trace = Trace::read(&record(session())[..]).expect("read");
+    let game = trace.replay_source().game_info().expect("game info");
+    assert_eq!(game.rom_name, "sm.sfc");
+}
+
+#[test]
+fn recorder_files_are_named_after_the_game() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let mut meta = meta();
+    meta.recorded_at.clear();
+    let (recorder, path) = TraceRecorder::create(dir.path(), &meta).expect("create");
+    recorder.finish().expect("finish");
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.ends_with("-Super_Metroid.trace"), "{name}");
+    let trace = Trace::load(&path).expect("load");
+    assert!(!trace.meta.recorded_at.is_empty());
+    assert!(trace.polls.is_empty());
+}
+
+#[test]
+fn player_steps_one_poll_at_a_time() {
+    let trace = Trace::read(&record(session())[..]).expect("read");
+    let mut player = TracePlayer::new(&trace);
+    let mut splitter = autosplitter();
+    assert_eq!(player.len(), 5);
+    assert_eq!(player.next_time(), Some(trace.polls[0].time));
+    let mut kinds = vec![];
+    while !player.is_finished() {
+        let before = player.position();
+        let (_, events) = player.step(&mut splitter).expect("step");
+        assert_eq!(player.position(), before + 1);
+        kinds.extend(events.into_iter().map(|e| (e.poll, e.kind)));
+    }
+    assert_eq!(
+        kinds,
+        [(2, TraceEventKind::Start), (4, TraceEventKind::Reset)]
+    );
+    assert_eq!(player.next_time(), None);
+    assert!(player.step(&mut splitter).is_err());
+}
+
+#[test]
+fn player_knows_which_polls_are_due() {
+    let mut writer = TraceWriter::new(vec![], &meta()).expect("writer");
+    for millis in [0, 100, 200] {
+        let poll = Poll {
+            time: Duration::from_millis(millis),
+            regions: vec![(GAME_STATE, vec![0])],
+        };
+        writer.write_poll(&poll).expect("write");
+    }
+    let trace = Trace::read(&writer.into_inner().expect("bytes")[..]).expect("read");
+    let mut player = TracePlayer::new(&trace);
+    let mut splitter = autosplitter();
+    assert!(player.is_due(Duration::ZERO));
+    player.step(&mut splitter).expect("step");
+    assert!(!player.is_due(Duration::from_millis(99)));
+    assert!(player.is_due(Duration::from_millis(100)));
+}
+
+#[test]
+fn clock_follows_speed_and_pauses() {
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    let mut clock = PlaybackClock::new(Duration::from_secs(1), start);
+    assert_eq!(clock.position(at(500)), Duration::from_millis(1500));
+
+    clock.set_speed(4.0, at(500));
+    assert_eq!(clock.position(at(750)), Duration::from_millis(2500));
+    assert_eq!(
+        clock.until(Duration::from_millis(3500), at(750)),
+        Some(Duration::from_millis(250))
+    );
+
+    clock.pause(at(750));
+    assert_eq!(clock.position(at(5000)), Duration::from_millis(2500));
+    assert_eq!(clock.until(Duration::from_secs(10), at(5000)), None);
+
+    clock.seek(Duration::from_secs(3), at(5000));
+    clock.resume(at(6000));
+    assert_eq!(clock.position(at(6100)), Duration::from_millis(3400));
+}
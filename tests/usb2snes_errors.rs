@@ -0,0 +1,179 @@
+use annelid::config::app_config::DeviceFallback;
+use annelid::usb2snes::{select_device, ClientOptions, SyncClient, USB2SnesError};
+use std::net::TcpListener;
+use std::time::Duration;
+use tungstenite::Message;
+
+/// What the scripted server does in answer to one request.
+enum Reply {
+    Send(Vec<Message>),
+    /// Drops the connection without a closing handshake.
+    Hangup,
+}
+
+/// Starts a WebSocket server that answers each request with the next entry
+/// of `script`, then closes the connection once the script runs out.
+fn scripted_server(script: Vec<Reply>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let address = listener.local_addr().expect("local addr").to_string();
+    std::thread::spawn(move || {
+        let Ok((stream, _)) = listener.accept() else {
+            return;
+        };
+        let Ok(mut socket) = tungstenite::accept(stream) else {
+            return;
+        };
+        for reply in script {
+            match socket.read() {
+                Ok(Message::Text(_)) => {}
+                _ => return,
+            }
+            match reply {
+                Reply::Send(messages) => {
+                    for message in messages {
+                        if socket.send(message).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Reply::Hangup => return,
+            }
+        }
+        let _ = socket.close(None);
+        let _ = socket.flush();
+    });
+    address
+}
+
+fn results(fields: &[&str]) -> Message {
+    Message::text(format!(r#"{{"Results":{fields:?}}}"#))
+}
+
+fn binary(data: &[u8]) -> Message {
+    Message::binary(data.to_vec())
+}
+
+fn client(address: &str) -> SyncClient {
+    let options = ClientOptions {
+        read_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    };
+    SyncClient::connect_to(address, options).expect("connect")
+}
+
+fn error_of<T>(result: anyhow::Result<T>) -> USB2SnesError {
+    let Err(err) = result else {
+        panic!("the reply should be rejected");
+    };
+    match err.downcast::<USB2SnesError>() {
+        Ok(e) => e,
+        Err(err) => panic!("expected a USB2SnesError, got: {err:#}"),
+    }
+}
+
+#[test]
+fn short_info_reply_is_a_protocol_error() {
+    let address = scripted_server(vec![Reply::Send(vec![results(&["1.11.0", "SD2SNES"])])]);
+    let err = error_of(client(&address).info());
+    assert!(matches!(err, USB2SnesError::Protocol(_)), "{err}");
+    assert!(err.is_protocol_error());
+}
+
+#[test]
+fn odd_list_reply_is_a_protocol_error() {
+    let address = scripted_server(vec![Reply::Send(vec![results(&["1", "sm.sfc", "0"])])]);
+    let err = error_of(client(&address).ls("/"));
+    assert!(matches!(err, USB2SnesError::Protocol(_)), "{err}");
+}
+
+#[test]
+fn list_reply_pairs_are_read() {
+    let address = scripted_server(vec![Reply::Send(vec![results(&[
+        "0", "roms", "1", "sm.sfc",
+    ])])]);
+    let entries = client(&address).ls("/").expect("ls");
+    let names: Vec<&str> = entries.iter().map(|e| &*e.name).collect();
+    assert_eq!(names, ["roms", "sm.sfc"]);
+}
+
+#[test]
+fn malformed_json_is_a_protocol_error() {
+    let address = scripted_server(vec![Reply::Send(vec![Message::text("{\"Res")])]);
+    let err = error_of(client(&address).app_version());
+    assert!(matches!(err, USB2SnesError::Protocol(_)), "{err}");
+}
+
+#[test]
+fn empty_results_are_a_protocol_error() {
+    let address = scripted_server(vec![Reply::Send(vec![results(&[])])]);
+    let err = error_of(client(&address).app_version());
+    assert!(matches!(err, USB2SnesError::Protocol(_)), "{err}");
+}
+
+#[test]
+fn binary_reply_to_a_text_command_is_an_unexpected_frame() {
+    let address = scripted_server(vec![Reply::Send(vec![binary(&[1, 2, 3])])]);
+    let err = error_of(client(&address).app_version());
+    assert!(
+        matches!(
+            err,
+            USB2SnesError::UnexpectedFrame {
+                expected: "text",
+                got: "binary"
+            }
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn text_reply_to_a_read_is_an_unexpected_frame() {
+    let address = scripted_server(vec![Reply::Send(vec![results(&["oops"])])]);
+    let err = error_of(client(&address).get_address(0xF50000, 4));
+    assert!(
+        matches!(
+            err,
+            USB2SnesError::UnexpectedFrame {
+                expected: "binary",
+                got: "text"
+            }
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn closing_partway_through_a_read_is_a_short_read() {
+    let address = scripted_server(vec![Reply::Send(vec![binary(&[1, 2])])]);
+    let err = error_of(client(&address).get_address(0xF50000, 4));
+    assert!(
+        matches!(
+            err,
+            USB2SnesError::ShortRead {
+                expected: 4,
+                got: 2
+            }
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn closing_before_a_reply_is_closed() {
+    let address = scripted_server(vec![]);
+    let err = error_of(client(&address).app_version());
+    assert!(matches!(err, USB2SnesError::Closed), "{err}");
+}
+
+#[test]
+fn hanging_up_before_a_reply_is_closed() {
+    let address = scripted_server(vec![Reply::Hangup]);
+    let err = error_of(client(&address).info());
+    assert!(matches!(err, USB2SnesError::Closed), "{err}");
+}
+
+#[test]
+fn an_empty_device_list_is_no_device() {
+    let err = error_of(select_device(&[], None, DeviceFallback::First));
+    assert!(matches!(err, USB2SnesError::NoDevice), "{err}");
+}
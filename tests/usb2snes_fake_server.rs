@@ -0,0 +1,299 @@
+mod common;
+
+use annelid::autosplitters::supermetroid::{Settings, SuperMetroidAutoSplitter};
+use annelid::autosplitters::AutoSplitter;
+use annelid::backend::Backend;
+use annelid::config::app_config::AppConfig;
+use annelid::memory_source::MemorySource;
+use annelid::usb2snes::{ClientOptions, ReadStrategy, SyncClient};
+use common::{FakeUsb2Snes, Request, WRAM};
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Super Metroid WRAM offsets used below.
+const ROOM_ID: u32 = 0x079B;
+const GAME_STATE: u32 = 0x0998;
+const MAX_MISSILES: u32 = 0x09C8;
+
+fn options() -> ClientOptions {
+    ClientOptions {
+        read_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    }
+}
+
+fn config_for(server: &FakeUsb2Snes) -> AppConfig {
+    AppConfig {
+        usb2snes_host: Some(server.host.clone()),
+        usb2snes_port: Some(server.port),
+        ..AppConfig::default()
+    }
+}
+
+fn connect_backend(server: &FakeUsb2Snes) -> Backend {
+    Backend::connect(&config_for(server), options(), |_| {}).expect("connect")
+}
+
+fn attached_client(server: &FakeUsb2Snes) -> SyncClient {
+    let mut client = SyncClient::connect_to(&server.address(), options()).expect("connect");
+    let devices = client.list_device().expect("device list");
+    client.attach(&devices[0]).expect("attach");
+    client
+}
+
+fn autosplitter(settings: Settings) -> SuperMetroidAutoSplitter {
+    SuperMetroidAutoSplitter::new(Arc::new(RwLock::new(settings)))
+}
+
+#[test]
+fn device_list_attach_and_info() {
+    let server = FakeUsb2Snes::start();
+    server.set_devices(&["SD2SNES COM3", "SD2SNES COM4"]);
+    let mut client = SyncClient::connect_to(&server.address(), options()).expect("connect");
+    let devices = client.list_device().expect("device list");
+    assert_eq!(devices.len(), 2);
+    client.attach(&devices[1]).expect("attach");
+    let info = client.info().expect("info");
+    assert_eq!(&*info.version, "1.11.0");
+    assert_eq!(&*info.dev_type, "SD2SNES");
+    assert_eq!(&*info.game, "/sm.sfc");
+    assert_eq!(info.flags.len(), 1);
+    assert_eq!(
+        server.requests()[1],
+        Request {
+            opcode: "Attach".to_owned(),
+            space: None,
+            operands: vec!["SD2SNES COM4".to_owned()],
+        }
+    );
+}
+
+#[test]
+fn get_addresses_sends_every_operand_pair() {
+    let server = FakeUsb2Snes::start();
+    server.write_wram(ROOM_ID, &[0xF8, 0x91]);
+    server.write_wram(GAME_STATE, &[0x08]);
+    let mut client = attached_client(&server);
+    let data = client
+        .get_addresses(&[(WRAM + ROOM_ID, 2), (WRAM + GAME_STATE, 1)])
+        .expect("read");
+    assert_eq!(data, vec![vec![0xF8, 0x91], vec![0x08]]);
+    let request = server.requests().pop().expect("a request");
+    assert_eq!(request.opcode, "GetAddress");
+    assert_eq!(request.space.as_deref(), Some("SNES"));
+    assert_eq!(request.operands, ["f5079b", "2", "f50998", "1"]);
+}
+
+fn get_address_operands(server: &FakeUsb2Snes) -> Vec<Vec<String>> {
+    server
+        .requests()
+        .into_iter()
+        .filter(|r| r.opcode == "GetAddress")
+        .map(|r| r.operands)
+        .collect()
+}
+
+#[test]
+fn emulators_get_vectored_reads_too() {
+    let server = FakeUsb2Snes::start();
+    server.set_dev_type("SNES9X");
+    server.write_wram(ROOM_ID, &[0xF8, 0x91]);
+    server.write_wram(GAME_STATE, &[0x08]);
+    let mut client = attached_client(&server);
+    let data = client
+        .get_addresses(&[(WRAM + ROOM_ID, 2), (WRAM + GAME_STATE, 1)])
+        .expect("read");
+    assert_eq!(data, vec![vec![0xF8, 0x91], vec![0x08]]);
+    assert_eq!(client.read_strategy(), ReadStrategy::Vectored);
+    assert_eq!(
+        get_address_operands(&server),
+        [["f5079b", "2", "f50998", "1"]]
+    );
+}
+
+#[test]
+fn a_failed_vectored_read_falls_back_to_one_region_per_request() {
+    let server = FakeUsb2Snes::start();
+    server.set_vectored_reads(false);
+    server.write_wram(ROOM_ID, &[0xF8, 0x91]);
+    server.write_wram(GAME_STATE, &[0x08]);
+    let mut client = attached_client(&server);
+    let pairs = [(WRAM + ROOM_ID, 2), (WRAM + GAME_STATE, 1)];
+    let data = client.get_addresses(&pairs).expect("read");
+    assert_eq!(data, vec![vec![0xF8, 0x91], vec![0x08]]);
+    assert_eq!(client.read_strategy(), ReadStrategy::Sequential);
+
+    // The connection remembers, so later reads don't try vectored again.
+    client.get_addresses(&pairs).expect("read");
+    let vectored = vec!["f5079b", "2", "f50998", "1"];
+    let (first, second) = (vec!["f5079b", "2"], vec!["f50998", "1"]);
+    assert_eq!(
+        get_address_operands(&server),
+        [vectored, first.clone(), second.clone(), first, second]
+    );
+}
+
+#[test]
+fn chunked_replies_are_reassembled() {
+    let server = FakeUsb2Snes::start();
+    server.set_chunk_size(7);
+    let pattern: Vec<u8> = (0..=255).collect();
+    server.write_wram(0x1000, &pattern);
+    server.write_wram(0x2000, &pattern[..100]);
+    let mut client = attached_client(&server);
+    let data = client
+        .get_addresses(&[(WRAM + 0x1000, 256), (WRAM + 0x2000, 100)])
+        .expect("read");
+    assert_eq!(data[0], pattern);
+    assert_eq!(data[1], &pattern[..100]);
+    assert_eq!(
+        client.get_address(WRAM + 0x1000, 256).expect("read"),
+        pattern
+    );
+}
+
+#[test]
+fn get_addresses_spreads_many_regions_over_requests() {
+    let server = FakeUsb2Snes::start();
+    let pairs: Vec<(u32, usize)> = (0..20u32)
+        .map(|i| {
+            server.write_wram(i * 0x40, &[i as u8; 3]);
+            (WRAM + i * 0x40, 3)
+        })
+        .collect();
+    let mut client = attached_client(&server);
+    let data = client.get_addresses(&pairs).expect("read");
+    for (i, region) in data.iter().enumerate() {
+        assert_eq!(region, &[i as u8; 3]);
+    }
+    let reads = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.opcode == "GetAddress")
+        .count();
+    assert_eq!(reads, 3);
+}
+
+#[test]
+fn get_addresses_reads_large_regions_alone() {
+    let server = FakeUsb2Snes::start();
+    let items: Vec<u8> = (0..0x1000).map(|i| (i % 251) as u8).collect();
+    server.write_wram(0xD000, &items);
+    server.write_wram(GAME_STATE, &[0x08]);
+    let mut client = attached_client(&server);
+    let data = client
+        .get_addresses(&[(WRAM + 0xD000, items.len()), (WRAM + GAME_STATE, 1)])
+        .expect("read");
+    assert_eq!(data, vec![items, vec![0x08]]);
+}
+
+#[test]
+fn whole_address_space_is_readable() {
+    let server = FakeUsb2Snes::start();
+    server.write(0xFFFFFE, &[0xAB, 0xCD]);
+    server.write(0x000000, &[0x78]);
+    let mut client = attached_client(&server);
+    assert_eq!(
+        client.get_address(0xFFFFFE, 2).expect("top of SRAM"),
+        [0xAB, 0xCD]
+    );
+    assert_eq!(client.get_address(0, 1).expect("ROM"), [0x78]);
+}
+
+#[test]
+fn device_commands_before_attach_close_the_connection() {
+    let server = FakeUsb2Snes::start();
+    let mut client = SyncClient::connect_to(&server.address(), options()).expect("connect");
+    assert!(client.get_addresses(&[(WRAM, 1)]).is_err());
+}
+
+#[test]
+fn backend_attaches_the_preferred_device() {
+    let server = FakeUsb2Snes::start();
+    server.set_devices(&["SD2SNES COM3", "EMU SNES9X"]);
+    let config = AppConfig {
+        preferred_device: Some("emu*".to_owned()),
+        ..config_for(&server)
+    };
+    let mut seen = vec![];
+    let mut client = Backend::connect(&config, options(), |devices| {
+        seen = devices.iter().map(|d| d.to_string()).collect()
+    })
+    .expect("connect");
+    assert_eq!(seen, ["SD2SNES COM3", "EMU SNES9X"]);
+    // Attach has no reply, so make a round trip before checking the log.
+    client.info().expect("info");
+    let attach = server
+        .requests()
+        .into_iter()
+        .find(|r| r.opcode == "Attach")
+        .expect("attach");
+    assert_eq!(attach.operands, ["EMU SNES9X"]);
+}
+
+#[test]
+fn update_detects_start() {
+    let server = FakeUsb2Snes::start();
+    let mut client = connect_backend(&server);
+    let mut splitter = autosplitter(Settings::new());
+    server.write_wram(GAME_STATE, &[0x02]);
+    assert!(!splitter.update(&mut client).expect("update").start);
+    server.write_wram(GAME_STATE, &[0x1F]);
+    assert!(splitter.update(&mut client).expect("update").start);
+    assert!(!splitter.update(&mut client).expect("update").start);
+}
+
+#[test]
+fn update_detects_reset() {
+    let server = FakeUsb2Snes::start();
+    let mut client = connect_backend(&server);
+    let mut splitter = autosplitter(Settings::new());
+    server.write_wram(ROOM_ID, &[0xF8, 0x91]);
+    assert!(!splitter.update(&mut client).expect("update").reset);
+    server.write_wram(ROOM_ID, &[0x00, 0x00]);
+    assert!(splitter.update(&mut client).expect("update").reset);
+}
+
+#[test]
+fn update_splits_on_first_missile() {
+    let server = FakeUsb2Snes::start();
+    let mut client = connect_backend(&server);
+    let mut settings = Settings::new();
+    settings.set("ammoPickups", true);
+    settings.set("firstMissile", true);
+    let mut splitter = autosplitter(settings);
+    assert!(!splitter.update(&mut client).expect("update").split);
+    server.write_wram(MAX_MISSILES, &[5]);
+    assert!(splitter.update(&mut client).expect("update").split);
+    assert!(!splitter.update(&mut client).expect("update").split);
+}
+
+#[test]
+fn reconnect_keeps_autosplitter_state() {
+    let server = FakeUsb2Snes::start();
+    let mut splitter = autosplitter(Settings::new());
+    let mut client = connect_backend(&server);
+    server.write_wram(GAME_STATE, &[0x02]);
+    assert!(!splitter.update(&mut client).expect("update").start);
+
+    server.disconnect_all();
+    assert!(splitter.update(&mut client).is_err());
+
+    // The polling thread reconnects with a fresh client but keeps the
+    // autosplitter, so a transition that spans the outage still counts.
+    let mut client = connect_backend(&server);
+    assert_eq!(server.connections(), 2);
+    server.write_wram(GAME_STATE, &[0x1F]);
+    assert!(splitter.update(&mut client).expect("update").start);
+}
+
+#[test]
+fn reset_is_sent_to_the_device() {
+    let server = FakeUsb2Snes::start();
+    let mut client = connect_backend(&server);
+    client.reset().expect("reset");
+    // Reset has no reply, so make a round trip before checking the count.
+    client.info().expect("info");
+    assert_eq!(server.resets(), 1);
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use annelid::usb2snes::{ClientOptions, SyncClient, USB2SnesFileType};
+use common::FakeUsb2Snes;
+use std::time::Duration;
+
+fn attached_client(server: &FakeUsb2Snes) -> SyncClient {
+    let options = ClientOptions {
+        read_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    };
+    let mut client = SyncClient::connect_to(&server.address(), options).expect("connect");
+    let devices = client.list_device().expect("device list");
+    client.attach(&devices[0]).expect("attach");
+    client
+}
+
+#[test]
+fn ls_lists_files_and_directories() {
+    let server = FakeUsb2Snes::start();
+    server.put_file("/roms/sm.sfc", &[1]);
+    server.put_file("/roms/hacks/redesign.sfc", &[2]);
+    server.put_file("/sd2snes/config.yml", &[3]);
+    let mut client = attached_client(&server);
+    let listing = client.ls("/roms").expect("ls");
+    let names: Vec<(&str, bool)> = listing
+        .iter()
+        .map(|i| (&*i.name, i.file_type == USB2SnesFileType::Dir))
+        .collect();
+    assert_eq!(names, [("hacks", true), ("sm.sfc", false)]);
+}
+
+#[test]
+fn upload_reports_progress_per_chunk() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    let data: Vec<u8> = (0..2500).map(|i| i as u8).collect();
+    let mut progress = vec![];
+    client
+        .send_file_with_progress("/roms/new.sfc", &data, |done, total| {
+            progress.push((done, total))
+        })
+        .expect("upload");
+    assert_eq!(progress, [(1024, 2500), (2048, 2500), (2500, 2500)]);
+    // PutFile has no reply, so make a round trip before checking.
+    client.ls("/roms").expect("ls");
+    assert_eq!(server.file("/roms/new.sfc"), Some(data));
+}
+
+#[test]
+fn download_reports_progress_until_complete() {
+    let server = FakeUsb2Snes::start();
+    server.set_chunk_size(1000);
+    let data: Vec<u8> = (0..2500).map(|i| (i % 7) as u8).collect();
+    server.put_file("/saves/sm.srm", &data);
+    let mut client = attached_client(&server);
+    let mut progress = vec![];
+    let downloaded = client
+        .get_file_with_progress("/saves/sm.srm", |done, total| progress.push((done, total)))
+        .expect("download");
+    assert_eq!(downloaded, data);
+    assert_eq!(progress, [(1000, 2500), (2000, 2500), (2500, 2500)]);
+}
+
+#[test]
+fn empty_files_round_trip() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    client.send_file("/empty.txt", &[]).expect("upload");
+    assert!(client.get_file("/empty.txt").expect("download").is_empty());
+}
+
+#[test]
+fn remove_and_boot() {
+    let server = FakeUsb2Snes::start();
+    server.put_file("/roms/sm.sfc", &[1]);
+    server.put_file("/roms/old.sfc", &[2]);
+    let mut client = attached_client(&server);
+    client.remove_path("/roms/old.sfc").expect("remove");
+    client.boot("/roms/sm.sfc").expect("boot");
+    let listing = client.ls("/roms").expect("ls");
+    assert_eq!(listing.len(), 1);
+    assert_eq!(&*listing[0].name, "sm.sfc");
+    assert_eq!(server.booted(), ["/roms/sm.sfc"]);
+}
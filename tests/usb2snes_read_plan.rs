@@ -0,0 +1,134 @@
+use annelid::usb2snes::{ReadPlan, ReadStrategy, MAX_VECTOR_REGIONS, MAX_VECTOR_SIZE};
+
+/// Answers every request in `plan` from a fake memory where each byte is
+/// the low byte of its address.
+fn replies(plan: &ReadPlan) -> Vec<Vec<u8>> {
+    plan.requests()
+        .iter()
+        .map(|request| {
+            request
+                .iter()
+                .flat_map(|&(address, size)| (address..address + size as u32).map(|a| a as u8))
+                .collect()
+        })
+        .collect()
+}
+
+fn expected(pairs: &[(u32, usize)]) -> Vec<Vec<u8>> {
+    pairs
+        .iter()
+        .map(|&(address, size)| (address..address + size as u32).map(|a| a as u8).collect())
+        .collect()
+}
+
+fn assert_legal(plan: &ReadPlan) {
+    for request in plan.requests() {
+        assert!(!request.is_empty());
+        if request.len() > 1 {
+            assert!(request.len() <= MAX_VECTOR_REGIONS, "{request:?}");
+            assert!(
+                request.iter().all(|&(_, size)| size <= MAX_VECTOR_SIZE),
+                "{request:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn eight_small_regions_fit_in_one_request() {
+    let pairs: Vec<(u32, usize)> = (0..8).map(|i| (0xF50000 + i * 0x100, 4)).collect();
+    let plan = ReadPlan::new(&pairs);
+    assert_eq!(plan.requests().len(), 1);
+    assert_eq!(plan.extract(&pairs, &replies(&plan)), expected(&pairs));
+}
+
+#[test]
+fn more_than_eight_regions_are_split() {
+    let pairs: Vec<(u32, usize)> = (0..20).map(|i| (0xF50000 + i * 0x100, 3)).collect();
+    let plan = ReadPlan::new(&pairs);
+    assert_legal(&plan);
+    assert_eq!(plan.requests().len(), 3);
+    assert_eq!(plan.extract(&pairs, &replies(&plan)), expected(&pairs));
+}
+
+#[test]
+fn overlapping_and_adjacent_ranges_are_merged() {
+    let pairs = [
+        (0xF50010, 4),
+        (0xF50012, 4),
+        (0xF50016, 2),
+        (0xF50000, 1),
+        (0xF50011, 1),
+    ];
+    let plan = ReadPlan::new(&pairs);
+    assert_eq!(
+        plan.requests(),
+        [vec![(0xF50000, 1), (0xF50010, 8)]].as_slice()
+    );
+    assert_eq!(plan.extract(&pairs, &replies(&plan)), expected(&pairs));
+}
+
+#[test]
+fn duplicate_pairs_are_read_once() {
+    let pairs = [(0xF5079B, 2), (0xF5079B, 2)];
+    let plan = ReadPlan::new(&pairs);
+    assert_eq!(plan.requests(), [vec![(0xF5079B, 2)]].as_slice());
+    assert_eq!(plan.extract(&pairs, &replies(&plan)), expected(&pairs));
+}
+
+#[test]
+fn medium_regions_are_cut_into_vector_slots() {
+    let pairs = [(0xF50000, 600), (0xF51000, 10)];
+    let plan = ReadPlan::new(&pairs);
+    assert_legal(&plan);
+    assert_eq!(plan.requests().len(), 1);
+    assert_eq!(plan.requests()[0].len(), 4);
+    assert_eq!(plan.extract(&pairs, &replies(&plan)), expected(&pairs));
+}
+
+#[test]
+fn large_regions_get_their_own_request() {
+    let pairs = [(0xF50000, 0x2000), (0xF60000, 2)];
+    let plan = ReadPlan::new(&pairs);
+    assert_legal(&plan);
+    assert_eq!(
+        plan.requests(),
+        [vec![(0xF50000, 0x2000)], vec![(0xF60000, 2)]].as_slice()
+    );
+    assert_eq!(plan.extract(&pairs, &replies(&plan)), expected(&pairs));
+}
+
+#[test]
+fn pair_spanning_several_slots_is_reassembled() {
+    let pairs = [(0xF50000, 700), (0xF500FE, 4)];
+    let plan = ReadPlan::new(&pairs);
+    assert_legal(&plan);
+    assert_eq!(plan.extract(&pairs, &replies(&plan)), expected(&pairs));
+}
+
+#[test]
+fn empty_reads_are_skipped() {
+    let pairs = [(0xF50000, 0), (0xF50010, 1)];
+    let plan = ReadPlan::new(&pairs);
+    assert_eq!(plan.requests(), [vec![(0xF50010, 1)]].as_slice());
+    assert_eq!(
+        plan.extract(&pairs, &replies(&plan)),
+        vec![vec![], vec![0x10]]
+    );
+    assert!(ReadPlan::new(&[]).requests().is_empty());
+}
+
+#[test]
+fn sequential_plans_send_one_region_per_request() {
+    let pairs = [(0xF50010, 4), (0xF50012, 4), (0xF51000, 600), (0xF52000, 1)];
+    let plan = ReadPlan::with_strategy(&pairs, ReadStrategy::Sequential);
+    assert_eq!(
+        plan.requests(),
+        [
+            vec![(0xF50010, 6)],
+            vec![(0xF51000, 600)],
+            vec![(0xF52000, 1)]
+        ]
+    );
+    assert_eq!(plan.extract(&pairs, &replies(&plan)), expected(&pairs));
+}
@@ -0,0 +1,87 @@
+use annelid::config::app_config::AppConfig;
+use annelid::usb2snes::{
+    candidate_ports, server_address, ClientOptions, SyncClient, DEFAULT_HOST, DEFAULT_PORT,
+    LEGACY_PORT,
+};
+use std::net::TcpListener;
+use std::time::Duration;
+
+#[test]
+fn default_port_falls_back_to_legacy_port() {
+    assert_eq!(
+        candidate_ports(DEFAULT_PORT),
+        vec![DEFAULT_PORT, LEGACY_PORT]
+    );
+    assert_eq!(candidate_ports(1234), vec![1234, LEGACY_PORT]);
+}
+
+#[test]
+fn legacy_port_is_not_tried_twice() {
+    assert_eq!(candidate_ports(LEGACY_PORT), vec![LEGACY_PORT]);
+}
+
+#[test]
+fn server_address_formats_host_and_port() {
+    assert_eq!(server_address("localhost", 23074), "localhost:23074");
+    assert_eq!(server_address(" 192.168.1.20 ", 8080), "192.168.1.20:8080");
+}
+
+#[test]
+fn server_address_brackets_ipv6() {
+    assert_eq!(server_address("::1", 23074), "[::1]:23074");
+    assert_eq!(server_address("[::1]", 23074), "[::1]:23074");
+}
+
+#[test]
+fn default_config_points_at_local_server() {
+    let config = AppConfig::default();
+    assert_eq!(config.usb2snes_host.as_deref(), Some(DEFAULT_HOST));
+    assert_eq!(config.usb2snes_port, Some(DEFAULT_PORT));
+}
+
+#[test]
+fn config_server_address_from_toml() {
+    let config: AppConfig = toml::from_str(
+        r#"
+usb2snes_host = "192.168.1.20"
+usb2snes_port = 8080
+"#,
+    )
+    .expect("should deserialize server address");
+    assert_eq!(config.usb2snes_host.as_deref(), Some("192.168.1.20"));
+    assert_eq!(config.usb2snes_port, Some(8080));
+}
+
+#[test]
+fn connect_any_uses_the_first_port_that_answers() {
+    let closed_port = {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        listener.local_addr().expect("local addr").port()
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let open_port = listener.local_addr().expect("local addr").port();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept");
+        tungstenite::accept(stream).expect("handshake")
+    });
+    let options = ClientOptions {
+        connect_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    };
+    SyncClient::connect_any("127.0.0.1", &[closed_port, open_port], options)
+        .expect("fallback port should connect");
+    server.join().expect("server thread");
+}
+
+#[test]
+fn connect_any_reports_failure_when_no_port_answers() {
+    let closed_port = {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        listener.local_addr().expect("local addr").port()
+    };
+    let options = ClientOptions {
+        connect_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    };
+    assert!(SyncClient::connect_any("127.0.0.1", &[closed_port], options).is_err());
+}
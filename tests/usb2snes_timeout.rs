@@ -0,0 +1,106 @@
+use annelid::usb2snes::{CancelHandle, ClientOptions, SyncClient, USB2SnesError};
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+/// Starts a WebSocket server that completes the handshake and then never
+/// answers, like a QUsb2snes instance that has stopped responding.
+fn silent_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let address = listener.local_addr().expect("local addr").to_string();
+    std::thread::spawn(move || {
+        let mut sockets = vec![];
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { break };
+            if let Ok(socket) = tungstenite::accept(stream) {
+                // Keep the socket open so the client sees silence, not EOF.
+                sockets.push(socket);
+            }
+        }
+    });
+    address
+}
+
+fn options(read_timeout: Duration) -> ClientOptions {
+    ClientOptions {
+        read_timeout,
+        ..ClientOptions::default()
+    }
+}
+
+#[test]
+fn read_times_out_when_server_is_silent() {
+    let address = silent_server();
+    let mut client =
+        SyncClient::connect_to(&address, options(Duration::from_millis(200))).expect("connect");
+    let started = Instant::now();
+    let err = client.app_version().expect_err("server never replies");
+    assert!(
+        matches!(
+            USB2SnesError::from_anyhow(&err),
+            Some(USB2SnesError::Timeout(_))
+        ),
+        "expected a timeout, got: {err}"
+    );
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn get_address_times_out_when_server_is_silent() {
+    let address = silent_server();
+    let mut client =
+        SyncClient::connect_to(&address, options(Duration::from_millis(200))).expect("connect");
+    let err = client
+        .get_addresses(&[(0xF50000, 2)])
+        .expect_err("server never replies");
+    assert!(matches!(
+        USB2SnesError::from_anyhow(&err),
+        Some(USB2SnesError::Timeout(_))
+    ));
+}
+
+#[test]
+fn cancel_interrupts_a_blocked_read() {
+    let address = silent_server();
+    let cancel = CancelHandle::new();
+    let mut client = SyncClient::connect_to(
+        &address,
+        ClientOptions {
+            read_timeout: Duration::from_secs(30),
+            cancel: cancel.clone(),
+            ..ClientOptions::default()
+        },
+    )
+    .expect("connect");
+    let canceller = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        cancel.cancel();
+    });
+    let started = Instant::now();
+    let err = client.app_version().expect_err("read should be cancelled");
+    canceller.join().unwrap();
+    assert!(matches!(
+        USB2SnesError::from_anyhow(&err),
+        Some(USB2SnesError::Cancelled)
+    ));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn cancel_handle_reset_clears_flag() {
+    let cancel = CancelHandle::new();
+    let clone = cancel.clone();
+    clone.cancel();
+    assert!(cancel.is_cancelled());
+    cancel.reset();
+    assert!(!clone.is_cancelled());
+}
+
+#[test]
+fn connect_fails_when_nothing_is_listening() {
+    // Bind and immediately drop to get a port that is very likely closed.
+    let address = {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        listener.local_addr().expect("local addr").to_string()
+    };
+    assert!(SyncClient::connect_to(&address, options(Duration::from_millis(200))).is_err());
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use annelid::usb2snes::{
+    ClientOptions, Space, SyncClient, USB2SnesError, FLAG_NO_ROM_WRITE, SRAM_START, WRAM_SIZE,
+};
+use common::{FakeUsb2Snes, WRAM};
+use std::time::Duration;
+
+fn attached_client(server: &FakeUsb2Snes) -> SyncClient {
+    let options = ClientOptions {
+        read_timeout: Duration::from_millis(500),
+        ..ClientOptions::default()
+    };
+    let mut client = SyncClient::connect_to(&server.address(), options).expect("connect");
+    let devices = client.list_device().expect("device list");
+    client.attach(&devices[0]).expect("attach");
+    client
+}
+
+fn is_unsupported(err: &anyhow::Error) -> bool {
+    matches!(
+        USB2SnesError::from_anyhow(err),
+        Some(USB2SnesError::Unsupported(_))
+    )
+}
+
+#[test]
+fn write_wram_puts_bytes_in_snes_space() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    client.write_wram(0x09DA, &[0, 0, 0, 0]).expect("write");
+    // PutAddress has no reply; reading back makes the round trip.
+    assert_eq!(
+        client.get_address(WRAM + 0x09DA, 4).expect("read"),
+        [0, 0, 0, 0]
+    );
+    client.write_wram(0x09DA, &[1, 2, 3, 4]).expect("write");
+    assert_eq!(
+        client.get_address(WRAM + 0x09DA, 4).expect("read"),
+        [1, 2, 3, 4]
+    );
+    let put = server
+        .requests()
+        .into_iter()
+        .find(|r| r.opcode == "PutAddress")
+        .expect("put");
+    assert_eq!(put.space.as_deref(), Some("SNES"));
+    assert_eq!(put.operands, ["f509da", "4"]);
+}
+
+#[test]
+fn write_sram_is_offset_from_sram_start() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    client.write_sram(0x10, &[0xAA, 0xBB]).expect("write");
+    client.info().expect("round trip");
+    assert_eq!(server.read(SRAM_START + 0x10, 2), [0xAA, 0xBB]);
+}
+
+#[test]
+fn large_writes_are_sent_in_chunks() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    let data: Vec<u8> = (0..5000).map(|i| (i % 253) as u8).collect();
+    client.write_wram(0x2000, &data).expect("write");
+    client.info().expect("round trip");
+    assert_eq!(server.read(WRAM + 0x2000, data.len()), data);
+}
+
+#[test]
+fn writes_past_the_end_of_a_region_are_rejected() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    assert!(client.write_wram(WRAM_SIZE - 1, &[0, 0]).is_err());
+    assert!(client.write_wram(WRAM_SIZE - 2, &[0, 0]).is_ok());
+}
+
+#[test]
+fn cmd_writes_respect_no_control_cmd() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    // The fake reports NO_CONTROL_CMD by default.
+    let err = client.write_cmd(0x2C00, &[0x60]).expect_err("CMD disabled");
+    assert!(is_unsupported(&err), "{err}");
+    assert!(server.cmd_writes().is_empty());
+
+    server.set_flags(&[]);
+    let mut client = attached_client(&server);
+    client.write_cmd(0x2C00, &[0x60]).expect("write");
+    client.info().expect("round trip");
+    assert_eq!(server.cmd_writes(), [(0x2C00, vec![0x60])]);
+}
+
+#[test]
+fn rom_writes_respect_no_rom_write() {
+    let server = FakeUsb2Snes::start();
+    server.set_flags(&[FLAG_NO_ROM_WRITE]);
+    let mut client = attached_client(&server);
+    let err = client
+        .put_address(Space::SNES, 0x7FC0, &[0])
+        .expect_err("ROM writes disabled");
+    assert!(is_unsupported(&err), "{err}");
+    // WRAM is still writable.
+    client.write_wram(0, &[1]).expect("write");
+}
+
+#[test]
+fn info_is_fetched_once_per_attach() {
+    let server = FakeUsb2Snes::start();
+    let mut client = attached_client(&server);
+    client.write_wram(0, &[1]).expect("write");
+    client.write_wram(1, &[2]).expect("write");
+    client.get_address(WRAM, 2).expect("read");
+    let infos = server
+        .requests()
+        .iter()
+        .filter(|r| r.opcode == "Info")
+        .count();
+    assert_eq!(infos, 1);
+}
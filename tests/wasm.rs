@@ -0,0 +1,175 @@
+#![cfg(feature = "wasm")]
+
+use std::sync::Arc;
+
+use annelid::autosplitters::supermetroid::Settings;
+use annelid::autosplitters::wasm::WasmAutoSplitter;
+use annelid::autosplitters::{AutoSplitter, SNESSummary};
+use annelid::memory_source::InMemorySource;
+use annelid::usb2snes::WRAM_START;
+use parking_lot::RwLock;
+
+/// Starts when the game state at $7E0998 becomes $1F and splits when
+/// Kraid's bit is set at $7ED829, if its setting is on.
+const MODULE: &str = r#"
+(module
+  (import "env" "process_attach" (func $attach (param i32 i32) (result i64)))
+  (import "env" "process_read" (func $read (param i64 i64 i32 i32) (result i32)))
+  (import "env" "timer_get_state" (func $state (result i32)))
+  (import "env" "timer_start" (func $start))
+  (import "env" "timer_split" (func $split))
+  (import "env" "timer_set_game_time" (func $game_time (param i64 i32)))
+  (import "env" "user_settings_add_title" (func $add_title (param i32 i32 i32 i32 i32)))
+  (import "env" "user_settings_add_bool"
+    (func $add_bool (param i32 i32 i32 i32 i32) (result i32)))
+  (import "env" "process_get_module_address" (func $module (param i64 i32 i32) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "snes9x")
+  (data (i32.const 16) "bosses")
+  (data (i32.const 32) "Bosses")
+  (data (i32.const 48) "kraidDead")
+  (data (i32.const 64) "Kraid")
+  (global $process (mut i64) (i64.const 0))
+  (global $kraid (mut i32) (i32.const 0))
+  (func (export "_initialize")
+    (call $add_title (i32.const 16) (i32.const 6) (i32.const 32) (i32.const 6) (i32.const 0))
+    (global.set $kraid
+      (call $add_bool (i32.const 48) (i32.const 9) (i32.const 64) (i32.const 5) (i32.const 1)))
+    (global.set $process (call $attach (i32.const 0) (i32.const 6))))
+  (func (export "update")
+    (if (i32.eqz (call $read (global.get $process)
+          (i64.add (call $module (global.get $process) (i32.const 0) (i32.const 6))
+                   (i64.const 0x0998))
+          (i32.const 128) (i32.const 1)))
+      (then (return)))
+    (if (i32.eqz (call $state))
+      (then
+        (if (i32.eq (i32.load8_u (i32.const 128)) (i32.const 0x1F)) (then (call $start)))
+        (return)))
+    (call $game_time (i64.const 90) (i32.const 500000000))
+    (if (i32.eqz (call $read (global.get $process) (i64.const 0x7ED829) (i32.const 129) (i32.const 1)))
+      (then (return)))
+    (if (i32.and (global.get $kraid) (i32.and (i32.load8_u (i32.const 129)) (i32.const 1)))
+      (then (call $split))))
+)
+"#;
+
+fn poll(autosplitter: &mut WasmAutoSplitter, source: &mut InMemorySource) -> SNESSummary {
+    autosplitter.update(source).expect("update")
+}
+
+#[test]
+fn module_settings_join_the_autosplitter_settings() {
+    let settings = Arc::new(RwLock::new(Settings::new()));
+    let wasm = WasmAutoSplitter::new(MODULE.as_bytes(), settings.clone()).expect("load");
+    let added = wasm.settings();
+    assert_eq!(added.len(), 2);
+    assert_eq!(added[1].key, "kraidDead");
+    assert_eq!(added[1].label, "Kraid");
+    assert_eq!(added[1].parent.as_deref(), Some("bosses"));
+    assert!(settings.read().get("kraidDead"));
+    assert_eq!(
+        settings.read().children("bosses").last().unwrap(),
+        "kraidDead"
+    );
+    assert_eq!(settings.read().label("kraidDead"), "Kraid");
+}
+
+#[test]
+fn new_reads_are_fetched_on_the_spot_then_with_the_poll() {
+    let settings = Arc::new(RwLock::new(Settings::new()));
+    let mut wasm = WasmAutoSplitter::new(MODULE.as_bytes(), settings).expect("load");
+    let mut source = InMemorySource::new();
+    source.write(WRAM_START + 0x0998, &[0x1F]);
+    assert!(poll(&mut wasm, &mut source).start);
+    assert_eq!(source.reads(), [vec![(WRAM_START + 0x0998, 1)]]);
+    assert_eq!(wasm.reads(), [(0x0998, 1)]);
+
+    // The renderer passes on where the timer is before each poll.
+    wasm.set_current_split(Some(0));
+    source.write(WRAM_START + 0xD829, &[0x01]);
+    assert!(poll(&mut wasm, &mut source).split);
+    assert_eq!(
+        source.reads()[1..],
+        [
+            vec![(WRAM_START + 0x0998, 1)],
+            vec![(WRAM_START + 0xD829, 1)]
+        ]
+    );
+    poll(&mut wasm, &mut source);
+    assert_eq!(
+        source.reads().last().unwrap(),
+        &[(WRAM_START + 0x0998, 1), (WRAM_START + 0xD829, 1)]
+    );
+    let time = wasm.gametime_to_seconds().expect("game time");
+    assert_eq!(time.total_seconds(), 90.5);
+}
+
+#[test]
+fn saved_settings_win_over_the_module_defaults() {
+    let mut saved = Settings::new();
+    saved.set("kraidDead", false);
+    let mut wasm =
+        WasmAutoSplitter::new(MODULE.as_bytes(), Arc::new(RwLock::new(saved))).expect("load");
+    let mut source = InMemorySource::new();
+    source.write(WRAM_START + 0xD829, &[0x01]);
+    wasm.set_current_split(Some(0));
+    for _ in 0..3 {
+        assert!(!poll(&mut wasm, &mut source).split);
+    }
+}
+
+#[test]
+fn modules_without_update_are_rejected() {
+    let error = WasmAutoSplitter::new(b"(module)", Arc::default())
+        .err()
+        .expect("error");
+    assert!(
+        format!("{error:#}").contains("no update function"),
+        "{error:#}"
+    );
+}
+
+/// Starts if `$check` holds on the first poll.
+fn module_that_starts_if(imports: &str, check: &str) -> String {
+    format!(
+        r#"
+(module
+  (import "env" "process_attach" (func $attach (param i32 i32) (result i64)))
+  (import "env" "process_get_module_address" (func $module (param i64 i32 i32) (result i64)))
+  (import "env" "timer_start" (func $start))
+  {imports}
+  (memory (export "memory") 1)
+  (data (i32.const 0) "snes9x")
+  (data (i32.const 16) "snes9x.dll")
+  (global $process (mut i64) (i64.const 0))
+  (func (export "update")
+    (global.set $process (call $attach (i32.const 0) (i32.const 6)))
+    (if {check} (then (call $start))))
+)
+"#
+    )
+}
+
+#[test]
+fn unknown_imports_return_zero() {
+    let module = module_that_starts_if(
+        r#"(import "env" "timer_from_the_future" (func $future (param i32) (result i64)))"#,
+        "(i64.eqz (call $future (i32.const 1)))",
+    );
+    let mut wasm = WasmAutoSplitter::new(module.as_bytes(), Arc::default()).expect("load");
+    assert!(poll(&mut wasm, &mut InMemorySource::new()).start);
+}
+
+#[test]
+fn only_the_process_module_is_wram() {
+    let module = module_that_starts_if(
+        "",
+        "(i32.and
+          (i64.eq (call $module (global.get $process) (i32.const 0) (i32.const 6))
+                  (i64.const 0x7E0000))
+          (i64.eqz (call $module (global.get $process) (i32.const 16) (i32.const 10))))",
+    );
+    let mut wasm = WasmAutoSplitter::new(module.as_bytes(), Arc::default()).expect("load");
+    assert!(poll(&mut wasm, &mut InMemorySource::new()).start);
+}